@@ -0,0 +1,4620 @@
+// app.rs
+
+use crate::file_browser::BrowserMode;
+use crate::palette::{self, get_default_tool_palette, rgb_to_hsv, PaletteEntry, PaletteFormat, Tool};
+use crate::commands::COMMANDS;
+use ratatui::{
+    prelude::*,
+    widgets::ListState,
+};
+use std::io::Result;
+use std::time::Instant;
+use std::path::PathBuf;
+use std::collections::{HashSet, VecDeque};
+use crate::keybindings::Keybindings;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use crate::{Canvas, ExportLayerMode, ExportSettings, font, keybindings, utils};
+
+pub const PIXEL_WIDTH: u16 = 2;
+
+pub const DEFAULT_SHADE_FACTOR: f32 = 0.03;
+pub const MAX_BRUSH_SIZE: u32 = 64;
+// The widest and tallest a canvas can be resized to.
+pub const MAX_CANVAS_SIZE: usize = 1024;
+// How many distinct colors the recent colors strip remembers.
+pub const RECENT_COLORS: usize = 10;
+// How many status messages the `messages` log keeps.
+pub const MESSAGE_LOG_LEN: usize = 50;
+// The most suggestions the command prompt lists at once.
+const MAX_SUGGESTIONS: usize = 15;
+// Below this alpha the soft eraser clears a pixel completely.
+const SOFT_ERASE_EPSILON: f32 = 0.01;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct SerializableColor(pub u8, pub u8, pub u8);
+
+impl From<Color> for SerializableColor {
+    fn from(color: Color) -> Self {
+        let (r, g, b) = utils::to_rgb(color);
+        SerializableColor(r, g, b)
+    }
+}
+
+impl From<SerializableColor> for Color {
+    fn from(sc: SerializableColor) -> Self {
+        Color::Rgb(sc.0, sc.1, sc.2)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Pixel {
+    pub color: SerializableColor,
+    pub alpha: f32,
+}
+
+impl Default for Pixel {
+    fn default() -> Self {
+        Pixel {
+            color: Color::Reset.into(),
+            alpha: 0.0,
+        }
+    }
+}
+
+
+#[derive(Serialize, Deserialize)]
+pub struct PaletteFile(pub Vec<SerializableColor>);
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Config {
+    pub pen_size_sensitivity: u16,
+    pub opacity_sensitivity: f32,
+    pub pen_shape: PenShape,
+    pub highlighter_enabled: bool,
+    pub highlighter_value: f32,
+    pub highlighter_mode: HighlighterMode,
+    pub shade_factor: f32,
+    pub protect_stroke: bool,
+    pub apply_color_sec: f32,
+    pub minimap_mode: MinimapMode,
+    pub mouse_events_enabled: bool,
+    pub hover_cursor: bool,
+    pub color_mode: ColorMode,
+    pub default_palette_name: String,
+    pub canvas_scroll_action: CanvasScrollAction,
+    pub spray_size: u16,
+    pub spray_speed: u16,
+    pub spray_intensity: f32,
+    pub spray_falloff: bool,
+    pub snap_to_palette: bool,
+    pub snap_to_palette_mode: SnapToPaletteMode,
+    pub protect_color_transitions: bool,
+    pub palette_menu_position: PaletteMenuPosition,
+    pub onion_skin_enabled: bool,
+    pub onion_skin_opacity: f32,
+    pub onion_skin_frames_before: u16,
+    pub onion_skin_frames_after: u16,
+    pub playback_fps: u16,
+    pub palette_colors: u16,
+    pub export_layer_mode: ExportLayerMode,
+    pub antialias: bool,
+    pub flat_mode: bool,
+    pub linear_blending: bool,
+    pub stabilizer: u16,
+    pub fill_shapes: bool,
+    pub perfect_circles: bool,
+    pub dither_pattern: DitherPattern,
+    pub clone_sample_merged: bool,
+    pub fill_mode: FillMode,
+    pub fill_tolerance: u8,
+    pub color_jitter: f32,
+    pub soft_eraser: bool,
+    pub blur_radius: u8,
+    pub pick_from: PickSource,
+    pub color_info: ColorInfo,
+    pub pick_alpha: bool,
+    pub selection_mask: bool,
+    pub radial_segments: u8,
+    pub transparency_checker: bool,
+    pub checker_size: u16,
+    pub checker_light: SerializableColor,
+    pub checker_dark: SerializableColor,
+    pub pixel_grid: bool,
+    pub pixel_grid_min_zoom: u16,
+    pub max_zoom: u16,
+    pub major_grid_spacing: u16,
+    pub restore_session: bool,
+    pub autosave_rotations: u16,
+    pub plain_project_save: bool,
+    pub embed_palettes: bool,
+    pub undo_limit: u16,
+    pub undo_memory_mb: u16,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            pen_size_sensitivity: 1,
+            opacity_sensitivity: 0.05,
+            pen_shape: PenShape::Circular,
+            highlighter_enabled: true,
+            highlighter_value: 0.5,
+            highlighter_mode: HighlighterMode::Blend,
+            shade_factor: DEFAULT_SHADE_FACTOR,
+            protect_stroke: true,
+            apply_color_sec: 0.2,
+            minimap_mode: MinimapMode::Auto,
+            mouse_events_enabled: true,
+            hover_cursor: true,
+            color_mode: ColorMode::TrueColor,
+            default_palette_name: "default".to_string(),
+            canvas_scroll_action: CanvasScrollAction::ChangePenSize,
+            spray_size: 5,
+            spray_speed: 3,
+            spray_intensity: 0.1,
+            spray_falloff: false,
+            snap_to_palette: false,
+            snap_to_palette_mode: SnapToPaletteMode::ClosestHue,
+            protect_color_transitions: false,
+            palette_menu_position: PaletteMenuPosition::Left,
+            onion_skin_enabled: false,
+            onion_skin_opacity: 0.3,
+            onion_skin_frames_before: 1,
+            onion_skin_frames_after: 0,
+            playback_fps: 8,
+            palette_colors: 16,
+            export_layer_mode: ExportLayerMode::United,
+            antialias: false,
+            flat_mode: false,
+            linear_blending: false,
+            stabilizer: 0,
+            fill_shapes: false,
+            perfect_circles: false,
+            dither_pattern: DitherPattern::Checker,
+            clone_sample_merged: false,
+            fill_mode: FillMode::Contiguous,
+            fill_tolerance: 0,
+            color_jitter: 0.0,
+            soft_eraser: false,
+            blur_radius: 1,
+            pick_from: PickSource::ActiveLayer,
+            color_info: ColorInfo::Off,
+            pick_alpha: false,
+            selection_mask: false,
+            radial_segments: 6,
+            transparency_checker: false,
+            checker_size: 1,
+            checker_light: SerializableColor(153, 153, 153),
+            checker_dark: SerializableColor(102, 102, 102),
+            pixel_grid: false,
+            pixel_grid_min_zoom: 4,
+            max_zoom: 16,
+            major_grid_spacing: 8,
+            restore_session: true,
+            autosave_rotations: 3,
+            plain_project_save: false,
+            embed_palettes: false,
+            undo_limit: 100,
+            undo_memory_mb: 512,
+        }
+    }
+}
+
+
+
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum PenShape { Circular, Square }
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum HighlighterMode { Underscore, Blend }
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum SymmetryMode {
+    Off,
+    Vertical(u16),
+    DiagonalForward(i32),  // Represents y = x + c
+    Horizontal(u16),
+    DiagonalBackward(i32), // Represents y = -x + c
+    Radial(u8, u16, u16),  // Copies, center x, center y
+    Quad(u16, u16),        // Vertical and horizontal lines at once
+}
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum MinimapMode { Auto, On, Off }
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum QuitChoice { Yes, No, Save }
+
+impl QuitChoice {
+    pub fn next(self) -> Self {
+        match self { QuitChoice::Yes => QuitChoice::No, QuitChoice::No => QuitChoice::Save, QuitChoice::Save => QuitChoice::Yes }
+    }
+
+    pub fn previous(self) -> Self {
+        match self { QuitChoice::Yes => QuitChoice::Save, QuitChoice::No => QuitChoice::Yes, QuitChoice::Save => QuitChoice::No }
+    }
+}
+
+#[derive(PartialEq)]
+pub enum AppMode { Drawing, ColorPicker, ToolPicker, ResizingWidth, ResizingHeight, Command, HelpScreen, ConfirmOverwrite, Keybindings, ConfirmKeybindingSave, ConfigEditor, ConfirmConfigSave, ScriptEditor, ConfirmScriptSave, FileBrowser, CommandPalette, ConfirmPaletteSwitch, InfoPopup, ConfirmRestoreAutosave, ConfirmQuit, UndoHistory, ColorEditor }
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum ColorMode { TrueColor, Ansi256 }
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum SnapToPaletteMode { ClosestRgb, ClosestHue }
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PaletteSort { Hue, Brightness, Saturation }
+
+/// Pixels as (x, y, pixel), for undo steps that only keep what changed.
+pub type PixelChanges = Vec<(usize, usize, Pixel)>;
+
+/// One undo step, holding the state it replaced. Undo and redo swap it with the
+/// current state, so the same entry moves between the two stacks.
+#[derive(Clone)]
+pub enum UndoEntry {
+    /// The previous pixels of every layer it touched, by layer index.
+    Pixels(Vec<(usize, Canvas)>),
+    /// Only the pixels that changed, as (x, y, previous pixel) per layer index. When the
+    /// stroke that recorded a `Pixels` entry ends, the entry becomes this if few pixels changed.
+    PixelDiff(Vec<(usize, PixelChanges)>),
+    /// The whole layer stack and active layer, for adding, deleting, moving and merging layers.
+    Layers { layers: VecDeque<Layer>, active_layer_index: usize },
+    /// One layer's previous opacity.
+    LayerOpacity { index: usize, opacity: f32 },
+    /// One layer's previous blend mode.
+    LayerBlendMode { index: usize, blend_mode: BlendMode },
+}
+
+impl UndoEntry {
+    /// Roughly how many bytes the entry keeps alive, for the undo memory cap.
+    pub fn approximate_size(&self) -> usize {
+        let canvas_size = |canvas: &Canvas| std::mem::size_of_val(canvas.pixels());
+        std::mem::size_of::<UndoEntry>() + match self {
+            UndoEntry::Pixels(canvases) => canvases.iter().map(|(_, canvas)| canvas_size(canvas)).sum(),
+            UndoEntry::PixelDiff(diffs) => diffs.iter().map(|(_, pixels)| pixels.len() * std::mem::size_of::<(usize, usize, Pixel)>()).sum(),
+            UndoEntry::Layers { layers, .. } => layers.iter().map(|layer| canvas_size(&layer.canvas)).sum(),
+            UndoEntry::LayerOpacity { .. } | UndoEntry::LayerBlendMode { .. } => 0,
+        }
+    }
+}
+
+/// An undo or redo entry with what the undo history panel shows for it.
+#[derive(Clone)]
+pub struct UndoStep {
+    pub entry: UndoEntry,
+    pub label: String,
+    pub time: chrono::DateTime<chrono::Local>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum FillMode { Contiguous, Global }
+
+/// Where the old artwork lands when the canvas is resized.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ResizeAnchor {
+    TopLeft,
+    Center,
+    Offset(i32, i32), // Position of the old top-left pixel on the new canvas
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum PickSource { ActiveLayer, Composite }
+
+/// Where the hex readout of the drawing color and the pixel under the cursor is shown.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum ColorInfo { Off, StatusBar, Cursor }
+
+impl ColorInfo {
+    pub fn next(self) -> Self {
+        match self { ColorInfo::Off => ColorInfo::StatusBar, ColorInfo::StatusBar => ColorInfo::Cursor, ColorInfo::Cursor => ColorInfo::Off }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "off" | "false" => Some(ColorInfo::Off),
+            "bar" | "statusbar" | "true" => Some(ColorInfo::StatusBar),
+            "cursor" => Some(ColorInfo::Cursor),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum DitherPattern { Light, Checker, Dense }
+
+impl DitherPattern {
+    // 2x2 Bayer matrix; each pattern paints the cells below its level, so denser
+    // patterns always contain the lighter ones.
+    const BAYER: [[u8; 2]; 2] = [[0, 2], [3, 1]];
+
+    /// Whether the pattern paints cell `(x, y)`. Checker paints cells where x + y is even.
+    pub fn covers(self, x: usize, y: usize) -> bool {
+        Self::BAYER[y % 2][x % 2] < self.percent() / 25
+    }
+
+    pub fn percent(self) -> u8 {
+        match self {
+            DitherPattern::Light => 25,
+            DitherPattern::Checker => 50,
+            DitherPattern::Dense => 75,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "light" | "25" => Some(DitherPattern::Light),
+            "checker" | "50" => Some(DitherPattern::Checker),
+            "dense" | "75" => Some(DitherPattern::Dense),
+            _ => None,
+        }
+    }
+}
+
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum CanvasScrollAction { ChangePenSize, ChangeOpacity }
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum PaletteMenuPosition { Left, Right }
+
+/// How a layer's colors combine with what is below it.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum BlendMode { #[default] Normal, Multiply, Screen, Overlay, Add, Subtract }
+
+impl BlendMode {
+    pub const ALL: [BlendMode; 6] = [BlendMode::Normal, BlendMode::Multiply, BlendMode::Screen, BlendMode::Overlay, BlendMode::Add, BlendMode::Subtract];
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&mode| mode == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BlendMode::Normal => "normal",
+            BlendMode::Multiply => "multiply",
+            BlendMode::Screen => "screen",
+            BlendMode::Overlay => "overlay",
+            BlendMode::Add => "add",
+            BlendMode::Subtract => "subtract",
+        }
+    }
+
+    /// The short label shown next to the layer name in the Layers panel.
+    pub fn abbreviation(self) -> &'static str {
+        match self {
+            BlendMode::Normal => "Nrm",
+            BlendMode::Multiply => "Mul",
+            BlendMode::Screen => "Scr",
+            BlendMode::Overlay => "Ovr",
+            BlendMode::Add => "Add",
+            BlendMode::Subtract => "Sub",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+        Self::ALL.into_iter().find(|mode| mode.name() == name || mode.abbreviation().to_lowercase() == name)
+    }
+
+    /// Combines one channel of the layer (`top`) with the backdrop (`base`).
+    pub fn blend_channel(self, base: u8, top: u8) -> u8 {
+        let (a, b) = (base as f32 / 255.0, top as f32 / 255.0);
+        let value = match self {
+            BlendMode::Normal => b,
+            BlendMode::Multiply => a * b,
+            BlendMode::Screen => 1.0 - (1.0 - a) * (1.0 - b),
+            BlendMode::Overlay if a < 0.5 => 2.0 * a * b,
+            BlendMode::Overlay => 1.0 - 2.0 * (1.0 - a) * (1.0 - b),
+            BlendMode::Add => a + b,
+            BlendMode::Subtract => a - b,
+        };
+        (value.clamp(0.0, 1.0) * 255.0).round() as u8
+    }
+}
+
+
+// A palette switch waiting for confirmation because it would drop colors used on the canvas.
+pub struct PendingPaletteSwitch {
+    pub name: String,
+    pub missing_colors: Vec<Color>,
+    pub set_as_default: bool,
+}
+
+/// The downsampled minimap colors, kept between frames until the canvas, the layers'
+/// visibility or opacity, or the minimap size changes.
+pub struct MinimapCache {
+    pub canvas_generation: u64,
+    pub layer_key: Vec<(bool, u32)>,
+    pub size: (u16, u16),
+    /// (top, bottom) half of each minimap cell, row by row.
+    pub cells: Vec<(Color, Color)>,
+}
+
+#[derive(PartialEq)]
+pub enum BrowserFocus {
+    List,
+    NameInput,
+    ScaleInput,
+    BackgroundInput,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Layer {
+    pub name: String,
+    pub canvas: Canvas,
+    pub visible: bool,
+    pub opacity: f32,
+    #[serde(default)]
+    pub locked: bool,
+    #[serde(default)]
+    pub blend_mode: BlendMode,
+    /// Painting only recolors pixels that are already there and never raises their alpha.
+    #[serde(default)]
+    pub alpha_locked: bool,
+}
+
+impl Layer {
+    /// Whether painting can change (x, y): an alpha-locked layer never paints where it is transparent.
+    pub fn can_paint(&self, x: usize, y: usize) -> bool {
+        !self.alpha_locked || self.canvas.get(x, y).is_some_and(|pixel| pixel.alpha > 0.0)
+    }
+
+    /// Writes `pixel` at (x, y), the way every bulk writer (fills, text, tiles, imports)
+    /// goes through the alpha lock. A locked layer only takes the color, keeping its alpha,
+    /// and a transparent `pixel` would erase, so it is skipped. Returns whether it was written.
+    pub fn put_pixel(&mut self, x: usize, y: usize, pixel: Pixel) -> bool {
+        if !self.can_paint(x, y) || (self.alpha_locked && pixel.alpha == 0.0) { return false; }
+        let Some(dest) = self.canvas.get_mut(x, y) else { return false };
+        if self.alpha_locked {
+            dest.color = pixel.color;
+        } else {
+            *dest = pixel;
+        }
+        true
+    }
+}
+
+#[derive(PartialEq)]
+pub enum LayerFocus {
+    List,
+    NameInput,
+}
+
+
+pub struct App {
+    pub canvas: Canvas,
+    pub canvas_width: usize, pub canvas_height: usize,
+    pub cursor_pos: (u16, u16),
+    pub current_selection: PaletteEntry,
+    pub color_palette: Vec<PaletteEntry>,
+    pub palette_index: usize,
+    pub tool_palette: Vec<PaletteEntry>,
+    pub tool_index: usize,
+    pub palette_scroll_state: usize,
+    pub mode: AppMode,
+    pub symmetry_mode: SymmetryMode,
+    pub should_quit: bool,
+    pub status_message: Option<(String, Instant)>,
+    // The last MESSAGE_LOG_LEN status messages with the time they were shown, oldest first.
+    pub message_log: VecDeque<(String, String)>,
+    pub input_buffer: String,
+    pub temp_width: usize,
+    pub last_pixel_area: Option<Rect>,
+    /// Set by input and timed painting; the main loop skips drawing frames while it is false.
+    pub needs_redraw: bool,
+    /// Bumped whenever the composite canvas is rebuilt or a pixel of it changes.
+    pub canvas_generation: u64,
+    pub minimap_cache: Option<MinimapCache>,
+    pub last_palette_area: Option<Rect>,
+    pub last_tool_area: Option<Rect>,
+    // Colors most recently painted with, newest first, and the one QuickSelectRecent last picked.
+    pub recent_colors: Vec<Color>,
+    pub recent_color_cursor: usize,
+    pub last_recent_colors_area: Option<Rect>,
+    pub is_side_panel_visible: bool,
+    pub pen_size: u16,
+    pub opacity: f32,
+    pub pen_size_sensitivity: u16,
+    pub opacity_sensitivity: f32,
+    pub pen_shape: PenShape,
+    pub view_offset_x: i32,
+    pub view_offset_y: i32,
+    pub zoom_level: u16,
+    pub suggestion_index: usize,
+    pub undo_stack: VecDeque<UndoStep>,
+    pub redo_stack: VecDeque<UndoStep>,
+    pub undo_history_selection: usize,
+    pub is_mouse_dragging: bool,
+    pub pan_anchor: Option<((u16, u16), (i32, i32))>,
+    pub shade_factor: f32,
+    pub highlighter_enabled: bool,
+    pub highlighter_value: f32,
+    pub highlighter_mode: HighlighterMode,
+    pub protect_stroke: bool,
+    pub is_space_held: bool,
+    pub is_spraying: bool,
+    pub last_apply_time: Option<chrono::DateTime<chrono::Local>>,
+    /// The frame rate while layers are played back as frames, None when not playing.
+    pub playing: Option<u16>,
+    /// The layer shown on its own during playback.
+    pub playback_frame: usize,
+    pub last_playback_time: Option<chrono::DateTime<chrono::Local>>,
+    pub apply_color_interval: chrono::Duration,
+    pub drawn_pixels_in_stroke: std::collections::HashSet<(u16, u16)>,
+    pub minimap_mode: MinimapMode,
+    pub mouse_events_enabled: bool,
+    pub hover_cursor: bool,
+    pub color_mode: ColorMode,
+    pub default_palette_name: String,
+    pub command_history: Vec<String>,
+    pub history_index: usize,
+    pub command_input_before_history: String,
+    pub command_cursor_pos: usize,
+    pub suggestion_active: bool,
+    pub project_path: Option<PathBuf>,
+    pub autosave_interval: Option<std::time::Duration>,
+    pub last_autosave_time: Instant,
+    // Autosaves cycle through this many `<name>.autosave-N.consolet` files next to the project.
+    pub autosave_rotations: u16,
+    // Saves without an extension write pretty-printed `.consolet.json` instead of gzip.
+    pub plain_project_save: bool,
+    // Saves carry the named palettes the project uses; `embed_palettes_once` does it
+    // for the next save only (`save --embed-palettes`).
+    pub embed_palettes: bool,
+    pub embed_palettes_once: bool,
+    // The most undo steps kept, and roughly how much memory they may take (0 for no cap).
+    pub undo_limit: u16,
+    pub undo_memory_mb: u16,
+    // Set once old undo steps have been dropped to stay within those limits.
+    pub undo_history_trimmed: bool,
+    // The rotation the next autosave writes, once the first one has picked the oldest.
+    pub next_autosave_slot: Option<usize>,
+    // A newer autosave found when loading, waiting on ConfirmRestoreAutosave.
+    pub pending_autosave: Option<PathBuf>,
+    // Set when layers, the palette or the canvas size change; cleared by an explicit save or load.
+    pub dirty: bool,
+    pub pending_save_path: Option<PathBuf>,
+    pub help_scroll: u16,
+    pub loaded_palettes: std::collections::HashMap<String, Vec<PaletteEntry>>,
+    pub keybindings: Keybindings,
+    pub keybindings_selection_index: usize,
+    pub is_changing_keybinding: bool,
+    pub keybinding_change_has_occured: bool,
+    pub confirm_selection_yes: bool, // For the dialog
+    pub quit_choice: QuitChoice,
+    pub keybindings_scroll_state: u16,
+    pub selection_before_picker: Option<PaletteEntry>,
+    pub config_selection_index: usize,
+    pub config_change_has_occured: bool,
+    pub script_content_lines: Vec<String>,
+    pub script_cursor_line: usize,
+    pub script_scroll_state: u16,
+    pub script_cursor_char_pos: usize, // Tracks horizontal cursor position
+    pub script_change_has_occured: bool,
+    pub canvas_scroll_action: CanvasScrollAction,
+    pub spray_size: u16,
+    pub spray_speed: u16,
+    pub spray_intensity: f32,
+    pub spray_falloff: bool,
+    pub snap_to_palette: bool,
+    pub snap_to_palette_mode: SnapToPaletteMode,
+    pub protect_color_transitions: bool,
+    pub browser_mode: Option<BrowserMode>,
+    pub browser_entries: Vec<PathBuf>,
+    // How many of `browser_entries` are the pinned recent projects at the top.
+    pub browser_pinned: usize,
+    pub browser_list_state: ListState,
+    pub browser_current_dir: PathBuf,
+    pub browser_history_back: Vec<PathBuf>,
+    pub browser_history_forward: Vec<PathBuf>,
+    pub browser_error: Option<String>,
+    pub browser_input_buffer: String,
+    pub browser_scale_buffer: String,
+    // Export background; empty keeps transparency.
+    pub browser_background_buffer: String,
+    pub browser_focus: BrowserFocus,
+    pub last_generated_palette: Option<Vec<PaletteEntry>>,
+    pub last_image_palette_source: Option<String>,
+    pub palette_menu_position: PaletteMenuPosition,
+    pub last_centered_canvas_rect: Option<Rect>,
+    pub layers: VecDeque<Layer>,
+    pub active_layer_index: usize,
+    pub onion_skin_enabled: bool,
+    pub onion_skin_opacity: f32,
+    pub onion_skin_frames_before: u16,
+    pub onion_skin_frames_after: u16,
+    pub playback_fps: u16,
+    pub palette_colors: u16,
+    pub tile_preview: bool,
+    // An image to trace over: drawn beneath every layer, never exported or edited.
+    pub reference: Option<Canvas>,
+    pub reference_opacity: f32,
+    pub reference_visible: bool,
+    pub layer_scroll_state: usize,
+    pub last_layer_area: Option<Rect>,
+    pub last_minimap_area: Option<Rect>,
+    pub minimap_dragging: bool,
+    pub layer_input_buffer: String,
+    pub layer_focus: LayerFocus,
+    pub is_renaming_layer: bool,
+    /// Byte offset of the cursor in layer_input_buffer, always on a grapheme boundary.
+    pub layer_name_cursor_pos: usize,
+    /// The layer row last clicked and when, to spot double-clicks.
+    pub last_layer_click: Option<(usize, Instant)>,
+    /// A Layers panel row being dragged, as (its index, the index it would drop at).
+    pub layer_drag: Option<(usize, usize)>,
+    pub export_layer_mode: ExportLayerMode,
+    pub antialias: bool,
+    pub last_export: Option<ExportSettings>,
+    pub flat_mode: bool,
+    pub linear_blending: bool,
+    pub stabilizer: u16,
+    pub stabilizer_queue: VecDeque<(u16, u16)>,
+    pub last_drag_point: Option<(u16, u16)>,
+    pub brush_mask: Option<Vec<Vec<f32>>>,
+    pub brush_path: Option<String>,
+    pub shape_tool: Option<Tool>,
+    pub fill_shapes: bool,
+    pub perfect_circles: bool,
+    pub shape_constrained: bool,
+    pub dither_enabled: bool,
+    pub replace_mode: bool,
+    pub dither_pattern: DitherPattern,
+    pub clone_source: Option<(u16, u16)>,
+    pub clone_offset: Option<(i32, i32)>,
+    pub clone_sample_merged: bool,
+    pub fill_mode: FillMode,
+    pub fill_tolerance: u8,
+    pub color_jitter: f32,
+    pub soft_eraser: bool,
+    pub blur_radius: u8,
+    pub pick_from: PickSource,
+    pub color_info: ColorInfo,
+    pub pick_alpha: bool,
+    /// Canvas pixels picked by the magic wand; empty when nothing is selected.
+    pub selection: std::collections::HashSet<(usize, usize)>,
+    pub selection_mask: bool,
+    pub radial_segments: u8,
+    // Drawn behind transparent canvas pixels in cells of checker_size pixels.
+    pub transparency_checker: bool,
+    pub checker_size: u16,
+    pub checker_light: Color,
+    pub checker_dark: Color,
+    // Lines between canvas pixels from pixel_grid_min_zoom (as shown in the status bar) up, brighter every
+    // major_grid_spacing pixels (0 turns those off).
+    pub pixel_grid: bool,
+    pub pixel_grid_min_zoom: u16,
+    pub max_zoom: u16,
+    pub major_grid_spacing: u16,
+    // Whether load_project brings back the view and tool state saved with the project.
+    pub restore_session: bool,
+    // The palette last switched to, saved with projects.
+    pub active_palette_name: String,
+    // color_jitter noise is seeded from this and history_position, the number of undo
+    // steps recorded so far, so redrawing a stroke after undo gives the same texture.
+    pub jitter_seed: u64,
+    pub history_position: u64,
+    pub shape_anchor: Option<(u16, u16)>,
+    pub command_palette_query: String,
+    pub command_palette_index: usize,
+    /// The color editor's color. Hue, saturation and value are kept alongside the RGB
+    /// value so the hue survives dragging saturation or value down to zero.
+    pub color_editor_hsv: (f32, f32, f32),
+    pub color_editor_rgb: (u8, u8, u8),
+    /// The selected slider: H, S, V, R, G, B.
+    pub color_editor_row: usize,
+    pub color_editor_add_to_palette: bool,
+    /// The palette index being edited in place, or None when mixing a new color.
+    pub color_editor_target: Option<usize>,
+    /// When editing in place, also recolor every pixel of the old color on all layers.
+    pub color_editor_remap: bool,
+    /// Where the slider bars were last drawn, one row per slider.
+    pub last_color_editor_area: Option<Rect>,
+    pub tile_size: Option<(usize, usize)>,
+    pub tile_snap: bool,
+    pub tile_clipboard: Option<Canvas>,
+    pub pending_palette_switch: Option<PendingPaletteSwitch>,
+    pub pixel_block: u16,
+    pub info_popup: Option<(String, Vec<String>)>,
+    pub info_popup_scroll: u16,
+}
+
+impl Default for App {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Fuzzy-matches `query` against `candidates`, best first. An empty query keeps them
+// all in their given order.
+fn rank_suggestions(query: &str, candidates: impl IntoIterator<Item = String>) -> Vec<(String, Vec<usize>)> {
+    if query.is_empty() {
+        return candidates.into_iter().map(|candidate| (candidate, Vec::new())).collect();
+    }
+    let mut scored: Vec<(i32, String, Vec<usize>)> = candidates.into_iter()
+        .filter_map(|candidate| utils::fuzzy_match(query, &candidate).map(|(score, matched)| (score, candidate, matched)))
+        .collect();
+    // Stable, so equal scores keep the given order.
+    scored.sort_by_key(|&(score, ..)| std::cmp::Reverse(score));
+    scored.into_iter().map(|(_, candidate, matched)| (candidate, matched)).collect()
+}
+
+// The start and text of the path being typed after `export ... -o` or `import palette|image`.
+fn path_argument(input: &str) -> Option<(usize, &str)> {
+    let (head, partial) = input.rsplit_once(' ')?;
+    let words: Vec<&str> = head.split_whitespace().collect();
+    matches!(words.as_slice(), ["export", .., "-o"] | ["import", "palette" | "image"]).then_some((head.len() + 1, partial))
+}
+
+// Entries of the directory `partial` points into that match its last component, with
+// a trailing `/` on directories. Hidden ones show up once a `.` is typed.
+fn path_suggestions(partial: &str) -> Vec<(String, Vec<usize>)> {
+    let split = partial.rfind(['/', std::path::MAIN_SEPARATOR]).map_or(0, |i| i + 1);
+    let (dir_part, name_part) = partial.split_at(split);
+    let dir = if dir_part.is_empty() { PathBuf::from(".") } else { PathBuf::from(shellexpand::tilde(dir_part).into_owned()) };
+    let Ok(entries) = std::fs::read_dir(dir) else { return Vec::new() };
+    let mut names: Vec<String> = entries.filter_map(Result::ok)
+        .map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.path().is_dir() { format!("{}/", name) } else { name }
+        })
+        .filter(|name| !name.starts_with('.') || name_part.starts_with('.'))
+        .collect();
+    names.sort();
+    let offset = dir_part.chars().count();
+    rank_suggestions(name_part, names).into_iter()
+        .map(|(name, matched)| (format!("{}{}", dir_part, name), matched.into_iter().map(|i| i + offset).collect()))
+        .collect()
+}
+
+// The choices listed in a setter's usage, like `circular` and `square` for
+// `penShape={circular|square}` or `palette_sort=<hue|brightness|saturation>`.
+// Ranges such as `{1-16}` have none.
+fn setter_values(name: &str) -> Vec<String> {
+    COMMANDS.iter().find(|cmd| cmd.name == name)
+        .and_then(|cmd| {
+            let usage = cmd.usage.strip_prefix(name)?.strip_prefix('=')?;
+            usage.strip_prefix('{').and_then(|rest| rest.split_once('}'))
+                .or_else(|| usage.strip_prefix('<')?.split_once('>'))
+        })
+        .filter(|(values, _)| values.contains('|'))
+        .map(|(values, _)| values.split('|').map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+// Relative luminance (Rec. 709 weights) from 0 to 1, for ordering colors by brightness.
+fn luminance(color: Color) -> f32 {
+    let (r, g, b) = utils::to_rgb(color);
+    (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32) / 255.0
+}
+
+// A palette color as #RRGGBB, for status messages.
+fn palette_entry_label(entry: PaletteEntry) -> String {
+    match entry {
+        PaletteEntry::Color(color) => {
+            let (r, g, b) = utils::to_rgb(color);
+            format!("#{:02X}{:02X}{:02X}", r, g, b)
+        }
+        PaletteEntry::Tool(tool) => format!("{:?}", tool),
+    }
+}
+
+// The inclusive (min_x, min_y, max_x, max_y) box around the points, if there are any.
+pub(crate) fn bounding_box(points: impl Iterator<Item = (usize, usize)>) -> Option<(usize, usize, usize, usize)> {
+    points.fold(None, |bounds, (x, y)| Some(match bounds {
+        None => (x, y, x, y),
+        Some((min_x, min_y, max_x, max_y)) => (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y)),
+    }))
+}
+
+// Copies `src` into `dst` with its top-left corner at (offset_x, offset_y),
+// dropping whatever falls outside.
+fn copy_into(src: &Canvas, dst: &mut Canvas, offset_x: i32, offset_y: i32) {
+    for (y, row) in src.rows().enumerate() {
+        let Ok(dy) = usize::try_from(y as i32 + offset_y) else { continue };
+        for (x, pixel) in row.iter().enumerate() {
+            if let Some(target) = usize::try_from(x as i32 + offset_x).ok().and_then(|dx| dst.get_mut(dx, dy)) {
+                *target = *pixel;
+            }
+        }
+    }
+}
+
+impl App {
+
+
+    pub fn translate_color(&self, color: Color) -> Color {
+        if self.color_mode == ColorMode::TrueColor {
+            return color;
+        }
+
+        // ANSI 256 Color Mode Logic
+        let (r, g, b) = utils::to_rgb(color);
+
+        // Grayscale check
+        if r == g && g == b {
+            if r < 8 { return Color::Indexed(16); } // Black
+            if r > 248 { return Color::Indexed(231); } // White
+            let gray_index = 232 + ((r as u16 - 8) * 24 / 247) as u8;
+            return Color::Indexed(gray_index);
+        }
+
+        // Color cube check
+        let r_idx = (r as u16 * 6 / 256) as u8;
+        let g_idx = (g as u16 * 6 / 256) as u8;
+        let b_idx = (b as u16 * 6 / 256) as u8;
+        let index = 16 + (r_idx * 36) + (g_idx * 6) + b_idx;
+        Color::Indexed(index)
+    }
+
+
+    // Every built-in palette, then the palette files, which win over built-ins of the same name.
+    fn load_palettes_from_disk() -> std::collections::HashMap<String, Vec<PaletteEntry>> {
+        let mut palettes: std::collections::HashMap<String, Vec<PaletteEntry>> = palette::get_built_in_palettes().into_iter()
+            .map(|(name, generator)| (name.to_string(), generator()))
+            .collect();
+
+        if let Ok(app_dir) = utils::get_or_create_app_dir() {
+            let palettes_dir = app_dir.join("palettes");
+            if let Ok(entries) = std::fs::read_dir(palettes_dir) {
+                for entry in entries.filter_map(Result::ok) {
+                    let path = entry.path();
+                    if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("consolet") {
+                        if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
+                            if let Ok(json_data) = std::fs::read_to_string(&path) {
+                                if let Ok(palette_file) = serde_json::from_str::<PaletteFile>(&json_data) {
+                                    let entries = palette_file.0.into_iter().map(|sc| PaletteEntry::Color(sc.into())).collect();
+                                    palettes.insert(name.to_string(), entries);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        palettes
+    }
+
+    /// Parses `#RRGGBB` (the `#` is optional) or the `#RGB` shorthand.
+    pub fn parse_hex_color(hex_str: &str) -> Option<Color> {
+        let (hex, prefixed) = match hex_str.strip_prefix('#') {
+            Some(hex) => (hex, true),
+            None => (hex_str, false),
+        };
+        if !hex.is_ascii() { return None; }
+        match hex.len() {
+            6 => {
+                let r = u8::from_str_radix(&hex[0..2], 16).ok()?;
+                let g = u8::from_str_radix(&hex[2..4], 16).ok()?;
+                let b = u8::from_str_radix(&hex[4..6], 16).ok()?;
+                Some(Color::Rgb(r, g, b))
+            }
+            // Without the '#', three letters are more likely a word than a color.
+            3 if prefixed => {
+                let digit = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).ok().map(|d| d * 17);
+                Some(Color::Rgb(digit(0)?, digit(1)?, digit(2)?))
+            }
+            _ => None,
+        }
+    }
+
+    /// Parses a color written as `#RRGGBB`, `#RGB`, `rgb(r,g,b)` or a name such as
+    /// `orange` or `lightblue`. The error says what was wrong with it.
+    pub fn parse_color(text: &str) -> std::result::Result<Color, String> {
+        let text = text.trim();
+        const RGB_USAGE: &str = "Usage: rgb(<0-255>,<0-255>,<0-255>)";
+        if text.get(..4).is_some_and(|start| start.eq_ignore_ascii_case("rgb(")) {
+            let args = text[4..].strip_suffix(')').ok_or_else(|| format!("Missing ')' in {}. {}", text, RGB_USAGE))?;
+            let components: Vec<&str> = args.split(',').map(str::trim).collect();
+            let [r, g, b] = components.as_slice() else {
+                return Err(format!("rgb() takes 3 components, got {}. {}", components.len(), RGB_USAGE));
+            };
+            let component = |part: &str| -> std::result::Result<u8, String> {
+                let value = part.parse::<i64>().map_err(|_| format!("'{}' is not a number. {}", part, RGB_USAGE))?;
+                u8::try_from(value).map_err(|_| format!("Color component {} is out of range (0-255).", value))
+            };
+            return Ok(Color::Rgb(component(r)?, component(g)?, component(b)?));
+        }
+        if text.starts_with('#') {
+            return Self::parse_hex_color(text).ok_or_else(|| format!("Invalid hex color: {}. Use #RRGGBB or #RGB.", text));
+        }
+        Self::parse_hex_color(text)
+            .or_else(|| palette::named_color(text))
+            .ok_or_else(|| format!("Unknown color: {}. Use #RRGGBB, #RGB, rgb(r,g,b) or a name such as orange.", text))
+    }
+
+    pub fn load_and_store_palette(&mut self, path_str: &str) -> bool {
+        let source_path = PathBuf::from(shellexpand::tilde(&path_str.replace("\"", "")).into_owned());
+
+        if !source_path.exists() {
+            self.set_status(format!("Source file not found: {:?}", source_path));
+            return false;
+        }
+
+        let palettes_dir = match utils::get_or_create_app_dir() {
+            Ok(dir) => dir.join("palettes"),
+            Err(_) => { self.set_status("Could not access app data directory."); return false; }
+        };
+
+        let palette_name = source_path.file_stem().and_then(|s| s.to_str()).unwrap_or("").to_string();
+        if palette_name.is_empty() {
+            self.set_status("Invalid palette file name.");
+            return false;
+        }
+
+        let text = match std::fs::read_to_string(&source_path) {
+            Ok(data) => data,
+            Err(e) => { self.set_status(format!("Error reading palette file: {}", e)); return false; }
+        };
+
+        let colors = match PaletteFormat::from_path(&source_path) {
+            PaletteFormat::Gpl => palette::parse_gpl(&text),
+            PaletteFormat::Hex => palette::parse_hex_palette(&text),
+            PaletteFormat::Consolet => serde_json::from_str::<PaletteFile>(&text).map(|pf| pf.0.into_iter().map(Color::from).collect()).map_err(|e| e.to_string()),
+        };
+        let colors: Vec<Color> = match colors {
+            Ok(colors) if colors.is_empty() => { self.set_status("The palette file has no colors."); return false; }
+            Ok(colors) => colors,
+            Err(e) => { self.set_status(format!("Error parsing palette: {}", e)); return false; }
+        };
+
+        // Every format is kept as .consolet, so it loads with the other palettes next time.
+        let dest_path = palettes_dir.join(format!("{}.consolet", palette_name));
+        let palette_file = PaletteFile(colors.iter().map(|&c| c.into()).collect());
+        if let Err(e) = serde_json::to_string_pretty(&palette_file).map_err(std::io::Error::from).and_then(|json| std::fs::write(&dest_path, json)) {
+            self.set_status(format!("Failed to save palette to app data: {}", e));
+            return false;
+        }
+
+        let entries = colors.into_iter().map(PaletteEntry::Color).collect();
+        self.loaded_palettes.insert(palette_name.clone(), entries);
+        self.set_status(format!("Palette '{}' imported and saved.", palette_name));
+        true
+    }
+
+
+    pub fn pick_color_at_cursor(&mut self) {
+        let (x, y) = (self.cursor_pos.0 as usize, self.cursor_pos.1 as usize);
+        if x >= self.canvas_width || y >= self.canvas_height { return; }
+
+        let pixel = match self.pick_from {
+            PickSource::ActiveLayer => self.layers[self.active_layer_index].canvas[y][x],
+            PickSource::Composite => self.canvas[y][x],
+        };
+        if pixel.alpha == 0.0 {
+            let message = if self.pick_from == PickSource::ActiveLayer && self.canvas[y][x].alpha > 0.0 {
+                "The active layer is transparent here; the color is on another layer (see pick_from)."
+            } else {
+                "Cannot pick color from a transparent pixel."
+            };
+            self.set_status(message.to_string());
+            return;
+        }
+
+        let picked_color: Color = pixel.color.into();
+        let picked_entry = PaletteEntry::Color(picked_color);
+
+        if let Some(index) = self.color_palette.iter().position(|&entry| entry == picked_entry) {
+            self.palette_index = index;
+        } else {
+            self.color_palette.push(picked_entry);
+            self.dirty = true;
+            self.palette_index = self.color_palette.len() - 1;
+        }
+        
+        self.current_selection = picked_entry;
+        let (r,g,b) = utils::to_rgb(picked_color);
+        if self.pick_alpha {
+            self.opacity = pixel.alpha;
+            self.set_status(format!("Color picked: ({}, {}, {}), opacity {:.2}", r, g, b, pixel.alpha));
+        } else {
+            self.set_status(format!("Color picked: ({}, {}, {})", r, g, b));
+        }
+    }
+
+
+    /// Suggestions for the command prompt, best match first.
+    pub fn get_suggestions(&self, input: &str) -> Vec<String> {
+        self.suggest(input).1.into_iter().map(|(text, _)| text).collect()
+    }
+
+    /// Like `get_suggestions`, with the char positions in each suggestion that match
+    /// what was typed, for highlighting.
+    pub fn get_suggestions_with_matches(&self, input: &str) -> Vec<(String, Vec<usize>)> {
+        self.suggest(input).1
+    }
+
+    /// What Tab turns `input` into with `suggestion` picked: the suggestion replaces the
+    /// word it completes, such as the value after `=` or the path after `-o`.
+    pub fn complete_with(&self, input: &str, suggestion: &str) -> String {
+        format!("{}{}", &input[..self.suggest(input).0], suggestion)
+    }
+
+    // Where the word being completed starts in `input`, and the suggestions for it.
+    fn suggest(&self, input: &str) -> (usize, Vec<(String, Vec<usize>)>) {
+        if input.is_empty() {
+            return (0, Vec::new());
+        }
+
+        let (start, mut suggestions) = if input == "recent" || input.starts_with("recent ") {
+            // Offered as full `load` commands, so Tab turns the input into one.
+            let filter = input["recent".len()..].trim().to_lowercase();
+            let suggestions = crate::project::read_recent_projects().into_iter()
+                .map(|path| path.to_string_lossy().into_owned())
+                .filter_map(|path| {
+                    let at = path.to_lowercase().find(&filter)?;
+                    let command = if path.contains(char::is_whitespace) { format!("load \"{}\"", path) } else { format!("load {}", path) };
+                    let offset = command.len() - path.len() - usize::from(command.ends_with('"'));
+                    let first = command[..offset + at].chars().count();
+                    Some((command, (first..first + filter.chars().count()).collect()))
+                })
+                .collect();
+            (0, suggestions)
+        } else if let Some(prefix) = input.strip_prefix("load ") {
+            let projects: Vec<String> = utils::get_or_create_app_dir().ok()
+                .and_then(|app_dir| std::fs::read_dir(app_dir.join("saved_projects")).ok())
+                .map(|entries| entries.filter_map(Result::ok).map(|entry| entry.file_name().to_string_lossy().into_owned()).filter(|name| !name.starts_with('.')).collect())
+                .unwrap_or_default();
+            ("load ".len(), rank_suggestions(prefix, projects))
+        } else if let Some(prefix) = input.strip_prefix("colorpalette:") {
+            let mut names: Vec<String> = self.loaded_palettes.keys().cloned().collect();
+            names.sort();
+            ("colorpalette:".len(), rank_suggestions(prefix, names))
+        } else if let Some((start, partial)) = path_argument(input) {
+            (start, path_suggestions(partial))
+        } else if let Some((name, value)) = input.split_once('=').filter(|(name, _)| !name.contains(char::is_whitespace)) {
+            (name.len() + 1, rank_suggestions(value, setter_values(name)))
+        } else {
+            (0, rank_suggestions(input, COMMANDS.iter().map(|cmd| cmd.name.to_string())))
+        };
+        suggestions.truncate(MAX_SUGGESTIONS);
+        (start, suggestions)
+    }
+
+
+
+
+    pub fn new() -> Self {
+    let (width, height) = (30, 30);
+    let loaded_palettes = App::load_palettes_from_disk();
+    let default_palette = loaded_palettes.get("default").unwrap().clone();
+
+        App {
+            canvas: Canvas::new(width, height),
+            layers: [Layer {
+                name: "Layer 1".to_string(),
+                canvas: Canvas::new(width, height),
+                visible: true,
+                opacity: 1.0,
+                locked: false,
+                blend_mode: BlendMode::Normal,
+                alpha_locked: false,
+            }].into(),
+            active_layer_index: 0,
+            canvas_width: width, canvas_height: height,
+            cursor_pos: (0, 0),
+            current_selection: PaletteEntry::Color(Color::White),
+            tool_palette: get_default_tool_palette(),
+            color_palette: default_palette,
+            loaded_palettes,
+            palette_index: 0,
+            tool_index: 0, 
+            palette_scroll_state: 0,
+            mode: AppMode::Drawing,
+            symmetry_mode: SymmetryMode::Off,
+            should_quit: false,
+            status_message: None,
+            message_log: VecDeque::new(),
+            input_buffer: String::new(),
+            temp_width: 0,
+            last_pixel_area: None,
+            needs_redraw: true,
+            canvas_generation: 0,
+            minimap_cache: None,
+            last_palette_area: None,
+            last_tool_area: None,
+            recent_colors: Vec::new(),
+            recent_color_cursor: 0,
+            last_recent_colors_area: None,
+            is_side_panel_visible: true,
+            pen_size: 1,
+            opacity: 1.0,
+            pen_size_sensitivity: 1,
+            opacity_sensitivity: 0.05,
+            pen_shape: PenShape::Circular,
+            view_offset_x: 0,
+            view_offset_y: 0,
+            zoom_level: PIXEL_WIDTH,
+            suggestion_index: 0,
+            undo_stack: VecDeque::new(),
+            redo_stack: VecDeque::new(),
+            undo_history_selection: 0,
+            is_mouse_dragging: false,
+            pan_anchor: None,
+            shade_factor: DEFAULT_SHADE_FACTOR,
+            highlighter_enabled: true,
+            highlighter_value: 0.5,
+            highlighter_mode: HighlighterMode::Blend,
+            protect_stroke: true,
+            is_space_held: false,
+            is_spraying: false,
+            last_apply_time: None,
+            playing: None,
+            playback_frame: 0,
+            last_playback_time: None,
+            apply_color_interval: chrono::Duration::milliseconds(200),
+            drawn_pixels_in_stroke: std::collections::HashSet::new(),
+            minimap_mode: MinimapMode::Auto,
+            mouse_events_enabled: true,
+            hover_cursor: true,
+            color_mode: ColorMode::TrueColor,
+            default_palette_name: "default".to_string(),
+            command_history: Vec::new(),
+            history_index: 0,
+            command_input_before_history: String::new(),
+            command_cursor_pos: 0,
+            suggestion_active: false,        
+            project_path: None,
+            autosave_interval: None,
+            last_autosave_time: Instant::now(),
+            autosave_rotations: 3,
+            plain_project_save: false,
+            embed_palettes: false,
+            embed_palettes_once: false,
+            undo_limit: 100,
+            undo_memory_mb: 512,
+            undo_history_trimmed: false,
+            next_autosave_slot: None,
+            pending_autosave: None,
+            dirty: false,
+            pending_save_path: None,
+            help_scroll: 0,
+
+            keybindings: Keybindings::load(),
+            keybindings_selection_index: 0,
+            is_changing_keybinding: false,
+            keybinding_change_has_occured: false,
+            confirm_selection_yes: true,
+            quit_choice: QuitChoice::No,
+            keybindings_scroll_state: 0,
+            selection_before_picker: None,
+            config_selection_index: 0,
+            config_change_has_occured: false,
+
+            script_content_lines: Vec::new(),
+            script_cursor_line: 0,
+            script_scroll_state: 0,
+
+            script_cursor_char_pos: 0,
+            script_change_has_occured: false,
+            canvas_scroll_action: CanvasScrollAction::ChangePenSize,
+            spray_size: 5,
+            spray_speed: 3,
+            spray_intensity: 0.1,
+            spray_falloff: false,
+            snap_to_palette: false,
+            snap_to_palette_mode: SnapToPaletteMode::ClosestHue,
+            protect_color_transitions: false,
+            browser_mode: None,
+            browser_entries: Vec::new(),
+            browser_pinned: 0,
+            browser_list_state: ListState::default(),
+            browser_current_dir: PathBuf::new(),
+            browser_history_back: Vec::new(),
+            browser_history_forward: Vec::new(),
+            browser_error: None,
+            browser_input_buffer: String::new(),
+            browser_scale_buffer: "1".to_string(), // Default scale is 1
+            browser_background_buffer: String::new(),
+            browser_focus: BrowserFocus::List,
+
+            last_generated_palette: None,
+            last_image_palette_source: None,
+            palette_menu_position: PaletteMenuPosition::Left,
+            last_centered_canvas_rect: None,
+            onion_skin_enabled: false,
+            onion_skin_opacity: 0.3,
+            onion_skin_frames_before: 1,
+            onion_skin_frames_after: 0,
+            playback_fps: 8,
+            palette_colors: 16,
+            tile_preview: false,
+            reference: None,
+            reference_opacity: 0.3,
+            reference_visible: true,
+            layer_scroll_state: 0,
+            last_layer_area: None,
+            last_minimap_area: None,
+            minimap_dragging: false,
+            layer_input_buffer: String::new(),
+            layer_focus: LayerFocus::List,
+            is_renaming_layer: false,
+            layer_name_cursor_pos: 0,
+            last_layer_click: None,
+            layer_drag: None,
+            export_layer_mode: ExportLayerMode::United,
+            antialias: false,
+            last_export: None,
+            flat_mode: false,
+            linear_blending: false,
+            stabilizer: 0,
+            stabilizer_queue: VecDeque::new(),
+            last_drag_point: None,
+            brush_mask: None,
+            brush_path: None,
+            shape_tool: None,
+            fill_shapes: false,
+            perfect_circles: false,
+            shape_constrained: false,
+            dither_enabled: false,
+            replace_mode: false,
+            dither_pattern: DitherPattern::Checker,
+            clone_source: None,
+            clone_offset: None,
+            clone_sample_merged: false,
+            fill_mode: FillMode::Contiguous,
+            fill_tolerance: 0,
+            color_jitter: 0.0,
+            soft_eraser: false,
+            blur_radius: 1,
+            pick_from: PickSource::ActiveLayer,
+            color_info: ColorInfo::Off,
+            pick_alpha: false,
+            selection: std::collections::HashSet::new(),
+            selection_mask: false,
+            radial_segments: 6,
+            transparency_checker: false,
+            checker_size: 1,
+            checker_light: Color::Rgb(153, 153, 153),
+            checker_dark: Color::Rgb(102, 102, 102),
+            pixel_grid: false,
+            pixel_grid_min_zoom: 4,
+            max_zoom: 16,
+            major_grid_spacing: 8,
+            restore_session: true,
+            active_palette_name: "default".to_string(),
+            jitter_seed: rand::random(),
+            history_position: 0,
+            shape_anchor: None,
+            command_palette_query: String::new(),
+            command_palette_index: 0,
+            color_editor_hsv: (0.0, 0.0, 1.0),
+            color_editor_rgb: (255, 255, 255),
+            color_editor_row: 0,
+            color_editor_add_to_palette: true,
+            color_editor_target: None,
+            color_editor_remap: false,
+            last_color_editor_area: None,
+            tile_size: None,
+            tile_snap: false,
+            tile_clipboard: None,
+            pending_palette_switch: None,
+            pixel_block: 1,
+            info_popup: None,
+            info_popup_scroll: 0,
+    }
+}
+
+
+
+    pub fn get_active_canvas(&self) -> &Canvas {
+        &self.layers[self.active_layer_index].canvas
+    }
+
+    /// Indices of the layers that are not locked, for edits that span every layer.
+    fn unlocked_layers(&self) -> Vec<usize> {
+        (0..self.layers.len()).filter(|&i| !self.layers[i].locked).collect()
+    }
+
+    // Layers whose pixels may be moved or erased: neither locked nor alpha-locked.
+    fn erasable_layers(&self) -> Vec<usize> {
+        (0..self.layers.len()).filter(|&i| !self.layers[i].locked && !self.layers[i].alpha_locked).collect()
+    }
+
+    pub fn get_active_canvas_mut(&mut self) -> &mut Canvas {
+        &mut self.layers[self.active_layer_index].canvas
+    }
+
+    pub fn add_new_layer(&mut self) {
+        let new_layer = Layer {
+            name: format!("Layer {}", self.layers.len() + 1),
+            canvas: Canvas::new(self.canvas_width, self.canvas_height),
+            visible: true,
+            opacity: 1.0,
+            locked: false,
+            blend_mode: BlendMode::Normal,
+            alpha_locked: false,
+        };
+        self.save_layer_stack_for_undo("Add layer");
+        self.layers.insert(self.active_layer_index, new_layer);
+        self.sync_canvas_from_layers();
+        self.set_status(format!("Added {}", self.layers[self.active_layer_index].name));
+    }
+
+    pub fn delete_active_layer(&mut self) {
+        if self.layers.len() <= 1 {
+            self.set_status("Cannot delete the only layer.");
+            return;
+        }
+        self.save_layer_stack_for_undo("Delete layer");
+        self.layers.remove(self.active_layer_index);
+        if self.active_layer_index >= self.layers.len() {
+            self.active_layer_index = self.layers.len() - 1;
+        }
+        self.sync_canvas_from_layers();
+        self.set_status("Layer deleted.");
+    }
+
+    pub fn toggle_layer_visibility(&mut self) {
+        self.set_layer_visible(self.active_layer_index, !self.layers[self.active_layer_index].visible);
+    }
+
+    fn set_layer_visible(&mut self, index: usize, visible: bool) {
+        self.layers[index].visible = visible;
+        self.dirty = true;
+        self.sync_canvas_from_layers();
+    }
+
+    pub(crate) fn set_layer_opacity(&mut self, index: usize, opacity: f32) {
+        let opacity = opacity.clamp(0.0, 1.0);
+        if self.layers[index].opacity != opacity {
+            self.push_undo_entry(UndoEntry::LayerOpacity { index, opacity: self.layers[index].opacity }, "Layer opacity");
+            self.layers[index].opacity = opacity;
+        }
+        self.sync_canvas_from_layers();
+    }
+
+    /// Whether the active layer is locked, saying so in the status bar. Every edit to
+    /// the active layer checks this first and does nothing when it is.
+    pub fn active_layer_locked(&mut self) -> bool {
+        let locked = self.layers.get(self.active_layer_index).is_some_and(|layer| layer.locked);
+        if locked {
+            self.set_status("Layer is locked.");
+        }
+        locked
+    }
+
+    pub fn toggle_layer_lock(&mut self) {
+        let index = self.active_layer_index;
+        self.set_layer_locked(index, !self.layers[index].locked);
+        let layer = &self.layers[index];
+        self.set_status(format!("'{}' is now {}.", layer.name, if layer.locked { "locked" } else { "unlocked" }));
+    }
+
+    fn set_layer_locked(&mut self, index: usize, locked: bool) {
+        self.layers[index].locked = locked;
+        self.dirty = true;
+    }
+
+    /// Erasing would lower alpha, so it is refused on an alpha-locked layer.
+    pub fn active_layer_alpha_locked(&mut self) -> bool {
+        let alpha_locked = self.layers.get(self.active_layer_index).is_some_and(|layer| layer.alpha_locked);
+        if alpha_locked {
+            self.set_status("Layer is alpha-locked.");
+        }
+        alpha_locked
+    }
+
+    pub fn toggle_alpha_lock(&mut self) {
+        let index = self.active_layer_index;
+        self.set_layer_alpha_locked(index, !self.layers[index].alpha_locked);
+        let layer = &self.layers[index];
+        self.set_status(format!("'{}' alpha lock {}.", layer.name, if layer.alpha_locked { "on" } else { "off" }));
+    }
+
+    fn set_layer_alpha_locked(&mut self, index: usize, alpha_locked: bool) {
+        self.layers[index].alpha_locked = alpha_locked;
+        self.dirty = true;
+    }
+
+    pub(crate) fn set_layer_blend_mode(&mut self, index: usize, blend_mode: BlendMode) {
+        if self.layers[index].blend_mode != blend_mode {
+            self.push_undo_entry(UndoEntry::LayerBlendMode { index, blend_mode: self.layers[index].blend_mode }, "Layer blend mode");
+            self.layers[index].blend_mode = blend_mode;
+        }
+        self.sync_canvas_from_layers();
+    }
+
+    pub fn cycle_layer_blend_mode(&mut self) {
+        let index = self.active_layer_index;
+        self.set_layer_blend_mode(index, self.layers[index].blend_mode.next());
+        self.set_status(format!("'{}' blend mode: {}.", self.layers[index].name, self.layers[index].blend_mode.name()));
+    }
+
+    /// Opens the Layers panel input on the active layer's row, holding its current name.
+    pub fn start_layer_rename(&mut self) {
+        let Some(layer) = self.layers.get(self.active_layer_index) else { return };
+        self.layer_input_buffer = layer.name.clone();
+        self.layer_name_cursor_pos = self.layer_input_buffer.len();
+        self.layer_focus = LayerFocus::NameInput;
+        self.is_renaming_layer = true;
+    }
+
+    /// Renames the active layer to the trimmed input. An empty name is refused and the
+    /// input stays open.
+    pub fn commit_layer_rename(&mut self) {
+        let name = self.layer_input_buffer.trim().to_string();
+        if name.is_empty() {
+            self.set_status("Layer name cannot be empty.");
+            return;
+        }
+        let index = self.active_layer_index;
+        if self.layers[index].name != name {
+            self.layers[index].name = name.clone();
+            self.dirty = true;
+        }
+        self.cancel_layer_rename();
+        self.set_status(format!("Renamed layer to '{}'.", name));
+    }
+
+    pub fn cancel_layer_rename(&mut self) {
+        self.is_renaming_layer = false;
+        self.layer_focus = LayerFocus::List;
+        self.layer_input_buffer.clear();
+        self.layer_name_cursor_pos = 0;
+    }
+
+    pub fn set_active_layer(&mut self, index: usize) {
+        self.active_layer_index = index;
+        self.sync_canvas_from_layers();
+    }
+
+    /// Resolves a layer by its 1-based position in the layer panel (top first) or by
+    /// a case-insensitive name prefix. An exact name match wins over prefixes, and two
+    /// layers with that exact name make the query ambiguous.
+    fn find_layer(&self, query: &str) -> std::result::Result<usize, String> {
+        if let Ok(position) = query.parse::<usize>() {
+            return match position {
+                1.. if position <= self.layers.len() => Ok(position - 1),
+                _ => Err(format!("No layer at position {} (1-{}).", position, self.layers.len())),
+            };
+        }
+        let query_lower = query.to_lowercase();
+        let exact: Vec<usize> = (0..self.layers.len()).filter(|&i| self.layers[i].name.to_lowercase() == query_lower).collect();
+        let matches = if exact.is_empty() {
+            (0..self.layers.len()).filter(|&i| self.layers[i].name.to_lowercase().starts_with(&query_lower)).collect()
+        } else {
+            exact
+        };
+        match matches.as_slice() {
+            [index] => Ok(*index),
+            [] => Err(format!("No layer matches '{}'.", query)),
+            _ => {
+                let names: Vec<&str> = matches.iter().map(|&i| self.layers[i].name.as_str()).collect();
+                Err(format!("'{}' is ambiguous: {}.", query, names.join(", ")))
+            }
+        }
+    }
+
+    /// Applies `visible=`, `opacity=`, `blend=`, `locked=` or `active` to the layer matching `target`.
+    /// Shared by the `layer` command and the script `layer:` verbs.
+    pub fn apply_layer_verb(&mut self, target: &str, verb: &str) -> std::result::Result<String, String> {
+        let index = self.find_layer(target)?;
+        let name = self.layers[index].name.clone();
+        match verb.split_once('=') {
+            Some(("visible", value)) => {
+                let visible = value.parse::<bool>().map_err(|_| format!("Invalid value for visible: {}", value))?;
+                self.set_layer_visible(index, visible);
+                Ok(format!("'{}' is now {}.", name, if visible { "visible" } else { "hidden" }))
+            }
+            Some(("opacity", value)) => {
+                let opacity = value.parse::<f32>().map_err(|_| format!("Invalid value for opacity: {}", value))?;
+                self.set_layer_opacity(index, opacity);
+                Ok(format!("'{}' opacity set to {:.2}.", name, self.layers[index].opacity))
+            }
+            Some(("locked", value)) => {
+                let locked = value.parse::<bool>().map_err(|_| format!("Invalid value for locked: {}", value))?;
+                self.set_layer_locked(index, locked);
+                Ok(format!("'{}' is now {}.", name, if locked { "locked" } else { "unlocked" }))
+            }
+            Some(("alpha_locked", value)) => {
+                let alpha_locked = value.parse::<bool>().map_err(|_| format!("Invalid value for alpha_locked: {}", value))?;
+                self.set_layer_alpha_locked(index, alpha_locked);
+                Ok(format!("'{}' alpha lock {}.", name, if alpha_locked { "on" } else { "off" }))
+            }
+            Some(("blend", value)) => {
+                let blend_mode = BlendMode::from_name(value).ok_or_else(|| format!("Unknown blend mode: {} (normal, multiply, screen, overlay, add, subtract)", value))?;
+                self.set_layer_blend_mode(index, blend_mode);
+                Ok(format!("'{}' blend mode: {}.", name, blend_mode.name()))
+            }
+            None if verb == "active" => {
+                self.set_active_layer(index);
+                Ok(format!("Active layer: '{}'.", name))
+            }
+            _ => Err(format!("Unknown layer property: {}", verb)),
+        }
+    }
+
+    /// Shows `message` in the status bar and adds it to the message log.
+    pub fn set_status(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        if self.message_log.len() == MESSAGE_LOG_LEN {
+            self.message_log.pop_front();
+        }
+        self.message_log.push_back((chrono::Local::now().format("%H:%M:%S").to_string(), message.clone()));
+        self.status_message = Some((message, Instant::now()));
+    }
+
+    /// Lists the logged status messages, newest first, in a scrollable popup.
+    pub fn show_message_log(&mut self) {
+        let lines = if self.message_log.is_empty() {
+            vec!["No messages yet.".to_string()]
+        } else {
+            self.message_log.iter().rev().map(|(time, message)| format!("{}  {}", time, message)).collect()
+        };
+        self.info_popup = Some((" Messages ".to_string(), lines));
+        self.info_popup_scroll = 0;
+        self.mode = AppMode::InfoPopup;
+    }
+
+    /// Shows every palette `colorpalette:` can switch to, with its color count.
+    pub fn show_palette_list(&mut self) {
+        let mut names: Vec<&String> = self.loaded_palettes.keys().collect();
+        names.sort();
+        let mut lines = vec![format!("   {:<20} {:>6}", "Name", "Colors")];
+        for name in names {
+            let colors = self.loaded_palettes[name].iter().filter(|e| matches!(e, PaletteEntry::Color(_))).count();
+            let marker = if *name == self.active_palette_name { ">" } else { " " };
+            lines.push(format!("{}  {:<20} {:>6}", marker, name, colors));
+        }
+        self.info_popup = Some((" Palettes ".to_string(), lines));
+        self.info_popup_scroll = 0;
+        self.mode = AppMode::InfoPopup;
+    }
+
+    pub fn show_layer_list(&mut self) {
+        let mut lines = vec![format!("{:>3}  {:<20} {:<8} {:>7}  {:<7} {:>7}", "#", "Name", "Visible", "Opacity", "Blend", "Pixels")];
+        for (i, layer) in self.layers.iter().enumerate() {
+            let pixel_count = layer.canvas.pixels().iter().filter(|p| p.alpha > 0.0).count();
+            let marker = if i == self.active_layer_index { ">" } else { " " };
+            lines.push(format!("{}{:>2}  {:<20} {:<8} {:>6.0}%  {:<7} {:>7}",
+                marker, i + 1, layer.name, if layer.visible { "yes" } else { "no" }, layer.opacity * 100.0, layer.blend_mode.name(), pixel_count));
+        }
+        self.info_popup = Some((" Layers ".to_string(), lines));
+        self.info_popup_scroll = 0;
+        self.mode = AppMode::InfoPopup;
+    }
+
+    /// Shows the canvas size, each layer's painted pixel count, the distinct colors on
+    /// visible layers (and which are missing from the palette) and the saved file size.
+    pub fn show_canvas_stats(&mut self) {
+        let mut lines = vec![
+            format!("Canvas     {}x{} ({} pixels)", self.canvas_width, self.canvas_height, self.canvas_width * self.canvas_height),
+            format!("Layers     {}", self.layers.len()),
+        ];
+        for (i, layer) in self.layers.iter().enumerate() {
+            let pixel_count = layer.canvas.pixels().iter().filter(|p| p.alpha > 0.0).count();
+            let marker = if i == self.active_layer_index { ">" } else { " " };
+            lines.push(format!("  {}{:>2}  {:<20} {:>7} px{}", marker, i + 1, layer.name, pixel_count, if layer.visible { "" } else { " (hidden)" }));
+        }
+
+        // Alpha is bucketed to sixteenths so that soft brush edges don't each count as a color.
+        let mut colors = HashSet::new();
+        let mut rgb_used = HashSet::new();
+        for layer in self.layers.iter().filter(|layer| layer.visible) {
+            for pixel in layer.canvas.pixels().iter().filter(|p| p.alpha > 0.0) {
+                let SerializableColor(r, g, b) = pixel.color;
+                colors.insert((r, g, b, (pixel.alpha * 16.0).round() as u8));
+                rgb_used.insert((r, g, b));
+            }
+        }
+        lines.push(format!("Colors     {} distinct on visible layers ({} without alpha)", colors.len(), rgb_used.len()));
+
+        let palette: HashSet<(u8, u8, u8)> = self.color_palette.iter()
+            .filter_map(|entry| if let PaletteEntry::Color(c) = entry { Some(utils::to_rgb(*c)) } else { None })
+            .collect();
+        let mut missing: Vec<String> = rgb_used.difference(&palette).map(|(r, g, b)| format!("#{:02X}{:02X}{:02X}", r, g, b)).collect();
+        missing.sort();
+        if missing.is_empty() {
+            lines.push("Palette    every color is in the palette".to_string());
+        } else {
+            lines.push(format!("Palette    {} color{} not in the palette:", missing.len(), if missing.len() == 1 { "" } else { "s" }));
+            lines.extend(missing.chunks(6).map(|row| format!("  {}", row.join(" "))));
+        }
+
+        lines.push(match self.estimated_project_size() {
+            Ok(bytes) => format!("File size  about {:.1} KB when saved", bytes as f64 / 1024.0),
+            Err(e) => format!("File size  unknown ({})", e),
+        });
+        self.info_popup = Some((" Canvas Stats ".to_string(), lines));
+        self.info_popup_scroll = 0;
+        self.mode = AppMode::InfoPopup;
+    }
+
+    pub fn move_layer_up(&mut self) {
+        if self.active_layer_index > 0 {
+            self.save_layer_stack_for_undo("Move layer up");
+            self.layers.swap(self.active_layer_index, self.active_layer_index - 1);
+            self.active_layer_index -= 1;
+            self.sync_canvas_from_layers();
+        }
+    }
+
+    pub fn move_layer_down(&mut self) {
+        if self.active_layer_index < self.layers.len() - 1 {
+            self.save_layer_stack_for_undo("Move layer down");
+            self.layers.swap(self.active_layer_index, self.active_layer_index + 1);
+            self.active_layer_index += 1;
+            self.sync_canvas_from_layers();
+        }
+    }
+
+    /// Moves a layer to another place in the stack, shifting the layers in between.
+    /// The active layer stays active wherever it ends up.
+    pub fn move_layer_to(&mut self, from: usize, to: usize) {
+        if from == to || from >= self.layers.len() || to >= self.layers.len() { return; }
+        self.save_layer_stack_for_undo("Move layer");
+        let layer = self.layers.remove(from).unwrap();
+        self.layers.insert(to, layer);
+        let active = self.active_layer_index;
+        self.active_layer_index = if active == from {
+            to
+        } else if from < active && active <= to {
+            active - 1
+        } else if to <= active && active < from {
+            active + 1
+        } else {
+            active
+        };
+        self.sync_canvas_from_layers();
+    }
+
+    /// Steps a layer's opacity by delta, landing on whole 5% steps.
+    pub fn nudge_layer_opacity(&mut self, index: usize, delta: f32) {
+        let Some(layer) = self.layers.get(index) else { return };
+        let opacity = ((layer.opacity + delta) * 20.0).round() / 20.0;
+        self.set_layer_opacity(index, opacity);
+        let layer = &self.layers[index];
+        self.set_status(format!("'{}' opacity set to {:.0}%.", layer.name, layer.opacity * 100.0));
+    }
+
+    pub fn sync_canvas_from_layers(&mut self) {
+        let all: Vec<usize> = (0..self.layers.len()).collect();
+        self.canvas = self.composite_layers(&all);
+        self.canvas_generation = self.canvas_generation.wrapping_add(1);
+    }
+
+    /// Recomposites the one canvas cell a per-pixel edit touched, instead of the whole canvas.
+    pub fn sync_canvas_pixel(&mut self, x: usize, y: usize) {
+        let pixel = self.composite_pixel(x, y);
+        if let Some(cell) = self.canvas.get_mut(x, y) {
+            *cell = pixel;
+            self.canvas_generation = self.canvas_generation.wrapping_add(1);
+        }
+    }
+
+    /// Blends (x, y) across the visible layers, the same as `composite_layers` does for
+    /// every pixel.
+    pub fn composite_pixel(&self, x: usize, y: usize) -> Pixel {
+        let mut result = Pixel::default();
+        for layer in self.layers.iter().rev().filter(|layer| layer.visible) {
+            let Some(src) = layer.canvas.get(x, y) else { continue };
+            if src.alpha == 0.0 {
+                continue;
+            }
+            result = utils::composite_blended(result, src.color, src.alpha * layer.opacity, layer.blend_mode, self.linear_blending);
+        }
+        result
+    }
+
+    /// The topmost visible layer with paint at (x, y), i.e. the one whose color is seen
+    /// first there. None over transparent pixels.
+    pub fn topmost_layer_at(&self, x: usize, y: usize) -> Option<usize> {
+        self.layers.iter().position(|layer| {
+            layer.visible && layer.opacity > 0.0 && layer.canvas.get(x, y).is_some_and(|pixel| pixel.alpha > 0.0)
+        })
+    }
+
+    /// The color readout: the drawing color and, over a painted pixel, its visible
+    /// color, alpha and the layer it comes from.
+    pub fn color_info_text(&self) -> String {
+        let hex = |color: Color| {
+            let (r, g, b) = utils::to_rgb(color);
+            format!("#{:02X}{:02X}{:02X}", r, g, b)
+        };
+        let mut text = match self.current_selection {
+            PaletteEntry::Color(color) => format!("Color {}", hex(color)),
+            PaletteEntry::Tool(tool) => format!("Tool {:?}", tool),
+        };
+        let (x, y) = (self.cursor_pos.0 as usize, self.cursor_pos.1 as usize);
+        if let (Some(pixel), Some(layer)) = (self.canvas.get(x, y).filter(|pixel| pixel.alpha > 0.0), self.topmost_layer_at(x, y)) {
+            text.push_str(&format!(" | Pixel {} {:.0}% on {}", hex(pixel.color.into()), pixel.alpha * 100.0, self.layers[layer].name));
+        }
+        text
+    }
+
+    /// Composites the visible layers among `indices` (bottom to top) with the same
+    /// math as the on-screen canvas.
+    pub(crate) fn composite_layers(&self, indices: &[usize]) -> Canvas {
+        let mut result = Canvas::new(self.canvas_width, self.canvas_height);
+        for &i in indices.iter().rev() {
+            let layer = &self.layers[i];
+            if !layer.visible {
+                continue;
+            }
+            for (dest, src) in result.pixels_mut().iter_mut().zip(layer.canvas.pixels()) {
+                if src.alpha == 0.0 {
+                    continue;
+                }
+                *dest = utils::composite_blended(*dest, src.color, src.alpha * layer.opacity, layer.blend_mode, self.linear_blending);
+            }
+        }
+        result
+    }
+
+    /// Plays the layers back as animation frames, top of the panel first, showing one
+    /// at a time in the canvas view. Layer visibility is left as it is.
+    pub fn start_playback(&mut self, fps: Option<u16>) {
+        let fps = fps.unwrap_or(self.playback_fps).clamp(1, 30);
+        self.playing = Some(fps);
+        self.playback_frame = 0;
+        self.last_playback_time = Some(chrono::Local::now());
+        self.status_message = None;
+    }
+
+    pub fn stop_playback(&mut self) {
+        self.playing = None;
+        self.last_playback_time = None;
+    }
+
+    /// Moves playback on to the next frame once the frame time has passed. Returns
+    /// whether it did, so the caller knows to redraw.
+    pub fn advance_playback(&mut self) -> bool {
+        let (Some(fps), Some(last_time)) = (self.playing, self.last_playback_time) else { return false };
+        if chrono::Local::now() < last_time + chrono::Duration::milliseconds(1000 / fps as i64) {
+            return false;
+        }
+        self.playback_frame = (self.playback_frame + 1) % self.layers.len().max(1);
+        self.last_playback_time = Some(chrono::Local::now());
+        true
+    }
+
+    /// Merges the active layer into the one below it, which keeps its name.
+    pub fn merge_down(&mut self) {
+        let index = self.active_layer_index;
+        if index + 1 >= self.layers.len() {
+            self.set_status("Cannot merge bottom layer.");
+            return;
+        }
+        if self.merge_layer_pair(index, false, "Merge down") {
+            self.set_status("Layer merged down.");
+        }
+    }
+
+    /// Merges the active layer into the one above it, which keeps its name.
+    pub fn merge_up(&mut self) {
+        let index = self.active_layer_index;
+        if index == 0 {
+            self.set_status("Cannot merge top layer.");
+            return;
+        }
+        if self.merge_layer_pair(index - 1, true, "Merge up") {
+            self.set_status("Layer merged up.");
+        }
+    }
+
+    // Composites the layer at `upper` onto the one below it as one undo step. The result
+    // keeps the lower layer's opacity and blend mode, takes the upper or lower name, and
+    // becomes the active layer.
+    fn merge_layer_pair(&mut self, upper: usize, keep_upper_name: bool, label: &str) -> bool {
+        if let Some(layer) = self.layers.range(upper..=upper + 1).find(|layer| layer.locked) {
+            self.set_status(format!("Layer '{}' is locked.", layer.name));
+            return false;
+        }
+        self.save_layer_stack_for_undo(label);
+        let top = self.layers.remove(upper).unwrap();
+        let bottom = &mut self.layers[upper];
+        for (dest, src) in bottom.canvas.pixels_mut().iter_mut().zip(top.canvas.pixels()) {
+            if src.alpha == 0.0 {
+                continue;
+            }
+            *dest = utils::composite_blended(*dest, src.color, src.alpha * top.opacity, top.blend_mode, self.linear_blending);
+        }
+        if keep_upper_name {
+            bottom.name = top.name;
+        }
+        self.active_layer_index = upper;
+        self.sync_canvas_from_layers();
+        true
+    }
+
+    /// Moves or copies the selected pixels, or every painted pixel when nothing is
+    /// selected, from the active layer onto the layer matching `target`. Both layers
+    /// change in one undo step.
+    pub fn transfer_to_layer(&mut self, target: &str, copy: bool) -> bool {
+        let index = match self.find_layer(target) {
+            Ok(index) => index,
+            Err(message) => {
+                self.set_status(message);
+                return false;
+            }
+        };
+        let source = self.active_layer_index;
+        if index == source {
+            self.set_status("The target is the active layer.");
+            return false;
+        }
+        if !copy && (self.active_layer_locked() || self.active_layer_alpha_locked()) { return false; }
+        let target_layer = &self.layers[index];
+        if target_layer.locked || target_layer.alpha_locked {
+            let lock = if target_layer.locked { "locked" } else { "alpha-locked" };
+            self.set_status(format!("Layer '{}' is {}.", target_layer.name, lock));
+            return false;
+        }
+
+        let points: Vec<(usize, usize)> = if self.selection.is_empty() {
+            (0..self.canvas_height).flat_map(|y| (0..self.canvas_width).map(move |x| (x, y))).collect()
+        } else {
+            self.selection.iter().copied().collect()
+        };
+        let points: Vec<(usize, usize)> = points.into_iter().filter(|&(x, y)| self.layers[source].canvas[y][x].alpha > 0.0).collect();
+        let (label, verb, done) = if copy { ("Copy to layer", "copy", "Copied") } else { ("Move to layer", "move", "Moved") };
+        if points.is_empty() {
+            self.set_status(format!("Nothing to {}.", verb));
+            return true;
+        }
+
+        self.save_layers_for_undo(&[source, index], label);
+        for &(x, y) in &points {
+            let pixel = self.layers[source].canvas[y][x];
+            let dest = &mut self.layers[index].canvas[y][x];
+            *dest = utils::composite_over(*dest, pixel.color, pixel.alpha, self.linear_blending);
+            if !copy {
+                self.layers[source].canvas[y][x] = Pixel::default();
+            }
+        }
+        self.sync_canvas_from_layers();
+        self.set_status(format!("{} {} pixels to '{}'.", done, points.len(), self.layers[index].name));
+        true
+    }
+
+    pub fn merge_visible_layers(&mut self, force: bool) {
+        let visible: Vec<usize> = (0..self.layers.len()).filter(|&i| self.layers[i].visible).collect();
+        if visible.len() < 2 {
+            self.set_status("Need at least two visible layers to merge.");
+            return;
+        }
+        if !force {
+            if let Some(&i) = visible.iter().find(|&&i| self.layers[i].locked) {
+                self.set_status(format!("Layer '{}' is locked. Use merge_visible --force to merge it anyway.", self.layers[i].name));
+                return;
+            }
+        }
+
+        let merged_canvas = self.composite_layers(&visible);
+        let name = if visible.len() == 2 {
+            format!("{} + {}", self.layers[visible[0]].name, self.layers[visible[1]].name)
+        } else {
+            format!("Merged ({} layers)", visible.len())
+        };
+
+        // The merged layer takes the place of the bottom-most visible layer; hidden layers keep their order.
+        let target = *visible.last().unwrap();
+        self.save_layer_stack_for_undo("Merge visible layers");
+        self.layers[target] = Layer { name: name.clone(), canvas: merged_canvas, visible: true, opacity: 1.0, locked: false, blend_mode: BlendMode::Normal, alpha_locked: false };
+        for &i in visible.iter().rev().skip(1) {
+            self.layers.remove(i);
+        }
+        self.active_layer_index = target - (visible.len() - 1);
+        self.sync_canvas_from_layers();
+        self.set_status(format!("Merged {} visible layers into '{}'.", visible.len(), name));
+    }
+
+    pub fn flatten_layers(&mut self, force: bool) {
+        if !force {
+            if let Some(layer) = self.layers.iter().find(|layer| layer.locked) {
+                self.set_status(format!("Layer '{}' is locked. Use flatten --force to flatten anyway.", layer.name));
+                return;
+            }
+        }
+
+        // Hidden layers are not part of the visible result, so they are dropped.
+        let count = self.layers.len();
+        self.save_layer_stack_for_undo("Flatten layers");
+        self.sync_canvas_from_layers();
+        self.layers = [Layer { name: "Flattened".to_string(), canvas: self.canvas.clone(), visible: true, opacity: 1.0, locked: false, blend_mode: BlendMode::Normal, alpha_locked: false }].into();
+        self.active_layer_index = 0;
+        self.set_status(format!("Flattened {} layers.", count));
+    }
+
+    /// Makes the topmost visible layer with paint at (x, y) the active one, like
+    /// auto-select in image editors. Leaves the selection alone over empty pixels.
+    pub fn select_layer_at(&mut self, x: usize, y: usize) {
+        let Some(index) = self.topmost_layer_at(x, y) else {
+            self.set_status(format!("No layer has paint at ({}, {}).", x, y));
+            return;
+        };
+        self.set_active_layer(index);
+        self.reveal_active_layer();
+        self.set_status(format!("Active layer: '{}'.", self.layers[index].name));
+    }
+
+    // Scrolls the layer panel just enough to show the active layer's row.
+    fn reveal_active_layer(&mut self) {
+        let rows = self.last_layer_area.map_or(1, |area| area.height.saturating_sub(2).max(1) as usize);
+        if self.active_layer_index < self.layer_scroll_state {
+            self.layer_scroll_state = self.active_layer_index;
+        } else if self.active_layer_index >= self.layer_scroll_state + rows {
+            self.layer_scroll_state = self.active_layer_index + 1 - rows;
+        }
+    }
+
+    pub fn change_layer_selection(&mut self, delta: i16) {
+        let new_index = (self.active_layer_index as i16 + delta)
+            .max(0)
+            .min(self.layers.len() as i16 - 1) as usize;
+        self.active_layer_index = new_index;
+        self.sync_canvas_from_layers();
+    }
+
+
+
+
+    pub fn reset_keybindings(&mut self) {
+        // 1. Delete the saved keybindings file.
+        if let Ok(path) = keybindings::Keybindings::get_path() {
+            // We ignore the result, it's okay if the file didn't exist.
+            let _ = std::fs::remove_file(path);
+        }
+
+        // 2. Load the default bindings back into the current app state.
+        self.keybindings = Keybindings::default();
+
+        // 3. Inform the user.
+        self.set_status("Keybindings have been reset to default.");
+    }
+
+
+
+
+fn rgb_to_hue(&self, r: u8, g: u8, b: u8) -> f32 {
+    let r_norm = r as f32 / 255.0;
+    let g_norm = g as f32 / 255.0;
+    let b_norm = b as f32 / 255.0;
+    let max = r_norm.max(g_norm).max(b_norm);
+    let min = r_norm.min(g_norm).min(b_norm);
+    let delta = max - min;
+    if delta == 0.0 {
+        return 0.0;
+    }
+    let hue = if max == r_norm {
+        60.0 * (((g_norm - b_norm) / delta) % 6.0)
+    } else if max == g_norm {
+        60.0 * (((b_norm - r_norm) / delta) + 2.0)
+    } else {
+        60.0 * (((r_norm - g_norm) / delta) + 4.0)
+    };
+    if hue < 0.0 { hue + 360.0 } else { hue }
+}
+fn hue_distance(&self, h1: f32, h2: f32) -> f32 {
+    let diff = (h1 - h2).abs();
+    if diff > 180.0 { 360.0 - diff } else { diff }
+}
+
+
+pub(crate) fn find_closest_palette_color(&self, target: Color) -> Color {
+    let (tr, tg, tb) = utils::to_rgb(target);
+    let mut closest = target;
+    let mut min_dist = f32::MAX;
+    for entry in &self.color_palette {
+        if let PaletteEntry::Color(c) = entry {
+            let (r, g, b) = utils::to_rgb(*c);
+            let dr = tr as f32 - r as f32;
+            let dg = tg as f32 - g as f32;
+            let db = tb as f32 - b as f32;
+            let dist = (dr * dr + dg * dg + db * db).sqrt();
+            if dist < min_dist {
+                min_dist = dist;
+                closest = *c;
+            }
+        }
+    }
+    closest
+}
+
+
+
+
+
+
+
+
+fn find_lighter_rgb(&self, current: Color) -> Color {
+    let (cr, cg, cb) = utils::to_rgb(current);
+    let current_brightness = cr as f32 + cg as f32 + cb as f32;
+    if !self.protect_color_transitions {
+        let total = cr.max(1) as f32;
+        let ratio_g = cg as f32 / total;
+        let ratio_b = cb as f32 / total;
+        let mut closest = current;
+        let mut min_score = f32::MAX;
+        for entry in &self.color_palette {
+            if let PaletteEntry::Color(c) = entry {
+                let (r, g, b) = utils::to_rgb(*c);
+                let brightness = r as f32 + g as f32 + b as f32;
+                if brightness <= current_brightness { continue; }
+                let cand_total = r.max(1) as f32;
+                let cand_ratio_g = g as f32 / cand_total;
+                let cand_ratio_b = b as f32 / cand_total;
+                let ratio_diff = (ratio_g - cand_ratio_g).abs() + (ratio_b - cand_ratio_b).abs();
+                let dr = cr as f32 - r as f32;
+                let dg = cg as f32 - g as f32;
+                let db = cb as f32 - b as f32;
+                let rgb_dist = (dr * dr + dg * dg + db * db).sqrt();
+                let score = rgb_dist + (ratio_diff * 500.0);
+                if score < min_score {
+                    min_score = score;
+                    closest = *c;
+                }
+            }
+        }
+        return closest;
+    }
+    let current_hue = self.rgb_to_hue(cr, cg, cb);
+    let mut closest = current;
+    let mut min_score = f32::MAX;
+    for entry in &self.color_palette {
+        if let PaletteEntry::Color(c) = entry {
+            let (r, g, b) = utils::to_rgb(*c);
+            let brightness = r as f32 + g as f32 + b as f32;
+            if brightness <= current_brightness { continue; }
+            let cand_hue = self.rgb_to_hue(r, g, b);
+            let hue_dist = self.hue_distance(current_hue, cand_hue);
+            if hue_dist > 45.0 { continue; }
+            let dr = cr as f32 - r as f32;
+            let dg = cg as f32 - g as f32;
+            let db = cb as f32 - b as f32;
+            let rgb_dist = (dr * dr + dg * dg + db * db).sqrt();
+            let score = rgb_dist + (hue_dist * 10.0);
+            if score < min_score {
+                min_score = score;
+                closest = *c;
+            }
+        }
+    }
+    closest
+}
+
+fn find_darker_rgb(&self, current: Color) -> Color {
+    let (cr, cg, cb) = utils::to_rgb(current);
+    let current_brightness = cr as f32 + cg as f32 + cb as f32;
+    if !self.protect_color_transitions {
+        let total = cr.max(1) as f32;
+        let ratio_g = cg as f32 / total;
+        let ratio_b = cb as f32 / total;
+        let mut closest = current;
+        let mut min_score = f32::MAX;
+        for entry in &self.color_palette {
+            if let PaletteEntry::Color(c) = entry {
+                let (r, g, b) = utils::to_rgb(*c);
+                let brightness = r as f32 + g as f32 + b as f32;
+                if brightness >= current_brightness { continue; }
+                let cand_total = r.max(1) as f32;
+                let cand_ratio_g = g as f32 / cand_total;
+                let cand_ratio_b = b as f32 / cand_total;
+                let ratio_diff = (ratio_g - cand_ratio_g).abs() + (ratio_b - cand_ratio_b).abs();
+                let dr = cr as f32 - r as f32;
+                let dg = cg as f32 - g as f32;
+                let db = cb as f32 - b as f32;
+                let rgb_dist = (dr * dr + dg * dg + db * db).sqrt();
+                let score = rgb_dist + (ratio_diff * 500.0);
+                if score < min_score {
+                    min_score = score;
+                    closest = *c;
+                }
+            }
+        }
+        return closest;
+    }
+    let current_hue = self.rgb_to_hue(cr, cg, cb);
+    let mut closest = current;
+    let mut min_score = f32::MAX;
+    for entry in &self.color_palette {
+        if let PaletteEntry::Color(c) = entry {
+            let (r, g, b) = utils::to_rgb(*c);
+            let brightness = r as f32 + g as f32 + b as f32;
+            if brightness >= current_brightness { continue; }
+            let cand_hue = self.rgb_to_hue(r, g, b);
+            let hue_dist = self.hue_distance(current_hue, cand_hue);
+            if hue_dist > 45.0 { continue; }
+            let dr = cr as f32 - r as f32;
+            let dg = cg as f32 - g as f32;
+            let db = cb as f32 - b as f32;
+            let rgb_dist = (dr * dr + dg * dg + db * db).sqrt();
+            let score = rgb_dist + (hue_dist * 10.0);
+            if score < min_score {
+                min_score = score;
+                closest = *c;
+            }
+        }
+    }
+    closest
+}
+
+fn find_lighter_palette_color(&self, current: Color) -> Color {
+    let (cr, cg, cb) = utils::to_rgb(current);
+    let current_brightness = cr as f32 + cg as f32 + cb as f32;
+    let mut closest = current;
+    let mut min_combined = f32::MAX;
+    for entry in &self.color_palette {
+        if let PaletteEntry::Color(c) = entry {
+            let (r, g, b) = utils::to_rgb(*c);
+            let brightness = r as f32 + g as f32 + b as f32;
+            if brightness <= current_brightness {
+                continue;
+            }
+            let dr = cr as f32 - r as f32;
+            let dg = cg as f32 - g as f32;
+            let db = cb as f32 - b as f32;
+            let rgb_dist = (dr * dr + dg * dg + db * db).sqrt();
+            let hue_diff = (dr.abs() + dg.abs() + db.abs()) / 3.0;
+            let combined = rgb_dist + (hue_diff * 2.0);
+            if combined < min_combined {
+                min_combined = combined;
+                closest = *c;
+            }
+        }
+    }
+    closest
+}
+
+fn find_darker_palette_color(&self, current: Color) -> Color {
+    let (cr, cg, cb) = utils::to_rgb(current);
+    let current_brightness = cr as f32 + cg as f32 + cb as f32;
+    let mut closest = current;
+    let mut min_combined = f32::MAX;
+    for entry in &self.color_palette {
+        if let PaletteEntry::Color(c) = entry {
+            let (r, g, b) = utils::to_rgb(*c);
+            let brightness = r as f32 + g as f32 + b as f32;
+            if brightness >= current_brightness {
+                continue;
+            }
+            let dr = cr as f32 - r as f32;
+            let dg = cg as f32 - g as f32;
+            let db = cb as f32 - b as f32;
+            let rgb_dist = (dr * dr + dg * dg + db * db).sqrt();
+            let hue_diff = (dr.abs() + dg.abs() + db.abs()) / 3.0;
+            let combined = rgb_dist + (hue_diff * 2.0);
+            if combined < min_combined {
+                min_combined = combined;
+                closest = *c;
+            }
+        }
+    }
+    closest
+}
+
+
+    fn apply_effect_with_stroke_tracking(&mut self, x: usize, y: usize) {
+        if x >= self.canvas_width || y >= self.canvas_height { return; }
+
+        if self.protect_stroke {
+            let coord = (x as u16, y as u16);
+            if !self.drawn_pixels_in_stroke.contains(&coord) {
+                self.apply_effect_at_pixel(x, y);
+                self.drawn_pixels_in_stroke.insert(coord);
+            }
+        } else {
+            self.apply_effect_at_pixel(x, y);
+        }
+    }
+
+    // Like apply_effect_with_stroke_tracking, but for anti-aliased edges where the
+    // pixel is only partially covered. Full coverage goes through the normal path.
+    fn apply_coverage_with_stroke_tracking(&mut self, x: usize, y: usize, coverage: f32) {
+        // The dither pattern is anchored to the canvas (in block units), so overlapping strokes tile.
+        let block = self.pixel_block.max(1) as usize;
+        if self.dither_enabled && !self.dither_pattern.covers(x / block, y / block) { return; }
+        if self.flat_mode {
+            // No partial alpha in flat mode: a pixel is either covered or it isn't.
+            if coverage >= 0.5 { self.apply_effect_with_stroke_tracking(x, y); }
+            return;
+        }
+        if coverage >= 1.0 {
+            self.apply_effect_with_stroke_tracking(x, y);
+            return;
+        }
+        if x >= self.canvas_width || y >= self.canvas_height { return; }
+
+        let coord = (x as u16, y as u16);
+        if self.protect_stroke && self.drawn_pixels_in_stroke.contains(&coord) { return; }
+        self.plot_with_coverage(x, y, coverage);
+        if self.protect_stroke {
+            self.drawn_pixels_in_stroke.insert(coord);
+        }
+    }
+
+    // Composites the selected color onto the active layer with alpha scaled by coverage.
+    // snap_to_palette only affects the resulting color; the computed alpha is kept as is.
+    fn plot_with_coverage(&mut self, x: usize, y: usize, coverage: f32) {
+        let src_color = match self.current_selection {
+            PaletteEntry::Color(c) => self.jittered(c, x, y),
+            PaletteEntry::Tool(_) => return,
+        };
+        if x >= self.canvas_width || y >= self.canvas_height || coverage <= 0.0 || self.masked_out(x, y) { return; }
+
+        let dest_pixel = self.layers[self.active_layer_index].canvas[y][x];
+        let src_alpha = self.opacity * coverage.min(1.0);
+        let (final_color, final_alpha) = if self.layers[self.active_layer_index].alpha_locked {
+            if dest_pixel.alpha == 0.0 { return; }
+            (utils::blend_colors(dest_pixel.color.into(), src_color, src_alpha, self.linear_blending), dest_pixel.alpha)
+        } else if dest_pixel.alpha == 0.0 {
+            (src_color, src_alpha)
+        } else {
+            let final_alpha = src_alpha + dest_pixel.alpha * (1.0 - src_alpha);
+            let factor = src_alpha / final_alpha;
+            (utils::blend_colors(dest_pixel.color.into(), src_color, factor, self.linear_blending), final_alpha)
+        };
+        let final_color = if self.snap_to_palette { self.find_closest_palette_color(final_color) } else { final_color };
+        self.layers[self.active_layer_index].canvas[y][x] = Pixel { color: final_color.into(), alpha: final_alpha };
+        self.sync_canvas_pixel(x, y);
+    }
+
+    // Applies color_jitter: up to ±30° of hue and ±0.3 of brightness at full strength, the
+    // same across each pixel block. Snapped to the palette when snap_to_palette is on.
+    fn jittered(&self, color: Color, x: usize, y: usize) -> Color {
+        if self.color_jitter <= 0.0 { return color; }
+        let block = self.pixel_block.max(1) as usize;
+        let (x, y) = (x / block, y / block);
+        let seed = self.jitter_seed ^ self.history_position.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        let hue_shift = utils::pixel_noise(seed, x, y) * self.color_jitter * 30.0;
+        let value_shift = utils::pixel_noise(!seed, x, y) * self.color_jitter * 0.3;
+        let jittered = utils::jitter_color(color, hue_shift, value_shift);
+        if self.snap_to_palette { self.find_closest_palette_color(jittered) } else { jittered }
+    }
+
+    // In flat mode opacity dims the color toward black instead of lowering alpha.
+    fn flat_color(&self, color: Color) -> Color {
+        utils::blend_colors(color, Color::Black, 1.0 - self.opacity, self.linear_blending)
+    }
+
+    /// Resizes the canvas, keeping the artwork in the top-left corner.
+    pub fn resize_canvas(&mut self, new_width: usize, new_height: usize) {
+        self.resize_canvas_anchored(new_width, new_height, ResizeAnchor::TopLeft, false);
+    }
+
+    /// Resizes every layer as one undo step. The old pixels are copied to `anchor`
+    /// and clipped when shrinking, or dropped entirely with `clear`.
+    pub fn resize_canvas_anchored(&mut self, new_width: usize, new_height: usize, anchor: ResizeAnchor, clear: bool) {
+        let (new_width, new_height) = (new_width.max(1), new_height.max(1));
+        let (offset_x, offset_y) = match anchor {
+            ResizeAnchor::TopLeft => (0, 0),
+            ResizeAnchor::Center => ((new_width as i32 - self.canvas_width as i32) / 2, (new_height as i32 - self.canvas_height as i32) / 2),
+            ResizeAnchor::Offset(x, y) => (x, y),
+        };
+        // The whole layer stack, so undo brings back the old size exactly.
+        self.save_layer_stack_for_undo(&format!("Resize {}x{}→{}x{}", self.canvas_width, self.canvas_height, new_width, new_height));
+        for layer in &mut self.layers {
+            let mut canvas = Canvas::new(new_width, new_height);
+            if !clear {
+                copy_into(&layer.canvas, &mut canvas, offset_x, offset_y);
+            }
+            layer.canvas = canvas;
+        }
+        self.apply_canvas_size(new_width, new_height);
+    }
+
+    // Brings everything tied to the canvas size in line with the layers after
+    // they were resized (or restored at another size by undo).
+    fn apply_canvas_size(&mut self, new_width: usize, new_height: usize) {
+        self.canvas_width = new_width;
+        self.canvas_height = new_height;
+        self.dirty = true;
+        // Layers a resize entry didn't cover (e.g. added since) keep their top-left pixels.
+        for layer in &mut self.layers {
+            if layer.canvas.size() != (new_width, new_height) {
+                let mut canvas = Canvas::new(new_width, new_height);
+                copy_into(&layer.canvas, &mut canvas, 0, 0);
+                layer.canvas = canvas;
+            }
+        }
+        self.canvas = Canvas::new(self.canvas_width, self.canvas_height);
+        self.selection.clear();
+        self.sync_canvas_from_layers();
+
+        self.cursor_pos.0 = self.cursor_pos.0.min(self.canvas_width.saturating_sub(1) as u16);
+        self.cursor_pos.1 = self.cursor_pos.1.min(self.canvas_height.saturating_sub(1) as u16);
+
+        // --- NEW: Auto-zoom to fit the new canvas to the screen ---
+        if let Some(pixel_area) = self.last_pixel_area {
+            if self.canvas_width > 0 && self.canvas_height > 0 {
+                // Calculate the maximum possible zoom level based on width
+                let max_zoom_x = pixel_area.width / self.canvas_width as u16;
+                
+                // Calculate the maximum possible zoom level based on height
+                let max_zoom_y = (pixel_area.height * PIXEL_WIDTH) / self.canvas_height as u16;
+
+                // The new zoom must respect both constraints, so we take the smaller of the two.
+                let mut new_zoom = max_zoom_x.min(max_zoom_y);
+                
+                // Ensure zoom is at least 2 (for 1x) and is an even number to maintain the square aspect ratio.
+                new_zoom = new_zoom.max(2);
+                new_zoom = (new_zoom / 2) * 2;
+                
+                self.zoom_level = new_zoom;
+            }
+        }
+        
+        // --- NEW: Reset the camera pan to the top-left corner ---
+        self.view_offset_x = 0;
+        self.view_offset_y = 0;
+    }
+
+    /// Rotates every layer clockwise by the given number of quarter turns.
+    pub fn rotate_canvas(&mut self, quarter_turns: u8) {
+        let (width, height) = (self.canvas_width, self.canvas_height);
+        match quarter_turns % 4 {
+            0 => return,
+            1 => self.transform_canvas("Rotate 90°", height, width, |x, y| (height - 1 - y, x)),
+            2 => self.transform_canvas("Rotate 180°", width, height, |x, y| (width - 1 - x, height - 1 - y)),
+            _ => self.transform_canvas("Rotate 270°", height, width, |x, y| (y, width - 1 - x)),
+        }
+        self.set_status(format!("Rotated canvas {} degrees", quarter_turns as u16 % 4 * 90));
+    }
+
+    /// Mirrors every layer left-to-right (`horizontal`) or top-to-bottom.
+    pub fn flip_canvas(&mut self, horizontal: bool) {
+        let (width, height) = (self.canvas_width, self.canvas_height);
+        if horizontal {
+            self.transform_canvas("Flip horizontally", width, height, |x, y| (width - 1 - x, y));
+        } else {
+            self.transform_canvas("Flip vertically", width, height, |x, y| (x, height - 1 - y));
+        }
+        self.set_status(format!("Flipped canvas {}", if horizontal { "horizontally" } else { "vertically" }));
+    }
+
+    // Moves every pixel of every layer to `map(x, y)` on a new_width x new_height
+    // canvas as one undo step. The cursor follows its pixel; symmetry is turned off
+    // since its axes no longer match the artwork.
+    fn transform_canvas(&mut self, label: &str, new_width: usize, new_height: usize, map: impl Fn(usize, usize) -> (usize, usize)) {
+        let all_layers: Vec<usize> = (0..self.layers.len()).collect();
+        self.save_layers_for_undo(&all_layers, label);
+        for layer in &mut self.layers {
+            let mut canvas = Canvas::new(new_width, new_height);
+            for (y, row) in layer.canvas.rows().enumerate() {
+                for (x, pixel) in row.iter().enumerate() {
+                    let (new_x, new_y) = map(x, y);
+                    canvas[new_y][new_x] = *pixel;
+                }
+            }
+            layer.canvas = canvas;
+        }
+        let (cursor_x, cursor_y) = map(self.cursor_pos.0 as usize, self.cursor_pos.1 as usize);
+        self.apply_canvas_size(new_width, new_height);
+        self.cursor_pos = (cursor_x as u16, cursor_y as u16);
+        self.symmetry_mode = SymmetryMode::Off;
+    }
+
+    /// Clears the active layer, or every unlocked layer with `all_layers`, as one undo step.
+    pub fn clear_canvas(&mut self, all_layers: bool) {
+        if !all_layers && (self.active_layer_locked() || self.active_layer_alpha_locked()) { return; }
+        let indices: Vec<usize> = if all_layers { self.erasable_layers() } else { vec![self.active_layer_index] };
+        self.save_layers_for_undo(&indices, if all_layers { "Clear all layers" } else { "Clear layer" });
+        for &i in &indices {
+            self.layers[i].canvas = Canvas::new(self.canvas_width, self.canvas_height);
+        }
+        self.sync_canvas_from_layers();
+        let message = if all_layers { "All layers cleared." } else { "Active layer cleared." };
+        self.set_status(message.to_string());
+    }
+
+    /// Quits right away when everything is saved; otherwise asks first.
+    pub fn quit(&mut self) {
+        if self.dirty {
+            self.quit_choice = QuitChoice::No;
+            self.mode = AppMode::ConfirmQuit;
+        } else {
+            self.should_quit = true;
+        }
+    }
+
+    // Resolves the ConfirmQuit dialog. Save without a project path opens the save
+    // browser instead of quitting.
+    pub fn confirm_quit(&mut self, choice: QuitChoice) {
+        self.mode = AppMode::Drawing;
+        match choice {
+            QuitChoice::Yes => self.should_quit = true,
+            QuitChoice::No => {}
+            QuitChoice::Save => match self.project_path.clone() {
+                Some(path) => {
+                    self.save_project(&path, true);
+                    self.should_quit = !self.dirty;
+                }
+                None => crate::file_browser::open_browser(self, BrowserMode::Save),
+            },
+        }
+    }
+
+    pub fn move_cursor(&mut self, dx: i16, dy: i16) {
+        if self.mode != AppMode::Drawing { return; }
+        if let (true, Some((tile_w, tile_h))) = (self.tile_snap, self.tile_size) {
+            // Jump whole tiles, landing on the tile origin.
+            let (x, y) = self.tile_origin_at(self.cursor_pos.0 as usize, self.cursor_pos.1 as usize);
+            let new_x = (x as i32 + dx as i32 * tile_w as i32).clamp(0, self.canvas_width.saturating_sub(1) as i32);
+            let new_y = (y as i32 + dy as i32 * tile_h as i32).clamp(0, self.canvas_height.saturating_sub(1) as i32);
+            let (snapped_x, snapped_y) = self.tile_origin_at(new_x as usize, new_y as usize);
+            self.cursor_pos = (snapped_x as u16, snapped_y as u16);
+            return;
+        }
+        // In pixel_block mode the cursor steps block by block, staying on block origins.
+        let block = self.pixel_block.max(1) as i16;
+        let (x, y) = (self.cursor_pos.0 as i16 / block * block, self.cursor_pos.1 as i16 / block * block);
+        let new_x = (x + dx * block).max(0).min(self.canvas_width.saturating_sub(1) as i16);
+        let new_y = (y + dy * block).max(0).min(self.canvas_height.saturating_sub(1) as i16);
+        self.cursor_pos = (new_x as u16 / block as u16 * block as u16, new_y as u16 / block as u16 * block as u16);
+    }
+    
+    pub fn begin_stabilized_stroke(&mut self, x: u16, y: u16) {
+        self.stabilizer_queue.clear();
+        self.stabilizer_queue.push_back((x, y));
+    }
+
+    /// Feeds a raw mouse position into the stabilizer and returns where to paint:
+    /// a weighted average of the last `stabilizer + 1` positions, newest weighing most.
+    pub fn stabilized_point(&mut self, x: u16, y: u16) -> (u16, u16) {
+        if self.stabilizer == 0 { return (x, y); }
+        self.stabilizer_queue.push_back((x, y));
+        while self.stabilizer_queue.len() > self.stabilizer as usize + 1 {
+            self.stabilizer_queue.pop_front();
+        }
+        Self::weighted_average(&self.stabilizer_queue)
+    }
+
+    /// Paints, or erases, every block on the line from the previous drag point to
+    /// `(x, y)` so fast mouse moves leave no gaps. The previous point was painted
+    /// already and is skipped; with protect_stroke on, repeated pixels are skipped too.
+    pub fn drag_brush_to(&mut self, x: u16, y: u16, erase: bool) {
+        let block = self.pixel_block.max(1) as i32;
+        let (from_x, from_y) = self.last_drag_point.unwrap_or((x, y));
+        let line = utils::bresenham_line(from_x as i32 / block, from_y as i32 / block, x as i32 / block, y as i32 / block);
+        let skip = usize::from(self.last_drag_point.is_some());
+        for (bx, by) in line.into_iter().skip(skip) {
+            let (px, py) = ((bx * block) as u16, (by * block) as u16);
+            if erase { self.erase_brush(px, py); } else { self.apply_brush(px, py); }
+        }
+        self.last_drag_point = Some((x, y));
+    }
+
+    /// Drains the stabilizer at the end of a stroke, returning the points that let
+    /// the smoothed line catch up with the last raw position.
+    pub fn flush_stabilizer(&mut self) -> Vec<(u16, u16)> {
+        let mut points = Vec::new();
+        if self.stabilizer > 0 {
+            while self.stabilizer_queue.len() > 1 {
+                self.stabilizer_queue.pop_front();
+                points.push(Self::weighted_average(&self.stabilizer_queue));
+            }
+        }
+        self.stabilizer_queue.clear();
+        points
+    }
+
+    /// Cells a shape tool covers between two corners, clipped to the canvas. With
+    /// pixel_block > 1 the shape is laid out in block units and every cell is a block origin.
+    pub fn shape_points(&self, tool: Tool, from: (i32, i32), to: (i32, i32), filled: bool) -> Vec<(u16, u16)> {
+        let block = self.pixel_block.max(1) as i32;
+        let ((x0, y0), (x1, y1)) = self.shape_corners(tool, from, to);
+        let cells = match tool {
+            Tool::Rectangle => {
+                let (left, right, top, bottom) = (x0.min(x1), x0.max(x1), y0.min(y1), y0.max(y1));
+                let mut cells = Vec::new();
+                for y in top..=bottom {
+                    for x in left..=right {
+                        if filled || y == top || y == bottom || x == left || x == right {
+                            cells.push((x, y));
+                        }
+                    }
+                }
+                cells
+            }
+            Tool::Ellipse => utils::ellipse_points(x0, y0, x1, y1, filled),
+            _ => utils::bresenham_line(x0, y0, x1, y1),
+        };
+        cells.into_iter()
+            .map(|(x, y)| (x * block, y * block))
+            .filter(|&(x, y)| x >= 0 && y >= 0 && (x as usize) < self.canvas_width && (y as usize) < self.canvas_height)
+            .map(|(x, y)| (x as u16, y as u16))
+            .collect()
+    }
+
+    // A shape's two corners in block units, squared up for perfect circles.
+    fn shape_corners(&self, tool: Tool, from: (i32, i32), to: (i32, i32)) -> ((i32, i32), (i32, i32)) {
+        let block = self.pixel_block.max(1) as i32;
+        let (x0, y0) = (from.0.div_euclid(block), from.1.div_euclid(block));
+        let (mut x1, mut y1) = (to.0.div_euclid(block), to.1.div_euclid(block));
+        if tool == Tool::Ellipse && (self.perfect_circles || self.shape_constrained) {
+            // Grow the shorter side so the bounding box is square, keeping the drag direction.
+            let side = (x1 - x0).abs().max((y1 - y0).abs());
+            x1 = if x1 < x0 { x0 - side } else { x0 + side };
+            y1 = if y1 < y0 { y0 - side } else { y0 + side };
+        }
+        ((x0, y0), (x1, y1))
+    }
+
+    // With antialias on, a line or ellipse drawn in a color with the plain 1px pen gets
+    // partial alpha along its edges: block-unit cells with their coverage. Thicker and custom
+    // brushes are stamped instead, with the brush's own soft edge.
+    fn antialiased_shape(&self, tool: Tool, from: (i32, i32), to: (i32, i32)) -> Option<Vec<(i32, i32, f32)>> {
+        if !self.antialias || self.pen_size > 1 || self.brush_mask.is_some() || !matches!(self.current_selection, PaletteEntry::Color(_)) {
+            return None;
+        }
+        let ((x0, y0), (x1, y1)) = self.shape_corners(tool, from, to);
+        match tool {
+            Tool::Line => Some(utils::wu_line(x0, y0, x1, y1)),
+            Tool::Ellipse => Some(utils::ellipse_coverage(x0, y0, x1, y1, self.fill_shapes)),
+            _ => None,
+        }
+    }
+
+    /// Stamps the brush over a whole shape as one undo step. Stroke protection
+    /// keeps overlapping stamps from stacking opacity.
+    pub fn draw_shape(&mut self, tool: Tool, from: (i32, i32), to: (i32, i32)) {
+        if self.active_layer_locked() { return; }
+        self.save_state_for_undo(&format!("{:?}", tool));
+        if self.protect_stroke { self.drawn_pixels_in_stroke.clear(); }
+        if let Some(cells) = self.antialiased_shape(tool, from, to) {
+            let block = self.pixel_block.max(1) as i32;
+            for (x, y, coverage) in cells {
+                for py in y * block..(y + 1) * block {
+                    for px in x * block..(x + 1) * block {
+                        self.apply_brush_pixel(px, py, coverage);
+                    }
+                }
+            }
+        } else {
+            for (x, y) in self.shape_points(tool, from, to, self.fill_shapes) {
+                self.apply_brush(x, y);
+            }
+        }
+        if self.protect_stroke { self.drawn_pixels_in_stroke.clear(); }
+        self.finish_undo_step();
+    }
+
+    /// The DrawLine action: the first press anchors the selected shape tool (a line
+    /// if none is selected) at the cursor, the second draws it to the cursor.
+    pub fn anchor_or_draw_shape(&mut self) {
+        let tool = self.shape_tool.unwrap_or(Tool::Line);
+        match self.shape_anchor.take() {
+            Some(anchor) => self.draw_shape(tool, (anchor.0 as i32, anchor.1 as i32), (self.cursor_pos.0 as i32, self.cursor_pos.1 as i32)),
+            None => {
+                self.shape_anchor = Some(self.cursor_pos);
+                self.set_status(format!("{:?} anchored at ({}, {})", tool, self.cursor_pos.0, self.cursor_pos.1));
+            }
+        }
+    }
+
+    pub fn set_clone_source(&mut self) {
+        self.clone_source = Some(self.cursor_pos);
+        self.clone_offset = None;
+        self.set_status(format!("Clone source set at ({}, {})", self.cursor_pos.0, self.cursor_pos.1));
+    }
+
+    /// Where the clone tool is currently sampling from: the locked offset applied to
+    /// the cursor, or the source itself before the first dab.
+    pub fn clone_sample_pos(&self) -> Option<(i32, i32)> {
+        let source = self.clone_source?;
+        Some(match self.clone_offset {
+            Some((offset_x, offset_y)) => (self.cursor_pos.0 as i32 + offset_x, self.cursor_pos.1 as i32 + offset_y),
+            None => (source.0 as i32, source.1 as i32),
+        })
+    }
+
+    // Composites the pixel at the clone offset onto (x, y). Sources off the canvas are skipped.
+    fn clone_pixel(&mut self, x: usize, y: usize) {
+        let Some((offset_x, offset_y)) = self.clone_offset else { return };
+        let (source_x, source_y) = (x as i32 + offset_x, y as i32 + offset_y);
+        if source_x < 0 || source_y < 0 || source_x >= self.canvas_width as i32 || source_y >= self.canvas_height as i32 { return; }
+        let source = if self.clone_sample_merged {
+            self.canvas[source_y as usize][source_x as usize]
+        } else {
+            self.layers[self.active_layer_index].canvas[source_y as usize][source_x as usize]
+        };
+        let original_pixel = self.layers[self.active_layer_index].canvas[y][x];
+        let blended = utils::composite_over(original_pixel, source.color, source.alpha * self.opacity, self.linear_blending);
+        let mut new_pixel = if !self.flat_mode {
+            blended
+        } else if blended.alpha >= 0.5 {
+            Pixel { alpha: 1.0, ..blended }
+        } else {
+            original_pixel
+        };
+        if self.layers[self.active_layer_index].alpha_locked {
+            new_pixel.alpha = original_pixel.alpha;
+        }
+        self.layers[self.active_layer_index].canvas[y][x] = new_pixel;
+        self.sync_canvas_pixel(x, y);
+    }
+
+    fn weighted_average(points: &VecDeque<(u16, u16)>) -> (u16, u16) {
+        let (mut sum_x, mut sum_y, mut total) = (0.0, 0.0, 0.0);
+        for (i, &(x, y)) in points.iter().enumerate() {
+            let weight = (i + 1) as f32;
+            sum_x += x as f32 * weight;
+            sum_y += y as f32 * weight;
+            total += weight;
+        }
+        ((sum_x / total).round() as u16, (sum_y / total).round() as u16)
+    }
+
+    pub fn cycle_symmetry_mode(&mut self) {
+        self.symmetry_mode = match self.symmetry_mode {
+            SymmetryMode::Off => SymmetryMode::Vertical(self.canvas_width as u16 / 2),
+            SymmetryMode::Vertical(_) => {
+                let center_x = self.canvas_width as i32 / 2;
+                let center_y = self.canvas_height as i32 / 2;
+                SymmetryMode::DiagonalForward(center_y - center_x)
+            }
+            SymmetryMode::DiagonalForward(_) => SymmetryMode::Horizontal(self.canvas_height as u16 / 2),
+            SymmetryMode::Horizontal(_) => {
+                let center_x = self.canvas_width as i32 / 2;
+                let center_y = self.canvas_height as i32 / 2;
+                SymmetryMode::DiagonalBackward(center_y + center_x)
+            }
+            SymmetryMode::DiagonalBackward(_) => SymmetryMode::Quad(self.canvas_width as u16 / 2, self.canvas_height as u16 / 2),
+            SymmetryMode::Quad(..) => SymmetryMode::Radial(self.radial_segments, self.canvas_width as u16 / 2, self.canvas_height as u16 / 2),
+            SymmetryMode::Radial(..) => SymmetryMode::Off,
+        };
+    }
+
+    /// Moves the active symmetry axis (or radial center) so it passes through the cursor.
+    pub fn set_symmetry_at_cursor(&mut self) {
+        let (cursor_x, cursor_y) = self.cursor_pos;
+        self.symmetry_mode = match self.symmetry_mode {
+            SymmetryMode::Off => {
+                self.set_status("Symmetry is off");
+                return;
+            }
+            SymmetryMode::Vertical(_) => SymmetryMode::Vertical(cursor_x),
+            SymmetryMode::Horizontal(_) => SymmetryMode::Horizontal(cursor_y),
+            SymmetryMode::DiagonalForward(_) => SymmetryMode::DiagonalForward(cursor_y as i32 - cursor_x as i32),
+            SymmetryMode::DiagonalBackward(_) => SymmetryMode::DiagonalBackward(cursor_y as i32 + cursor_x as i32),
+            SymmetryMode::Quad(..) => SymmetryMode::Quad(cursor_x, cursor_y),
+            SymmetryMode::Radial(segments, ..) => SymmetryMode::Radial(segments, cursor_x, cursor_y),
+        };
+        self.set_status(format!("Symmetry axis moved to {},{}", cursor_x, cursor_y));
+    }
+
+    pub fn change_pen_size(&mut self, delta: i16) {
+        let change = self.pen_size_sensitivity as i16 * delta;
+        let new_size = (self.pen_size as i16 + change).max(1);
+        self.pen_size = new_size as u16;
+        self.set_status(format!("Pen size: {}", self.pen_size));
+
+    }
+
+    pub fn change_opacity(&mut self, direction: f32) {
+        let change = self.opacity_sensitivity * direction;
+        self.opacity = (self.opacity + change).clamp(0.0, 1.0);
+        self.set_status(format!("Opacity: {:.0}%", self.opacity * 100.0));
+
+    }
+
+    pub fn pan_view(&mut self, dx: i32, dy: i32) {
+        self.view_offset_x += dx;
+        self.view_offset_y += dy;
+        // Clamping will be handled in the UI function to ensure it's always correct.
+    }
+
+    /// Follows a pan drag: `pan_anchor` holds the screen cell the drag started on and
+    /// the view offsets at that moment, so the canvas stays under the mouse.
+    pub fn drag_pan_to(&mut self, column: u16, row: u16) {
+        let Some(((start_column, start_row), (offset_x, offset_y))) = self.pan_anchor else { return };
+        let pixel_render_height = (self.zoom_level / PIXEL_WIDTH).max(1) as i32;
+        self.view_offset_x = offset_x - (column as i32 - start_column as i32) / self.zoom_level.max(1) as i32;
+        self.view_offset_y = offset_y - (row as i32 - start_row as i32) / pixel_render_height;
+        if let Some(area) = self.last_pixel_area {
+            self.clamp_view_offsets(area.width, area.height);
+        }
+    }
+
+    /// Centers the view on the canvas under a minimap cell. Each cell packs two canvas
+    /// rows into one ▀ half block, so a row spans twice the height a column spans width.
+    /// Positions outside the minimap count as its nearest edge.
+    pub fn center_view_on_minimap(&mut self, column: u16, row: u16) {
+        let (Some(minimap), Some(pixel_area)) = (self.last_minimap_area, self.last_pixel_area) else { return };
+        if minimap.width == 0 || minimap.height == 0 { return; }
+        let cell_x = column.saturating_sub(minimap.x).min(minimap.width - 1) as f32 + 0.5;
+        let cell_y = row.saturating_sub(minimap.y).min(minimap.height - 1) as f32 + 0.5;
+        let scale_x = self.canvas_width as f32 / minimap.width as f32;
+        let scale_y = self.canvas_height as f32 / (minimap.height as f32 * 2.0);
+        let canvas_x = (cell_x * scale_x) as i32;
+        let canvas_y = (cell_y * 2.0 * scale_y) as i32;
+
+        let pixel_render_height = (self.zoom_level / PIXEL_WIDTH).max(1);
+        let visible_pixels_x = (pixel_area.width / self.zoom_level.max(1)) as i32;
+        let visible_pixels_y = (pixel_area.height / pixel_render_height) as i32;
+        self.view_offset_x = canvas_x - visible_pixels_x / 2;
+        self.view_offset_y = canvas_y - visible_pixels_y / 2;
+        self.clamp_view_offsets(pixel_area.width, pixel_area.height);
+    }
+
+    /// Zooms around the drawing cursor.
+    pub fn zoom(&mut self, delta: i16) {
+        let pixel_render_height = (self.zoom_level / PIXEL_WIDTH).max(1) as i32;
+        let cell_x = (self.cursor_pos.0 as i32 - self.view_offset_x) * self.zoom_level as i32 + self.zoom_level as i32 / 2;
+        let cell_y = (self.cursor_pos.1 as i32 - self.view_offset_y) * pixel_render_height + pixel_render_height / 2;
+        self.zoom_around(delta, (cell_x, cell_y));
+    }
+
+    /// Changes the zoom level while keeping the canvas pixel under `view_cell`, a
+    /// screen cell counted from the top-left of the visible canvas, in place.
+    /// max_zoom is in status bar units, half the zoom level.
+    pub fn zoom_around(&mut self, delta: i16, view_cell: (i32, i32)) {
+        let old_height = (self.zoom_level / PIXEL_WIDTH).max(1) as i32;
+        let anchor_x = self.view_offset_x + view_cell.0.div_euclid(self.zoom_level.max(1) as i32);
+        let anchor_y = self.view_offset_y + view_cell.1.div_euclid(old_height);
+        self.zoom_level = (self.zoom_level as i16 + delta).clamp(2, self.max_zoom as i16 * 2) as u16;
+        let new_height = (self.zoom_level / PIXEL_WIDTH).max(1) as i32;
+
+        self.view_offset_x = anchor_x - view_cell.0.div_euclid(self.zoom_level as i32);
+        self.view_offset_y = anchor_y - view_cell.1.div_euclid(new_height);
+        if let Some(area) = self.last_pixel_area {
+            self.clamp_view_offsets(area.width, area.height);
+        }
+    }
+
+    pub fn clamp_view_offsets(&mut self, visible_width: u16, visible_height: u16) {
+        let pixel_render_height = (self.zoom_level / PIXEL_WIDTH).max(1);
+
+        // --- FIX: Use ceiling division to correctly calculate how many pixels can fit ---
+        
+        // Horizontal clamping in PIXELS
+        let visible_pixels_x = visible_width.div_ceil(self.zoom_level);
+        let max_offset_x = self.canvas_width.saturating_sub(visible_pixels_x as usize) as i32;
+        self.view_offset_x = self.view_offset_x.clamp(0, max_offset_x);
+
+        // Vertical clamping in PIXELS
+        let visible_pixels_y = visible_height.div_ceil(pixel_render_height);
+        let max_offset_y = self.canvas_height.saturating_sub(visible_pixels_y as usize) as i32;
+        self.view_offset_y = self.view_offset_y.clamp(0, max_offset_y);
+    }
+
+    pub fn change_palette_selection_2d(&mut self, dx: i16, dy: i16) {
+        if let Some(palette_area) = self.last_palette_area {
+            let columns = (palette_area.width / 3).max(1) as usize;
+            let visible_rows = palette_area.height as usize;
+
+            // Calculate the proposed new index
+            let current_col = (self.palette_index % columns) as i16;
+            let current_row = (self.palette_index / columns) as i16;
+            let new_col = (current_col + dx).clamp(0, columns as i16 - 1);
+            let new_row = current_row + dy;
+            let new_index = (new_row * columns as i16 + new_col)
+                .clamp(0, self.color_palette.len() as i16 - 1) as usize;
+
+            self.palette_index = new_index;
+
+            // Now, adjust the scroll state to keep the new index visible
+            let top_visible_row = self.palette_scroll_state / columns;
+            let bottom_visible_row = top_visible_row + visible_rows - 1;
+            let new_item_row = new_index / columns;
+
+            if new_item_row < top_visible_row {
+                // Scrolled up past the top
+                self.palette_scroll_state = new_item_row * columns;
+            } else if new_item_row > bottom_visible_row {
+                // Scrolled down past the bottom
+                let new_top_row = new_item_row - visible_rows + 1;
+                self.palette_scroll_state = new_top_row * columns;
+            }
+        }
+    }
+
+    pub fn change_tool_selection(&mut self, delta: i16) {
+        let new_index = self.tool_index as i16 + delta;
+        self.tool_index = new_index.max(0).min(self.tool_palette.len() as i16 - 1) as usize;
+    }
+
+    pub fn select_color_entry(&mut self) {
+        self.current_selection = self.color_palette[self.palette_index];
+        self.mode = AppMode::Drawing;
+        self.status_message = None;
+    }
+
+    // Moves `color` to the front of the recent colors. Painting on with the same color
+    // is the common case, so that returns straight away.
+    fn note_recent_color(&mut self, color: Color) {
+        if self.recent_colors.first() == Some(&color) { return; }
+        self.recent_colors.retain(|&c| c != color);
+        self.recent_colors.insert(0, color);
+        self.recent_colors.truncate(RECENT_COLORS);
+        self.recent_color_cursor = 0;
+    }
+
+    /// Draws with recent color `index` (0 is the newest), highlighting it in the
+    /// palette when it is there.
+    pub fn select_recent_color(&mut self, index: usize) {
+        let Some(&color) = self.recent_colors.get(index) else { return; };
+        self.recent_color_cursor = index;
+        self.current_selection = PaletteEntry::Color(color);
+        if let Some(position) = self.color_palette.iter().position(|&e| e == PaletteEntry::Color(color)) {
+            self.palette_index = position;
+        }
+        let (r, g, b) = utils::to_rgb(color);
+        self.set_status(format!("Recent color {} of {} (#{:02X}{:02X}{:02X}).", index + 1, self.recent_colors.len(), r, g, b));
+    }
+
+    /// Steps back through the recent colors. Starting from a color that isn't the one
+    /// last picked here goes to the newest, so one press swaps between two colors.
+    pub fn cycle_recent_color(&mut self) {
+        if self.recent_colors.is_empty() {
+            self.set_status("No recent colors yet.");
+            return;
+        }
+        let cursor = self.recent_color_cursor.min(self.recent_colors.len() - 1);
+        let next = if self.current_selection == PaletteEntry::Color(self.recent_colors[cursor]) {
+            (cursor + 1) % self.recent_colors.len()
+        } else {
+            0
+        };
+        self.select_recent_color(next);
+    }
+
+    /// Removes the palette entry at `index`, keeping the highlight on its neighbour. If it
+    /// was the drawing color and no copy remains, the closest remaining color takes over.
+    pub fn remove_palette_entry(&mut self, index: usize) -> bool {
+        if index >= self.color_palette.len() {
+            self.set_status(format!("No palette color at position {}.", index + 1));
+            return false;
+        }
+        if self.color_palette.len() == 1 {
+            self.set_status("Cannot remove the last palette color.");
+            return false;
+        }
+        let removed = self.color_palette.remove(index);
+        self.dirty = true;
+        if self.palette_index > index { self.palette_index -= 1; }
+        self.palette_index = self.palette_index.min(self.color_palette.len() - 1);
+        if self.current_selection == removed && !self.color_palette.contains(&removed) {
+            if let PaletteEntry::Color(color) = removed {
+                let closest = self.color_palette.iter()
+                    .filter_map(|entry| match entry { PaletteEntry::Color(c) => Some(*c), _ => None })
+                    .min_by(|a, b| utils::color_distance(*a, color).total_cmp(&utils::color_distance(*b, color)));
+                if let Some(closest) = closest { self.current_selection = PaletteEntry::Color(closest); }
+            }
+        }
+        self.keep_palette_index_visible();
+        self.set_status(format!("Removed palette color {} ({}).", index + 1, palette_entry_label(removed)));
+        true
+    }
+
+    /// Moves the palette entry at `from` to position `to`, shifting the ones in between.
+    pub fn move_palette_entry(&mut self, from: usize, to: usize) -> bool {
+        let len = self.color_palette.len();
+        if from >= len || to >= len {
+            self.set_status(format!("No palette color at position {}.", from.max(to) + 1));
+            return false;
+        }
+        if from == to { return false; }
+        let entry = self.color_palette.remove(from);
+        self.color_palette.insert(to, entry);
+        self.dirty = true;
+        self.palette_index = to;
+        self.keep_palette_index_visible();
+        self.set_status(format!("Moved palette color {} to position {}.", from + 1, to + 1));
+        true
+    }
+
+    /// Moves the highlighted palette entry one cell across the grid, as the arrows move the highlight.
+    pub fn shift_palette_entry(&mut self, dx: i16, dy: i16) {
+        let from = self.palette_index;
+        self.change_palette_selection_2d(dx, dy);
+        let to = self.palette_index;
+        self.palette_index = from;
+        self.move_palette_entry(from, to);
+    }
+
+    /// Reorders the palette's colors by `sort`. Tool entries keep their slots and the
+    /// highlight stays on the same color.
+    pub fn sort_palette(&mut self, sort: PaletteSort) {
+        let highlighted = self.color_palette.get(self.palette_index).copied();
+        let slots: Vec<usize> = (0..self.color_palette.len()).filter(|&i| matches!(self.color_palette[i], PaletteEntry::Color(_))).collect();
+        // Grays have no hue, so they go first, dark to light, when sorting by hue.
+        let key = |entry: &PaletteEntry| {
+            let PaletteEntry::Color(color) = *entry else { return (0.0, 0.0, 0.0) };
+            let (r, g, b) = utils::to_rgb(color);
+            let (_, saturation, _) = rgb_to_hsv(r, g, b);
+            let luminance = luminance(color);
+            match sort {
+                PaletteSort::Hue if saturation == 0.0 => (0.0, luminance, 0.0),
+                PaletteSort::Hue => (1.0, self.rgb_to_hue(r, g, b), luminance),
+                PaletteSort::Brightness => (luminance, self.rgb_to_hue(r, g, b), 0.0),
+                PaletteSort::Saturation => (saturation, luminance, 0.0),
+            }
+        };
+        let mut colors: Vec<PaletteEntry> = slots.iter().map(|&i| self.color_palette[i]).collect();
+        colors.sort_by(|a, b| {
+            let (a, b) = (key(a), key(b));
+            a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)).then(a.2.total_cmp(&b.2))
+        });
+        for (&slot, entry) in slots.iter().zip(colors) {
+            self.color_palette[slot] = entry;
+        }
+        if let Some(index) = highlighted.and_then(|entry| self.color_palette.iter().position(|&e| e == entry)) {
+            self.palette_index = index;
+        }
+        self.dirty = true;
+        self.keep_palette_index_visible();
+        let name = match sort { PaletteSort::Hue => "hue", PaletteSort::Brightness => "brightness", PaletteSort::Saturation => "saturation" };
+        self.set_status(format!("Sorted {} colors by {}.", slots.len(), name));
+    }
+
+    /// Removes palette colors within `tolerance` (RGB distance; 0 for exact copies) of an
+    /// earlier one. The highlight moves to the color that was kept. Returns how many went.
+    pub fn dedupe_palette(&mut self, tolerance: f32) -> usize {
+        let mut kept: Vec<PaletteEntry> = Vec::with_capacity(self.color_palette.len());
+        let mut new_index = None;
+        for (i, &entry) in self.color_palette.iter().enumerate() {
+            let duplicate_of = match entry {
+                PaletteEntry::Color(color) => kept.iter().position(|k| matches!(k, PaletteEntry::Color(c) if utils::color_distance(*c, color) <= tolerance)),
+                PaletteEntry::Tool(_) => None,
+            };
+            let position = duplicate_of.unwrap_or_else(|| {
+                kept.push(entry);
+                kept.len() - 1
+            });
+            if i == self.palette_index { new_index = Some(position); }
+        }
+        let removed = self.color_palette.len() - kept.len();
+        if removed > 0 {
+            self.color_palette = kept;
+            self.palette_index = new_index.unwrap_or(0).min(self.color_palette.len() - 1);
+            self.dirty = true;
+            self.keep_palette_index_visible();
+        }
+        self.set_status(format!("Removed {} duplicate color{}.", removed, if removed == 1 { "" } else { "s" }));
+        removed
+    }
+
+    // Scrolls the palette grid so the highlighted entry is on screen.
+    fn keep_palette_index_visible(&mut self) {
+        self.palette_scroll_state = self.palette_scroll_state.min(self.palette_index);
+        self.change_palette_selection_2d(0, 0);
+    }
+
+    pub fn select_tool_entry(&mut self) {
+        let entry = self.tool_palette[self.tool_index];
+        self.shape_anchor = None;
+        match entry {
+            // Shape tools keep the current selection so lines come out in the active color.
+            PaletteEntry::Tool(tool) if tool.is_shape() => {
+                self.shape_tool = if self.shape_tool == Some(tool) { None } else { Some(tool) };
+                self.replace_mode = false;
+            }
+            // Dither is a toggle on top of whatever is selected, shapes included.
+            PaletteEntry::Tool(Tool::Dither) => self.dither_enabled = !self.dither_enabled,
+            // Replace also keeps the selection: it is the color clicked pixels get swapped to.
+            PaletteEntry::Tool(Tool::Replace) => {
+                self.replace_mode = !self.replace_mode;
+                self.shape_tool = None;
+            }
+            _ => {
+                self.current_selection = entry;
+                self.shape_tool = None;
+            }
+        }
+        self.mode = AppMode::Drawing;
+        self.status_message = None;
+    }
+
+/// The Blur tool's result at (x, y) on the active layer: the pixel blended by `opacity`
+/// toward the average of its (2 * blur_radius + 1)² neighborhood. The window is clipped
+/// at the canvas edges. Also used for the cursor preview.
+pub fn calculate_blur_at(&self, x: usize, y: usize, opacity: f32) -> Pixel {
+        let active_canvas = &self.layers[self.active_layer_index].canvas;
+        let original_pixel = active_canvas[y][x];
+        let mut r_sum: u32 = 0;
+        let mut g_sum: u32 = 0;
+        let mut b_sum: u32 = 0;
+        let mut a_sum: f32 = 0.0;
+        let mut count: u32 = 0;
+        let mut has_colored_neighbor = false;
+
+        let radius = self.blur_radius as i32;
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let nx = x as i32 + dx;
+                let ny = y as i32 + dy;
+
+                if nx >= 0 && nx < self.canvas_width as i32 && ny >= 0 && ny < self.canvas_height as i32 {
+                    let neighbor = active_canvas[ny as usize][nx as usize];
+                    if neighbor.alpha > 0.0 {
+                        let (r, g, b) = utils::to_rgb(neighbor.color.into());
+                        r_sum += r as u32;
+                        g_sum += g as u32;
+                        b_sum += b as u32;
+                        if dx != 0 || dy != 0 {
+                            has_colored_neighbor = true;
+                        }
+                    }
+                    a_sum += neighbor.alpha;
+                    count += 1;
+                }
+            }
+        }
+
+        if original_pixel.alpha == 0.0 && !has_colored_neighbor {
+            return original_pixel;
+        }
+
+        if let (Some(r), Some(g), Some(b)) = (r_sum.checked_div(count), g_sum.checked_div(count), b_sum.checked_div(count)) {
+            let blurred_color = Color::Rgb(r as u8, g as u8, b as u8);
+            let blurred_alpha = a_sum / count as f32;
+
+            let intermediate_color = utils::blend_colors(original_pixel.color.into(), blurred_color, opacity, self.linear_blending);
+            let final_color = if self.snap_to_palette {
+                self.find_closest_palette_color(intermediate_color)
+            } else {
+                intermediate_color
+            };
+            let final_alpha = original_pixel.alpha * (1.0 - opacity) + blurred_alpha * opacity;
+
+            Pixel {
+                color: final_color.into(),
+                alpha: final_alpha,
+            }
+
+        } else {
+            original_pixel
+        }
+    }
+
+fn apply_effect_at_pixel(&mut self, x: usize, y: usize) {
+    if x >= self.canvas_width || y >= self.canvas_height || self.masked_out(x, y) { return; }
+    let alpha_locked = self.layers[self.active_layer_index].alpha_locked;
+    if alpha_locked && self.layers[self.active_layer_index].canvas[y][x].alpha == 0.0 { return; }
+
+    if let PaletteEntry::Tool(tool) = self.current_selection {
+        let original_pixel = self.layers[self.active_layer_index].canvas[y][x];
+        if original_pixel.alpha == 0.0 && !matches!(tool, Tool::Blur | Tool::Clone) { return; }
+
+        let mut new_pixel = match tool {
+            Tool::Lighter => {
+                let new_color = if self.snap_to_palette {
+                    match self.snap_to_palette_mode {
+                        SnapToPaletteMode::ClosestRgb => self.find_lighter_rgb(original_pixel.color.into()),
+                        SnapToPaletteMode::ClosestHue => self.find_lighter_palette_color(original_pixel.color.into()),
+                    }
+                } else {
+                    utils::blend_colors(original_pixel.color.into(), Color::White, self.shade_factor, self.linear_blending)
+                };
+                Pixel { color: new_color.into(), ..original_pixel }
+            }
+            Tool::Darker => {
+                let new_color = if self.snap_to_palette {
+                    match self.snap_to_palette_mode {
+                        SnapToPaletteMode::ClosestRgb => self.find_darker_rgb(original_pixel.color.into()),
+                        SnapToPaletteMode::ClosestHue => self.find_darker_palette_color(original_pixel.color.into()),
+                    }
+                } else {
+                    utils::blend_colors(original_pixel.color.into(), Color::Black, self.shade_factor, self.linear_blending)
+                };
+                Pixel { color: new_color.into(), ..original_pixel }
+            }
+            // Shape tools, dither and replace never become the current selection.
+            Tool::Line | Tool::Rectangle | Tool::Ellipse | Tool::Dither | Tool::Replace => return,
+            Tool::Clone => {
+                self.clone_pixel(x, y);
+                return;
+            }
+            Tool::Blur => {
+                let blurred = self.calculate_blur_at(x, y, self.opacity);
+                if !self.flat_mode {
+                    blurred
+                } else if blurred.alpha >= 0.5 {
+                    Pixel { alpha: 1.0, ..blurred }
+                } else {
+                    original_pixel
+                }
+            }
+        };
+        if alpha_locked {
+            new_pixel.alpha = original_pixel.alpha;
+        }
+        self.layers[self.active_layer_index].canvas[y][x] = new_pixel;
+        self.sync_canvas_pixel(x, y);
+        return;
+    }
+
+    if let PaletteEntry::Color(src_color) = self.current_selection {
+        self.note_recent_color(src_color);
+        let src_color = self.jittered(src_color, x, y);
+        if self.flat_mode {
+            let flat_color = self.flat_color(src_color);
+            let pixel = &mut self.layers[self.active_layer_index].canvas[y][x];
+            *pixel = Pixel { color: flat_color.into(), alpha: if alpha_locked { pixel.alpha } else { 1.0 } };
+            self.sync_canvas_pixel(x, y);
+            return;
+        }
+        let active_canvas = &mut self.layers[self.active_layer_index].canvas;
+        let dest_pixel = active_canvas[y][x];
+        let src_alpha = self.opacity;
+
+        if alpha_locked {
+            let final_color = utils::blend_colors(dest_pixel.color.into(), src_color, src_alpha, self.linear_blending);
+            active_canvas[y][x] = Pixel { color: final_color.into(), alpha: dest_pixel.alpha };
+        } else if dest_pixel.alpha == 0.0 {
+            active_canvas[y][x] = Pixel { color: src_color.into(), alpha: src_alpha };
+        } else {
+            let final_alpha = src_alpha + dest_pixel.alpha * (1.0 - src_alpha);
+            let factor = src_alpha / final_alpha;
+            let final_color = utils::blend_colors(dest_pixel.color.into(), src_color, factor, self.linear_blending);
+            active_canvas[y][x] = Pixel { color: final_color.into(), alpha: final_alpha };
+        }
+        self.sync_canvas_pixel(x, y);
+    }
+}
+
+/// Loads a grayscale PNG as the brush mask; brightness (times the PNG's own alpha)
+/// becomes the per-cell alpha. Large images are shrunk to MAX_BRUSH_SIZE.
+pub fn load_brush(&mut self, path: &str) -> std::result::Result<(), String> {
+    let img = image::open(path).map_err(|e| e.to_string())?;
+    let img = if img.width() > MAX_BRUSH_SIZE || img.height() > MAX_BRUSH_SIZE {
+        img.resize(MAX_BRUSH_SIZE, MAX_BRUSH_SIZE, image::imageops::FilterType::Nearest)
+    } else {
+        img
+    };
+    let luma = img.into_luma_alpha8();
+    let mask: Vec<Vec<f32>> = (0..luma.height()).map(|y| {
+        (0..luma.width()).map(|x| {
+            let [l, a] = luma.get_pixel(x, y).0;
+            (l as f32 / 255.0) * (a as f32 / 255.0)
+        }).collect()
+    }).collect();
+    if mask.iter().flatten().all(|&v| v == 0.0) {
+        return Err("the image is completely black".to_string());
+    }
+    self.brush_mask = Some(mask);
+    // Projects remember the brush, so keep a path that still works from another directory.
+    let absolute = std::fs::canonicalize(path).map(|p| p.to_string_lossy().into_owned());
+    self.brush_path = Some(absolute.unwrap_or_else(|_| path.to_string()));
+    Ok(())
+}
+
+pub fn clear_brush(&mut self) {
+    self.brush_mask = None;
+    self.brush_path = None;
+}
+
+/// The custom brush mask resampled (nearest neighbor) so its longer side is
+/// `pen_size` cells, keeping the aspect ratio.
+pub fn scaled_brush_mask(&self) -> Option<Vec<Vec<f32>>> {
+    let mask = self.brush_mask.as_ref()?;
+    let (mask_w, mask_h) = (mask[0].len(), mask.len());
+    let longest = mask_w.max(mask_h);
+    let pen = self.pen_size as usize;
+    let (out_w, out_h) = ((mask_w * pen / longest).max(1), (mask_h * pen / longest).max(1));
+    Some((0..out_h).map(|y| {
+        (0..out_w).map(|x| mask[y * mask_h / out_h][x * mask_w / out_w]).collect()
+    }).collect())
+}
+
+pub fn apply_brush(&mut self, center_x: u16, center_y: u16) {
+    if self.active_layer_locked() { return; }
+    if self.current_selection == PaletteEntry::Tool(Tool::Clone) {
+        // Like raster editors, the offset locks on the first dab after a source is set
+        // and holds for every later stroke until the source is set again.
+        match (self.clone_source, self.clone_offset) {
+            (None, _) => {
+                self.set_status("Set a clone source first.");
+                return;
+            }
+            (Some(source), None) => self.clone_offset = Some((source.0 as i32 - center_x as i32, source.1 as i32 - center_y as i32)),
+            _ => {}
+        }
+    }
+    // With pixel_block > 1 the brush works in block units and every target covers a whole block.
+    let block = self.pixel_block.max(1) as i32;
+    if let Some(mask) = self.scaled_brush_mask() {
+        let start_x = center_x as i32 / block - mask[0].len() as i32 / 2;
+        let start_y = center_y as i32 / block - mask.len() as i32 / 2;
+        // Tools have no partial strength, so they use the mask as a hard shape.
+        let is_tool = matches!(self.current_selection, PaletteEntry::Tool(_));
+        for (y_offset, row) in mask.iter().enumerate() {
+            for (x_offset, &value) in row.iter().enumerate() {
+                let coverage = if is_tool { if value >= 0.5 { 1.0 } else { 0.0 } } else { value };
+                if coverage <= 0.0 { continue; }
+                let block_x = (start_x + x_offset as i32) * block;
+                let block_y = (start_y + y_offset as i32) * block;
+                for canvas_y_i32 in block_y..block_y + block {
+                    for canvas_x_i32 in block_x..block_x + block {
+                        self.apply_brush_pixel(canvas_x_i32, canvas_y_i32, coverage);
+                    }
+                }
+            }
+        }
+        return;
+    }
+    let radius = self.pen_size as i32 / 2;
+    let start_x = center_x as i32 / block - radius;
+    let start_y = center_y as i32 / block - radius;
+
+    // Soft circular edges only make sense for colors on brushes big enough to have an edge.
+    let soft_edge = self.antialias && self.pen_size > 2 && matches!(self.current_selection, PaletteEntry::Color(_));
+
+    for y_offset in 0..self.pen_size as i32 {
+        for x_offset in 0..self.pen_size as i32 {
+            let mut should_draw = false;
+            let mut coverage = 1.0;
+            match self.pen_shape {
+                PenShape::Square => should_draw = true,
+                PenShape::Circular => {
+                    let dx = x_offset - radius;
+                    let dy = y_offset - radius;
+                    if soft_edge {
+                        let dist = ((dx * dx + dy * dy) as f32).sqrt();
+                        coverage = (radius as f32 + 0.5 - dist).clamp(0.0, 1.0);
+                        should_draw = coverage > 0.0;
+                    } else if (dx * dx + dy * dy) <= (radius * radius) {
+                        should_draw = true;
+                    }
+                }
+            }
+            if !should_draw { continue; }
+
+            let block_x = (start_x + x_offset) * block;
+            let block_y = (start_y + y_offset) * block;
+            for canvas_y_i32 in block_y..block_y + block {
+                for canvas_x_i32 in block_x..block_x + block {
+                    self.apply_brush_pixel(canvas_x_i32, canvas_y_i32, coverage);
+                }
+            }
+        }
+    }
+}
+
+// Applies the brush to a single canvas pixel and its symmetry copies.
+fn apply_brush_pixel(&mut self, canvas_x_i32: i32, canvas_y_i32: i32, coverage: f32) {
+    if canvas_x_i32 < 0 || canvas_x_i32 >= self.canvas_width as i32 ||
+    canvas_y_i32 < 0 || canvas_y_i32 >= self.canvas_height as i32 {
+        return;
+    }
+    let canvas_x = canvas_x_i32 as usize;
+    let canvas_y = canvas_y_i32 as usize;
+
+    self.apply_coverage_with_stroke_tracking(canvas_x, canvas_y, coverage);
+    for (image_x, image_y) in self.symmetry_images(canvas_x, canvas_y) {
+        self.apply_coverage_with_stroke_tracking(image_x, image_y, coverage);
+    }
+}
+
+pub fn erase_brush(&mut self, center_x: u16, center_y: u16) {
+    if self.active_layer_locked() || self.active_layer_alpha_locked() { return; }
+    let block = self.pixel_block.max(1) as i32;
+    let radius = self.pen_size as i32 / 2;
+    let start_x = center_x as i32 / block - radius;
+    let start_y = center_y as i32 / block - radius;
+
+    for y_offset in 0..self.pen_size as i32 {
+        for x_offset in 0..self.pen_size as i32 {
+            let mut should_erase = false;
+            match self.pen_shape {
+                PenShape::Square => should_erase = true,
+                PenShape::Circular => {
+                    let dx = x_offset - radius;
+                    let dy = y_offset - radius;
+                    if (dx * dx + dy * dy) <= (radius * radius) {
+                        should_erase = true;
+                    }
+                }
+            }
+            if !should_erase { continue; }
+
+            let block_x = (start_x + x_offset) * block;
+            let block_y = (start_y + y_offset) * block;
+            for canvas_y_i32 in block_y..block_y + block {
+                for canvas_x_i32 in block_x..block_x + block {
+                    self.erase_brush_pixel(canvas_x_i32, canvas_y_i32);
+                }
+            }
+        }
+    }
+    self.sync_canvas_from_layers();
+}
+
+// Erases a single canvas pixel and its symmetry copies.
+fn erase_brush_pixel(&mut self, canvas_x_i32: i32, canvas_y_i32: i32) {
+    if canvas_x_i32 < 0 || canvas_x_i32 >= self.canvas_width as i32 ||
+    canvas_y_i32 < 0 || canvas_y_i32 >= self.canvas_height as i32 {
+        return;
+    }
+    let canvas_x = canvas_x_i32 as usize;
+    let canvas_y = canvas_y_i32 as usize;
+
+    // The soft eraser fades alpha by the current opacity; flat mode has no partial alpha.
+    let fade = if self.soft_eraser && !self.flat_mode { Some(1.0 - self.opacity) } else { None };
+    let apply_erase = |app: &mut App, x: usize, y: usize| {
+        if app.masked_out(x, y) { return; }
+        if app.protect_stroke && !app.drawn_pixels_in_stroke.insert((x as u16, y as u16)) { return; }
+        let pixel = &mut app.layers[app.active_layer_index].canvas[y][x];
+        match fade {
+            Some(keep) if pixel.alpha * keep >= SOFT_ERASE_EPSILON => pixel.alpha *= keep,
+            _ => *pixel = Pixel::default(),
+        }
+    };
+
+    let coord = (canvas_x as u16, canvas_y as u16);
+    if !self.protect_stroke || !self.drawn_pixels_in_stroke.contains(&coord) {
+        apply_erase(self, canvas_x, canvas_y);
+        for (image_x, image_y) in self.symmetry_images(canvas_x, canvas_y) {
+            apply_erase(self, image_x, image_y);
+        }
+    }
+}
+
+
+
+
+pub fn apply_spray(&mut self) {
+    if self.active_layer_locked() { return; }
+    if self.current_selection == PaletteEntry::Tool(Tool::Clone) {
+        self.set_status("Spray works with colors and the Lighter, Darker and Blur tools.");
+        return;
+    }
+
+    let (center_x, center_y) = (self.cursor_pos.0 as i32, self.cursor_pos.1 as i32);
+    let radius = self.spray_size as i32 / 2;
+    let mut rng = rand::thread_rng();
+
+    for _ in 0..self.spray_speed {
+        let (offset_x, offset_y) = self.spray_offset(&mut rng, radius);
+
+        let target_x = center_x + offset_x;
+        let target_y = center_y + offset_y;
+
+        // NEW: Use intensity to decide whether to draw
+        if rng.gen::<f32>() < self.spray_intensity &&
+           target_x >= 0 && target_x < self.canvas_width as i32 &&
+           target_y >= 0 && target_y < self.canvas_height as i32 {
+            self.apply_effect_at_pixel(target_x as usize, target_y as usize);
+        }
+    }
+}
+
+// A random spray offset within `radius`, inside the circle for a circular pen. With
+// spray_falloff the offsets follow a normal distribution (sigma = radius / 2), so they
+// cluster toward the center.
+fn spray_offset(&self, rng: &mut impl Rng, radius: i32) -> (i32, i32) {
+    if radius == 0 { return (0, 0); }
+    loop {
+        let (offset_x, offset_y) = if self.spray_falloff {
+            // Box-Muller transform.
+            let (u1, u2): (f32, f32) = (rng.gen_range(f32::EPSILON..1.0), rng.gen());
+            let magnitude = (-2.0 * u1.ln()).sqrt() * radius as f32 / 2.0;
+            let angle = std::f32::consts::TAU * u2;
+            ((magnitude * angle.cos()).round() as i32, (magnitude * angle.sin()).round() as i32)
+        } else {
+            (rng.gen_range(-radius..=radius), rng.gen_range(-radius..=radius))
+        };
+        let in_square = offset_x.abs() <= radius && offset_y.abs() <= radius;
+        let in_shape = match self.pen_shape {
+            PenShape::Square => in_square,
+            PenShape::Circular => offset_x * offset_x + offset_y * offset_y <= radius * radius,
+        };
+        if in_shape { return (offset_x, offset_y); }
+    }
+}
+
+
+
+
+    /// How the undo history names a stroke with the current color or tool.
+    pub fn stroke_label(&self) -> String {
+        match self.current_selection {
+            PaletteEntry::Tool(tool) => format!("{:?} stroke", tool),
+            PaletteEntry::Color(_) => "Brush stroke".to_string(),
+        }
+    }
+
+    pub fn use_current_tool(&mut self) {
+        if self.active_layer_locked() { return; }
+        let (x, y) = self.cursor_pos;
+        if self.replace_mode {
+            self.replace_color_at(x as usize, y as usize);
+            return;
+        }
+        self.save_state_for_undo(&self.stroke_label());
+        self.apply_brush(x, y);
+        self.finish_undo_step();
+    }
+
+    /// Recolors every pixel on the active layer (or every layer) whose color is exactly
+    /// `from`, keeping each pixel's alpha. One undo step; returns the number of pixels changed.
+    pub fn replace_color(&mut self, from: Color, to: Color, all_layers: bool) -> usize {
+        let (from, to): (SerializableColor, SerializableColor) = (from.into(), to.into());
+        if !all_layers && self.active_layer_locked() { return 0; }
+        let candidates: Vec<usize> = if all_layers { self.unlocked_layers() } else { vec![self.active_layer_index] };
+        let touched: Vec<usize> = candidates.into_iter()
+            .filter(|&i| self.layers[i].canvas.pixels().iter().any(|p| p.alpha > 0.0 && p.color == from))
+            .collect();
+        if touched.is_empty() || from == to {
+            self.set_status("Replaced 0 pixels.");
+            return 0;
+        }
+
+        self.save_layers_for_undo(&touched, "Replace color");
+        let mut changed = 0;
+        for &i in &touched {
+            for pixel in self.layers[i].canvas.pixels_mut() {
+                if pixel.alpha > 0.0 && pixel.color == from {
+                    pixel.color = to;
+                    changed += 1;
+                }
+            }
+        }
+        self.sync_canvas_from_layers();
+        let scope = if all_layers { format!(" on {} layer{}", touched.len(), if touched.len() == 1 { "" } else { "s" }) } else { String::new() };
+        self.set_status(format!("Replaced {} pixel{}{}.", changed, if changed == 1 { "" } else { "s" }, scope));
+        changed
+    }
+
+    /// The Replace tool: swaps the color of the pixel at (x, y) for the selected color
+    /// across the whole active layer.
+    pub fn replace_color_at(&mut self, x: usize, y: usize) {
+        if x >= self.canvas_width || y >= self.canvas_height { return; }
+        let PaletteEntry::Color(to) = self.current_selection else {
+            self.set_status("Select a color to replace with.");
+            return;
+        };
+        let pixel = self.layers[self.active_layer_index].canvas[y][x];
+        if pixel.alpha == 0.0 {
+            self.set_status("Nothing to replace: the pixel is transparent.");
+            return;
+        }
+        // Holding the draw key repeats this; once swapped there is nothing left to do.
+        if pixel.color == to.into() { return; }
+        self.replace_color(pixel.color.into(), to, false);
+    }
+
+    /// Draws a 1-pixel outline in the selected color around every opaque region of the
+    /// active layer: on the transparent pixels bordering it, or with `inside` on its own edge
+    /// pixels. `diagonal` counts corner neighbours as adjacent too. With `new_layer` the
+    /// outline goes on a fresh layer above instead. Returns the number of pixels written.
+    pub fn outline(&mut self, inside: bool, diagonal: bool, new_layer: bool) -> usize {
+        if !new_layer && self.active_layer_locked() { return 0; }
+        let PaletteEntry::Color(color) = self.current_selection else {
+            self.set_status("Select a color to outline with.");
+            return 0;
+        };
+        let (color, alpha) = if self.flat_mode { (self.flat_color(color), 1.0) } else { (color, self.opacity) };
+
+        let source = &self.layers[self.active_layer_index].canvas;
+        let (width, height) = (self.canvas_width as i32, self.canvas_height as i32);
+        // Off-canvas counts as transparent, so shapes touching the edge get an inner outline there.
+        let opaque = |x: i32, y: i32| x >= 0 && y >= 0 && x < width && y < height && source[y as usize][x as usize].alpha > 0.0;
+        let neighbours: &[(i32, i32)] = if diagonal {
+            &[(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)]
+        } else {
+            &[(0, -1), (-1, 0), (1, 0), (0, 1)]
+        };
+        let mut points = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let is_edge = if inside {
+                    opaque(x, y) && neighbours.iter().any(|(dx, dy)| !opaque(x + dx, y + dy))
+                } else {
+                    !opaque(x, y) && neighbours.iter().any(|(dx, dy)| opaque(x + dx, y + dy))
+                };
+                if is_edge { points.push((x as usize, y as usize)); }
+            }
+        }
+        if !new_layer {
+            let layer = &self.layers[self.active_layer_index];
+            points.retain(|&(x, y)| layer.can_paint(x, y));
+        }
+        if points.is_empty() {
+            self.set_status("Nothing to outline.");
+            return 0;
+        }
+
+        // A new layer's undo step already covers the pixels drawn onto it.
+        if new_layer { self.add_new_layer(); } else { self.save_state_for_undo("Outline"); }
+        let pixel = Pixel { color: color.into(), alpha };
+        let layer = &mut self.layers[self.active_layer_index];
+        for &(x, y) in &points {
+            layer.put_pixel(x, y, pixel);
+        }
+        self.sync_canvas_from_layers();
+        self.set_status(format!("Outlined {} pixel{}.", points.len(), if points.len() == 1 { "" } else { "s" }));
+        points.len()
+    }
+
+    /// Moves every pixel of the active layer (or of every layer) by (dx, dy) as one undo step.
+    /// With `wrap`, pixels pushed off one edge come back on the opposite one; otherwise they
+    /// are dropped and the vacated pixels become transparent.
+    pub fn shift_layers(&mut self, dx: i32, dy: i32, wrap: bool, all_layers: bool) -> bool {
+        if !all_layers && (self.active_layer_locked() || self.active_layer_alpha_locked()) { return false; }
+        let indices: Vec<usize> = if all_layers { self.erasable_layers() } else { vec![self.active_layer_index] };
+        if (dx, dy) == (0, 0) || self.canvas_width == 0 || self.canvas_height == 0 { return true; }
+        self.save_layers_for_undo(&indices, "Shift");
+
+        let (width, height) = (self.canvas_width as i32, self.canvas_height as i32);
+        for &i in &indices {
+            let source = std::mem::replace(&mut self.layers[i].canvas, Canvas::new(self.canvas_width, self.canvas_height));
+            for (y, row) in source.rows().enumerate() {
+                for (x, &pixel) in row.iter().enumerate() {
+                    let (mut new_x, mut new_y) = (x as i32 + dx, y as i32 + dy);
+                    if wrap {
+                        new_x = new_x.rem_euclid(width);
+                        new_y = new_y.rem_euclid(height);
+                    } else if new_x < 0 || new_x >= width || new_y < 0 || new_y >= height {
+                        continue;
+                    }
+                    self.layers[i].canvas[new_y as usize][new_x as usize] = pixel;
+                }
+            }
+        }
+        self.sync_canvas_from_layers();
+        let scope = if all_layers { "all layers" } else { "layer" };
+        self.set_status(format!("Shifted {} by ({}, {}){}.", scope, dx, dy, if wrap { " with wrap" } else { "" }));
+        true
+    }
+
+    /// Stamps `text` in the built-in 3x5 font with its top-left corner at (x, y), in the
+    /// selected color and opacity, as one undo step. Each font pixel covers one pixel_block.
+    /// Glyphs running past the right edge are clipped, or with `wrap` start a new line.
+    pub fn stamp_text(&mut self, text: &str, x: usize, y: usize, wrap: bool) -> bool {
+        if self.active_layer_locked() { return false; }
+        let PaletteEntry::Color(color) = self.current_selection else {
+            self.set_status("Select a color to write with.");
+            return false;
+        };
+        let block = (self.pixel_block as usize).max(1);
+        let (start_x, start_y) = (x / block * block, y / block * block);
+        let (advance, line_height) = ((font::GLYPH_WIDTH + 1) * block, (font::GLYPH_HEIGHT + 1) * block);
+
+        let mut points = Vec::new();
+        let mut skipped = String::new();
+        let (mut pen_x, mut pen_y) = (start_x, start_y);
+        for c in text.chars() {
+            let Some(rows) = font::glyph(c) else {
+                if !skipped.contains(c) { skipped.push(c); }
+                continue;
+            };
+            if wrap && pen_x > start_x && pen_x + font::GLYPH_WIDTH * block > self.canvas_width {
+                pen_x = start_x;
+                pen_y += line_height;
+            }
+            for gy in 0..font::GLYPH_HEIGHT {
+                for gx in (0..font::GLYPH_WIDTH).filter(|&gx| font::glyph_covers(&rows, gx, gy)) {
+                    for py in 0..block {
+                        for px in 0..block {
+                            let (cx, cy) = (pen_x + gx * block + px, pen_y + gy * block + py);
+                            if cx < self.canvas_width && cy < self.canvas_height { points.push((cx, cy)); }
+                        }
+                    }
+                }
+            }
+            pen_x += advance;
+        }
+
+        points.retain(|&(cx, cy)| self.layers[self.active_layer_index].can_paint(cx, cy));
+        if !points.is_empty() {
+            self.save_state_for_undo("Text");
+            for (cx, cy) in points {
+                let dest = self.layers[self.active_layer_index].canvas[cy][cx];
+                let mut pixel = if self.flat_mode {
+                    Pixel { color: self.flat_color(color).into(), alpha: 1.0 }
+                } else {
+                    utils::composite_over(dest, color.into(), self.opacity, self.linear_blending)
+                };
+                if self.snap_to_palette { pixel.color = self.find_closest_palette_color(pixel.color.into()).into(); }
+                self.layers[self.active_layer_index].put_pixel(cx, cy, pixel);
+            }
+            self.sync_canvas_from_layers();
+        }
+        let message = if skipped.is_empty() {
+            "Text stamped.".to_string()
+        } else {
+            format!("Skipped unsupported characters: {}", skipped)
+        };
+        self.set_status(message);
+        true
+    }
+
+    /// The magic wand: selects the pixels a fill from (x, y) would cover, using the fill
+    /// tolerance and fill mode, replacing any previous selection.
+    pub fn magic_wand(&mut self, x: usize, y: usize) {
+        if x >= self.canvas_width || y >= self.canvas_height { return; }
+        let block = (self.pixel_block as usize).max(1);
+        self.selection = self.fill_targets(x / block * block, y / block * block).into_iter()
+            .flat_map(|(bx, by)| (by..by + block).flat_map(move |py| (bx..bx + block).map(move |px| (px, py))))
+            .filter(|&(px, py)| px < self.canvas_width && py < self.canvas_height)
+            .collect();
+        let count = self.selection.len();
+        self.set_status(format!("Selected {} pixel{}.", count, if count == 1 { "" } else { "s" }));
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selection.clear();
+        self.set_status("Selection cleared.");
+    }
+
+    // With selection_mask on, drawing and erasing only reach selected pixels.
+    fn masked_out(&self, x: usize, y: usize) -> bool {
+        self.selection_mask && !self.selection.is_empty() && !self.selection.contains(&(x, y))
+    }
+
+    /// Clears the selected pixels on the active layer.
+    pub fn delete_selection(&mut self) -> bool {
+        if self.active_layer_locked() || self.active_layer_alpha_locked() { return false; }
+        if self.selection.is_empty() {
+            self.set_status("Nothing is selected.");
+            return false;
+        }
+        self.save_state_for_undo("Delete selection");
+        for &(x, y) in &self.selection {
+            self.layers[self.active_layer_index].canvas[y][x] = Pixel::default();
+        }
+        self.sync_canvas_from_layers();
+        self.set_status(format!("Deleted {} selected pixels.", self.selection.len()));
+        true
+    }
+
+    /// Paints the selected pixels on the active layer with the selected color and opacity.
+    pub fn fill_selection(&mut self) -> bool {
+        if self.active_layer_locked() { return false; }
+        let PaletteEntry::Color(color) = self.current_selection else {
+            self.set_status("Select a color to fill the selection with.");
+            return false;
+        };
+        if self.selection.is_empty() {
+            self.set_status("Nothing is selected.");
+            return false;
+        }
+        let pixel = if self.flat_mode {
+            Pixel { color: self.flat_color(color).into(), alpha: 1.0 }
+        } else {
+            Pixel { color: color.into(), alpha: self.opacity }
+        };
+        self.save_state_for_undo("Fill selection");
+        let layer = &mut self.layers[self.active_layer_index];
+        let mut filled = 0;
+        for &(x, y) in &self.selection {
+            if layer.put_pixel(x, y, pixel) { filled += 1; }
+        }
+        self.sync_canvas_from_layers();
+        self.set_status(format!("Filled {} selected pixels.", filled));
+        true
+    }
+
+    /// Moves the selected pixels of the active layer by (dx, dy), leaving transparency
+    /// behind. Pixels moved off the canvas are dropped and the selection moves along.
+    pub fn move_selection(&mut self, dx: i32, dy: i32) -> bool {
+        if self.active_layer_locked() || self.active_layer_alpha_locked() { return false; }
+        if self.selection.is_empty() {
+            self.set_status("Nothing is selected.");
+            return false;
+        }
+        self.save_state_for_undo("Move selection");
+        let canvas = &mut self.layers[self.active_layer_index].canvas;
+        let lifted: Vec<((usize, usize), Pixel)> = self.selection.iter()
+            .map(|&(x, y)| ((x, y), std::mem::take(&mut canvas[y][x])))
+            .collect();
+        let (width, height) = (self.canvas_width as i32, self.canvas_height as i32);
+        let mut moved = std::collections::HashSet::new();
+        for ((x, y), pixel) in lifted {
+            let (new_x, new_y) = (x as i32 + dx, y as i32 + dy);
+            if new_x < 0 || new_y < 0 || new_x >= width || new_y >= height { continue; }
+            canvas[new_y as usize][new_x as usize] = pixel;
+            moved.insert((new_x as usize, new_y as usize));
+        }
+        self.selection = moved;
+        self.sync_canvas_from_layers();
+        self.set_status(format!("Moved selection by ({}, {}).", dx, dy));
+        true
+    }
+
+    /// Crops every layer to the selection's bounding box.
+    pub fn crop_to_selection(&mut self) {
+        match bounding_box(self.selection.iter().copied()) {
+            Some(bounds) => self.crop_to(bounds),
+            None => self.set_status("Nothing is selected. Use crop --content to crop to the artwork."),
+        }
+    }
+
+    /// Crops every layer to the bounding box of the non-transparent pixels on visible layers.
+    pub fn crop_to_content(&mut self) {
+        let opaque = self.layers.iter().filter(|l| l.visible).flat_map(|layer| {
+            layer.canvas.rows().enumerate().flat_map(|(y, row)| {
+                row.iter().enumerate().filter(|(_, pixel)| pixel.alpha > 0.0).map(move |(x, _)| (x, y))
+            })
+        });
+        match bounding_box(opaque) {
+            Some(bounds) => self.crop_to(bounds),
+            None => self.set_status("Nothing to crop to: the visible layers are empty."),
+        }
+    }
+
+    // Crops to the inclusive box as one undo step, keeping the cursor and symmetry
+    // axes over the same artwork.
+    fn crop_to(&mut self, (min_x, min_y, max_x, max_y): (usize, usize, usize, usize)) {
+        let (width, height) = (max_x - min_x + 1, max_y - min_y + 1);
+        let (dx, dy) = (min_x as i32, min_y as i32);
+        let clamp_x = |x: u16| (x as i32 - dx).clamp(0, width as i32 - 1) as u16;
+        let clamp_y = |y: u16| (y as i32 - dy).clamp(0, height as i32 - 1) as u16;
+        let cursor = (clamp_x(self.cursor_pos.0), clamp_y(self.cursor_pos.1));
+        let symmetry = match self.symmetry_mode {
+            SymmetryMode::Off => SymmetryMode::Off,
+            SymmetryMode::Vertical(x) => SymmetryMode::Vertical(clamp_x(x)),
+            SymmetryMode::Horizontal(y) => SymmetryMode::Horizontal(clamp_y(y)),
+            // y = x + c and y = -x + c, rewritten in the cropped coordinates.
+            SymmetryMode::DiagonalForward(c) => SymmetryMode::DiagonalForward(c + dx - dy),
+            SymmetryMode::DiagonalBackward(c) => SymmetryMode::DiagonalBackward(c - dx - dy),
+            SymmetryMode::Quad(x, y) => SymmetryMode::Quad(clamp_x(x), clamp_y(y)),
+            SymmetryMode::Radial(segments, x, y) => SymmetryMode::Radial(segments, clamp_x(x), clamp_y(y)),
+        };
+        self.resize_canvas_anchored(width, height, ResizeAnchor::Offset(-dx, -dy), false);
+        if let Some(step) = self.undo_stack.back_mut() {
+            step.label = format!("Crop to {}x{}", width, height);
+        }
+        self.cursor_pos = cursor;
+        self.symmetry_mode = symmetry;
+        self.set_status(format!("Cropped to {}x{}", width, height));
+    }
+
+    pub fn erase_at_cursor(&mut self) {
+        if self.active_layer_locked() || self.active_layer_alpha_locked() { return; }
+        self.save_state_for_undo("Erase");
+        let (x, y) = self.cursor_pos;
+        self.erase_brush(x, y);
+        self.finish_undo_step();
+    }
+
+pub fn fill_from_point(&mut self, start_x: usize, start_y: usize, fill_color: Color, fill_alpha: f32) {
+    self.fill_from_points(&[(start_x, start_y)], fill_color, fill_alpha);
+}
+
+// Fills from every start point as one undo step and returns how many distinct regions changed.
+// Regions are all matched against the canvas as it was before any of them were filled.
+fn fill_from_points(&mut self, starts: &[(usize, usize)], fill_color: Color, fill_alpha: f32) -> usize {
+    if self.active_layer_locked() { return 0; }
+    let block = (self.pixel_block as usize).max(1);
+    let serializable_fill_color: SerializableColor = fill_color.into();
+    let mut targets = std::collections::HashSet::new();
+    let mut regions = 0;
+    for &(start_x, start_y) in starts {
+        if start_x >= self.canvas_width || start_y >= self.canvas_height { continue; }
+        let (start_x, start_y) = (start_x / block * block, start_y / block * block);
+        if targets.contains(&(start_x, start_y)) { continue; }
+        let target_pixel = self.layers[self.active_layer_index].canvas[start_y][start_x];
+        if self.fill_tolerance == 0 && target_pixel.color == serializable_fill_color && (target_pixel.alpha == fill_alpha || (self.flat_mode && target_pixel.alpha > 0.0)) {
+            continue;
+        }
+        targets.extend(self.fill_targets(start_x, start_y));
+        regions += 1;
+    }
+    if regions == 0 { return 0; }
+
+    self.save_state_for_undo("Fill");
+    let fill = Pixel { color: serializable_fill_color, alpha: fill_alpha };
+    let layer = &mut self.layers[self.active_layer_index];
+    for (x, y) in targets {
+        for by in y..y + block {
+            for bx in x..x + block {
+                layer.put_pixel(bx, by, fill);
+            }
+        }
+    }
+    self.sync_canvas_from_layers();
+    self.finish_undo_step();
+    regions
+}
+
+// Top-left pixels of every block a fill starting at the given block origin would recolor.
+fn fill_targets(&self, start_x: usize, start_y: usize) -> Vec<(usize, usize)> {
+    // With pixel_block > 1 the fill walks whole blocks, each represented by its top-left pixel.
+    let block = (self.pixel_block as usize).max(1);
+    let canvas = &self.layers[self.active_layer_index].canvas;
+    let target_pixel = canvas[start_y][start_x];
+    let flat_mode = self.flat_mode;
+    let tolerance = self.fill_tolerance as f32;
+    // Fully transparent pixels are one group whatever color they hold. Otherwise the RGB
+    // distance, and the alpha difference on the same 0-255 scale, must be within tolerance.
+    // Flat mode ignores alpha differences.
+    let matches_target = |pixel: &Pixel| {
+        if (pixel.alpha == 0.0) != (target_pixel.alpha == 0.0) { return false; }
+        if pixel.alpha == 0.0 { return true; }
+        let alpha_matches = flat_mode || (pixel.alpha - target_pixel.alpha).abs() * 255.0 <= tolerance;
+        alpha_matches && utils::color_distance(pixel.color.into(), target_pixel.color.into()) <= tolerance
+    };
+
+    let (width, height) = (self.canvas_width, self.canvas_height);
+    let mut targets = Vec::new();
+    match self.fill_mode {
+        FillMode::Global => {
+            for y in (0..height).step_by(block) {
+                for x in (0..width).step_by(block) {
+                    if matches_target(&canvas[y][x]) { targets.push((x, y)); }
+                }
+            }
+        }
+        FillMode::Contiguous => {
+            // Scanline fill over block cells: widen each seed to its whole run on the row,
+            // then seed one cell per unvisited run directly above and below it.
+            let (columns, rows) = (width.div_ceil(block), height.div_ceil(block));
+            let fillable = |cx: usize, cy: usize| matches_target(&canvas[cy * block][cx * block]);
+            let mut visited = vec![vec![false; columns]; rows];
+            let mut seeds = vec![(start_x / block, start_y / block)];
+            while let Some((cx, cy)) = seeds.pop() {
+                if visited[cy][cx] || !fillable(cx, cy) { continue; }
+                let mut left = cx;
+                while left > 0 && !visited[cy][left - 1] && fillable(left - 1, cy) { left -= 1; }
+                let mut right = cx;
+                while right + 1 < columns && !visited[cy][right + 1] && fillable(right + 1, cy) { right += 1; }
+                visited[cy][left..=right].fill(true);
+                targets.extend((left..=right).map(|x| (x * block, cy * block)));
+                for ny in [cy.checked_sub(1), Some(cy + 1).filter(|&ny| ny < rows)].into_iter().flatten() {
+                    let mut in_run = false;
+                    for (x, &done) in visited[ny].iter().enumerate().take(right + 1).skip(left) {
+                        let open = !done && fillable(x, ny);
+                        if open && !in_run { seeds.push((x, ny)); }
+                        in_run = open;
+                    }
+                }
+            }
+        }
+    }
+    targets
+}
+
+/// Where the active symmetry mode repeats a canvas pixel: its mirror, or its rotated
+/// copies in Radial mode. Copies that land off the canvas are left out.
+pub fn symmetry_images(&self, x: usize, y: usize) -> Vec<(usize, usize)> {
+    let (x, y) = (x as i32, y as i32);
+    // On even-sized canvases the line lies between pixels, so mirrors shift by one.
+    let mirror_x = |line_x: u16| 2 * line_x as i32 - x - self.canvas_width.is_multiple_of(2) as i32;
+    let mirror_y = |line_y: u16| 2 * line_y as i32 - y - self.canvas_height.is_multiple_of(2) as i32;
+    let images = match self.symmetry_mode {
+        SymmetryMode::Off => Vec::new(),
+        SymmetryMode::Vertical(line_x) => vec![(mirror_x(line_x), y)],
+        SymmetryMode::Horizontal(line_y) => vec![(x, mirror_y(line_y))],
+        SymmetryMode::Quad(line_x, line_y) => vec![(mirror_x(line_x), y), (x, mirror_y(line_y)), (mirror_x(line_x), mirror_y(line_y))],
+        SymmetryMode::DiagonalForward(c) => vec![(y - c, x + c)],
+        SymmetryMode::DiagonalBackward(c) => vec![(c - y, c - x)],
+        SymmetryMode::Radial(segments, center_x, center_y) => {
+            // Like the mirror lines, the center sits on a pixel boundary on even-sized
+            // canvases and in the middle of a pixel on odd-sized ones.
+            let center_x = center_x as f32 + if self.canvas_width.is_multiple_of(2) { 0.0 } else { 0.5 };
+            let center_y = center_y as f32 + if self.canvas_height.is_multiple_of(2) { 0.0 } else { 0.5 };
+            let (dx, dy) = (x as f32 + 0.5 - center_x, y as f32 + 0.5 - center_y);
+            (1..segments).map(|k| {
+                let (sin, cos) = (std::f32::consts::TAU * k as f32 / segments as f32).sin_cos();
+                let rotated_x = center_x + dx * cos - dy * sin;
+                let rotated_y = center_y + dx * sin + dy * cos;
+                ((rotated_x - 0.5).round() as i32, (rotated_y - 0.5).round() as i32)
+            }).collect()
+        }
+    };
+    images.into_iter()
+        .filter(|&(ix, iy)| ix >= 0 && iy >= 0 && ix < self.canvas_width as i32 && iy < self.canvas_height as i32)
+        .map(|(ix, iy)| (ix as usize, iy as usize))
+        .collect()
+}
+
+/// Sets the number of Radial symmetry copies, updating the active mode too.
+pub fn set_radial_segments(&mut self, segments: u8) {
+    self.radial_segments = segments.clamp(2, 16);
+    if let SymmetryMode::Radial(_, center_x, center_y) = self.symmetry_mode {
+        self.symmetry_mode = SymmetryMode::Radial(self.radial_segments, center_x, center_y);
+    }
+}
+
+    pub fn fill_area(&mut self) {
+        let fill_color_entry = if let PaletteEntry::Color(c) = self.current_selection {
+            c
+        } else {
+            self.set_status("Select a color to fill.");
+            return;
+        };
+        let (start_x, start_y) = (self.cursor_pos.0 as usize, self.cursor_pos.1 as usize);
+        let mut starts = vec![(start_x, start_y)];
+        starts.extend(self.symmetry_images(start_x, start_y));
+        let regions = if self.flat_mode {
+            self.fill_from_points(&starts, self.flat_color(fill_color_entry), 1.0)
+        } else {
+            self.fill_from_points(&starts, fill_color_entry, self.opacity)
+        };
+        if regions > 0 && self.symmetry_mode != SymmetryMode::Off {
+            let noun = if regions == 1 { "region" } else { "regions" };
+            self.set_status(format!("Filled {} {} (symmetry)", regions, noun));
+        }
+    }
+
+    /// Records the active layer as an undo step; `label` names it in the undo history.
+    pub fn save_state_for_undo(&mut self, label: &str) {
+        self.save_layers_for_undo(&[self.active_layer_index], label);
+    }
+
+    /// Records the given layers as a single undo step.
+    pub fn save_layers_for_undo(&mut self, indices: &[usize], label: &str) {
+        let canvases = indices.iter().map(|&i| (i, self.layers[i].canvas.clone())).collect();
+        self.push_undo_entry(UndoEntry::Pixels(canvases), label);
+    }
+
+    /// Records the whole layer stack before a change to its structure.
+    pub fn save_layer_stack_for_undo(&mut self, label: &str) {
+        self.push_undo_entry(UndoEntry::Layers { layers: self.layers.clone(), active_layer_index: self.active_layer_index }, label);
+    }
+
+    fn push_undo_entry(&mut self, entry: UndoEntry, label: &str) {
+        self.compact_last_undo_entry();
+        self.undo_stack.push_back(UndoStep { entry, label: label.to_string(), time: chrono::Local::now() });
+        self.history_position = self.history_position.wrapping_add(1);
+        self.trim_undo_history();
+        self.redo_stack.clear();
+        self.dirty = true;
+    }
+
+    pub fn set_undo_limit(&mut self, limit: u16) {
+        self.undo_limit = limit.clamp(10, 1000);
+        self.trim_undo_history();
+    }
+
+    pub fn set_undo_memory_mb(&mut self, megabytes: u16) {
+        self.undo_memory_mb = megabytes;
+        self.trim_undo_history();
+    }
+
+    // Drops the oldest undo steps beyond undo_limit or the memory cap. The newest
+    // step always stays, however large.
+    fn trim_undo_history(&mut self) {
+        let budget = self.undo_memory_mb as usize * 1024 * 1024;
+        let mut total: usize = self.undo_stack.iter().map(|step| step.entry.approximate_size()).sum();
+        while self.undo_stack.len() > 1 && (self.undo_stack.len() > self.undo_limit as usize || (budget > 0 && total > budget)) {
+            if let Some(oldest) = self.undo_stack.pop_front() {
+                total -= oldest.entry.approximate_size();
+            }
+            self.undo_history_trimmed = true;
+        }
+    }
+
+    /// Ends the newest undo step once the stroke, shape or fill that recorded it is done.
+    /// A mouse drag or spray that is still painting into it is left alone.
+    pub fn finish_undo_step(&mut self) {
+        if self.is_mouse_dragging || self.is_spraying { return; }
+        self.compact_last_undo_entry();
+    }
+
+    // The newest undo step holds full layer copies while it is being drawn. Once it is
+    // finished, it only needs the pixels that actually changed, unless most of them did
+    // (clear, resize) and the copy is no larger than the list would be.
+    fn compact_last_undo_entry(&mut self) {
+        let Some(UndoStep { entry: UndoEntry::Pixels(canvases), .. }) = self.undo_stack.back() else { return };
+        let mut diffs = Vec::new();
+        for (i, before) in canvases {
+            let Some(layer) = self.layers.get(*i) else { return };
+            if before.size() != layer.canvas.size() {
+                return;
+            }
+            let changed: PixelChanges = before.rows().zip(layer.canvas.rows()).enumerate()
+                .flat_map(|(y, (old_row, new_row))| old_row.iter().zip(new_row).enumerate()
+                    .filter(|(_, (old, new))| old != new)
+                    .map(move |(x, (old, _))| (x, y, *old)))
+                .collect();
+            if changed.len() * 2 > self.canvas_width * self.canvas_height {
+                return;
+            }
+            diffs.push((*i, changed));
+        }
+        self.undo_stack.back_mut().unwrap().entry = UndoEntry::PixelDiff(diffs);
+    }
+
+    // Puts an entry's state back and returns what it replaced, for the opposite stack.
+    fn swap_undo_entry(&mut self, entry: UndoEntry) -> UndoEntry {
+        self.dirty = true;
+        let swapped = match entry {
+            UndoEntry::Pixels(canvases) => UndoEntry::Pixels(canvases.into_iter()
+                .filter_map(|(i, canvas)| Some((i, std::mem::replace(&mut self.layers.get_mut(i)?.canvas, canvas))))
+                .collect()),
+            UndoEntry::PixelDiff(diffs) => UndoEntry::PixelDiff(diffs.into_iter()
+                .filter_map(|(i, pixels)| {
+                    let canvas = &mut self.layers.get_mut(i)?.canvas;
+                    let swapped = pixels.into_iter()
+                        .filter_map(|(x, y, pixel)| Some((x, y, std::mem::replace(canvas.get_mut(x, y)?, pixel))))
+                        .collect();
+                    Some((i, swapped))
+                })
+                .collect()),
+            UndoEntry::Layers { layers, active_layer_index } => UndoEntry::Layers {
+                layers: std::mem::replace(&mut self.layers, layers),
+                active_layer_index: std::mem::replace(&mut self.active_layer_index, active_layer_index.min(self.layers.len().saturating_sub(1))),
+            },
+            UndoEntry::LayerOpacity { index, opacity } => match self.layers.get_mut(index) {
+                Some(layer) => UndoEntry::LayerOpacity { index, opacity: std::mem::replace(&mut layer.opacity, opacity) },
+                None => UndoEntry::LayerOpacity { index, opacity },
+            },
+            UndoEntry::LayerBlendMode { index, blend_mode } => match self.layers.get_mut(index) {
+                Some(layer) => UndoEntry::LayerBlendMode { index, blend_mode: std::mem::replace(&mut layer.blend_mode, blend_mode) },
+                None => UndoEntry::LayerBlendMode { index, blend_mode },
+            },
+        };
+        // Undoing a resize brings back layers of another size.
+        let restored = match &swapped {
+            UndoEntry::Pixels(canvases) => canvases.first().and_then(|(i, _)| self.layers.get(*i)),
+            UndoEntry::Layers { .. } => self.layers.front(),
+            UndoEntry::PixelDiff(_) | UndoEntry::LayerOpacity { .. } | UndoEntry::LayerBlendMode { .. } => None,
+        };
+        if let Some(layer) = restored {
+            let (width, height) = layer.canvas.size();
+            if (width, height) != (self.canvas_width, self.canvas_height) {
+                self.apply_canvas_size(width, height);
+            }
+        }
+        swapped
+    }
+
+    pub fn undo(&mut self) {
+        self.compact_last_undo_entry();
+        if let Some(step) = self.undo_stack.pop_back() {
+            let entry = self.swap_undo_entry(step.entry);
+            self.redo_stack.push_back(UndoStep { entry, ..step });
+            self.history_position = self.history_position.wrapping_sub(1);
+            self.sync_canvas_from_layers();
+            self.set_status("Undo");
+        } else {
+            let message = if self.undo_history_trimmed { "Nothing to undo (history limit reached)" } else { "Nothing to undo" };
+            self.set_status(message.to_string());
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if let Some(step) = self.redo_stack.pop_back() {
+            let entry = self.swap_undo_entry(step.entry);
+            self.undo_stack.push_back(UndoStep { entry, ..step });
+            self.history_position = self.history_position.wrapping_add(1);
+            self.sync_canvas_from_layers();
+            self.set_status("Redo");
+        } else {
+            self.set_status("Nothing to redo");
+        }
+    }
+
+    /// Opens the undo history panel with the current state selected.
+    pub fn show_undo_history(&mut self) {
+        self.undo_history_selection = self.undo_stack.len();
+        self.mode = AppMode::UndoHistory;
+    }
+
+    /// The steps the undo history panel lists after the starting state, oldest first:
+    /// every undo step, then the redo steps in the order redo would replay them.
+    pub fn undo_history_steps(&self) -> impl Iterator<Item = &UndoStep> {
+        self.undo_stack.iter().chain(self.redo_stack.iter().rev())
+    }
+
+    /// Undoes or redoes until `row` of the undo history panel is current. Row 0 is
+    /// the state before the oldest step still kept.
+    pub fn jump_to_undo_history(&mut self, row: usize) {
+        self.mode = AppMode::Drawing;
+        let row = row.min(self.undo_stack.len() + self.redo_stack.len());
+        let current = self.undo_stack.len();
+        if row == current {
+            return;
+        }
+        for _ in row..current { self.undo(); }
+        for _ in current..row { self.redo(); }
+        let message = match self.undo_stack.back() {
+            Some(step) => format!("Undo history: now at '{}'", step.label),
+            None => "Undo history: now at the start".to_string(),
+        };
+        self.set_status(message);
+    }
+
+    pub fn apply_config(&mut self, config: &Config) {
+        self.pen_size_sensitivity = config.pen_size_sensitivity;
+        self.opacity_sensitivity = config.opacity_sensitivity;
+        self.pen_shape = config.pen_shape;
+        self.highlighter_enabled = config.highlighter_enabled;
+        self.highlighter_value = config.highlighter_value;
+        self.highlighter_mode = config.highlighter_mode;
+        self.shade_factor = config.shade_factor;
+        self.protect_stroke = config.protect_stroke;
+        self.apply_color_interval = chrono::Duration::milliseconds((config.apply_color_sec * 1000.0) as i64);
+        self.minimap_mode = config.minimap_mode;
+        self.mouse_events_enabled = config.mouse_events_enabled;
+        self.hover_cursor = config.hover_cursor;
+        self.color_mode = config.color_mode;
+        self.default_palette_name = config.default_palette_name.clone();
+        self.canvas_scroll_action = config.canvas_scroll_action;
+        self.spray_size = config.spray_size;
+        self.spray_speed = config.spray_speed;
+        self.spray_intensity = config.spray_intensity;
+        self.spray_falloff = config.spray_falloff;
+        self.snap_to_palette = config.snap_to_palette;
+        self.snap_to_palette_mode = config.snap_to_palette_mode;
+        self.protect_color_transitions = config.protect_color_transitions;
+        self.palette_menu_position = config.palette_menu_position;
+        self.onion_skin_enabled = config.onion_skin_enabled;
+        self.onion_skin_opacity = config.onion_skin_opacity;
+        self.onion_skin_frames_before = config.onion_skin_frames_before.min(3);
+        self.onion_skin_frames_after = config.onion_skin_frames_after.min(3);
+        self.playback_fps = config.playback_fps.clamp(1, 30);
+        self.palette_colors = config.palette_colors.clamp(4, 64);
+        self.export_layer_mode = config.export_layer_mode;
+        self.antialias = config.antialias;
+        self.flat_mode = config.flat_mode;
+        self.set_linear_blending(config.linear_blending);
+        self.stabilizer = config.stabilizer;
+        self.fill_shapes = config.fill_shapes;
+        self.perfect_circles = config.perfect_circles;
+        self.dither_pattern = config.dither_pattern;
+        self.clone_sample_merged = config.clone_sample_merged;
+        self.fill_mode = config.fill_mode;
+        self.fill_tolerance = config.fill_tolerance;
+        self.color_jitter = config.color_jitter;
+        self.soft_eraser = config.soft_eraser;
+        self.blur_radius = config.blur_radius.clamp(1, 5);
+        self.pick_from = config.pick_from;
+        self.color_info = config.color_info;
+        self.pick_alpha = config.pick_alpha;
+        self.selection_mask = config.selection_mask;
+        self.radial_segments = config.radial_segments.clamp(2, 16);
+        self.transparency_checker = config.transparency_checker;
+        self.checker_size = config.checker_size.clamp(1, 16);
+        self.checker_light = config.checker_light.into();
+        self.checker_dark = config.checker_dark.into();
+        self.pixel_grid = config.pixel_grid;
+        self.pixel_grid_min_zoom = config.pixel_grid_min_zoom.clamp(2, 32);
+        self.max_zoom = config.max_zoom.clamp(4, 32);
+        self.major_grid_spacing = config.major_grid_spacing;
+        self.restore_session = config.restore_session;
+        self.autosave_rotations = config.autosave_rotations.clamp(1, 20);
+        self.plain_project_save = config.plain_project_save;
+        self.embed_palettes = config.embed_palettes;
+        self.set_undo_limit(config.undo_limit);
+        self.set_undo_memory_mb(config.undo_memory_mb);
+    }
+
+    pub fn set_linear_blending(&mut self, enabled: bool) {
+        self.linear_blending = enabled;
+        self.sync_canvas_from_layers();
+    }
+
+    pub fn save_current_config(&mut self) {
+        let current_config = Config {
+            pen_size_sensitivity: self.pen_size_sensitivity,
+            opacity_sensitivity: self.opacity_sensitivity,
+            pen_shape: self.pen_shape,
+            highlighter_enabled: self.highlighter_enabled,
+            highlighter_value: self.highlighter_value,
+            highlighter_mode: self.highlighter_mode,
+            shade_factor: self.shade_factor,
+            protect_stroke: self.protect_stroke,
+            apply_color_sec: self.apply_color_interval.num_milliseconds() as f32 / 1000.0,
+            minimap_mode: self.minimap_mode,
+            mouse_events_enabled: self.mouse_events_enabled,
+            hover_cursor: self.hover_cursor,
+            color_mode: self.color_mode,
+            default_palette_name: self.default_palette_name.clone(),
+            canvas_scroll_action: self.canvas_scroll_action,
+            spray_size: self.spray_size,
+            spray_speed: self.spray_speed,
+            spray_intensity: self.spray_intensity,
+            spray_falloff: self.spray_falloff,
+            snap_to_palette: self.snap_to_palette,
+            snap_to_palette_mode: self.snap_to_palette_mode,
+            protect_color_transitions: self.protect_color_transitions,
+            palette_menu_position: self.palette_menu_position,
+            onion_skin_enabled: self.onion_skin_enabled,
+            onion_skin_opacity: self.onion_skin_opacity,
+            onion_skin_frames_before: self.onion_skin_frames_before,
+            onion_skin_frames_after: self.onion_skin_frames_after,
+            playback_fps: self.playback_fps,
+            palette_colors: self.palette_colors,
+            export_layer_mode: self.export_layer_mode,
+            antialias: self.antialias,
+            flat_mode: self.flat_mode,
+            linear_blending: self.linear_blending,
+            stabilizer: self.stabilizer,
+            fill_shapes: self.fill_shapes,
+            perfect_circles: self.perfect_circles,
+            dither_pattern: self.dither_pattern,
+            clone_sample_merged: self.clone_sample_merged,
+            fill_mode: self.fill_mode,
+            fill_tolerance: self.fill_tolerance,
+            color_jitter: self.color_jitter,
+            soft_eraser: self.soft_eraser,
+            blur_radius: self.blur_radius,
+            pick_from: self.pick_from,
+            color_info: self.color_info,
+            pick_alpha: self.pick_alpha,
+            selection_mask: self.selection_mask,
+            radial_segments: self.radial_segments,
+            transparency_checker: self.transparency_checker,
+            checker_size: self.checker_size,
+            checker_light: self.checker_light.into(),
+            checker_dark: self.checker_dark.into(),
+            pixel_grid: self.pixel_grid,
+            pixel_grid_min_zoom: self.pixel_grid_min_zoom,
+            max_zoom: self.max_zoom,
+            major_grid_spacing: self.major_grid_spacing,
+            restore_session: self.restore_session,
+            autosave_rotations: self.autosave_rotations,
+            plain_project_save: self.plain_project_save,
+            embed_palettes: self.embed_palettes,
+            undo_limit: self.undo_limit,
+            undo_memory_mb: self.undo_memory_mb,
+        };
+
+            if let Ok(path) = utils::get_config_path() {
+                if let Ok(json_data) = serde_json::to_string_pretty(&current_config) {
+                    if std::fs::write(path, json_data).is_ok() {
+                        self.set_status("Configuration saved.");
+                    } else {
+                        self.set_status("Error: Could not write to config file.");
+                    }
+                }
+            }
+    }
+
+
+/// Picks `color_count` colors from an image with k-means clustering, darkest first.
+pub fn generate_palette_from_image(&mut self, path: &PathBuf, add_to_current: bool, color_count: usize) {
+    let img = match image::open(path) {
+        Ok(i) => i.into_rgb8(),
+        Err(e) => {
+            self.set_status(format!("Error opening image: {}", e));
+            return;
+        }
+    };
+
+    // --- NEW: K-Means Clustering Algorithm ---
+    const MAX_ITERATIONS: usize = 20;
+    // Large images are sampled on a stride so clustering a photo stays quick.
+    const MAX_SAMPLES: usize = 100_000;
+
+    let step = (img.width() as usize * img.height() as usize / MAX_SAMPLES).max(1);
+    let mut color_counts = std::collections::HashMap::new();
+    for pixel in img.pixels().step_by(step) {
+        *color_counts.entry(pixel.0).or_insert(0) += 1;
+    }
+    let unique_colors: Vec<([u8; 3], u32)> = color_counts.into_iter().map(|(c, count)| (c, count as u32)).collect();
+
+    if unique_colors.is_empty() {
+        self.set_status("Image contains no colors.");
+        return;
+    }
+    // An image with fewer colors than asked for gives just those.
+    let target_colors = color_count.clamp(1, unique_colors.len());
+
+    // K-Means++ Initialization: Intelligently select initial palette colors that are far apart.
+    let mut palette: Vec<[f32; 3]> = Vec::with_capacity(target_colors);
+    let first_color = unique_colors[rand::thread_rng().gen_range(0..unique_colors.len())].0;
+    palette.push([first_color[0] as f32, first_color[1] as f32, first_color[2] as f32]);
+
+    while palette.len() < target_colors {
+        let mut max_dist = 0.0;
+        let mut best_next_color = [0.0, 0.0, 0.0];
+        for &(color, _) in &unique_colors {
+            let color_f = [color[0] as f32, color[1] as f32, color[2] as f32];
+            let dist_to_closest_center = palette.iter().map(|p| {
+                (p[0] - color_f[0]).powi(2) + (p[1] - color_f[1]).powi(2) + (p[2] - color_f[2]).powi(2)
+            }).fold(f32::INFINITY, f32::min);
+
+            if dist_to_closest_center > max_dist {
+                max_dist = dist_to_closest_center;
+                best_next_color = color_f;
+            }
+        }
+        palette.push(best_next_color);
+    }
+    
+    // --- Iterative Refinement ---
+    for _ in 0..MAX_ITERATIONS {
+        let mut clusters = vec![(vec![], 0u32); target_colors];
+        
+        for &(color, count) in &unique_colors {
+            let color_f = [color[0] as f32, color[1] as f32, color[2] as f32];
+            let closest_palette_index = palette.iter().enumerate().min_by(|(_, a), (_, b)| {
+                let dist_a = (a[0] - color_f[0]).powi(2) + (a[1] - color_f[1]).powi(2) + (a[2] - color_f[2]).powi(2);
+                let dist_b = (b[0] - color_f[0]).powi(2) + (b[1] - color_f[1]).powi(2) + (b[2] - color_f[2]).powi(2);
+                dist_a.partial_cmp(&dist_b).unwrap()
+            }).map(|(i, _)| i).unwrap_or(0);
+
+            clusters[closest_palette_index].0.push((color, count));
+        }
+
+        for i in 0..target_colors {
+            if !clusters[i].0.is_empty() {
+                let mut r_sum = 0.0;
+                let mut g_sum = 0.0;
+                let mut b_sum = 0.0;
+                let mut total_weight = 0.0;
+                for &(c, weight) in &clusters[i].0 {
+                    r_sum += c[0] as f32 * weight as f32;
+                    g_sum += c[1] as f32 * weight as f32;
+                    b_sum += c[2] as f32 * weight as f32;
+                    total_weight += weight as f32;
+                }
+                if total_weight > 0.0 {
+                    palette[i] = [r_sum / total_weight, g_sum / total_weight, b_sum / total_weight];
+                }
+            }
+        }
+    }
+
+    let mut colors: Vec<Color> = palette.into_iter().map(|c| Color::Rgb(c[0] as u8, c[1] as u8, c[2] as u8)).collect();
+    colors.sort_by(|a, b| luminance(*a).total_cmp(&luminance(*b)));
+    let new_palette: Vec<PaletteEntry> = colors.into_iter().map(PaletteEntry::Color).collect();
+
+    self.last_generated_palette = Some(new_palette.clone());
+    self.last_image_palette_source = path.file_stem().and_then(|s| s.to_str()).map(String::from);
+
+    if add_to_current {
+        self.add_palette_entries_uniquely(&new_palette);
+    } else {
+        self.color_palette = new_palette;
+        self.dirty = true;
+        self.palette_index = 0;
+        self.palette_scroll_state = 0;
+        self.set_status(format!("Palette of {} colors generated from image.", self.color_palette.len()));
+    }
+}
+    pub fn save_last_generated_palette(&mut self, desired_name: Option<String>) {
+        let Some(palette_entries) = self.last_generated_palette.as_ref() else {
+            self.set_status("No image palette has been generated yet.");
+            return;
+        };
+
+        let palette_name = desired_name.unwrap_or_else(|| {
+            self.last_image_palette_source.as_ref().map_or_else(
+                || "image_palette".to_string(),
+                |name| format!("{}_palette", name)
+            )
+        });
+        
+        let palettes_dir = match utils::get_or_create_app_dir() {
+            Ok(dir) => dir.join("palettes"),
+            Err(_) => { self.set_status("Could not access palettes directory."); return; }
+        };
+
+        let file_path = palettes_dir.join(format!("{}.consolet", palette_name));
+        let serializable_colors: Vec<SerializableColor> = palette_entries.iter().filter_map(|e| match e {
+            PaletteEntry::Color(c) => Some((*c).into()),
+            _ => None,
+        }).collect();
+
+        let palette_file = PaletteFile(serializable_colors);
+        if let Ok(json_data) = serde_json::to_string_pretty(&palette_file) {
+            if std::fs::write(&file_path, json_data).is_ok() {
+                self.loaded_palettes.insert(palette_name.clone(), palette_entries.clone());
+                self.set_status(format!("Palette saved as '{}.consolet'", palette_name));
+            } else {
+                self.set_status("Error writing palette file.");
+            }
+        }
+    }
+
+
+    pub fn save_current_palette(&mut self, palette_name: String) {
+        if palette_name.is_empty() {
+            self.set_status("Invalid palette name.");
+            return;
+        }
+
+        let palettes_dir = match utils::get_or_create_app_dir() {
+            Ok(dir) => dir.join("palettes"),
+            Err(_) => { self.set_status("Could not access palettes directory."); return; }
+        };
+
+        let file_path = palettes_dir.join(format!("{}.consolet", palette_name));
+        
+        // Extract only the Color entries
+        let serializable_colors: Vec<SerializableColor> = self.color_palette.iter().filter_map(|e| match e {
+            PaletteEntry::Color(c) => Some((*c).into()),
+            _ => None,
+        }).collect();
+
+        let palette_file = PaletteFile(serializable_colors);
+        if let Ok(json_data) = serde_json::to_string_pretty(&palette_file) {
+            if std::fs::write(&file_path, json_data).is_ok() {
+                // Also update the in-memory loaded palettes
+                self.loaded_palettes.insert(palette_name.clone(), self.color_palette.clone());
+                self.set_status(format!("Palette saved as '{}.consolet'", palette_name));
+            } else {
+                self.set_status("Error writing palette file.");
+            }
+        }
+    }
+
+
+
+
+    /// Writes the current palette's colors to `path` as a GIMP or `.hex` palette, for
+    /// other editors. Unlike savepalette: this does not add it to the loaded palettes.
+    pub fn export_palette(&mut self, path: &std::path::Path, format: PaletteFormat) {
+        let colors: Vec<Color> = self.color_palette.iter().filter_map(|e| match e {
+            PaletteEntry::Color(c) => Some(*c),
+            _ => None,
+        }).collect();
+        let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or("consolet");
+        let text = match format {
+            PaletteFormat::Gpl => palette::to_gpl(name, &colors),
+            PaletteFormat::Hex => palette::to_hex_palette(&colors),
+            PaletteFormat::Consolet => serde_json::to_string_pretty(&PaletteFile(colors.iter().map(|&c| c.into()).collect())).unwrap_or_default(),
+        };
+        self.set_status(match std::fs::write(path, text) {
+            Ok(()) => format!("Exported {} colors to {}.", colors.len(), path.display()),
+            Err(e) => format!("Error writing palette file: {}", e),
+        });
+    }
+
+    // Colors from the current palette that appear on some layer but not in `new_palette`.
+    pub fn used_colors_missing_from(&self, new_palette: &[PaletteEntry]) -> Vec<Color> {
+        let used: std::collections::HashSet<(u8, u8, u8)> = self.layers.iter()
+            .flat_map(|layer| layer.canvas.pixels())
+            .filter(|pixel| pixel.alpha > 0.0)
+            .map(|pixel| utils::to_rgb(pixel.color.into()))
+            .collect();
+        let in_new_palette = |rgb: (u8, u8, u8)| new_palette.iter().any(|entry| matches!(entry, PaletteEntry::Color(c) if utils::to_rgb(*c) == rgb));
+
+        self.color_palette.iter()
+            .filter_map(|entry| if let PaletteEntry::Color(c) = entry { Some(*c) } else { None })
+            .filter(|c| used.contains(&utils::to_rgb(*c)) && !in_new_palette(utils::to_rgb(*c)))
+            .collect()
+    }
+
+    pub fn switch_palette(&mut self, name: &str, new_palette: Vec<PaletteEntry>, keep_colors: bool) {
+        let kept = if keep_colors {
+            self.pending_palette_switch.take().map(|pending| pending.missing_colors).unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        self.color_palette = new_palette;
+        self.active_palette_name = name.to_string();
+        self.dirty = true;
+        self.palette_index = 0;
+        self.palette_scroll_state = 0;
+        if kept.is_empty() {
+            self.set_status(format!("Switched to palette '{}'", name));
+        } else {
+            let kept_entries: Vec<PaletteEntry> = kept.into_iter().map(PaletteEntry::Color).collect();
+            self.add_palette_entries_uniquely(&kept_entries);
+            self.set_status(format!("Switched to palette '{}' and kept {} used colors", name, kept_entries.len()));
+        }
+    }
+
+    // Resolves the ConfirmPaletteSwitch dialog: Yes keeps the used colors, No drops them.
+    pub fn confirm_palette_switch(&mut self, keep_colors: bool) {
+        self.mode = AppMode::Drawing;
+        let Some(pending) = self.pending_palette_switch.as_ref() else { return; };
+        let (name, set_as_default) = (pending.name.clone(), pending.set_as_default);
+        let Some(new_palette) = self.loaded_palettes.get(&name).cloned() else {
+            self.pending_palette_switch = None;
+            return;
+        };
+        self.switch_palette(&name, new_palette, keep_colors);
+        self.pending_palette_switch = None;
+        if set_as_default {
+            self.default_palette_name = name;
+            self.save_current_config();
+        }
+    }
+
+    pub fn add_palette_entries_uniquely(&mut self, entries_to_add: &[PaletteEntry]) {
+        let mut new_colors_added = 0;
+        for new_entry in entries_to_add {
+            // Only consider colors for addition
+            if let PaletteEntry::Color(new_color) = new_entry {
+                let already_exists = self.color_palette.iter().any(|existing_entry| {
+                    if let PaletteEntry::Color(existing_color) = existing_entry {
+                        return existing_color == new_color;
+                    }
+                    false
+                });
+
+                if !already_exists {
+                    self.color_palette.push(*new_entry);
+                    self.dirty = true;
+                    new_colors_added += 1;
+                }
+            }
+        }
+        self.set_status(format!("Added {} new colors to the palette.", new_colors_added));
+    }
+
+
+
+
+
+    // Spacing of the boundary lines drawn over the canvas, if any.
+    pub fn grid_spacing(&self) -> Option<(usize, usize)> {
+        self.tile_size
+    }
+
+    fn tile_origin_at(&self, x: usize, y: usize) -> (usize, usize) {
+        match self.tile_size {
+            Some((tile_w, tile_h)) => (x / tile_w * tile_w, y / tile_h * tile_h),
+            None => (x, y),
+        }
+    }
+
+    pub fn toggle_tile_snap(&mut self) {
+        if self.tile_size.is_none() {
+            self.set_status("Set a tile size first: tilesize <W>x<H>");
+            return;
+        }
+        self.tile_snap = !self.tile_snap;
+        if self.tile_snap {
+            let (x, y) = self.tile_origin_at(self.cursor_pos.0 as usize, self.cursor_pos.1 as usize);
+            self.cursor_pos = (x as u16, y as u16);
+        }
+        self.set_status(format!("Tile snapping: {}", if self.tile_snap { "ON" } else { "OFF" }));
+    }
+
+    pub fn set_tile_size(&mut self, tile_size: Option<(usize, usize)>) {
+        self.tile_size = tile_size;
+        let message = match tile_size {
+            Some((w, h)) => format!("Tile size set to {}x{}", w, h),
+            None => { self.tile_snap = false; "Tile grid off".to_string() }
+        };
+        self.set_status(message);
+    }
+
+    pub fn copy_tile(&mut self) -> bool {
+        let Some((tile_w, tile_h)) = self.tile_size else {
+            self.set_status("Set a tile size first: tilesize <W>x<H>");
+            return false;
+        };
+        let (origin_x, origin_y) = self.tile_origin_at(self.cursor_pos.0 as usize, self.cursor_pos.1 as usize);
+        let canvas = self.get_active_canvas();
+        let tile = (origin_y..(origin_y + tile_h).min(self.canvas_height))
+            .map(|y| canvas[y][origin_x..(origin_x + tile_w).min(self.canvas_width)].to_vec())
+            .collect();
+        self.tile_clipboard = Some(Canvas::from_rows(tile));
+        self.set_status(format!("Copied tile at ({}, {})", origin_x, origin_y));
+        true
+    }
+
+    // Stamps the copied tile with its top-left corner at (origin_x, origin_y).
+    fn stamp_tile(&mut self, tile: &Canvas, origin_x: usize, origin_y: usize, blend: bool) {
+        let linear = self.linear_blending;
+        let layer = &mut self.layers[self.active_layer_index];
+        for (dy, row) in tile.rows().enumerate() {
+            for (dx, src) in row.iter().enumerate() {
+                let (x, y) = (origin_x + dx, origin_y + dy);
+                let Some(&dest) = layer.canvas.get(x, y) else { continue };
+                let pixel = if blend { utils::composite_over(dest, src.color, src.alpha, linear) } else { *src };
+                layer.put_pixel(x, y, pixel);
+            }
+        }
+    }
+
+    pub fn paste_tile(&mut self, blend: bool) -> bool {
+        if self.active_layer_locked() { return false; }
+        let Some(tile) = self.tile_clipboard.clone() else {
+            self.set_status("No tile copied. Use 'tile copy' first.");
+            return false;
+        };
+        let (origin_x, origin_y) = self.tile_origin_at(self.cursor_pos.0 as usize, self.cursor_pos.1 as usize);
+        self.save_state_for_undo("Paste tile");
+        self.stamp_tile(&tile, origin_x, origin_y, blend);
+        self.sync_canvas_from_layers();
+        self.set_status(format!("Pasted tile at ({}, {})", origin_x, origin_y));
+        true
+    }
+
+    pub fn fill_tiles(&mut self, blend: bool) -> bool {
+        if self.active_layer_locked() { return false; }
+        let Some(tile) = self.tile_clipboard.clone() else {
+            self.set_status("No tile copied. Use 'tile copy' first.");
+            return false;
+        };
+        let (step_x, step_y) = self.tile_size.unwrap_or(tile.size());
+        self.save_state_for_undo("Fill tiles");
+        for origin_y in (0..self.canvas_height).step_by(step_y.max(1)) {
+            for origin_x in (0..self.canvas_width).step_by(step_x.max(1)) {
+                self.stamp_tile(&tile, origin_x, origin_y, blend);
+            }
+        }
+        self.sync_canvas_from_layers();
+        self.set_status("Filled layer with tile.");
+        true
+    }
+
+
+}
@@ -0,0 +1,117 @@
+// canvas.rs
+
+// Pixel storage for layers, the composite, undo snapshots and the tile clipboard.
+
+use crate::project::canvas_encoding;
+use crate::Pixel;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::ops::{Index, IndexMut};
+
+/// A width x height grid of pixels kept row by row in a single Vec. `canvas[y]` is a
+/// row slice, so `canvas[y][x]` reads and writes one pixel.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Canvas {
+    width: usize,
+    height: usize,
+    data: Vec<Pixel>,
+}
+
+impl Canvas {
+    /// A fully transparent canvas.
+    pub fn new(width: usize, height: usize) -> Self {
+        Self::filled(width, height, Pixel::default())
+    }
+
+    pub fn filled(width: usize, height: usize, pixel: Pixel) -> Self {
+        Canvas { width, height, data: vec![pixel; width * height] }
+    }
+
+    /// Builds a canvas as wide as the first row. Shorter rows are padded with
+    /// transparent pixels and longer ones cut.
+    pub fn from_rows(rows: Vec<Vec<Pixel>>) -> Self {
+        let width = rows.first().map_or(0, Vec::len);
+        let height = rows.len();
+        let mut data = Vec::with_capacity(width * height);
+        for mut row in rows {
+            row.resize(width, Pixel::default());
+            data.extend(row);
+        }
+        Canvas { width, height, data }
+    }
+
+    /// Takes `width * height` pixels in row order, or None if the count is wrong.
+    pub fn from_pixels(width: usize, height: usize, data: Vec<Pixel>) -> Option<Self> {
+        (data.len() == width * height).then_some(Canvas { width, height, data })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<&Pixel> {
+        if x < self.width && y < self.height { self.data.get(y * self.width + x) } else { None }
+    }
+
+    pub fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut Pixel> {
+        if x < self.width && y < self.height { self.data.get_mut(y * self.width + x) } else { None }
+    }
+
+    pub fn rows(&self) -> std::slice::Chunks<'_, Pixel> {
+        self.data.chunks(self.width.max(1))
+    }
+
+    pub fn rows_mut(&mut self) -> std::slice::ChunksMut<'_, Pixel> {
+        self.data.chunks_mut(self.width.max(1))
+    }
+
+    /// Every pixel in row order.
+    pub fn pixels(&self) -> &[Pixel] {
+        &self.data
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [Pixel] {
+        &mut self.data
+    }
+
+    /// Copies the rows out as nested vectors, the shape project files store.
+    pub fn to_rows(&self) -> Vec<Vec<Pixel>> {
+        self.rows().map(<[Pixel]>::to_vec).collect()
+    }
+}
+
+impl Index<usize> for Canvas {
+    type Output = [Pixel];
+
+    fn index(&self, y: usize) -> &[Pixel] {
+        assert!(y < self.height, "row {} out of range for a canvas {} high", y, self.height);
+        &self.data[y * self.width..(y + 1) * self.width]
+    }
+}
+
+impl IndexMut<usize> for Canvas {
+    fn index_mut(&mut self, y: usize) -> &mut [Pixel] {
+        assert!(y < self.height, "row {} out of range for a canvas {} high", y, self.height);
+        &mut self.data[y * self.width..(y + 1) * self.width]
+    }
+}
+
+// Saved as rows of pixels, or run-length encoded when that is smaller.
+impl Serialize for Canvas {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        canvas_encoding::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Canvas {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        canvas_encoding::deserialize(deserializer)
+    }
+}
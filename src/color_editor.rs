@@ -0,0 +1,208 @@
+// color_editor.rs
+use crate::palette::{hsv_to_rgb, rgb_to_hsv, PaletteEntry};
+use crate::{utils, App, AppMode};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+
+const LABELS: [&str; 6] = ["H", "S", "V", "R", "G", "B"];
+
+// The largest value of each slider, in the units shown next to it.
+const MAXIMUMS: [f32; 6] = [359.0, 100.0, 100.0, 255.0, 255.0, 255.0];
+
+/// Opens the editor on `seed`. Tools and other non-color entries start it on white.
+pub fn open_color_editor(app: &mut App, seed: PaletteEntry) {
+    let (r, g, b) = match seed {
+        PaletteEntry::Color(color) => utils::to_rgb(color),
+        _ => (255, 255, 255),
+    };
+    app.color_editor_rgb = (r, g, b);
+    app.color_editor_hsv = rgb_to_hsv(r, g, b);
+    app.color_editor_row = 0;
+    app.color_editor_target = None;
+    app.color_editor_remap = false;
+    app.mode = AppMode::ColorEditor;
+}
+
+/// Opens the editor on palette entry `index`; confirming writes the color back there.
+/// With `remap`, pixels of the old color on every unlocked layer are recolored too.
+pub fn edit_palette_color(app: &mut App, index: usize, remap: bool) {
+    let Some(&entry @ PaletteEntry::Color(_)) = app.color_palette.get(index) else {
+        app.set_status("Only palette colors can be edited.");
+        return;
+    };
+    open_color_editor(app, entry);
+    app.color_editor_target = Some(index);
+    app.color_editor_remap = remap;
+}
+
+pub fn handle_color_editor_input(app: &mut App, key: KeyEvent) {
+    let row = app.color_editor_row;
+    let step = if key.modifiers.contains(KeyModifiers::SHIFT) { 10.0 } else { 1.0 };
+    match key.code {
+        KeyCode::Esc => app.mode = AppMode::Drawing,
+        KeyCode::Up => app.color_editor_row = (row + LABELS.len() - 1) % LABELS.len(),
+        KeyCode::Down | KeyCode::Tab => app.color_editor_row = (row + 1) % LABELS.len(),
+        KeyCode::Left => set_slider_value(app, row, slider_value(app, row) - step),
+        KeyCode::Right => set_slider_value(app, row, slider_value(app, row) + step),
+        KeyCode::Char('a') if app.color_editor_target.is_none() => app.color_editor_add_to_palette = !app.color_editor_add_to_palette,
+        KeyCode::Char('r') if app.color_editor_target.is_some() => app.color_editor_remap = !app.color_editor_remap,
+        KeyCode::Enter => select_edited_color(app),
+        _ => {}
+    }
+}
+
+/// Clicking a slider selects it and jumps to the clicked value; dragging keeps adjusting it.
+pub fn handle_color_editor_mouse(app: &mut App, mouse: MouseEvent) {
+    let Some(area) = app.last_color_editor_area else { return };
+    let inside_rows = mouse.row >= area.y && mouse.row < area.bottom();
+    match mouse.kind {
+        MouseEventKind::Down(MouseButton::Left) if inside_rows && mouse.column >= area.x && mouse.column < area.right() => {
+            app.color_editor_row = (mouse.row - area.y) as usize;
+        }
+        MouseEventKind::Drag(MouseButton::Left) if inside_rows => {}
+        _ => return,
+    }
+    let column = mouse.column.clamp(area.x, area.right().saturating_sub(1)) - area.x;
+    let fraction = column as f32 / area.width.saturating_sub(1).max(1) as f32;
+    let row = app.color_editor_row;
+    set_slider_value(app, row, fraction * MAXIMUMS[row]);
+}
+
+fn slider_value(app: &App, row: usize) -> f32 {
+    let (h, s, v) = app.color_editor_hsv;
+    let (r, g, b) = app.color_editor_rgb;
+    match row {
+        0 => h,
+        1 => s * 100.0,
+        2 => v * 100.0,
+        3 => r as f32,
+        4 => g as f32,
+        _ => b as f32,
+    }
+}
+
+// The color the editor would hold with slider `row` at `value`, as (HSV, RGB).
+fn color_with(app: &App, row: usize, value: f32) -> ((f32, f32, f32), (u8, u8, u8)) {
+    let value = value.round().clamp(0.0, MAXIMUMS[row]);
+    let (mut h, mut s, mut v) = app.color_editor_hsv;
+    let (mut r, mut g, mut b) = app.color_editor_rgb;
+    match row {
+        0 => h = value,
+        1 => s = value / 100.0,
+        2 => v = value / 100.0,
+        3 => r = value as u8,
+        4 => g = value as u8,
+        _ => b = value as u8,
+    }
+    if row < 3 {
+        ((h, s, v), hsv_to_rgb(h, s, v))
+    } else {
+        let (new_h, new_s, new_v) = rgb_to_hsv(r, g, b);
+        // Grays have no hue of their own, so the one being edited is kept.
+        ((if new_s == 0.0 { h } else { new_h }, new_s, new_v), (r, g, b))
+    }
+}
+
+fn set_slider_value(app: &mut App, row: usize, value: f32) {
+    (app.color_editor_hsv, app.color_editor_rgb) = color_with(app, row, value);
+}
+
+fn select_edited_color(app: &mut App) {
+    let (r, g, b) = app.color_editor_rgb;
+    let entry = PaletteEntry::Color(Color::Rgb(r, g, b));
+    let hex = format!("#{:02X}{:02X}{:02X}", r, g, b);
+    let message = if let Some(index) = app.color_editor_target {
+        let PaletteEntry::Color(old) = app.color_palette[index] else { return };
+        app.color_palette[index] = entry;
+        app.palette_index = index;
+        app.dirty = true;
+        if app.color_editor_remap {
+            let changed = app.replace_color(old, Color::Rgb(r, g, b), true);
+            format!("Changed palette color {} to {} and recolored {} pixel{}.", index + 1, hex, changed, if changed == 1 { "" } else { "s" })
+        } else {
+            format!("Changed palette color {} to {}.", index + 1, hex)
+        }
+    } else if !app.color_editor_add_to_palette {
+        format!("Selected {}.", hex)
+    } else if let Some(index) = app.color_palette.iter().position(|&e| e == entry) {
+        app.palette_index = index;
+        format!("{} is already in the palette.", hex)
+    } else {
+        app.color_palette.push(entry);
+        app.palette_index = app.color_palette.len() - 1;
+        app.dirty = true;
+        format!("Added {} to the palette.", hex)
+    };
+    app.current_selection = entry;
+    app.mode = AppMode::Drawing;
+    app.set_status(message);
+}
+
+pub fn draw_color_editor(frame: &mut Frame, app: &mut App) {
+    let screen = frame.size();
+    let (width, height) = (48.min(screen.width), 14.min(screen.height));
+    let area = Rect::new(screen.x + (screen.width - width) / 2, screen.y + (screen.height - height) / 2, width, height);
+    frame.render_widget(Clear, area);
+    let title = match app.color_editor_target {
+        Some(index) => format!(" Edit Color {} ", index + 1),
+        None => " New Color ".to_string(),
+    };
+    let block = Block::default().title(title).borders(Borders::ALL).border_style(Style::default().fg(app.translate_color(Color::Yellow)));
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    // "> H " before each bar and " 359°" after it.
+    let bar_width = inner.width.saturating_sub(9);
+    let bar_area = Rect::new(inner.x + 4, inner.y, bar_width, (LABELS.len() as u16).min(inner.height));
+    app.last_color_editor_area = Some(bar_area);
+
+    let mut lines = Vec::new();
+    for (row, label) in LABELS.iter().enumerate() {
+        let selected = row == app.color_editor_row;
+        let label_style = if selected { Style::default().fg(app.translate_color(Color::Yellow)).add_modifier(Modifier::BOLD) } else { Style::default() };
+        let mut spans = vec![Span::styled(format!("{} {} ", if selected { ">" } else { " " }, label), label_style)];
+
+        // Each cell shows the color the slider would give there.
+        let value = slider_value(app, row);
+        let knob = (value / MAXIMUMS[row] * bar_width.saturating_sub(1) as f32).round() as u16;
+        for cell in 0..bar_width {
+            let (_, (r, g, b)) = color_with(app, row, cell as f32 / bar_width.saturating_sub(1).max(1) as f32 * MAXIMUMS[row]);
+            let style = Style::default().bg(app.translate_color(Color::Rgb(r, g, b)));
+            if cell == knob {
+                let light = r as u16 + g as u16 + b as u16 > 384;
+                spans.push(Span::styled("┃", style.fg(app.translate_color(if light { Color::Black } else { Color::White }))));
+            } else {
+                spans.push(Span::styled(" ", style));
+            }
+        }
+
+        let unit = match row { 0 => "°", 1 | 2 => "%", _ => " " };
+        spans.push(Span::styled(format!(" {:>3}{}", value.round() as u16, unit), label_style));
+        lines.push(Line::from(spans));
+    }
+    lines.push(Line::raw(""));
+
+    let (r, g, b) = app.color_editor_rgb;
+    let swatch = Span::styled("          ", Style::default().bg(app.translate_color(Color::Rgb(r, g, b))));
+    let (checked, option) = match app.color_editor_target {
+        Some(_) => (app.color_editor_remap, "Recolor canvas pixels (r)"),
+        None => (app.color_editor_add_to_palette, "Add to palette (a)"),
+    };
+    let was = match app.color_editor_target.map(|index| app.color_palette[index]) {
+        Some(PaletteEntry::Color(old)) => {
+            let (r, g, b) = utils::to_rgb(old);
+            format!("  Was #{:02X}{:02X}{:02X}", r, g, b)
+        }
+        _ => String::new(),
+    };
+    lines.push(Line::from(vec![swatch.clone(), Span::raw(format!("  Hex #{:02X}{:02X}{:02X}", r, g, b))]));
+    lines.push(Line::from(vec![swatch.clone(), Span::raw(format!("  {} {}", if checked { "[x]" } else { "[ ]" }, option))]));
+    lines.push(Line::from(vec![swatch, Span::raw(was)]));
+    lines.push(Line::raw(""));
+    lines.push(Line::styled("←→ Adjust  ↑↓ Slider  Enter Select  Esc Cancel", Style::default().fg(app.translate_color(Color::DarkGray))));
+    frame.render_widget(Paragraph::new(lines), inner);
+}
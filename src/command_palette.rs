@@ -0,0 +1,180 @@
+// command_palette.rs
+use crate::commands::{Command, CommandType, COMMANDS};
+use crate::keybindings::Action;
+use crate::{controller, execute_command, utils, App, AppMode};
+
+use crossterm::cursor::{SetCursorStyle, Show};
+use crossterm::event::{KeyCode, KeyEvent};
+use crossterm::ExecutableCommand;
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+use std::io::{stdout, Result};
+use strum::IntoEnumIterator;
+
+#[derive(Clone, Copy)]
+pub enum PaletteItem {
+    Command(&'static Command),
+    Action(Action),
+}
+
+impl PaletteItem {
+    fn name(&self) -> String {
+        match self {
+            PaletteItem::Command(cmd) => cmd.name.to_string(),
+            PaletteItem::Action(action) => action.to_string(),
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            PaletteItem::Command(cmd) => cmd.description,
+            PaletteItem::Action(_) => "Keybinding action.",
+        }
+    }
+}
+
+// Held-key and single-step navigation actions would only clutter the list.
+fn is_major_action(action: Action) -> bool {
+    !matches!(
+        action,
+        Action::Draw | Action::Spray | Action::OpenCommandPalette
+            | Action::MoveCursorUp | Action::MoveCursorDown | Action::MoveCursorLeft | Action::MoveCursorRight
+            | Action::PanViewUp | Action::PanViewDown | Action::PanViewLeft | Action::PanViewRight
+            | Action::QuickSelectColorUp | Action::QuickSelectColorDown | Action::QuickSelectColorLeft | Action::QuickSelectColorRight
+            | Action::QuickSelectToolLeft | Action::QuickSelectToolRight | Action::QuickSelectRecent
+            | Action::ShiftLayerUp | Action::ShiftLayerDown | Action::ShiftLayerLeft | Action::ShiftLayerRight
+    )
+}
+
+/// Every command and major action matching `query`, best match first, with the
+/// matched character positions of each name for highlighting.
+pub fn filtered_items(query: &str) -> Vec<(PaletteItem, Vec<usize>)> {
+    let items = COMMANDS.iter().map(PaletteItem::Command)
+        .chain(Action::iter().filter(|a| is_major_action(*a)).map(PaletteItem::Action));
+
+    let mut scored: Vec<(i32, PaletteItem, Vec<usize>)> = items
+        .filter_map(|item| utils::fuzzy_match(query, &item.name()).map(|(score, matched)| (score, item, matched)))
+        .collect();
+    // Stable sort, so equal scores keep the declaration order.
+    scored.sort_by_key(|entry| std::cmp::Reverse(entry.0));
+    scored.into_iter().map(|(_, item, matched)| (item, matched)).collect()
+}
+
+pub fn open_command_palette(app: &mut App) {
+    app.command_palette_query.clear();
+    app.command_palette_index = 0;
+    app.mode = AppMode::CommandPalette;
+}
+
+pub fn handle_command_palette_input(app: &mut App, key: KeyEvent) -> Result<()> {
+    let items = filtered_items(&app.command_palette_query);
+    match key.code {
+        KeyCode::Esc => app.mode = AppMode::Drawing,
+        KeyCode::Up if !items.is_empty() => {
+            app.command_palette_index = if app.command_palette_index == 0 { items.len() - 1 } else { app.command_palette_index - 1 };
+        }
+        KeyCode::Down if !items.is_empty() => {
+            app.command_palette_index = (app.command_palette_index + 1) % items.len();
+        }
+        KeyCode::Backspace => {
+            app.command_palette_query.pop();
+            app.command_palette_index = 0;
+        }
+        KeyCode::Char(c) => {
+            app.command_palette_query.push(c);
+            app.command_palette_index = 0;
+        }
+        KeyCode::Enter => {
+            if let Some((item, _)) = items.get(app.command_palette_index) {
+                run_item(app, *item)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn run_item(app: &mut App, item: PaletteItem) -> Result<()> {
+    app.mode = AppMode::Drawing;
+    match item {
+        PaletteItem::Action(action) => controller::execute_action(app, action)?,
+        PaletteItem::Command(cmd) => {
+            if let CommandType::Action(_) = cmd.command_type {
+                if app.command_history.first().map(String::as_str) != Some(cmd.name) {
+                    app.command_history.insert(0, cmd.name.to_string());
+                }
+                execute_command(app, cmd.name);
+                return Ok(());
+            }
+
+            // Anything that takes arguments goes to the prompt, ready for them.
+            let separator = match cmd.command_type {
+                CommandType::SetterBool(_) | CommandType::SetterU16(..) | CommandType::SetterF32(..) | CommandType::SetterString(_) | CommandType::SetterColor(_) => "=",
+                _ if cmd.name.ends_with(':') => "",
+                _ => " ",
+            };
+            stdout().execute(Show)?.execute(SetCursorStyle::SteadyBlock)?;
+            app.mode = AppMode::Command;
+            app.input_buffer = format!("{}{}", cmd.name, separator);
+            app.command_cursor_pos = app.input_buffer.len();
+            app.suggestion_index = 0;
+            app.suggestion_active = false;
+            app.history_index = 0;
+        }
+    }
+    Ok(())
+}
+
+pub fn draw_command_palette(frame: &mut Frame, app: &mut App) {
+    let area = utils::centered_rect(70, 60, frame.size());
+    frame.render_widget(Clear, area);
+    let block = Block::default().title(" Command Palette (Enter to Run, Esc to Close) ").borders(Borders::ALL);
+    let inner_area = block.inner(area);
+    frame.render_widget(block, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Length(1), Constraint::Min(0)])
+        .split(inner_area);
+
+    let query_line = Line::from(vec![
+        Span::styled("> ", Style::default().fg(Color::Yellow)),
+        Span::raw(app.command_palette_query.clone()),
+    ]);
+    frame.render_widget(Paragraph::new(query_line), chunks[0]);
+    frame.render_widget(Paragraph::new("─".repeat(chunks[1].width as usize)).style(Style::default().fg(Color::DarkGray)), chunks[1]);
+
+    let items = filtered_items(&app.command_palette_query);
+    if items.is_empty() {
+        frame.render_widget(Paragraph::new("No matches.").style(Style::default().fg(Color::DarkGray)), chunks[2]);
+        return;
+    }
+    app.command_palette_index = app.command_palette_index.min(items.len() - 1);
+
+    let visible_rows = chunks[2].height as usize;
+    let scroll = app.command_palette_index.saturating_sub(visible_rows.saturating_sub(1));
+
+    let mut lines = Vec::new();
+    for (i, (item, matched)) in items.iter().enumerate().skip(scroll).take(visible_rows) {
+        let is_selected = i == app.command_palette_index;
+        let base_style = if is_selected { Style::default().bg(Color::Yellow).fg(Color::Black) } else { Style::default() };
+        let match_style = if is_selected { base_style.add_modifier(Modifier::BOLD | Modifier::UNDERLINED) } else { Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD) };
+
+        let name = item.name();
+        let mut spans: Vec<Span> = name.chars().enumerate()
+            .map(|(ci, ch)| Span::styled(ch.to_string(), if matched.contains(&ci) { match_style } else { base_style }))
+            .collect();
+        spans.push(Span::styled(" ".repeat(24usize.saturating_sub(name.chars().count())), base_style));
+
+        let key_str = match item {
+            PaletteItem::Action(action) => app.keybindings.map.get(action).map(utils::format_keybinding).unwrap_or_default(),
+            PaletteItem::Command(_) => String::new(),
+        };
+        spans.push(Span::styled(format!("{:<14}", key_str), if is_selected { base_style } else { Style::default().fg(Color::Cyan) }));
+        spans.push(Span::styled(item.description(), if is_selected { base_style } else { Style::default().fg(Color::DarkGray) }));
+        lines.push(Line::from(spans));
+    }
+    frame.render_widget(Paragraph::new(lines), chunks[2]);
+}
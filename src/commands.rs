@@ -2,7 +2,9 @@
 
 use crate::App; // This allows us to use `App` in our function pointers
 use std::time::Instant;
-use crate::{Pixel, ExportLayerMode}; // Add Pixel and ExportLayerMode here
+use crate::palette::{PaletteEntry, PaletteFormat};
+use crate::{file_browser, utils, AppMode, PaletteSort, MAX_CANVAS_SIZE, PendingPaletteSwitch, ResizeAnchor, SymmetryMode};
+use std::path::{Path, PathBuf};
 
 
 pub enum CommandType {
@@ -11,6 +13,7 @@ pub enum CommandType {
     SetterU16(fn(&mut App, u16), u16, u16), // fn, min, max
     SetterF32(fn(&mut App, f32), f32, f32), // fn, min, max
     SetterString(fn(&mut App, String)),
+    SetterColor(fn(&mut App, ratatui::style::Color)),
     Complex, // For commands like save, load, export that need custom parsing
 }
 
@@ -29,18 +32,31 @@ pub const COMMANDS: &[Command] = &[
     Command { name: "q", description: "Alias for 'quit'.", usage: "q", example: "q", command_type: CommandType::Action(|app| app.quit()) },
     Command { name: "undo", description: "Undo the last action.", usage: "undo", example: "undo", command_type: CommandType::Action(|app| app.undo()) },
     Command { name: "redo", description: "Redo the last undone action.", usage: "redo", example: "redo", command_type: CommandType::Action(|app| app.redo()) },
-    Command { name: "clear", description: "Clears the entire canvas.", usage: "clear", example: "clear", command_type: CommandType::Action(|app| app.clear_canvas()) },
-    Command { name: "resize", description: "Begin resizing the canvas.", usage: "resize", example: "resize", command_type: CommandType::Action(|app| { app.mode = crate::AppMode::ResizingWidth; app.input_buffer.clear(); }) },
+    Command { name: "messages", description: "Lists the last 50 status messages, newest first.", usage: "messages", example: "messages", command_type: CommandType::Action(|app| app.show_message_log()) },
+    Command { name: "stats", description: "Shows canvas size, pixels per layer, colors used and whether they are in the palette, and the saved file size.", usage: "stats", example: "stats", command_type: CommandType::Action(|app| app.show_canvas_stats()) },
+    Command { name: "undo_history", description: "List the undo steps and jump to any of them.", usage: "undo_history", example: "undo_history", command_type: CommandType::Action(|app| app.show_undo_history()) },
+    Command { name: "clear", description: "Clears the active layer, or every layer with --all.", usage: "clear [--all]", example: "clear --all", command_type: CommandType::Complex },
+    Command { name: "resize", description: "Resizes the canvas, keeping the artwork. Without a size, asks for width and height.", usage: "resize [{<W>x<H>|<size>} [--anchor=<topleft|center|x,y>] [--clear]]", example: "resize 64x64 --anchor=center", command_type: CommandType::Action(|app| { app.mode = crate::AppMode::ResizingWidth; app.input_buffer.clear(); }) },
     Command { name: "keybindings:reset", description: "Resets all keybindings to their default values.", usage: "keybindings:reset", example: "keybindings:reset", command_type: CommandType::Action(|app| app.reset_keybindings()) },
 
     Command { name: "edit_script", description: "Opens the command drawing script editor.", usage: "edit_script", example: "edit_script", command_type: CommandType::Action(|app| { crate::script_handler::load_script_for_editing(app); })},
     Command { name: "draw_script", description: "Executes the command drawing script.", usage: "draw_script", example: "draw_script", command_type: CommandType::Action(|app| { crate::script_handler::parse_and_execute_script(app); })},
+    Command { name: "reexport", description: "Repeats the last export with the same settings.", usage: "reexport", example: "reexport", command_type: CommandType::Action(|app| app.repeat_last_export()) },
 
     // Boolean Setters
     Command { name: "minimap", description: "Toggles the minimap.", usage: "minimap={true|false}", example: "minimap=true", command_type: CommandType::SetterBool(|app, val| app.minimap_mode = if val { crate::MinimapMode::On } else { crate::MinimapMode::Off }) },
     Command { name: "highlighter", description: "Toggles the cursor highlighter.", usage: "highlighter={true|false}", example: "highlighter=false", command_type: CommandType::SetterBool(|app, val| app.highlighter_enabled = val) },
     Command { name: "protectStroke", description: "Prevents drawing over the same pixel in one stroke.", usage: "protectStroke={true|false}", example: "protectStroke=false", command_type: CommandType::SetterBool(|app, val| app.protect_stroke = val) },
     Command { name: "mouseEvents", description: "Enables or disables all mouse event handling.", usage: "mouseEvents={true|false}", example: "mouseEvents=false", command_type: CommandType::SetterBool(|app, val| app.mouse_events_enabled = val) },
+    Command { name: "hover_cursor", description: "Moves the canvas cursor to the mouse as it hovers over the canvas, without drawing.", usage: "hover_cursor={true|false}", example: "hover_cursor=false", command_type: CommandType::SetterBool(|app, val| app.hover_cursor = val) },
+    Command { name: "antialias", description: "Writes brush, line and ellipse edges with partial alpha based on coverage.", usage: "antialias={true|false}", example: "antialias=true", command_type: CommandType::SetterBool(|app, val| app.antialias = val) },
+    Command { name: "flat_mode", description: "Always draws at full alpha; opacity dims the color instead.", usage: "flat_mode={true|false}", example: "flat_mode=true", command_type: CommandType::SetterBool(|app, val| app.flat_mode = val) },
+    Command { name: "stabilizer", description: "Smooths mouse strokes; higher values are smoother but lag more (0 = off).", usage: "stabilizer={0-10}", example: "stabilizer=5", command_type: CommandType::SetterU16(|app, val| app.stabilizer = val, 0, 10) },
+    Command { name: "linear_blending", description: "Blends colors in linear light instead of raw sRGB.", usage: "linear_blending={true|false}", example: "linear_blending=true", command_type: CommandType::SetterBool(|app, val| app.set_linear_blending(val)) },
+    Command { name: "fill_shapes", description: "Draws rectangles and ellipses filled instead of as outlines.", usage: "fill_shapes={true|false}", example: "fill_shapes=true", command_type: CommandType::SetterBool(|app, val| app.fill_shapes = val) },
+    Command { name: "soft_eraser", description: "Makes the eraser fade pixels by the current opacity instead of clearing them.", usage: "soft_eraser={true|false}", example: "soft_eraser=true", command_type: CommandType::SetterBool(|app, val| app.soft_eraser = val) },
+    Command { name: "clone_sample_merged", description: "Makes the Clone tool sample the visible image instead of the active layer.", usage: "clone_sample_merged={true|false}", example: "clone_sample_merged=true", command_type: CommandType::SetterBool(|app, val| app.clone_sample_merged = val) },
+    Command { name: "perfect_circles", description: "Constrains the Ellipse tool to circles, as if Shift were held.", usage: "perfect_circles={true|false}", example: "perfect_circles=true", command_type: CommandType::SetterBool(|app, val| app.perfect_circles = val) },
     
     
     // U16 Setters
@@ -48,14 +64,19 @@ pub const COMMANDS: &[Command] = &[
     Command { name: "highlighterMode", description: "Sets highlighter mode (0=Underscore, 1=Blend).", usage: "highlighterMode={0|1}", example: "highlighterMode=1", command_type: CommandType::SetterU16(|app, val| app.highlighter_mode = if val == 0 { crate::HighlighterMode::Underscore } else { crate::HighlighterMode::Blend }, 0, 1) },
     Command { name: "spraySize", description: "Sets the size of the spray tool area.", usage: "spraySize={1-50}", example: "spraySize=10", command_type: CommandType::SetterU16(|app, val| app.spray_size = val, 1, 50) },
     Command { name: "spraySpeed", description: "Sets the density/speed of the spray tool.", usage: "spraySpeed={1-100}", example: "spraySpeed=5", command_type: CommandType::SetterU16(|app, val| app.spray_speed = val, 1, 100) },
+    Command { name: "fillTolerance", description: "Lets fill match colors within this RGB distance of the clicked pixel (0 = exact).", usage: "fillTolerance={0-255}", example: "fillTolerance=32", command_type: CommandType::SetterU16(|app, val| app.fill_tolerance = val as u8, 0, 255) },
+    Command { name: "pixel_block", description: "Draws, erases and fills in aligned NxN blocks (1 = off).", usage: "pixel_block={1-16}", example: "pixel_block=2", command_type: CommandType::SetterU16(|app, val| app.pixel_block = val, 1, 16) },
 
 
 
     // F32 Setters
     Command { name: "opacitySensitivity", description: "Sets opacity change sensitivity.", usage: "opacitySensitivity={0.01-0.5}", example: "opacitySensitivity=0.1", command_type: CommandType::SetterF32(|app, val| app.opacity_sensitivity = val, 0.01, 0.5) },
     Command { name: "highlighterValue", description: "Sets highlighter strength.", usage: "highlighterValue={0.0-1.0}", example: "highlighterValue=0.5", command_type: CommandType::SetterF32(|app, val| app.highlighter_value = val, 0.0, 1.0) },
+    Command { name: "radialSegments", description: "Sets how many rotated copies Radial symmetry draws.", usage: "radialSegments={2-16}", example: "radialSegments=6", command_type: CommandType::SetterU16(|app, val| app.set_radial_segments(val as u8), 2, 16) },
+    Command { name: "blurRadius", description: "Sets how far the Blur tool averages: a (2r+1)x(2r+1) window.", usage: "blurRadius={1-5}", example: "blurRadius=2", command_type: CommandType::SetterU16(|app, val| app.blur_radius = val as u8, 1, 5) },
     Command { name: "pencilDensity", description: "Sets Lighter/Darker tool density.", usage: "pencilDensity={0.01-1.0}", example: "pencilDensity=0.05", command_type: CommandType::SetterF32(|app, val| app.shade_factor = val, 0.01, 1.0) },
     Command { name: "applyColorSec", description: "Sets auto-apply interval for holding Spacebar.", usage: "applyColorSec={0.05-2.0}", example: "applyColorSec=0.1", command_type: CommandType::SetterF32(|app, val| app.apply_color_interval = chrono::Duration::milliseconds((val * 1000.0) as i64), 0.05, 2.0) },
+    Command { name: "sprayFalloff", description: "Makes the spray land more often near the center than at the rim.", usage: "sprayFalloff={true|false}", example: "sprayFalloff=true", command_type: CommandType::SetterBool(|app, val| app.spray_falloff = val) },
     Command { name: "sprayIntensity", description: "Sets the intensity/density of the spray tool.", usage: "sprayIntensity={0.01-1.0}", example: "sprayIntensity=0.5", command_type: CommandType::SetterF32(|app, val| app.spray_intensity = val, 0.01, 1.0) },
     
     
@@ -67,16 +88,70 @@ pub const COMMANDS: &[Command] = &[
         if val == "ChangeOpacity" { app.canvas_scroll_action = crate::CanvasScrollAction::ChangeOpacity; }
         else if val == "ChangePenSize" { app.canvas_scroll_action = crate::CanvasScrollAction::ChangePenSize; }
     }) },
+    Command { name: "color_jitter", description: "Randomly shifts the hue and brightness of each painted pixel by up to this amount.", usage: "color_jitter={0.0-1.0}", example: "color_jitter=0.2", command_type: CommandType::SetterF32(|app, val| app.color_jitter = val, 0.0, 1.0) },
+    Command { name: "pick_from", description: "Sets whether the color picker reads the active layer or the visible image.", usage: "pick_from={active|composite}", example: "pick_from=composite", command_type: CommandType::SetterString(|app, val| match val.to_lowercase().as_str() {
+        "active" | "activelayer" => app.pick_from = crate::PickSource::ActiveLayer,
+        "composite" => app.pick_from = crate::PickSource::Composite,
+        _ => {}
+    }) },
+    Command { name: "color_info", description: "Shows the hex value of the drawing color and of the pixel under the cursor, in the status bar or next to the cursor.", usage: "color_info={off|bar|cursor}", example: "color_info=bar", command_type: CommandType::SetterString(|app, val| if let Some(mode) = crate::ColorInfo::from_name(&val) { app.color_info = mode; }) },
+    Command { name: "pick_alpha", description: "Makes the color picker also set the opacity to the picked pixel's alpha.", usage: "pick_alpha={true|false}", example: "pick_alpha=true", command_type: CommandType::SetterBool(|app, val| app.pick_alpha = val) },
+    Command { name: "selection_mask", description: "Limits drawing and erasing to the magic wand selection while one exists.", usage: "selection_mask={true|false}", example: "selection_mask=true", command_type: CommandType::SetterBool(|app, val| app.selection_mask = val) },
+    Command { name: "transparency_checker", description: "Shows a gray checkerboard behind transparent pixels on the canvas.", usage: "transparency_checker={true|false}", example: "transparency_checker=true", command_type: CommandType::SetterBool(|app, val| app.transparency_checker = val) },
+    Command { name: "checker_size", description: "Sets the transparency checker cell size in canvas pixels.", usage: "checker_size={1-16}", example: "checker_size=4", command_type: CommandType::SetterU16(|app, val| app.checker_size = val, 1, 16) },
+    Command { name: "checker_light", description: "Sets the light transparency checker color.", usage: "checker_light=<color>", example: "checker_light=#CCCCCC", command_type: CommandType::SetterColor(|app, c| app.checker_light = c) },
+    Command { name: "restore_session", description: "Restores the view and tool state saved with a project when loading it.", usage: "restore_session={true|false}", example: "restore_session=false", command_type: CommandType::SetterBool(|app, val| app.restore_session = val) },
+    Command { name: "grid", description: "Draws lines between canvas pixels once zoomed in to gridMinZoom or more.", usage: "grid={true|false}", example: "grid=true", command_type: CommandType::SetterBool(|app, val| app.pixel_grid = val) },
+    Command { name: "gridSpacing", description: "Draws a brighter major grid line every N pixels (0 for none).", usage: "gridSpacing={0-256}", example: "gridSpacing=8", command_type: CommandType::SetterU16(|app, val| app.major_grid_spacing = val, 0, 256) },
+    Command { name: "gridMinZoom", description: "Sets the zoom (as shown in the status bar) from which the pixel grid is shown.", usage: "gridMinZoom={2-32}", example: "gridMinZoom=4", command_type: CommandType::SetterU16(|app, val| app.pixel_grid_min_zoom = val, 2, 32) },
+    Command { name: "maxZoom", description: "Sets the highest zoom (as shown in the status bar).", usage: "maxZoom={4-32}", example: "maxZoom=16", command_type: CommandType::SetterU16(|app, val| { app.max_zoom = val; app.zoom(0); }, 4, 32) },
+    Command { name: "checker_dark", description: "Sets the dark transparency checker color.", usage: "checker_dark=<color>", example: "checker_dark=#444444", command_type: CommandType::SetterColor(|app, c| app.checker_dark = c) },
+    Command { name: "fillMode", description: "Sets whether fill spreads from the clicked pixel or recolors every match on the layer.", usage: "fillMode={contiguous|global}", example: "fillMode=global", command_type: CommandType::SetterString(|app, val| match val.to_lowercase().as_str() {
+        "contiguous" => app.fill_mode = crate::FillMode::Contiguous,
+        "global" => app.fill_mode = crate::FillMode::Global,
+        _ => {}
+    }) },
+    Command { name: "ditherPattern", description: "Sets the Dither tool pattern (light = 25%, checker = 50%, dense = 75%).", usage: "ditherPattern={light|checker|dense}", example: "ditherPattern=light", command_type: CommandType::SetterString(|app, val| if let Some(pattern) = crate::DitherPattern::from_name(&val) { app.dither_pattern = pattern; }) },
     // Complex Commands (handled separately)
-    Command { name: "save", description: "Saves the project.", usage: "save <name.consolet> [-a mins] [-p path] [-f]", example: "save art.consolet -a 5", command_type: CommandType::Complex },
-    Command { name: "load", description: "Loads a project.", usage: "load <name.consolet>", example: "load art.consolet", command_type: CommandType::Complex },
-    Command { name: "export", description: "Exports canvas to PNG.", usage: "export [-o path] [-u scale] [-bg]", example: "export -o image.png -u 10", command_type: CommandType::Complex },
-    Command { name: "import", description: "Imports an asset.", usage: "import palette <path>", example: "import palette my_palette.consolet", command_type: CommandType::Complex },
-    Command { name: "colorpalette", description: "Switches to a loaded palette.", usage: "colorpalette:<name>", example: "colorpalette:default", command_type: CommandType::Complex },
+    Command { name: "save", description: "Saves the project. --plain writes uncompressed, pretty-printed .consolet.json; --embed-palettes stores the named palettes it uses.", usage: "save <name.consolet> [-a mins] [-p path] [-f] [--plain] [--embed-palettes]", example: "save art.consolet -a 5", command_type: CommandType::Complex },
+    Command { name: "load", description: "Loads a project. --force lets its embedded palettes replace local ones.", usage: "load <name.consolet> [--force]", example: "load art.consolet", command_type: CommandType::Complex },
+    Command { name: "recent", description: "Lists recently saved or loaded projects; pick one from the suggestions to load it.", usage: "recent [filter]", example: "recent", command_type: CommandType::Action(|app| app.show_recent_projects()) },
+    Command { name: "restore_autosave", description: "Loads the newest autosave of the open project.", usage: "restore_autosave", example: "restore_autosave", command_type: CommandType::Action(|app| app.restore_autosave()) },
+    Command { name: "undo_limit", description: "Sets how many undo steps are kept; older ones are dropped right away.", usage: "undo_limit=<10-1000>", example: "undo_limit=300", command_type: CommandType::SetterU16(|app, val| app.set_undo_limit(val), 10, 1000) },
+    Command { name: "undo_memory_mb", description: "Caps the memory the undo history may use, dropping the oldest steps first (0 for no cap).", usage: "undo_memory_mb=<0-8192>", example: "undo_memory_mb=256", command_type: CommandType::SetterU16(|app, val| app.set_undo_memory_mb(val), 0, 8192) },
+    Command { name: "plain_project_save", description: "Makes save write pretty-printed .consolet.json unless .consolet is given.", usage: "plain_project_save={true|false}", example: "plain_project_save=true", command_type: CommandType::SetterBool(|app, val| app.plain_project_save = val) },
+    Command { name: "embed_palettes", description: "Makes save store the named palettes the project uses inside it.", usage: "embed_palettes={true|false}", example: "embed_palettes=true", command_type: CommandType::SetterBool(|app, val| app.embed_palettes = val) },
+    Command { name: "autosave_rotations", description: "Sets how many autosave files are kept next to the project.", usage: "autosave_rotations=<1-20>", example: "autosave_rotations=5", command_type: CommandType::SetterU16(|app, val| app.autosave_rotations = val, 1, 20) },
+    Command { name: "export", description: "Exports canvas to PNG.", usage: "export [-o path] [-u scale] [-bg] [-bgcolor <#RRGGBB|white|black>] [--selection] [--autocrop]", example: "export -o image.png -u 10 -bgcolor white", command_type: CommandType::Complex },
+    Command { name: "layer", description: "Lists layers, sets a layer's visibility/opacity/blend mode, or exports/imports the active layer.", usage: "layer list | layer <name|#> {visible=|opacity=|blend=|locked=|alpha_locked=|active} | layer {export|import} <path.png>", example: "layer 2 opacity=0.5", command_type: CommandType::Complex },
+    Command { name: "merge_visible", description: "Merges all visible layers into one, leaving hidden layers untouched.", usage: "merge_visible [--force]", example: "merge_visible", command_type: CommandType::Complex },
+    Command { name: "flatten", description: "Flattens the visible image into a single layer.", usage: "flatten [--force]", example: "flatten", command_type: CommandType::Complex },
+    Command { name: "brush", description: "Loads a grayscale PNG as a soft brush, or returns to the built-in shapes.", usage: "brush load <path.png> | brush clear", example: "brush load ~/brushes/soft.png", command_type: CommandType::Complex },
+    Command { name: "replace_color", description: "Recolors every pixel of one color on the active layer (or all layers), keeping alpha.", usage: "replace_color=#RRGGBB,#RRGGBB [--all-layers]", example: "replace_color=#FF0000,#00AA00", command_type: CommandType::Complex },
+    Command { name: "shift_layer", description: "Moves the active layer's pixels, wrapping them around the edges or clipping them.", usage: "shift_layer=<dx>,<dy> [--wrap|--clip] [--all]", example: "shift_layer=8,0 --wrap", command_type: CommandType::Complex },
+    Command { name: "text", description: "Writes text in a built-in 3x5 pixel font at the cursor or at the given position.", usage: "text=<TEXT> | text \"<TEXT>\" [x,y] [--wrap]", example: "text \"HELLO\" 5,5", command_type: CommandType::Complex },
+    Command { name: "selection", description: "Clears, deletes, fills or moves the magic wand selection on the active layer.", usage: "selection {clear|delete|fill|move <dx>,<dy>}", example: "selection move 4,0", command_type: CommandType::Complex },
+    Command { name: "symmetry", description: "Sets the symmetry mode and its exact axis: X for vertical, Y for horizontal, c for the diagonals.", usage: "symmetry=<vertical|horizontal|diag_fwd|diag_bwd|off>:<coord>", example: "symmetry=vertical:16", command_type: CommandType::Complex },
+    Command { name: "rotate_canvas", description: "Rotates every layer clockwise; 90 and 270 swap the width and height.", usage: "rotate_canvas={90|180|270}", example: "rotate_canvas=90", command_type: CommandType::Complex },
+    Command { name: "flip_canvas", description: "Mirrors every layer horizontally or vertically.", usage: "flip_canvas={h|v}", example: "flip_canvas=h", command_type: CommandType::Complex },
+    Command { name: "crop", description: "Crops every layer to the selection, or to the artwork on visible layers with --content.", usage: "crop [--content]", example: "crop --content", command_type: CommandType::Complex },
+    Command { name: "outline", description: "Draws a 1px outline in the current color around opaque content on the active layer.", usage: "outline [--inside] [--diagonal] [--new-layer]", example: "outline --diagonal", command_type: CommandType::Complex },
+    Command { name: "tilesize", description: "Sets the tile grid size (or 'off').", usage: "tilesize <W>x<H> | tilesize off", example: "tilesize 16x16", command_type: CommandType::Complex },
+    Command { name: "tile", description: "Copies, pastes or repeats the tile under the cursor.", usage: "tile {copy|paste|fill} [--blend]", example: "tile fill", command_type: CommandType::Complex },
+    Command { name: "import", description: "Imports a palette, or an image onto the canvas.", usage: "import palette <path> | import image {<path>|--explorer} [-s factor] [--fit] [--box] [--new-layer]", example: "import image ref.png --fit --new-layer", command_type: CommandType::Complex },
+    Command { name: "colorpalette", description: "Switches to a loaded palette; --list shows them all.", usage: "colorpalette:<name> | colorpalette --list", example: "colorpalette --list", command_type: CommandType::Complex },
     
-    Command { name: "colorpalette:", description: "Switches to a loaded palette.", usage: "colorpalette:<name>", example: "colorpalette:default", command_type: CommandType::Complex },
+    Command { name: "colorpalette:", description: "Switches to a loaded palette.", usage: "colorpalette:<name> [--add] [--force]", example: "colorpalette:default", command_type: CommandType::Complex },
+    Command { name: "paletteColors", description: "Sets how many colors colorpalette_image picks by default.", usage: "paletteColors={4-64}", example: "paletteColors=24", command_type: CommandType::SetterU16(|app, val| app.palette_colors = val, 4, 64) },
     Command { name: "savepalette:", description: "Saves the current palette.", usage: "savepalette:<name>", example: "savepalette:my-palette", command_type: CommandType::Complex },
-    Command { name: "colorpalette_image", description: "Generate a new palette from an image file.", usage: "colorpalette_image [--add]", example: "colorpalette_image", command_type: CommandType::Complex },   
+    Command { name: "exportpalette:", description: "Writes the current palette as a GIMP (.gpl) or .hex palette file.", usage: "exportpalette:<name> [--format=gpl|hex]", example: "exportpalette:my-palette --format=hex", command_type: CommandType::Complex },
+    Command { name: "color_editor", description: "Opens the color editor to mix a new color with HSV or RGB sliders.", usage: "color_editor", example: "color_editor", command_type: CommandType::Action(|app| crate::color_editor::open_color_editor(app, app.current_selection)) },
+    Command { name: "edit_color", description: "Edits the highlighted palette color in place; --remap also recolors its pixels.", usage: "edit_color [--remap]", example: "edit_color --remap", command_type: CommandType::Complex },
+    Command { name: "palette_remove", description: "Removes a palette color by position (from 1) or hex value.", usage: "palette_remove=<index|#hex>", example: "palette_remove=#FF0000", command_type: CommandType::Complex },
+    Command { name: "palette_move", description: "Moves a palette color to another position (from 1).", usage: "palette_move=<from>,<to>", example: "palette_move=12,1", command_type: CommandType::Complex },
+    Command { name: "palette_sort", description: "Sorts the palette colors by hue, brightness or saturation.", usage: "palette_sort=<hue|brightness|saturation>", example: "palette_sort=hue", command_type: CommandType::Complex },
+    Command { name: "palette_dedupe", description: "Removes duplicate palette colors, or near-duplicates within an RGB distance.", usage: "palette_dedupe [tolerance]", example: "palette_dedupe 8", command_type: CommandType::Complex },
+    Command { name: "colorpalette_image", description: "Generate a new palette from an image file.", usage: "colorpalette_image [4-64] [--add]", example: "colorpalette_image 24", command_type: CommandType::Complex },   
     Command { name: "keybindings", description: "Opens the keybinding configuration panel.", usage: "keybindings", example: "keybindings", command_type: CommandType::Action(|app| { app.mode = crate::AppMode::Keybindings; })},
     Command { name: "config", description: "Opens the configuration editor panel.", usage: "config", example: "config", command_type: CommandType::Action(|app| { app.mode = crate::AppMode::ConfigEditor; })},
 
@@ -93,14 +168,48 @@ pub const COMMANDS: &[Command] = &[
         command_type: CommandType::SetterF32(
             |app, val| {
                 if app.active_layer_index < app.layers.len() {
-                    app.layers[app.active_layer_index].opacity = val;
-                    app.sync_canvas_from_layers();
+                    app.set_layer_opacity(app.active_layer_index, val);
                 }
             },
             0.0,
             1.0,
         ),
     },
+    Command {
+        name: "layer_blend",
+        description: "Set active layer blend mode",
+        usage: "layer_blend={normal|multiply|screen|overlay|add|subtract}",
+        example: "layer_blend=multiply",
+        command_type: CommandType::Complex,
+    },
+    Command {
+        name: "move_to_layer",
+        description: "Move the selection (or the whole active layer) onto another layer",
+        usage: "move_to_layer=<#|name>",
+        example: "move_to_layer=2",
+        command_type: CommandType::Complex,
+    },
+    Command {
+        name: "copy_to_layer",
+        description: "Copy the selection (or the whole active layer) onto another layer",
+        usage: "copy_to_layer=<#|name>",
+        example: "copy_to_layer=Shading",
+        command_type: CommandType::Complex,
+    },
+    Command {
+        name: "lock_layer",
+        description: "Lock or unlock the active layer against edits",
+        usage: "lock_layer",
+        example: "lock_layer",
+        command_type: CommandType::Action(|app| app.toggle_layer_lock()),
+    },
+    Command {
+        name: "alpha_lock_layer",
+        description: "Toggle alpha lock so painting only recolors existing pixels",
+        usage: "alpha_lock_layer",
+        example: "alpha_lock_layer",
+        command_type: CommandType::Action(|app| app.toggle_alpha_lock()),
+    },
     Command {
         name: "rename_layer",
         description: "Rename the active layer",
@@ -109,6 +218,7 @@ pub const COMMANDS: &[Command] = &[
         command_type: CommandType::SetterString(|app, name| {
             if app.active_layer_index < app.layers.len() {
                 app.layers[app.active_layer_index].name = name;
+                app.dirty = true;
             }
         }),
     },
@@ -121,7 +231,7 @@ pub const COMMANDS: &[Command] = &[
             match mode.to_lowercase().as_str() {
                 "united" => app.export_layer_mode = crate::ExportLayerMode::United,
                 "separate" => app.export_layer_mode = crate::ExportLayerMode::Separate,
-                _ => app.status_message = Some(("Invalid mode. Use 'united' or 'separate'.".to_string(), Instant::now())),
+                _ => app.set_status("Invalid mode. Use 'united' or 'separate'."),
             }
         }),
     },
@@ -139,6 +249,49 @@ pub const COMMANDS: &[Command] = &[
         example: "onion_skin=true",
         command_type: CommandType::SetterBool(|app, val| app.onion_skin_enabled = val),
     },
+    Command {
+        name: "onion_skin_frames_before",
+        description: "Set how many earlier layers onion skinning shows (0 to 3)",
+        usage: "onion_skin_frames_before={0-3}",
+        example: "onion_skin_frames_before=2",
+        command_type: CommandType::SetterU16(|app, val| app.onion_skin_frames_before = val, 0, 3),
+    },
+    Command {
+        name: "onion_skin_frames_after",
+        description: "Set how many later layers onion skinning shows (0 to 3)",
+        usage: "onion_skin_frames_after={0-3}",
+        example: "onion_skin_frames_after=1",
+        command_type: CommandType::SetterU16(|app, val| app.onion_skin_frames_after = val, 0, 3),
+    },
+    Command {
+        name: "play",
+        description: "Play the layers back as animation frames; any key stops",
+        usage: "play [fps]",
+        example: "play 12",
+        command_type: CommandType::Complex,
+    },
+    Command {
+        name: "playback_fps",
+        description: "Set the default frame rate for play (1 to 30)",
+        usage: "playback_fps={1-30}",
+        example: "playback_fps=8",
+        command_type: CommandType::SetterU16(|app, val| app.playback_fps = val, 1, 30),
+    },
+    Command {
+        name: "tile_preview",
+        description: "Show the canvas repeated 3x3 around itself, for tileable textures",
+        usage: "tile_preview=<true|false>",
+        example: "tile_preview=true",
+        command_type: CommandType::SetterBool(|app, val| app.tile_preview = val),
+    },
+    Command { name: "reference", description: "Sets an image to trace over, drawn beneath every layer, or removes it.", usage: "reference {<path>|off}", example: "reference photo.jpg", command_type: CommandType::Complex },
+    Command {
+        name: "reference_opacity",
+        description: "Set the reference image opacity (0.0 to 1.0)",
+        usage: "reference_opacity=<value>",
+        example: "reference_opacity=0.3",
+        command_type: CommandType::SetterF32(|app, val| app.reference_opacity = val, 0.0, 1.0),
+    },
     Command {
         name: "add_layer",
         description: "Add a new layer",
@@ -158,50 +311,850 @@ pub const COMMANDS: &[Command] = &[
         description: "Merge active layer with the layer below",
         usage: "merge_down",
         example: "merge_down",
-        command_type: CommandType::Action(|app| {
-            if app.active_layer_index == 0 {
-                app.status_message = Some(("Cannot merge bottom layer.".to_string(), Instant::now()));
-                return;
+        command_type: CommandType::Action(|app| app.merge_down()),
+    },
+    Command {
+        name: "merge_up",
+        description: "Merge active layer with the layer above",
+        usage: "merge_up",
+        example: "merge_up",
+        command_type: CommandType::Action(|app| app.merge_up()),
+    },
+
+
+
+
+
+
+    ];
+
+
+
+    fn parse_and_execute_save(app: &mut App, command: &str) -> Result<(), String> {
+        let parts: Vec<&str> = command.split_whitespace().collect();
+        // NEW: Open explorer if no filename or --explorer is provided
+        if parts.len() < 2 || parts.contains(&"--explorer") {
+            file_browser::open_browser(app, file_browser::BrowserMode::Save);
+            return Ok(());
+        }
+        
+        // `--plain` (or the plain_project_save default, unless `.consolet` is typed out)
+        // writes pretty-printed `.consolet.json`.
+        let mut filename = parts[1].to_string();
+        let plain = parts.contains(&"--plain") || filename.ends_with(".consolet.json") || (app.plain_project_save && !filename.ends_with(".consolet"));
+        let base = filename.strip_suffix(".consolet.json").or_else(|| filename.strip_suffix(".consolet")).unwrap_or(&filename).to_string();
+        filename = format!("{}{}", base, if plain { ".consolet.json" } else { ".consolet" });
+        let mut custom_path = None;
+        let mut force_overwrite = false;
+        let mut autosave_mins = None;
+
+        let mut i = 2;
+        while i < parts.len() {
+            match parts[i] {
+                "-p" => { i += 1; if i < parts.len() { custom_path = Some(parts[i].replace("\"", "")); } },
+                "-f" => force_overwrite = true,
+                "--embed-palettes" => app.embed_palettes_once = true,
+                "-a" => { i += 1; if i < parts.len() { autosave_mins = parts[i].parse::<u64>().ok(); } },
+                _ => {}
             }
-            let active_layer = app.layers[app.active_layer_index].clone();
-            let below_layer = &mut app.layers[app.active_layer_index - 1];
-            
-            for y in 0..app.canvas_height {
-                for x in 0..app.canvas_width {
-                    let src_pixel = active_layer.canvas[y][x];
-                    if src_pixel.alpha == 0.0 {
-                        continue;
-                    }
-                    let dest_pixel = below_layer.canvas[y][x];
-                    let src_alpha = src_pixel.alpha * active_layer.opacity;
-                    
-                    if dest_pixel.alpha == 0.0 {
-                        below_layer.canvas[y][x] = Pixel {
-                            color: src_pixel.color,
-                            alpha: src_alpha,
-                        };
-                    } else {
-                        let final_alpha = src_alpha + dest_pixel.alpha * (1.0 - src_alpha);
-                        let factor = src_alpha / final_alpha;
-                        let final_color = crate::utils::blend_colors(dest_pixel.color.into(), src_pixel.color.into(), factor);
-                        below_layer.canvas[y][x] = Pixel {
-                            color: final_color.into(),
-                            alpha: final_alpha,
-                        };
+            i += 1;
+        }
+
+        let path = match custom_path {
+            Some(p) => PathBuf::from(shellexpand::tilde(&p).into_owned()).join(&filename),
+            None => utils::get_or_create_app_dir().unwrap().join("saved_projects").join(&filename),
+        };
+
+        if path.exists() && !force_overwrite {
+            app.pending_save_path = Some(path);
+            app.mode = AppMode::ConfirmOverwrite;
+            return Ok(());
+        }
+
+        if let Some(mins) = autosave_mins {
+            app.autosave_interval = Some(std::time::Duration::from_secs(mins * 60));
+            app.last_autosave_time = Instant::now();
+        }
+        reported(app, |app| app.save_project(&path, true))
+    }
+
+fn parse_and_execute_load(app: &mut App, command: &str) -> Result<(), String> {
+    // Quoted so paths with spaces (as offered by `recent`) stay one argument.
+    let parts = utils::split_args(command);
+    // NEW: Open explorer if no filename or --explorer is provided
+    if parts.len() < 2 || parts.iter().any(|p| p == "--explorer") {
+        file_browser::open_browser(app, file_browser::BrowserMode::Load);
+        return Ok(());
+    }
+    
+    let filename = parts[1].clone();
+    let mut path = PathBuf::from(&filename);
+    
+    if !path.is_absolute() {
+        let default_path = utils::get_or_create_app_dir().unwrap().join("saved_projects").join(&filename);
+        if default_path.exists() {
+            path = default_path;
+        }
+    }
+    
+    if !path.exists() {
+        return Err(format!("File not found: {}", filename));
+    }
+    // `--force` lets palettes embedded in the project replace same-named local ones.
+    reported(app, |app| app.load_project_with(&path, parts.iter().any(|p| p == "--force")))
+}
+
+
+fn parse_and_execute_export(app: &mut App, command: &str) -> Result<(), String> {
+    let parts: Vec<&str> = command.split_whitespace().collect();
+    let mut output_path_str: Option<String> = None;
+    let mut upscale: u32 = 1;
+    let mut with_background = false;
+    let mut background = ratatui::style::Color::Black;
+    let (mut selection_only, mut autocrop) = (false, false);
+
+    if parts.get(1) == Some(&"gif") {
+        return parse_and_execute_export_gif(app, &parts[2..]);
+    }
+    if parts.get(1) == Some(&"ansi") {
+        return parse_and_execute_export_ansi(app, &parts[2..]);
+    }
+    if parts.get(1) == Some(&"sheet") {
+        return parse_and_execute_export_sheet(app, &parts[2..]);
+    }
+    if parts.get(1) == Some(&"ascii") {
+        return parse_and_execute_export_ascii(app, command);
+    }
+
+    // NEW: If "export" is typed alone or with --explorer, open the browser.
+    if parts.len() == 1 || parts.contains(&"--explorer") {
+        file_browser::open_browser(app, file_browser::BrowserMode::Export);
+        return Ok(());
+    }
+
+    // --- Keep the existing argument parsing logic ---
+    let mut i = 1;
+    while i < parts.len() {
+        match parts[i] {
+            "-o" => {
+                if i + 1 >= parts.len() { return Err("Error: -o requires a path.".to_string()); }
+                output_path_str = Some(parts[i + 1].to_string());
+                i += 2;
+            },
+            "-u" => {
+                if i + 1 >= parts.len() { return Err("Error: -u requires a number.".to_string()); }
+                upscale = parts[i + 1].parse::<u32>().unwrap_or(1).max(1);
+                i += 2;
+            },
+            "-bg" => { with_background = true; i += 1; },
+            "-bgcolor" => {
+                let Some(value) = parts.get(i + 1) else { return Err("Error: -bgcolor requires a color, such as #RRGGBB or white.".to_string()); };
+                background = App::parse_export_background(value).map_err(|e| format!("Error: Invalid background color '{}'. {}", value, e))?;
+                with_background = true;
+                i += 2;
+            },
+            "--selection" => { selection_only = true; i += 1; },
+            "--autocrop" => { autocrop = true; i += 1; },
+            // Ignore --explorer as it's already handled
+            "--explorer" => { i += 1; }, 
+            _ => { return Err(format!("Error: Unknown argument for export: {}", parts[i])); }
+        }
+    }
+    
+    // This part only runs if a path was provided via -o
+    if let Some(path_str) = output_path_str {
+        let final_path = match utils::normalize_output_path(Path::new(""), &path_str, "png") {
+            Ok(path) => path,
+            Err(e) => { return Err(format!("Error: {}", e)); }
+        };
+        if let Err(e) = utils::create_parent_dirs(&final_path) {
+            return Err(format!("Error creating directory: {}", e));
+        }
+        reported(app, |app| app.export_to_png(Some(final_path.to_string_lossy().into_owned()), upscale, !with_background, background, selection_only, autocrop))
+    } else {
+         // This case should now be rare, but we can keep a fallback
+         // Or simply show a help message. Let's do that.
+         Err("Usage: export -o <path.png> or export --explorer".to_string())
+    }
+}
+
+fn parse_and_execute_export_gif(app: &mut App, args: &[&str]) -> Result<(), String> {
+    const USAGE: &str = "Usage: export gif -o <path.gif> [-d ms] [-u scale] [-bg | -bgcolor <#RRGGBB|white|black>]";
+    let mut output_path_str = None;
+    let (mut delay_ms, mut upscale) = (100u32, 1u32);
+    let mut background = None;
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1).copied();
+        match (args[i], value) {
+            ("-o", Some(path)) => output_path_str = Some(path.to_string()),
+            ("-d", Some(ms)) => match ms.parse::<u32>() {
+                Ok(ms) if ms > 0 => delay_ms = ms,
+                _ => { return Err(format!("Error: Invalid frame delay '{}'.", ms)); }
+            },
+            ("-u", Some(scale)) => upscale = scale.parse::<u32>().unwrap_or(1).max(1),
+            ("-bgcolor", Some(color)) => match App::parse_export_background(color) {
+                Ok(color) => background = Some(color),
+                Err(e) => { return Err(format!("Error: Invalid background color '{}'. {}", color, e)); }
+            },
+            ("-bg", _) => { background = Some(ratatui::style::Color::Black); i += 1; continue; },
+            _ => { return Err(USAGE.to_string()); }
+        }
+        i += 2;
+    }
+
+    let Some(path_str) = output_path_str else {
+        return Err(USAGE.to_string());
+    };
+    let final_path = match utils::normalize_output_path(Path::new(""), &path_str, "gif") {
+        Ok(path) => path,
+        Err(e) => { return Err(format!("Error: {}", e)); }
+    };
+    if let Err(e) = utils::create_parent_dirs(&final_path) {
+        return Err(format!("Error creating directory: {}", e));
+    }
+    reported(app, |app| app.export_to_gif(&final_path.to_string_lossy(), upscale, delay_ms, background))
+}
+
+fn parse_and_execute_export_ansi(app: &mut App, args: &[&str]) -> Result<(), String> {
+    const USAGE: &str = "Usage: export ansi -o <path.ans> [--plain]";
+    let mut output_path_str = None;
+    let mut plain = false;
+    let mut i = 0;
+    while i < args.len() {
+        match (args[i], args.get(i + 1)) {
+            ("-o", Some(path)) => { output_path_str = Some(path.to_string()); i += 2; },
+            ("--plain", _) => { plain = true; i += 1; },
+            _ => { return Err(USAGE.to_string()); }
+        }
+    }
+
+    let Some(path_str) = output_path_str else {
+        return Err(USAGE.to_string());
+    };
+    let final_path = match utils::normalize_output_path(Path::new(""), &path_str, "ans") {
+        Ok(path) => path,
+        Err(e) => { return Err(format!("Error: {}", e)); }
+    };
+    if let Err(e) = utils::create_parent_dirs(&final_path) {
+        return Err(format!("Error creating directory: {}", e));
+    }
+    reported(app, |app| app.export_to_ansi(&final_path.to_string_lossy(), plain))
+}
+
+fn parse_and_execute_export_sheet(app: &mut App, args: &[&str]) -> Result<(), String> {
+    const USAGE: &str = "Usage: export sheet -o <path.png> [-cols N] [-u scale] [-gutter] [-bg | -bgcolor <#RRGGBB|white|black>]";
+    let mut output_path_str = None;
+    let (mut columns, mut upscale) = (4u32, 1u32);
+    let mut gutter = false;
+    let mut background = None;
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1).copied();
+        match (args[i], value) {
+            ("-o", Some(path)) => output_path_str = Some(path.to_string()),
+            ("-cols", Some(n)) => match n.parse::<u32>() {
+                Ok(n) if n > 0 => columns = n,
+                _ => { return Err(format!("Error: Invalid column count '{}'.", n)); }
+            },
+            ("-u", Some(scale)) => upscale = scale.parse::<u32>().unwrap_or(1).max(1),
+            ("-bgcolor", Some(color)) => match App::parse_export_background(color) {
+                Ok(color) => background = Some(color),
+                Err(e) => { return Err(format!("Error: Invalid background color '{}'. {}", color, e)); }
+            },
+            ("-bg", _) => { background = Some(ratatui::style::Color::Black); i += 1; continue; },
+            ("-gutter", _) => { gutter = true; i += 1; continue; },
+            _ => { return Err(USAGE.to_string()); }
+        }
+        i += 2;
+    }
+
+    let Some(path_str) = output_path_str else {
+        return Err(USAGE.to_string());
+    };
+    let final_path = match utils::normalize_output_path(Path::new(""), &path_str, "png") {
+        Ok(path) => path,
+        Err(e) => { return Err(format!("Error: {}", e)); }
+    };
+    if let Err(e) = utils::create_parent_dirs(&final_path) {
+        return Err(format!("Error creating directory: {}", e));
+    }
+    reported(app, |app| app.export_sprite_sheet(&final_path.to_string_lossy(), columns, upscale, gutter, background))
+}
+
+fn parse_and_execute_export_ascii(app: &mut App, command: &str) -> Result<(), String> {
+    const USAGE: &str = "Usage: export ascii -o <path.txt> [--charset \"<ramp>\"] [--invert] [--wide]";
+    // Quotes keep a ramp that starts with a space together.
+    let args = utils::split_args(command);
+    let mut output_path_str = None;
+    let mut ramp: Vec<char> = crate::export::DEFAULT_ASCII_RAMP.chars().collect();
+    let (mut invert, mut wide) = (false, false);
+    let mut i = 2;
+    while i < args.len() {
+        match (args[i].as_str(), args.get(i + 1)) {
+            ("-o", Some(path)) => { output_path_str = Some(path.clone()); i += 2; },
+            ("--charset", Some(charset)) if !charset.is_empty() => { ramp = charset.chars().collect(); i += 2; },
+            ("--invert", _) => { invert = true; i += 1; },
+            ("--wide", _) => { wide = true; i += 1; },
+            _ => { return Err(USAGE.to_string()); }
+        }
+    }
+
+    let Some(path_str) = output_path_str else {
+        return Err(USAGE.to_string());
+    };
+    let final_path = match utils::normalize_output_path(Path::new(""), &path_str, "txt") {
+        Ok(path) => path,
+        Err(e) => { return Err(format!("Error: {}", e)); }
+    };
+    if let Err(e) = utils::create_parent_dirs(&final_path) {
+        return Err(format!("Error creating directory: {}", e));
+    }
+    reported(app, |app| app.export_to_ascii(&final_path.to_string_lossy(), &ramp, invert, wide))
+}
+
+/// Runs `clear; colorpalette:ansi; resize 64x64` one command at a time (see
+/// `utils::split_commands`), stopping at the first one that is rejected.
+pub fn execute_command_line(app: &mut App, line: &str) -> bool {
+    let commands = utils::split_commands(line);
+    for (i, command) in commands.iter().enumerate() {
+        if !execute_command(app, command) {
+            if commands.len() > 1 {
+                let reason = app.status_message.take().map(|(msg, _)| msg).unwrap_or_default();
+                app.set_status(format!("Command {} of {} ({}) failed: {}", i + 1, commands.len(), command, reason));
+            }
+            return false;
+        }
+    }
+    true
+}
+
+/// Runs one command line. Returns false when it was rejected: an unknown command, a
+/// bad value, or wrong arguments, whose message is then in the status bar.
+pub fn execute_command(app: &mut App, command: &str) -> bool {
+    let command_to_run = command.trim();
+    let parts: Vec<&str> = command_to_run.split_whitespace().collect();
+    let should_save = parts.contains(&"--save");
+    let mut status_update = None;
+    let mut error = None;
+
+    // --- 1. Handle Complex Commands First ---
+    let main_cmd = parts.first().unwrap_or(&"");
+    if *main_cmd == "save" { if let Err(e) = parse_and_execute_save(app, command_to_run) { error = Some(e); }
+    } else if *main_cmd == "load" { if let Err(e) = parse_and_execute_load(app, command_to_run) { error = Some(e); }
+    } else if *main_cmd == "export" { if let Err(e) = parse_and_execute_export(app, command_to_run) { error = Some(e); }
+    } else if *main_cmd == "layer" { if let Err(e) = parse_and_execute_layer(app, command_to_run) { error = Some(e); }
+    } else if let Some(value) = main_cmd.strip_prefix("layer_blend=") {
+        match crate::BlendMode::from_name(value) {
+            Some(blend_mode) => {
+                app.set_layer_blend_mode(app.active_layer_index, blend_mode);
+                status_update = Some(format!("'{}' blend mode: {}.", app.layers[app.active_layer_index].name, blend_mode.name()));
+            }
+            None => error = Some(format!("Unknown blend mode: {}. Usage: layer_blend={{normal|multiply|screen|overlay|add|subtract}}", value)),
+        }
+    } else if main_cmd.starts_with("move_to_layer=") || main_cmd.starts_with("copy_to_layer=") {
+        // Split with quotes honored so names with spaces work: move_to_layer="Line art"
+        let argument = utils::split_args(command_to_run).swap_remove(0);
+        let (name, target) = argument.split_once('=').unwrap_or_default();
+        if let Err(e) = reported(app, |app| app.transfer_to_layer(target, name == "copy_to_layer")) { error = Some(e); }
+    } else if *main_cmd == "play" {
+        match parts.get(1).map(|fps| fps.parse::<u16>()) {
+            None => app.start_playback(None),
+            Some(Ok(fps)) if (1..=30).contains(&fps) => app.start_playback(Some(fps)),
+            Some(_) => error = Some("Usage: play [fps] (1-30)".to_string()),
+        }
+    } else if *main_cmd == "palette_remove" || main_cmd.starts_with("palette_remove=") {
+        let value = main_cmd.strip_prefix("palette_remove=").or_else(|| parts.get(1).copied()).unwrap_or("");
+        // Hex colors need the '#' so that a position like 112233 stays a position.
+        let color = if value.starts_with(|c: char| c.is_ascii_digit()) { None } else { App::parse_color(value).ok() };
+        if let Some(color) = color {
+            match app.color_palette.iter().position(|entry| matches!(entry, PaletteEntry::Color(c) if utils::to_rgb(*c) == utils::to_rgb(color))) {
+                Some(index) => { app.remove_palette_entry(index); }
+                None => error = Some(format!("{} is not in the palette.", if value.starts_with('#') { value.to_uppercase() } else { value.to_string() })),
+            }
+        } else if let Some(position) = value.parse::<usize>().ok().filter(|&position| position >= 1) {
+            app.remove_palette_entry(position - 1);
+        } else {
+            error = Some("Usage: palette_remove=<index|#hex>".to_string());
+        }
+    } else if *main_cmd == "palette_move" || main_cmd.starts_with("palette_move=") {
+        let value = main_cmd.strip_prefix("palette_move=").or_else(|| parts.get(1).copied()).unwrap_or("");
+        match value.split_once(',').map(|(from, to)| (from.trim().parse::<usize>(), to.trim().parse::<usize>())) {
+            Some((Ok(from), Ok(to))) if from >= 1 && to >= 1 => { app.move_palette_entry(from - 1, to - 1); }
+            _ => error = Some("Usage: palette_move=<from>,<to>".to_string()),
+        }
+    } else if *main_cmd == "palette_sort" || main_cmd.starts_with("palette_sort=") {
+        match main_cmd.strip_prefix("palette_sort=").or_else(|| parts.get(1).copied()).unwrap_or("").to_lowercase().as_str() {
+            "hue" => app.sort_palette(PaletteSort::Hue),
+            "brightness" => app.sort_palette(PaletteSort::Brightness),
+            "saturation" => app.sort_palette(PaletteSort::Saturation),
+            _ => error = Some("Usage: palette_sort=<hue|brightness|saturation>".to_string()),
+        }
+    } else if *main_cmd == "palette_dedupe" {
+        match parts.get(1).map(|tolerance| tolerance.parse::<f32>()) {
+            None => { app.dedupe_palette(0.0); }
+            Some(Ok(tolerance)) if tolerance >= 0.0 => { app.dedupe_palette(tolerance); }
+            Some(_) => error = Some("Usage: palette_dedupe [tolerance]".to_string()),
+        }
+    } else if *main_cmd == "edit_color" {
+        match parts[1..] {
+            [] => crate::color_editor::edit_palette_color(app, app.palette_index, false),
+            ["--remap"] => crate::color_editor::edit_palette_color(app, app.palette_index, true),
+            _ => error = Some("Usage: edit_color [--remap]".to_string()),
+        }
+    } else if *main_cmd == "resize" && parts.len() > 1 { if let Err(e) = parse_and_execute_resize(app, command_to_run) { error = Some(e); }
+    } else if *main_cmd == "tilesize" { if let Err(e) = parse_and_execute_tilesize(app, command_to_run) { error = Some(e); }
+    } else if *main_cmd == "tile" { if let Err(e) = parse_and_execute_tile(app, command_to_run) { error = Some(e); }
+    } else if *main_cmd == "brush" { if let Err(e) = parse_and_execute_brush(app, command_to_run) { error = Some(e); }
+    } else if *main_cmd == "merge_visible" { app.merge_visible_layers(parts.contains(&"--force"));
+    } else if *main_cmd == "flatten" { app.flatten_layers(parts.contains(&"--force"));
+    } else if *main_cmd == "clear" { app.clear_canvas(parts.contains(&"--all"));
+    } else if *main_cmd == "replace_color" || main_cmd.starts_with("replace_color=") { if let Err(e) = parse_and_execute_replace_color(app, command_to_run) { error = Some(e); }
+    } else if *main_cmd == "shift_layer" || main_cmd.starts_with("shift_layer=") { if let Err(e) = parse_and_execute_shift_layer(app, command_to_run) { error = Some(e); }
+    } else if *main_cmd == "text" || main_cmd.starts_with("text=") { if let Err(e) = parse_and_execute_text(app, command_to_run) { error = Some(e); }
+    } else if *main_cmd == "selection" { if let Err(e) = parse_and_execute_selection(app, command_to_run) { error = Some(e); }
+    } else if main_cmd.starts_with("symmetry=") { if let Err(e) = parse_and_execute_symmetry(app, main_cmd) { error = Some(e); }
+    } else if let Some(degrees) = main_cmd.strip_prefix("rotate_canvas=") {
+        match degrees { "90" => app.rotate_canvas(1), "180" => app.rotate_canvas(2), "270" => app.rotate_canvas(3), _ => error = Some("Usage: rotate_canvas={90|180|270}".to_string()) }
+    } else if let Some(axis) = main_cmd.strip_prefix("flip_canvas=") {
+        match axis { "h" => app.flip_canvas(true), "v" => app.flip_canvas(false), _ => error = Some("Usage: flip_canvas={h|v}".to_string()) }
+    } else if *main_cmd == "crop" { if parts.contains(&"--content") { app.crop_to_content(); } else { app.crop_to_selection(); }
+    } else if *main_cmd == "outline" { app.outline(parts.contains(&"--inside"), parts.contains(&"--diagonal"), parts.contains(&"--new-layer"));
+    } else if *main_cmd == "reference" {
+        // Quotes keep paths with spaces together.
+        match utils::split_args(command_to_run).get(1).map(String::as_str) {
+            Some("off") => { app.reference = None; status_update = Some("Reference removed.".to_string()); }
+            Some(path) => if let Err(e) = reported(app, |app| app.set_reference(Path::new(shellexpand::tilde(path).as_ref()))) { error = Some(e); },
+            None => error = Some("Usage: reference {<path>|off}".to_string()),
+        }
+    } else if *main_cmd == "import" {
+        match parts.get(1) {
+            Some(&"palette") => if let Err(e) = parse_and_execute_import_palette(app, command_to_run) { error = Some(e); },
+            Some(&"image") => if let Err(e) = parse_and_execute_import_image(app, command_to_run) { error = Some(e); },
+            _ => error = Some("Usage: import {palette|image} <path>".to_string()),
+        }
+    } else if main_cmd.trim_end_matches(':') == "colorpalette" && parts.contains(&"--list") { app.show_palette_list();
+    } else if let Some(p) = main_cmd.strip_prefix("colorpalette:") {
+        let n = p.strip_suffix(".consolet").unwrap_or(p);
+        if let Some(pal) = app.loaded_palettes.get(n) {
+            if parts.contains(&"--add") {
+                let palette_to_add = pal.clone(); // Clone the data to release the borrow
+                app.add_palette_entries_uniquely(&palette_to_add);
+            } else {
+                let new_palette = pal.clone();
+                let missing_colors = app.used_colors_missing_from(&new_palette);
+                if missing_colors.is_empty() || parts.contains(&"--force") {
+                    app.switch_palette(n, new_palette, false);
+                    if should_save {
+                        app.default_palette_name = n.to_string();
                     }
+                } else {
+                    app.pending_palette_switch = Some(PendingPaletteSwitch {
+                        name: n.to_string(),
+                        missing_colors,
+                        set_as_default: should_save,
+                    });
+                    app.confirm_selection_yes = true;
+                    app.mode = AppMode::ConfirmPaletteSwitch;
                 }
             }
-            
-            app.layers.remove(app.active_layer_index);
-            app.active_layer_index -= 1;
-            app.sync_canvas_from_layers();
-            app.status_message = Some(("Layer merged down.".to_string(), Instant::now()));
-        }),
-    },
+        } else {
+            error = Some(format!("Palette '{}' not found.", n));
+        }
+
+
+    } else if *main_cmd == "colorpalette_image" {
+        if parts.get(1) == Some(&"save") {
+            let desired_name = parts.get(2).map(|s| s.replace("\"", ""));
+            app.save_last_generated_palette(desired_name);
+        } else {
+            let add_to_current = parts.contains(&"--add");
+            let count = match parts[1..].iter().find(|arg| **arg != "--add") {
+                Some(count) => count.parse::<u16>().ok().filter(|count| (4..=64).contains(count)),
+                None => Some(app.palette_colors),
+            };
+            if let Some(count) = count {
+                // The browser's number box holds the color count in this mode.
+                file_browser::open_browser(app, file_browser::BrowserMode::GeneratePaletteFromImage(add_to_current));
+                app.browser_scale_buffer = count.to_string();
+            } else {
+                error = Some("Usage: colorpalette_image [4-64] [--add]".to_string());
+            }
+        }
+        } else if let Some(name) = main_cmd.strip_prefix("savepalette:") {
+            app.save_current_palette(name.to_string());
+        } else if let Some(name) = main_cmd.strip_prefix("exportpalette:") {
+            parse_and_execute_export_palette(app, name, &parts[1..]);
+
+    } else if main_cmd.starts_with('#') || main_cmd.get(..4).is_some_and(|start| start.eq_ignore_ascii_case("rgb(")) || App::parse_hex_color(main_cmd).is_some() {
+        // rgb() may be written with spaces after its commas.
+        let typed = parts.iter().filter(|part| **part != "--save").copied().collect::<Vec<_>>().join(" ");
+        match App::parse_color(&typed) {
+            Ok(color) => status_update = Some(select_typed_color(app, color, &typed)),
+            Err(e) => error = Some(e),
+        }
+    } else {
+        // --- 2. Handle Data-Driven Commands ---
+        let mut command_found = false;
+        let (cmd_name, value_str) = main_cmd.split_once('=').unwrap_or((main_cmd, ""));
+        for cmd in COMMANDS.iter() {
+            if cmd.name != cmd_name { continue; }
+            command_found = true;
+            match &cmd.command_type {
+                CommandType::Action(action) => action(app),
+                CommandType::SetterBool(action) => if let Ok(val) = value_str.parse::<bool>() { action(app, val); status_update = Some(format!("Set {} to {}", cmd.name, val)); } else { error = Some(format!("Invalid value. Usage: {}", cmd.usage)); },
+                CommandType::SetterU16(action, min, max) => match numeric_setter_value(app, cmd, value_str, *min as f32, *max as f32, true) { Ok(val) => { let val = val as u16; action(app, val); status_update = Some(format!("Set {} to {}", cmd.name, val)); } Err(e) => error = Some(e) },
+                CommandType::SetterF32(action, min, max) => match numeric_setter_value(app, cmd, value_str, *min, *max, false) { Ok(val) => { action(app, val); status_update = Some(format!("Set {} to {}", cmd.name, val)); } Err(e) => error = Some(e) },
+                CommandType::SetterString(action) => { action(app, value_str.to_string()); status_update = Some(format!("Set {} to {}", cmd.name, value_str)); },
+                CommandType::SetterColor(action) => match App::parse_color(value_str) { Ok(color) => { action(app, color); status_update = Some(format!("Set {} to {}", cmd.name, value_str)); } Err(e) => error = Some(e) },
+                _ => {}
+            }
+            break;
+        }
+        if !command_found && !command_to_run.is_empty() {
+            // Color names come last so they never shadow a command.
+            match crate::palette::named_color(main_cmd) {
+                Some(color) => status_update = Some(select_typed_color(app, color, main_cmd)),
+                None => error = Some(format!("Unknown command: {}", command_to_run)),
+            }
+        }
+    }
+
+    // Commands run to completion, so whatever they recorded for undo is finished too.
+    app.finish_undo_step();
+    let failed = error.is_some();
+    if let Some(msg) = error.or(status_update) { app.set_status(msg); }
+    // A palette switch waiting on ConfirmPaletteSwitch saves once it is confirmed.
+    if should_save && !failed && app.pending_palette_switch.is_none() { app.save_current_config(); }
+    !failed
+}
+
+// App methods that can fail explain why in the status bar and return false. This turns
+// that into the command's error, dropping the logged copy since the error is logged again.
+fn reported(app: &mut App, run: impl FnOnce(&mut App) -> bool) -> Result<(), String> {
+    if run(app) {
+        return Ok(());
+    }
+    app.message_log.pop_back();
+    Err(app.status_message.take().map(|(msg, _)| msg).unwrap_or_default())
+}
+
+// Makes a color typed at the prompt the drawing color, adding it to the palette if it's new.
+fn select_typed_color(app: &mut App, color: ratatui::style::Color, typed: &str) -> String {
+    app.current_selection = PaletteEntry::Color(color);
+    if !app.color_palette.contains(&app.current_selection) { app.color_palette.push(app.current_selection); app.dirty = true; }
+    app.palette_index = app.color_palette.iter().position(|&x| x == app.current_selection).unwrap_or(0);
+    format!("Color set to {}", typed)
+}
+
+// The value for a numeric setter: a number within its range, or `+N`/`-N` to move
+// the current value by N, clamped to the range.
+fn numeric_setter_value(app: &App, cmd: &Command, value: &str, min: f32, max: f32, integer: bool) -> Result<f32, String> {
+    let parse = |text: &str| if integer { text.parse::<u16>().ok().map(f32::from) } else { text.parse::<f32>().ok().filter(|v| v.is_finite()) };
+    let invalid = || format!("Invalid value. Usage: {} (or {}=+N / {}=-N to adjust it)", cmd.usage, cmd.name, cmd.name);
+    let sign = match value.chars().next() {
+        Some('+') => 1.0,
+        Some('-') => -1.0,
+        _ => {
+            let val = parse(value).ok_or_else(invalid)?;
+            return if (min..=max).contains(&val) { Ok(val) } else { Err(format!("Value out of range ({}-{}).", min, max)) };
+        }
+    };
+    let step = parse(&value[1..]).ok_or_else(invalid)?;
+    let current = current_setter_value(app, cmd.name)
+        .ok_or_else(|| format!("{} can't be adjusted with + or -. Usage: {}", cmd.name, cmd.usage))?;
+    let val = (current + sign * step).clamp(min, max);
+    // Rounded so steps like 0.1 don't leave float noise behind.
+    Ok(if integer { val.round() } else { (val * 10_000.0).round() / 10_000.0 })
+}
+
+// What a numeric setter is currently set to, in the units it takes.
+fn current_setter_value(app: &App, name: &str) -> Option<f32> {
+    Some(match name {
+        "stabilizer" => app.stabilizer as f32,
+        "penSizeSensitivity" => app.pen_size_sensitivity as f32,
+        "highlighterMode" => if app.highlighter_mode == crate::HighlighterMode::Underscore { 0.0 } else { 1.0 },
+        "spraySize" => app.spray_size as f32,
+        "spraySpeed" => app.spray_speed as f32,
+        "fillTolerance" => app.fill_tolerance as f32,
+        "pixel_block" => app.pixel_block as f32,
+        "opacitySensitivity" => app.opacity_sensitivity,
+        "highlighterValue" => app.highlighter_value,
+        "radialSegments" => app.radial_segments as f32,
+        "blurRadius" => app.blur_radius as f32,
+        "pencilDensity" => app.shade_factor,
+        "applyColorSec" => app.apply_color_interval.num_milliseconds() as f32 / 1000.0,
+        "sprayIntensity" => app.spray_intensity,
+        "color_jitter" => app.color_jitter,
+        "checker_size" => app.checker_size as f32,
+        "gridSpacing" => app.major_grid_spacing as f32,
+        "gridMinZoom" => app.pixel_grid_min_zoom as f32,
+        "maxZoom" => app.max_zoom as f32,
+        "undo_limit" => app.undo_limit as f32,
+        "undo_memory_mb" => app.undo_memory_mb as f32,
+        "autosave_rotations" => app.autosave_rotations as f32,
+        "paletteColors" => app.palette_colors as f32,
+        "layer_opacity" => app.layers.get(app.active_layer_index)?.opacity,
+        "onion_opacity" => app.onion_skin_opacity,
+        "onion_skin_frames_before" => app.onion_skin_frames_before as f32,
+        "onion_skin_frames_after" => app.onion_skin_frames_after as f32,
+        "playback_fps" => app.playback_fps as f32,
+        "reference_opacity" => app.reference_opacity,
+        _ => return None,
+    })
+}
+
+fn parse_and_execute_layer(app: &mut App, command: &str) -> Result<(), String> {
+    let parts: Vec<&str> = command.split_whitespace().collect();
+    match (parts.get(1).copied(), parts.get(2)) {
+        (Some("export"), Some(path_str)) if *path_str != "--explorer" => {
+            let final_path = utils::normalize_output_path(Path::new(""), path_str, "png").map_err(|e| format!("Error: {}", e))?;
+            utils::create_parent_dirs(&final_path).map_err(|e| format!("Error creating directory: {}", e))?;
+            reported(app, |app| app.export_active_layer_png(&final_path.to_string_lossy()))
+        }
+        (Some("export"), _) => { file_browser::open_browser(app, file_browser::BrowserMode::ExportLayer); Ok(()) }
+        (Some("import"), Some(path_str)) if *path_str != "--explorer" => {
+            let final_path = PathBuf::from(shellexpand::tilde(&path_str.replace("\"", "")).into_owned());
+            reported(app, |app| app.import_active_layer_png(&final_path))
+        }
+        (Some("import"), _) => { file_browser::open_browser(app, file_browser::BrowserMode::ImportLayer); Ok(()) }
+        (Some("list"), None) => { app.show_layer_list(); Ok(()) }
+        (Some(_), _) => {
+            let args = utils::split_args(command);
+            let result = match (args.get(1), args.get(2), args.len()) {
+                (Some(target), Some(verb), 3) => app.apply_layer_verb(target, verb),
+                _ => Err("Usage: layer <name|#> {visible=<bool>|opacity=<0-1>|active}".to_string()),
+            };
+            app.set_status(result.map_err(|e| format!("Error: {}", e))?);
+            Ok(())
+        }
+        _ => Err("Usage: layer {list|export|import} | layer <name|#> {visible=|opacity=|active}".to_string()),
+    }
+}
+
+fn parse_and_execute_brush(app: &mut App, command: &str) -> Result<(), String> {
+    let parts: Vec<&str> = command.split_whitespace().collect();
+    match (parts.get(1).copied(), parts.get(2)) {
+        (Some("load"), Some(path_str)) => {
+            let final_path = shellexpand::tilde(&path_str.replace("\"", "")).into_owned();
+            app.load_brush(&final_path).map_err(|e| format!("Error loading brush: {}", e))?;
+            app.set_status(format!("Brush loaded from {}", final_path));
+        }
+        (Some("clear"), None) => {
+            app.clear_brush();
+            app.set_status("Custom brush cleared.");
+        }
+        _ => return Err("Usage: brush load <path.png> | brush clear".to_string()),
+    }
+    Ok(())
+}
+
+fn parse_and_execute_tilesize(app: &mut App, command: &str) -> Result<(), String> {
+    let parts: Vec<&str> = command.split_whitespace().collect();
+    match parts.get(1).copied() {
+        Some("off") => app.set_tile_size(None),
+        Some(size) => {
+            let parsed = size.split_once(['x', 'X'])
+                .and_then(|(w, h)| Some((w.parse::<usize>().ok()?, h.parse::<usize>().ok()?)))
+                .filter(|&(w, h)| w > 0 && h > 0);
+            match parsed {
+                Some(tile_size) => app.set_tile_size(Some(tile_size)),
+                None => return Err(format!("Invalid tile size: {}", size)),
+            }
+        }
+        None => return Err("Usage: tilesize <W>x<H> | tilesize off".to_string()),
+    }
+    Ok(())
+}
+
+fn parse_and_execute_replace_color(app: &mut App, command: &str) -> Result<(), String> {
+    let parts: Vec<&str> = command.split_whitespace().collect();
+    // Accepts both `replace_color=#FROM,#TO` and `replace_color #FROM,#TO`.
+    let colors = parts[0].strip_prefix("replace_color=").or_else(|| parts.get(1).copied()).unwrap_or("");
+    // The comma between the two colors, not one inside rgb(...).
+    let mut depth = 0;
+    let comma = colors.char_indices().find(|&(_, c)| {
+        match c { '(' => depth += 1, ')' => depth -= 1, _ => {} }
+        c == ',' && depth == 0
+    });
+    let Some((comma, _)) = comma else {
+        return Err("Usage: replace_color=<from>,<to> [--all-layers] with colors like #RRGGBB, rgb(r,g,b) or orange".to_string());
+    };
+    let from = App::parse_color(&colors[..comma])?;
+    let to = App::parse_color(&colors[comma + 1..])?;
+    app.replace_color(from, to, parts.contains(&"--all-layers"));
+    Ok(())
+}
+
+fn parse_and_execute_resize(app: &mut App, command: &str) -> Result<(), String> {
+    let parts: Vec<&str> = command.split_whitespace().collect();
+    // `<W>x<H>`, or a single number for a square canvas.
+    let size = parts.get(1).and_then(|arg| match arg.split_once(['x', 'X']) {
+        Some((w, h)) => Some((w.parse::<usize>().ok()?, h.parse::<usize>().ok()?)),
+        None => arg.parse::<usize>().ok().map(|side| (side, side)),
+    });
+    let anchor = match parts.iter().find_map(|arg| arg.strip_prefix("--anchor=")) {
+        None | Some("topleft") => Some(ResizeAnchor::TopLeft),
+        Some("center") => Some(ResizeAnchor::Center),
+        Some(offset) => offset.split_once(',')
+            .and_then(|(x, y)| Some(ResizeAnchor::Offset(x.trim().parse().ok()?, y.trim().parse().ok()?))),
+    };
+    match (size, anchor) {
+        (Some((width, height)), _) if !(1..=MAX_CANVAS_SIZE).contains(&width) || !(1..=MAX_CANVAS_SIZE).contains(&height) => {
+            Err(format!("Canvas size must be 1-{} on each side.", MAX_CANVAS_SIZE))
+        }
+        (Some((width, height)), Some(anchor)) => { app.resize_canvas_anchored(width, height, anchor, parts.contains(&"--clear")); Ok(()) }
+        _ => Err("Usage: resize {<W>x<H>|<size>} [--anchor=<topleft|center|x,y>] [--clear]".to_string()),
+    }
+}
 
+fn parse_and_execute_shift_layer(app: &mut App, command: &str) -> Result<(), String> {
+    let parts: Vec<&str> = command.split_whitespace().collect();
+    let offset = parts[0].strip_prefix("shift_layer=").or_else(|| parts.get(1).copied()).unwrap_or("");
+    let parsed = offset.split_once(',')
+        .and_then(|(dx, dy)| Some((dx.trim().parse::<i32>().ok()?, dy.trim().parse::<i32>().ok()?)));
+    match parsed {
+        Some((dx, dy)) => reported(app, |app| app.shift_layers(dx, dy, !parts.contains(&"--clip"), parts.contains(&"--all"))),
+        None => Err("Usage: shift_layer=<dx>,<dy> [--wrap|--clip] [--all]".to_string()),
+    }
+}
 
+fn parse_and_execute_selection(app: &mut App, command: &str) -> Result<(), String> {
+    let parts: Vec<&str> = command.split_whitespace().collect();
+    let offset = parts.get(2).and_then(|arg| arg.split_once(','))
+        .and_then(|(dx, dy)| Some((dx.trim().parse::<i32>().ok()?, dy.trim().parse::<i32>().ok()?)));
+    match (parts.get(1).copied(), offset) {
+        (Some("clear"), _) => { app.clear_selection(); Ok(()) }
+        (Some("delete"), _) => reported(app, App::delete_selection),
+        (Some("fill"), _) => reported(app, App::fill_selection),
+        (Some("move"), Some((dx, dy))) => reported(app, |app| app.move_selection(dx, dy)),
+        _ => Err("Usage: selection {clear|delete|fill|move <dx>,<dy>}".to_string()),
+    }
+}
 
+fn parse_and_execute_symmetry(app: &mut App, command: &str) -> Result<(), String> {
+    let spec = command.strip_prefix("symmetry=").unwrap_or("");
+    let (mode, coord) = spec.split_once(':').unwrap_or((spec, ""));
+    let coord = coord.trim().parse::<i32>().ok();
+    let max_x = app.canvas_width.saturating_sub(1) as i32;
+    let max_y = app.canvas_height.saturating_sub(1) as i32;
+    let new_mode = match (mode, coord) {
+        ("off", _) => SymmetryMode::Off,
+        ("vertical", Some(x)) => SymmetryMode::Vertical(x.clamp(0, max_x) as u16),
+        ("horizontal", Some(y)) => SymmetryMode::Horizontal(y.clamp(0, max_y) as u16),
+        ("diag_fwd", Some(c)) => SymmetryMode::DiagonalForward(c),
+        ("diag_bwd", Some(c)) => SymmetryMode::DiagonalBackward(c),
+        _ => return Err("Usage: symmetry=<vertical|horizontal|diag_fwd|diag_bwd|off>:<coord>".to_string()),
+    };
+    app.symmetry_mode = new_mode;
+    app.set_status(format!("Symmetry set to {}", spec));
+    Ok(())
+}
 
+fn parse_and_execute_text(app: &mut App, command: &str) -> Result<(), String> {
+    // Quotes keep spaces together, so both `text="HI THERE"` and `text "HI THERE" 2,2` work.
+    let args = utils::split_args(command);
+    let (text, rest) = match args[0].strip_prefix("text=") {
+        Some(text) => (text.to_string(), &args[1..]),
+        None => match args.get(1) {
+            Some(text) => (text.clone(), &args[2..]),
+            None => (String::new(), &args[1..]),
+        },
+    };
+    let position = rest.iter().find_map(|arg| {
+        let (x, y) = arg.split_once(',')?;
+        Some((x.trim().parse::<usize>().ok()?, y.trim().parse::<usize>().ok()?))
+    });
+    if text.is_empty() {
+        return Err("Usage: text=<TEXT> | text \"<TEXT>\" [x,y] [--wrap]".to_string());
+    }
+    let (x, y) = position.unwrap_or((app.cursor_pos.0 as usize, app.cursor_pos.1 as usize));
+    reported(app, |app| app.stamp_text(&text, x, y, rest.iter().any(|arg| arg == "--wrap")))
+}
 
+fn parse_and_execute_tile(app: &mut App, command: &str) -> Result<(), String> {
+    let parts: Vec<&str> = command.split_whitespace().collect();
+    let blend = parts.contains(&"--blend");
+    match parts.get(1).copied() {
+        Some("copy") => reported(app, App::copy_tile),
+        Some("paste") => reported(app, |app| app.paste_tile(blend)),
+        Some("fill") => reported(app, |app| app.fill_tiles(blend)),
+        _ => Err("Usage: tile {copy|paste|fill} [--blend]".to_string()),
+    }
+}
 
-    ];
\ No newline at end of file
+fn parse_and_execute_export_palette(app: &mut App, name: &str, args: &[&str]) {
+    const USAGE: &str = "Usage: exportpalette:<name> [--format=gpl|hex]";
+    // Without --format, a .hex name picks that format and anything else gets .gpl.
+    let mut format = match PaletteFormat::from_path(Path::new(name)) {
+        PaletteFormat::Hex => PaletteFormat::Hex,
+        _ => PaletteFormat::Gpl,
+    };
+    for arg in args {
+        format = match *arg {
+            "--format=gpl" => PaletteFormat::Gpl,
+            "--format=hex" => PaletteFormat::Hex,
+            _ => { app.set_status(USAGE.to_string()); return; }
+        };
+    }
+    if name.is_empty() {
+        app.set_status(USAGE.to_string());
+        return;
+    }
+    let final_path = match utils::normalize_output_path(Path::new(""), name, format.extension()) {
+        Ok(path) => path,
+        Err(e) => { app.set_status(format!("Error: {}", e)); return; }
+    };
+    if let Err(e) = utils::create_parent_dirs(&final_path) {
+        app.set_status(format!("Error creating directory: {}", e));
+        return;
+    }
+    app.export_palette(&final_path, format);
+}
+
+fn parse_and_execute_import_palette(app: &mut App, command: &str) -> Result<(), String> {
+    let parts: Vec<&str> = command.split_whitespace().collect();
+    if parts.contains(&"--explorer") {
+        file_browser::open_browser(app, file_browser::BrowserMode::ImportPalette);
+        return Ok(());
+    }
+    match parts.get(2) {
+        Some(path_str) => reported(app, |app| app.load_and_store_palette(path_str)),
+        None => Err("Usage: import palette <path>".to_string()),
+    }
+}
+
+fn parse_and_execute_import_image(app: &mut App, command: &str) -> Result<(), String> {
+    const USAGE: &str = "Usage: import image {<path>|--explorer} [-s factor] [--fit] [--box] [--new-layer]";
+    // Quotes keep paths with spaces together.
+    let args = utils::split_args(command);
+    let mut path = None;
+    let (mut downscale, mut fit, mut explorer) = (1u32, false, false);
+    let (mut resample, mut new_layer) = (crate::ImportResample::Nearest, false);
+    let mut i = 2;
+    while i < args.len() {
+        match (args[i].as_str(), args.get(i + 1)) {
+            ("-s", Some(factor)) => match factor.parse::<u32>() {
+                Ok(factor) if factor > 0 => { downscale = factor; i += 1; },
+                _ => { return Err(format!("Error: Invalid downscale factor '{}'.", factor)); }
+            },
+            ("--fit", _) => fit = true,
+            ("--box", _) => resample = crate::ImportResample::Box,
+            ("--new-layer", _) => new_layer = true,
+            ("--explorer", _) => explorer = true,
+            (arg, _) if !arg.starts_with('-') && path.is_none() => path = Some(arg.to_string()),
+            _ => { return Err(USAGE.to_string()); }
+        }
+        i += 1;
+    }
+
+    if explorer {
+        file_browser::open_browser(app, file_browser::BrowserMode::ImportImage { new_layer, resample });
+        return Ok(());
+    }
+    let Some(path) = path else {
+        return Err(USAGE.to_string());
+    };
+    let path = shellexpand::tilde(&path).into_owned();
+    reported(app, |app| app.import_image(Path::new(&path), downscale, fit, resample, new_layer))
+}
@@ -20,15 +20,51 @@ pub enum ConfigSetting {
     ApplyColorInterval,
     MinimapMode,
     MouseEvents,
+    HoverCursor,
     ColorMode,
     CanvasScrollAction,
     SpraySize,
     SpraySpeed,
     SprayIntensity,
+    SprayFalloff,
+    PickFrom,
+    ColorInfo,
+    PickAlpha,
+    SelectionMask,
+    RadialSegments,
     SnapToPalette,
     SnapToPaletteMode,
     ProtectColorTransitions,
     PaletteMenuPosition,
+    Antialias,
+    FlatMode,
+    LinearBlending,
+    Stabilizer,
+    FillShapes,
+    PerfectCircles,
+    DitherPattern,
+    CloneSampleMerged,
+    FillMode,
+    FillTolerance,
+    ColorJitter,
+    SoftEraser,
+    BlurRadius,
+    TransparencyChecker,
+    CheckerSize,
+    PixelGrid,
+    PixelGridMinZoom,
+    MaxZoom,
+    MajorGridSpacing,
+    OnionSkinFramesBefore,
+    OnionSkinFramesAfter,
+    PlaybackFps,
+    PaletteColors,
+    RestoreSession,
+    AutosaveRotations,
+    PlainProjectSave,
+    EmbedPalettes,
+    UndoLimit,
+    UndoMemoryMb,
 
 
 
@@ -51,15 +87,51 @@ impl ConfigSetting {
             Self::ApplyColorInterval => format!("{:.2}", app.apply_color_interval.num_milliseconds() as f32 / 1000.0),
             Self::MinimapMode => format!("{:?}", app.minimap_mode),
             Self::MouseEvents => app.mouse_events_enabled.to_string(),
+            Self::HoverCursor => app.hover_cursor.to_string(),
             Self::ColorMode => format!("{:?}", app.color_mode),
             Self::CanvasScrollAction => format!("{:?}", app.canvas_scroll_action),
             Self::SpraySize => app.spray_size.to_string(),
             Self::SpraySpeed => app.spray_speed.to_string(),
             Self::SprayIntensity => format!("{:.2}", app.spray_intensity),
+            Self::SprayFalloff => app.spray_falloff.to_string(),
+            Self::PickFrom => format!("{:?}", app.pick_from),
+            Self::ColorInfo => format!("{:?}", app.color_info),
+            Self::PickAlpha => app.pick_alpha.to_string(),
+            Self::SelectionMask => app.selection_mask.to_string(),
+            Self::RadialSegments => app.radial_segments.to_string(),
             Self::SnapToPalette => app.snap_to_palette.to_string(),
             Self::SnapToPaletteMode => format!("{:?}", app.snap_to_palette_mode),
             Self::ProtectColorTransitions => app.protect_color_transitions.to_string(),
             Self::PaletteMenuPosition => format!("{:?}", app.palette_menu_position),
+            Self::Antialias => app.antialias.to_string(),
+            Self::FlatMode => app.flat_mode.to_string(),
+            Self::LinearBlending => app.linear_blending.to_string(),
+            Self::Stabilizer => app.stabilizer.to_string(),
+            Self::FillShapes => app.fill_shapes.to_string(),
+            Self::PerfectCircles => app.perfect_circles.to_string(),
+            Self::DitherPattern => format!("{:?}", app.dither_pattern),
+            Self::CloneSampleMerged => app.clone_sample_merged.to_string(),
+            Self::FillMode => format!("{:?}", app.fill_mode),
+            Self::FillTolerance => app.fill_tolerance.to_string(),
+            Self::ColorJitter => format!("{:.2}", app.color_jitter),
+            Self::SoftEraser => app.soft_eraser.to_string(),
+            Self::BlurRadius => app.blur_radius.to_string(),
+            Self::TransparencyChecker => app.transparency_checker.to_string(),
+            Self::CheckerSize => app.checker_size.to_string(),
+            Self::PixelGrid => app.pixel_grid.to_string(),
+            Self::PixelGridMinZoom => app.pixel_grid_min_zoom.to_string(),
+            Self::MaxZoom => app.max_zoom.to_string(),
+            Self::MajorGridSpacing => app.major_grid_spacing.to_string(),
+            Self::OnionSkinFramesBefore => app.onion_skin_frames_before.to_string(),
+            Self::OnionSkinFramesAfter => app.onion_skin_frames_after.to_string(),
+            Self::PlaybackFps => app.playback_fps.to_string(),
+            Self::PaletteColors => app.palette_colors.to_string(),
+            Self::RestoreSession => app.restore_session.to_string(),
+            Self::AutosaveRotations => app.autosave_rotations.to_string(),
+            Self::PlainProjectSave => app.plain_project_save.to_string(),
+            Self::EmbedPalettes => app.embed_palettes.to_string(),
+            Self::UndoLimit => app.undo_limit.to_string(),
+            Self::UndoMemoryMb => if app.undo_memory_mb == 0 { "off".to_string() } else { format!("{} MB", app.undo_memory_mb) },
 
 
         }
@@ -79,12 +151,36 @@ impl ConfigSetting {
                 MinimapMode::Off => MinimapMode::Auto,
             },
             Self::MouseEvents => app.mouse_events_enabled = !app.mouse_events_enabled,
+            Self::HoverCursor => app.hover_cursor = !app.hover_cursor,
             Self::ColorMode => app.color_mode = if app.color_mode == ColorMode::TrueColor { ColorMode::Ansi256 } else { ColorMode::TrueColor },
             Self::CanvasScrollAction => app.canvas_scroll_action = if app.canvas_scroll_action == CanvasScrollAction::ChangePenSize { CanvasScrollAction::ChangeOpacity } else { CanvasScrollAction::ChangePenSize },
             Self::SnapToPalette => app.snap_to_palette = !app.snap_to_palette,
             Self::SnapToPaletteMode => app.snap_to_palette_mode = if app.snap_to_palette_mode == crate::SnapToPaletteMode::ClosestRgb { crate::SnapToPaletteMode::ClosestHue } else { crate::SnapToPaletteMode::ClosestRgb },
             Self::ProtectColorTransitions => app.protect_color_transitions = !app.protect_color_transitions,
             Self::PaletteMenuPosition => app.palette_menu_position = if app.palette_menu_position == crate::PaletteMenuPosition::Left { crate::PaletteMenuPosition::Right } else { crate::PaletteMenuPosition::Left },
+            Self::Antialias => app.antialias = !app.antialias,
+            Self::FlatMode => app.flat_mode = !app.flat_mode,
+            Self::LinearBlending => app.set_linear_blending(!app.linear_blending),
+            Self::FillShapes => app.fill_shapes = !app.fill_shapes,
+            Self::PerfectCircles => app.perfect_circles = !app.perfect_circles,
+            Self::CloneSampleMerged => app.clone_sample_merged = !app.clone_sample_merged,
+            Self::SoftEraser => app.soft_eraser = !app.soft_eraser,
+            Self::SprayFalloff => app.spray_falloff = !app.spray_falloff,
+            Self::PickFrom => app.pick_from = if app.pick_from == crate::PickSource::ActiveLayer { crate::PickSource::Composite } else { crate::PickSource::ActiveLayer },
+            Self::ColorInfo => app.color_info = app.color_info.next(),
+            Self::PickAlpha => app.pick_alpha = !app.pick_alpha,
+            Self::SelectionMask => app.selection_mask = !app.selection_mask,
+            Self::TransparencyChecker => app.transparency_checker = !app.transparency_checker,
+            Self::PixelGrid => app.pixel_grid = !app.pixel_grid,
+            Self::RestoreSession => app.restore_session = !app.restore_session,
+            Self::PlainProjectSave => app.plain_project_save = !app.plain_project_save,
+            Self::EmbedPalettes => app.embed_palettes = !app.embed_palettes,
+            Self::FillMode => app.fill_mode = if app.fill_mode == crate::FillMode::Contiguous { crate::FillMode::Global } else { crate::FillMode::Contiguous },
+            Self::DitherPattern => app.dither_pattern = match app.dither_pattern {
+                crate::DitherPattern::Light => crate::DitherPattern::Checker,
+                crate::DitherPattern::Checker => crate::DitherPattern::Dense,
+                crate::DitherPattern::Dense => crate::DitherPattern::Light,
+            },
 
 
             _ => {}
@@ -102,6 +198,22 @@ impl ConfigSetting {
             Self::HighlighterValue => app.highlighter_value = (app.highlighter_value + 0.05).clamp(0.0, 1.0),
             Self::ShadeFactor => app.shade_factor = (app.shade_factor + 0.005).clamp(0.01, 1.0),
             Self::SpraySize => app.spray_size = app.spray_size.saturating_add(1).clamp(1, 50),
+            Self::Stabilizer => app.stabilizer = app.stabilizer.saturating_add(1).min(10),
+            Self::FillTolerance => app.fill_tolerance = app.fill_tolerance.saturating_add(1),
+            Self::ColorJitter => app.color_jitter = (app.color_jitter + 0.05).clamp(0.0, 1.0),
+            Self::BlurRadius => app.blur_radius = (app.blur_radius + 1).min(5),
+            Self::CheckerSize => app.checker_size = (app.checker_size + 1).min(16),
+            Self::AutosaveRotations => app.autosave_rotations = (app.autosave_rotations + 1).min(20),
+            Self::UndoLimit => app.set_undo_limit(app.undo_limit.saturating_add(10)),
+            Self::UndoMemoryMb => app.set_undo_memory_mb(app.undo_memory_mb.saturating_add(64).min(8192)),
+            Self::PixelGridMinZoom => app.pixel_grid_min_zoom = (app.pixel_grid_min_zoom + 1).min(32),
+            Self::MaxZoom => app.max_zoom = (app.max_zoom + 1).min(32),
+            Self::MajorGridSpacing => app.major_grid_spacing = (app.major_grid_spacing + 1).min(256),
+            Self::OnionSkinFramesBefore => app.onion_skin_frames_before = (app.onion_skin_frames_before + 1).min(3),
+            Self::OnionSkinFramesAfter => app.onion_skin_frames_after = (app.onion_skin_frames_after + 1).min(3),
+            Self::PlaybackFps => app.playback_fps = (app.playback_fps + 1).min(30),
+            Self::PaletteColors => app.palette_colors = (app.palette_colors + 1).min(64),
+            Self::RadialSegments => app.set_radial_segments(app.radial_segments + 1),
             Self::SpraySpeed => app.spray_speed = app.spray_speed.saturating_add(1).clamp(1, 100),
             Self::SprayIntensity => app.spray_intensity = (app.spray_intensity + 0.05).clamp(0.0, 1.0),
             Self::SnapToPalette => self.cycle_value(app),
@@ -125,6 +237,25 @@ impl ConfigSetting {
             Self::HighlighterValue => app.highlighter_value = (app.highlighter_value - 0.05).clamp(0.0, 1.0),
             Self::ShadeFactor => app.shade_factor = (app.shade_factor - 0.005).clamp(0.01, 1.0),
             Self::SpraySize => app.spray_size = app.spray_size.saturating_sub(1).max(1),
+            Self::Stabilizer => app.stabilizer = app.stabilizer.saturating_sub(1),
+            Self::FillTolerance => app.fill_tolerance = app.fill_tolerance.saturating_sub(1),
+            Self::ColorJitter => app.color_jitter = (app.color_jitter - 0.05).clamp(0.0, 1.0),
+            Self::BlurRadius => app.blur_radius = app.blur_radius.saturating_sub(1).max(1),
+            Self::CheckerSize => app.checker_size = app.checker_size.saturating_sub(1).max(1),
+            Self::AutosaveRotations => app.autosave_rotations = app.autosave_rotations.saturating_sub(1).max(1),
+            Self::UndoLimit => app.set_undo_limit(app.undo_limit.saturating_sub(10)),
+            Self::UndoMemoryMb => app.set_undo_memory_mb(app.undo_memory_mb.saturating_sub(64)),
+            Self::PixelGridMinZoom => app.pixel_grid_min_zoom = app.pixel_grid_min_zoom.saturating_sub(1).max(2),
+            Self::MaxZoom => {
+                app.max_zoom = app.max_zoom.saturating_sub(1).max(4);
+                app.zoom(0);
+            }
+            Self::MajorGridSpacing => app.major_grid_spacing = app.major_grid_spacing.saturating_sub(1),
+            Self::OnionSkinFramesBefore => app.onion_skin_frames_before = app.onion_skin_frames_before.saturating_sub(1),
+            Self::OnionSkinFramesAfter => app.onion_skin_frames_after = app.onion_skin_frames_after.saturating_sub(1),
+            Self::PlaybackFps => app.playback_fps = app.playback_fps.saturating_sub(1).max(1),
+            Self::PaletteColors => app.palette_colors = app.palette_colors.saturating_sub(1).max(4),
+            Self::RadialSegments => app.set_radial_segments(app.radial_segments.saturating_sub(1)),
             Self::SpraySpeed => app.spray_speed = app.spray_speed.saturating_sub(1).max(1),
             Self::SprayIntensity => app.spray_intensity = (app.spray_intensity - 0.05).clamp(0.0, 1.0),
             Self::SnapToPalette => self.cycle_value(app),
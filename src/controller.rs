@@ -1,5 +1,5 @@
 // controller.rs
-use crate::{App, AppMode, PIXEL_WIDTH, execute_command, Config, file_browser};
+use crate::{App, AppMode, QuitChoice, MAX_CANVAS_SIZE, PIXEL_WIDTH, execute_command_line, Config, file_browser, color_editor, command_palette};
 
 use crate::keybindings::{Action, Keybinding, Keybindings};
 use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, MouseEvent, MouseEventKind, MouseButton};
@@ -13,15 +13,19 @@ use strum::IntoEnumIterator;
 use crate::config::ConfigSetting;
 use unicode_segmentation::UnicodeSegmentation;
 
+// Two clicks on the same layer row within this long start a rename.
+const DOUBLE_CLICK_TIME: std::time::Duration = std::time::Duration::from_millis(400);
+
 
 pub fn handle_events(app: &mut App) -> Result<()> {
     if event::poll(std::time::Duration::from_millis(20))? {
+        app.needs_redraw = true;
         match event::read()? {
             Event::Key(key) => handle_key_event(app, key)?,
             Event::Mouse(mouse_event) => {
                 if app.mode == AppMode::FileBrowser {
                     file_browser::handle_browser_input(app, None, Some(mouse_event));
-                } else if app.mouse_events_enabled {
+                } else if app.mouse_events_enabled && !matches!(app.mode, AppMode::CommandPalette | AppMode::InfoPopup) {
                     handle_mouse_event(app, mouse_event);
                 }
             },
@@ -32,8 +36,47 @@ pub fn handle_events(app: &mut App) -> Result<()> {
 }
 
 pub fn handle_mouse_event(app: &mut App, mouse_event: MouseEvent) {
+    if app.mode == AppMode::ColorEditor {
+        color_editor::handle_color_editor_mouse(app, mouse_event);
+        return;
+    }
 
+    // A pan keeps following the mouse wherever it goes until the button is released.
+    if app.pan_anchor.is_some() {
+        match mouse_event.kind {
+            MouseEventKind::Drag(_) => app.drag_pan_to(mouse_event.column, mouse_event.row),
+            MouseEventKind::Up(_) => app.pan_anchor = None,
+            _ => {}
+        }
+        return;
+    }
+    if app.minimap_dragging {
+        match mouse_event.kind {
+            MouseEventKind::Drag(MouseButton::Left) => app.center_view_on_minimap(mouse_event.column, mouse_event.row),
+            MouseEventKind::Up(_) => app.minimap_dragging = false,
+            _ => {}
+        }
+        return;
+    }
 
+    // A dragged layer row follows the mouse until release, even outside the panel.
+    if let Some((from, _)) = app.layer_drag {
+        match mouse_event.kind {
+            MouseEventKind::Drag(MouseButton::Left) => {
+                if let (Some(layer_area), Some(last)) = (app.last_layer_area, app.layers.len().checked_sub(1)) {
+                    let row = mouse_event.row.clamp(layer_area.y, layer_area.bottom().saturating_sub(1)) - layer_area.y;
+                    app.layer_drag = Some((from, (app.layer_scroll_state + row as usize).min(last)));
+                }
+            }
+            MouseEventKind::Up(_) => {
+                if let Some((from, to)) = app.layer_drag.take() {
+                    app.move_layer_to(from, to);
+                }
+            }
+            _ => {}
+        }
+        return;
+    }
 
     if let Some(layer_area) = app.last_layer_area {
         if mouse_event.row >= layer_area.y && mouse_event.row < layer_area.bottom() && 
@@ -43,10 +86,26 @@ pub fn handle_mouse_event(app: &mut App, mouse_event: MouseEvent) {
                     let clicked_row = (mouse_event.row - layer_area.y) as usize;
                     let clicked_index = app.layer_scroll_state + clicked_row;
                     if clicked_index < app.layers.len() {
-                        app.active_layer_index = clicked_index;
-                        app.sync_canvas_from_layers();
+                        let double_click = app.last_layer_click.is_some_and(|(index, at)| index == clicked_index && at.elapsed() < DOUBLE_CLICK_TIME);
+                        if app.is_renaming_layer && clicked_index != app.active_layer_index {
+                            app.cancel_layer_rename();
+                        }
+                        app.set_active_layer(clicked_index);
+                        if double_click {
+                            app.last_layer_click = None;
+                            if !app.is_renaming_layer { app.start_layer_rename(); }
+                        } else {
+                            app.last_layer_click = Some((clicked_index, Instant::now()));
+                        }
+                        app.layer_drag = Some((clicked_index, clicked_index));
                     }
                 }
+                // Alt+scroll over a row steps that layer's opacity instead of scrolling the list.
+                MouseEventKind::ScrollUp | MouseEventKind::ScrollDown if mouse_event.modifiers == event::KeyModifiers::ALT => {
+                    let index = app.layer_scroll_state + (mouse_event.row - layer_area.y) as usize;
+                    let delta = if mouse_event.kind == MouseEventKind::ScrollUp { 0.05 } else { -0.05 };
+                    app.nudge_layer_opacity(index, delta);
+                }
                 MouseEventKind::ScrollUp => {
                     app.layer_scroll_state = app.layer_scroll_state.saturating_sub(1);
                 }
@@ -62,6 +121,20 @@ pub fn handle_mouse_event(app: &mut App, mouse_event: MouseEvent) {
 
 
 
+    if let Some(recent_area) = app.last_recent_colors_area {
+        if mouse_event.row >= recent_area.y && mouse_event.row < recent_area.bottom() && mouse_event.column >= recent_area.x && mouse_event.column < recent_area.right() {
+            if app.mode != AppMode::Drawing && app.mode != AppMode::ColorPicker {
+                return;
+            }
+            if let MouseEventKind::Down(MouseButton::Left) = mouse_event.kind {
+                app.select_recent_color(((mouse_event.column - recent_area.x) / 3) as usize);
+                app.mode = AppMode::Drawing;
+            }
+            app.is_mouse_dragging = false;
+            return;
+        }
+    }
+
     if let Some(palette_area) = app.last_palette_area {
         if mouse_event.row >= palette_area.y && mouse_event.row < palette_area.bottom() && mouse_event.column >= palette_area.x && mouse_event.column < palette_area.right() {
             let columns = (palette_area.width / 3).max(1) as usize;
@@ -88,7 +161,7 @@ pub fn handle_mouse_event(app: &mut App, mouse_event: MouseEvent) {
                     app.palette_scroll_state = app.palette_scroll_state.saturating_sub(columns);
                 }
                 MouseEventKind::ScrollDown => {
-                    let total_rows = (app.color_palette.len() + columns - 1) / columns;
+                    let total_rows = app.color_palette.len().div_ceil(columns);
                     let visible_rows = palette_area.height as usize;
                     let max_scroll_row = total_rows.saturating_sub(visible_rows);
                     let max_scroll = max_scroll_row * columns;
@@ -114,9 +187,12 @@ pub fn handle_mouse_event(app: &mut App, mouse_event: MouseEvent) {
     if let Some(tool_area) = app.last_tool_area {
         if mouse_event.row >= tool_area.y && mouse_event.row < tool_area.bottom() && mouse_event.column >= tool_area.x && mouse_event.column < tool_area.right() {
             if let MouseEventKind::Down(MouseButton::Left) = mouse_event.kind {
+                let columns = (tool_area.width / 3).max(1) as usize;
                 let col = ((mouse_event.column - tool_area.x) / 3) as usize;
-                if col < app.tool_palette.len() {
-                    app.tool_index = col;
+                let row = (mouse_event.row - tool_area.y) as usize;
+                let index = row * columns + col;
+                if col < columns && index < app.tool_palette.len() {
+                    app.tool_index = index;
                     app.select_tool_entry();
                 }
             }
@@ -127,16 +203,43 @@ pub fn handle_mouse_event(app: &mut App, mouse_event: MouseEvent) {
 
 
 
+    // The minimap sits on top of the canvas: click or drag to move the view, scroll to zoom.
+    if let Some(minimap_area) = app.last_minimap_area {
+        if mouse_event.row >= minimap_area.y && mouse_event.row < minimap_area.bottom() && mouse_event.column >= minimap_area.x && mouse_event.column < minimap_area.right() {
+            match mouse_event.kind {
+                MouseEventKind::Down(MouseButton::Left) => {
+                    app.minimap_dragging = true;
+                    app.center_view_on_minimap(mouse_event.column, mouse_event.row);
+                }
+                MouseEventKind::ScrollUp => app.zoom(2),
+                MouseEventKind::ScrollDown => app.zoom(-2),
+                _ => {}
+            }
+            return;
+        }
+    }
+
 if let Some(canvas_rect) = app.last_centered_canvas_rect {
     if mouse_event.column >= canvas_rect.x && mouse_event.column < canvas_rect.right() &&
        mouse_event.row >= canvas_rect.y && mouse_event.row < canvas_rect.bottom() {
         
         match mouse_event.kind {
+            // Middle drag pans; so does Shift+left drag, except with a shape tool where
+            // Shift constrains the shape.
+            MouseEventKind::Down(MouseButton::Middle) => {
+                app.pan_anchor = Some(((mouse_event.column, mouse_event.row), (app.view_offset_x, app.view_offset_y)));
+                return;
+            },
+            MouseEventKind::Down(MouseButton::Left)
+                if mouse_event.modifiers.contains(event::KeyModifiers::SHIFT) && app.shape_tool.is_none() => {
+                app.pan_anchor = Some(((mouse_event.column, mouse_event.row), (app.view_offset_x, app.view_offset_y)));
+                return;
+            },
             MouseEventKind::ScrollUp => {
                 match mouse_event.modifiers {
                     event::KeyModifiers::CONTROL => app.pan_view(0, -1),
                     event::KeyModifiers::SHIFT => app.pan_view(-2, 0),
-                    event::KeyModifiers::ALT => app.zoom(2),
+                    event::KeyModifiers::ALT => app.zoom_around(2, ((mouse_event.column - canvas_rect.x) as i32, (mouse_event.row - canvas_rect.y) as i32)),
                     _ => match app.canvas_scroll_action {
                         crate::CanvasScrollAction::ChangePenSize => app.change_pen_size(1),
                         crate::CanvasScrollAction::ChangeOpacity => app.change_opacity(1.0),
@@ -148,7 +251,7 @@ if let Some(canvas_rect) = app.last_centered_canvas_rect {
                 match mouse_event.modifiers {
                     event::KeyModifiers::CONTROL => app.pan_view(0, 1),
                     event::KeyModifiers::SHIFT => app.pan_view(2, 0),
-                    event::KeyModifiers::ALT => app.zoom(-2),
+                    event::KeyModifiers::ALT => app.zoom_around(-2, ((mouse_event.column - canvas_rect.x) as i32, (mouse_event.row - canvas_rect.y) as i32)),
                     _ => match app.canvas_scroll_action {
                         crate::CanvasScrollAction::ChangePenSize => app.change_pen_size(-1),
                         crate::CanvasScrollAction::ChangeOpacity => app.change_opacity(-1.0),
@@ -168,54 +271,253 @@ if let Some(canvas_rect) = app.last_centered_canvas_rect {
 
         if canvas_x_i32 < 0 || canvas_x_i32 >= app.canvas_width as i32 ||
            canvas_y_i32 < 0 || canvas_y_i32 >= app.canvas_height as i32 {
-            if let MouseEventKind::Up(_) = mouse_event.kind {
-                app.is_mouse_dragging = false;
-                if app.protect_stroke { app.drawn_pixels_in_stroke.clear(); }
+            if let MouseEventKind::Up(button) = mouse_event.kind {
+                finish_mouse_stroke(app, button);
             }
             return;
         }
         let canvas_x = canvas_x_i32 as u16;
         let canvas_y = canvas_y_i32 as u16;
 
+        // Hovering only moves the cursor, so the brush preview follows the mouse.
+        if mouse_event.kind == MouseEventKind::Moved {
+            if app.hover_cursor { app.cursor_pos = (canvas_x, canvas_y); }
+            return;
+        }
         app.cursor_pos = (canvas_x, canvas_y);
+        if app.shape_tool.is_some() {
+            // Shift turns ellipses into circles while dragging.
+            app.shape_constrained = mouse_event.modifiers.contains(event::KeyModifiers::SHIFT);
+        }
 
         match mouse_event.kind {
+            // Ctrl+click picks the layer under the pointer instead of painting.
+            MouseEventKind::Down(MouseButton::Left) if mouse_event.modifiers.contains(event::KeyModifiers::CONTROL) => {
+                app.select_layer_at(canvas_x as usize, canvas_y as usize);
+            },
+            // Shape tools anchor on press and only draw on release; the ui previews in between.
+            MouseEventKind::Down(MouseButton::Left) if app.shape_tool.is_some() => {
+                app.is_mouse_dragging = true;
+                app.shape_anchor = Some((canvas_x, canvas_y));
+            },
+            MouseEventKind::Drag(MouseButton::Left) if app.shape_tool.is_some() => {},
+            MouseEventKind::Down(MouseButton::Left) if app.replace_mode => app.replace_color_at(canvas_x as usize, canvas_y as usize),
+            MouseEventKind::Drag(MouseButton::Left) if app.replace_mode => {},
             MouseEventKind::Down(MouseButton::Left) => {
+                if app.active_layer_locked() { return; }
                 app.is_mouse_dragging = true;
                 if app.protect_stroke { app.drawn_pixels_in_stroke.clear(); }
-                app.save_state_for_undo();
+                app.save_state_for_undo(&app.stroke_label());
+                app.begin_stabilized_stroke(canvas_x, canvas_y);
                 app.apply_brush(canvas_x, canvas_y);
+                app.last_drag_point = Some((canvas_x, canvas_y));
             },
-            MouseEventKind::Drag(MouseButton::Left) => {
-                if app.is_mouse_dragging {
-                    app.apply_brush(canvas_x, canvas_y);
-                }
-            },
-            MouseEventKind::Up(MouseButton::Left) => {
-                app.is_mouse_dragging = false;
-                if app.protect_stroke { app.drawn_pixels_in_stroke.clear(); }
+            MouseEventKind::Drag(MouseButton::Left) if app.is_mouse_dragging => {
+                let (x, y) = app.stabilized_point(canvas_x, canvas_y);
+                app.drag_brush_to(x, y, false);
             },
+            MouseEventKind::Up(button @ MouseButton::Left) => finish_mouse_stroke(app, button),
             MouseEventKind::Down(MouseButton::Right) => {
+                if app.active_layer_locked() || app.active_layer_alpha_locked() { return; }
                 app.is_mouse_dragging = true;
                 if app.protect_stroke { app.drawn_pixels_in_stroke.clear(); }
-                app.save_state_for_undo();
+                app.save_state_for_undo("Erase stroke");
+                app.begin_stabilized_stroke(canvas_x, canvas_y);
                 app.erase_brush(canvas_x, canvas_y);
+                app.last_drag_point = Some((canvas_x, canvas_y));
             },
-            MouseEventKind::Drag(MouseButton::Right) => {
-                if app.is_mouse_dragging {
-                    app.erase_brush(canvas_x, canvas_y);
-                }
-            },
-            MouseEventKind::Up(MouseButton::Right) => {
-                app.is_mouse_dragging = false;
-                if app.protect_stroke { app.drawn_pixels_in_stroke.clear(); }
+            MouseEventKind::Drag(MouseButton::Right) if app.is_mouse_dragging => {
+                let (x, y) = app.stabilized_point(canvas_x, canvas_y);
+                app.drag_brush_to(x, y, true);
             },
+            MouseEventKind::Up(button @ MouseButton::Right) => finish_mouse_stroke(app, button),
             _ => {}
         }
     }
 }
 }
 
+
+// Commits a dragged shape, or paints whatever the stabilizer still holds so the
+// stroke ends where the mouse was released.
+fn finish_mouse_stroke(app: &mut App, button: MouseButton) {
+    if app.is_mouse_dragging && button == MouseButton::Left && app.shape_tool.is_some() {
+        if let (Some(tool), Some(anchor)) = (app.shape_tool, app.shape_anchor.take()) {
+            app.draw_shape(tool, (anchor.0 as i32, anchor.1 as i32), (app.cursor_pos.0 as i32, app.cursor_pos.1 as i32));
+        }
+        app.shape_constrained = false;
+    } else if app.is_mouse_dragging {
+        for (x, y) in app.flush_stabilizer() {
+            match button {
+                MouseButton::Left => app.drag_brush_to(x, y, false),
+                MouseButton::Right => app.drag_brush_to(x, y, true),
+                MouseButton::Middle => {}
+            }
+        }
+    }
+    app.is_mouse_dragging = false;
+    app.last_drag_point = None;
+    if app.protect_stroke { app.drawn_pixels_in_stroke.clear(); }
+    app.finish_undo_step();
+}
+
+// Runs a keybinding action as if its key had just been pressed in drawing mode.
+pub fn execute_action(app: &mut App, action: Action) -> Result<()> {
+    match action {
+        Action::MoveCursorUp => app.move_cursor(0, -1),
+        Action::MoveCursorDown => app.move_cursor(0, 1),
+        Action::MoveCursorLeft => app.move_cursor(-1, 0),
+        Action::MoveCursorRight => app.move_cursor(1, 0),
+        Action::PanViewUp => app.pan_view(0, -1),
+        Action::PanViewDown => app.pan_view(0, 1),
+        Action::PanViewLeft => app.pan_view(-1, 0),
+        Action::PanViewRight => app.pan_view(1, 0),
+        Action::ZoomIn => app.zoom(2),
+        Action::ZoomOut => app.zoom(-2),
+        Action::OpenCommandPrompt => { stdout().execute(Show)?.execute(SetCursorStyle::SteadyBlock)?; app.mode = AppMode::Command; app.input_buffer.clear(); app.command_cursor_pos = 0; },
+        Action::OpenColorPicker => {
+            app.selection_before_picker = Some(app.current_selection);
+            app.mode = AppMode::ColorPicker;
+        },
+        Action::OpenColorEditor => color_editor::open_color_editor(app, app.current_selection),
+        Action::OpenToolPicker => {
+            app.selection_before_picker = Some(app.current_selection);
+            app.mode = AppMode::ToolPicker;
+        },
+        Action::IncreasePenSize => app.change_pen_size(1),
+        Action::DecreasePenSize => app.change_pen_size(-1),
+        Action::IncreaseOpacity => app.change_opacity(1.0),
+        Action::DecreaseOpacity => app.change_opacity(-1.0),
+        Action::Undo => app.undo(),
+        Action::Redo => app.redo(),
+        Action::ShowUndoHistory => app.show_undo_history(),
+        Action::CycleSymmetry => app.cycle_symmetry_mode(),
+        Action::PickColor => app.pick_color_at_cursor(),
+        Action::Fill => app.fill_area(),
+        Action::Erase => app.erase_at_cursor(),
+        Action::Spray => {
+            if !app.is_spraying && !app.active_layer_locked() {
+                app.is_spraying = true;
+                app.save_state_for_undo("Spray");
+                app.apply_spray();
+                app.last_apply_time = Some(Local::now());
+            }
+        }
+
+
+        Action::SelectLayerUp => app.change_layer_selection(-1),
+        Action::SelectLayerDown => app.change_layer_selection(1),
+        Action::SelectLayerUnderCursor => app.select_layer_at(app.cursor_pos.0 as usize, app.cursor_pos.1 as usize),
+        Action::AddLayer => app.add_new_layer(),
+        Action::DeleteLayer => app.delete_active_layer(),
+        Action::ToggleLayerVisibility => app.toggle_layer_visibility(),
+        Action::CycleLayerBlendMode => app.cycle_layer_blend_mode(),
+        Action::ToggleLayerLock => app.toggle_layer_lock(),
+        Action::ToggleAlphaLock => app.toggle_alpha_lock(),
+        Action::RenameLayer => app.start_layer_rename(),
+        Action::MergeDown => app.merge_down(),
+        Action::MergeUp => app.merge_up(),
+        Action::MergeVisible => app.merge_visible_layers(false),
+        Action::Flatten => app.flatten_layers(false),
+        Action::PlayAnimation => app.start_playback(None),
+        Action::MoveLayerUp => app.move_layer_up(),
+        Action::MoveLayerDown => app.move_layer_down(),
+        Action::RepeatLastExport => app.repeat_last_export(),
+        Action::RepeatLastCommand => match app.command_history.first().cloned() {
+            Some(line) => { execute_command_line(app, &line); }
+            None => app.set_status("No command to repeat yet."),
+        },
+        Action::OpenCommandPalette => command_palette::open_command_palette(app),
+        Action::SnapCursorToTile => app.toggle_tile_snap(),
+        Action::DrawLine => app.anchor_or_draw_shape(),
+        Action::SetCloneSource => app.set_clone_source(),
+        Action::MagicWand => app.magic_wand(app.cursor_pos.0 as usize, app.cursor_pos.1 as usize),
+        Action::ToggleColorInfo => {
+            app.color_info = app.color_info.next();
+            let place = match app.color_info { crate::ColorInfo::Off => "off", crate::ColorInfo::StatusBar => "in the status bar", crate::ColorInfo::Cursor => "next to the cursor" };
+            app.set_status(format!("Color info {}.", place));
+        },
+        // Nudges wrap, since keeping art tileable is what they are for.
+        Action::ShiftLayerUp => { app.shift_layers(0, -(app.pixel_block as i32), true, false); },
+        Action::ShiftLayerDown => { app.shift_layers(0, app.pixel_block as i32, true, false); },
+        Action::ShiftLayerLeft => { app.shift_layers(-(app.pixel_block as i32), 0, true, false); },
+        Action::ShiftLayerRight => { app.shift_layers(app.pixel_block as i32, 0, true, false); },
+        Action::ToggleOnionSkin => {
+            app.onion_skin_enabled = !app.onion_skin_enabled;
+            app.set_status(format!("Onion Skin: {}", if app.onion_skin_enabled { "ON" } else { "OFF" }));
+        },
+        Action::ToggleTilePreview => {
+            app.tile_preview = !app.tile_preview;
+            app.set_status(format!("Tile Preview: {}", if app.tile_preview { "ON" } else { "OFF" }));
+        },
+        Action::ToggleReference => {
+            if app.reference.is_none() {
+                app.set_status("No reference image. Use `reference <path>`.");
+            } else {
+                app.reference_visible = !app.reference_visible;
+                app.set_status(format!("Reference: {}", if app.reference_visible { "ON" } else { "OFF" }));
+            }
+        },
+        Action::IncreaseOnionOpacity => {
+            app.onion_skin_opacity = (app.onion_skin_opacity + 0.1).min(1.0);
+            app.set_status(format!("Onion Opacity: {:.0}%", app.onion_skin_opacity * 100.0));
+        },
+        Action::DecreaseOnionOpacity => {
+            app.onion_skin_opacity = (app.onion_skin_opacity - 0.1).max(0.0);
+            app.set_status(format!("Onion Opacity: {:.0}%", app.onion_skin_opacity * 100.0));
+        },
+
+
+        Action::QuickSelectColorUp => { app.change_palette_selection_2d(0, -1); app.select_color_entry(); },
+        Action::QuickSelectColorDown => { app.change_palette_selection_2d(0, 1); app.select_color_entry(); },
+        Action::QuickSelectColorLeft => { app.change_palette_selection_2d(-1, 0); app.select_color_entry(); },
+        Action::QuickSelectColorRight => { app.change_palette_selection_2d(1, 0); app.select_color_entry(); },
+        Action::QuickSelectToolLeft => { app.change_tool_selection(-1); app.select_tool_entry(); },
+        Action::QuickSelectToolRight => { app.change_tool_selection(1); app.select_tool_entry(); },
+        Action::QuickSelectRecent => app.cycle_recent_color(),
+        Action::AdjustSymmetryNegative => match &mut app.symmetry_mode {
+            crate::SymmetryMode::Vertical(x) => *x = x.saturating_sub(1),
+            crate::SymmetryMode::Horizontal(y) => *y = y.saturating_add(1).min(app.canvas_height.saturating_sub(1) as u16),
+            crate::SymmetryMode::DiagonalForward(c) => *c -= 1,
+            crate::SymmetryMode::DiagonalBackward(c) => *c -= 1,
+            crate::SymmetryMode::Radial(_, x, y) => (*x, *y) = app.cursor_pos,
+            crate::SymmetryMode::Quad(x, _) => *x = x.saturating_sub(1),
+            _ => {}
+        },
+        Action::AdjustSymmetryPositive => match &mut app.symmetry_mode {
+            crate::SymmetryMode::Vertical(x) => *x = x.saturating_add(1).min(app.canvas_width.saturating_sub(1) as u16),
+            crate::SymmetryMode::Horizontal(y) => *y = y.saturating_sub(1),
+            crate::SymmetryMode::DiagonalForward(c) => *c += 1,
+            crate::SymmetryMode::DiagonalBackward(c) => *c += 1,
+            // Radial centers have two coordinates, so both keys move the center to the cursor.
+            crate::SymmetryMode::Radial(_, x, y) => (*x, *y) = app.cursor_pos,
+            crate::SymmetryMode::Quad(x, _) => *x = x.saturating_add(1).min(app.canvas_width.saturating_sub(1) as u16),
+            _ => {}
+        },
+        // Quad symmetry's horizontal line; the keys above move its vertical line.
+        Action::AdjustSymmetryYNegative => if let crate::SymmetryMode::Quad(_, y) = &mut app.symmetry_mode {
+            *y = y.saturating_sub(1);
+        },
+        Action::AdjustSymmetryYPositive => if let crate::SymmetryMode::Quad(_, y) = &mut app.symmetry_mode {
+            *y = y.saturating_add(1).min(app.canvas_height.saturating_sub(1) as u16);
+        },
+        Action::SetSymmetryAtCursor => app.set_symmetry_at_cursor(),
+        Action::FlipCanvasHorizontal => app.flip_canvas(true),
+        Action::Draw => {
+            if !app.is_space_held {
+                app.is_space_held = true;
+                if app.protect_stroke {
+                    app.drawn_pixels_in_stroke.clear();
+                }
+                app.use_current_tool();
+                app.last_apply_time = Some(Local::now());
+            }
+        },
+        Action::Quit => app.quit(),
+    }
+    Ok(())
+}
+
 pub fn handle_key_event(app: &mut App, key: KeyEvent) -> Result<()> {
     if app.is_changing_keybinding {
         if key.kind == KeyEventKind::Press {
@@ -244,6 +546,7 @@ pub fn handle_key_event(app: &mut App, key: KeyEvent) -> Result<()> {
             if key.code == binding.code && key.modifiers == binding.modifiers {
                 app.is_spraying = false;
                 app.last_apply_time = None;
+                app.finish_undo_step();
             }
         }
 
@@ -254,114 +557,46 @@ pub fn handle_key_event(app: &mut App, key: KeyEvent) -> Result<()> {
         return Ok(());
     }
 
+    // Any key stops playback, and does nothing else.
+    if app.playing.is_some() {
+        app.stop_playback();
+        return Ok(());
+    }
+
     match app.mode {
 
         AppMode::FileBrowser => {
             file_browser::handle_browser_input(app, Some(key), None);
         },
 
+        AppMode::CommandPalette => command_palette::handle_command_palette_input(app, key)?,
+
+        AppMode::ColorEditor => color_editor::handle_color_editor_input(app, key),
+
+        AppMode::InfoPopup => match key.code {
+            KeyCode::Esc | KeyCode::Enter => {
+                app.info_popup = None;
+                app.mode = AppMode::Drawing;
+            }
+            KeyCode::Up => app.info_popup_scroll = app.info_popup_scroll.saturating_sub(1),
+            KeyCode::Down => app.info_popup_scroll += 1,
+            _ => {}
+        },
+
         AppMode::Drawing => {
             // Only proceed if a non-modifier key was pressed.
             // This prevents Ctrl/Shift alone from triggering actions.
-            if !matches!(key.code, KeyCode::Modifier(_)) {
-            if let Some((action, _)) = app.keybindings.map.iter().find(|(_, &binding)| {
-                binding.code == key.code && binding.modifiers == key.modifiers
-            }) {
-                    match action {
-                        Action::MoveCursorUp => app.move_cursor(0, -1),
-                        Action::MoveCursorDown => app.move_cursor(0, 1),
-                        Action::MoveCursorLeft => app.move_cursor(-1, 0),
-                        Action::MoveCursorRight => app.move_cursor(1, 0),
-                        Action::PanViewUp => app.pan_view(0, -1),
-                        Action::PanViewDown => app.pan_view(0, 1),
-                        Action::PanViewLeft => app.pan_view(-1, 0),
-                        Action::PanViewRight => app.pan_view(1, 0),
-                        Action::ZoomIn => app.zoom(2),
-                        Action::ZoomOut => app.zoom(-2),
-                        Action::OpenCommandPrompt => { stdout().execute(Show)?.execute(SetCursorStyle::SteadyBlock)?; app.mode = AppMode::Command; app.input_buffer.clear(); app.command_cursor_pos = 0; },
-                        Action::OpenColorPicker => {
-                            app.selection_before_picker = Some(app.current_selection);
-                            app.mode = AppMode::ColorPicker;
-                        },
-                        Action::OpenToolPicker => {
-                            app.selection_before_picker = Some(app.current_selection);
-                            app.mode = AppMode::ToolPicker;
-                        },
-                        Action::IncreasePenSize => app.change_pen_size(1),
-                        Action::DecreasePenSize => app.change_pen_size(-1),
-                        Action::IncreaseOpacity => app.change_opacity(1.0),
-                        Action::DecreaseOpacity => app.change_opacity(-1.0),
-                        Action::Undo => app.undo(),
-                        Action::Redo => app.redo(),
-                        Action::CycleSymmetry => app.cycle_symmetry_mode(),
-                        Action::PickColor => app.pick_color_at_cursor(),
-                        Action::Fill => app.fill_area(),
-                        Action::Erase => app.erase_at_cursor(),
-                        Action::Spray => {
-                            if !app.is_spraying {
-                                app.is_spraying = true;
-                                app.save_state_for_undo();
-                                app.apply_spray();
-                                app.last_apply_time = Some(Local::now());
-                            }
-                        }
-
-
-                        Action::SelectLayerUp => app.change_layer_selection(-1),
-                        Action::SelectLayerDown => app.change_layer_selection(1),
-                        Action::AddLayer => app.add_new_layer(),
-                        Action::DeleteLayer => app.delete_active_layer(),
-                        Action::ToggleLayerVisibility => app.toggle_layer_visibility(),
-                        Action::MoveLayerUp => app.move_layer_up(),
-                        Action::MoveLayerDown => app.move_layer_down(),
-                        Action::ToggleOnionSkin => {
-                            app.onion_skin_enabled = !app.onion_skin_enabled;
-                            app.status_message = Some((format!("Onion Skin: {}", if app.onion_skin_enabled { "ON" } else { "OFF" }), Instant::now()));
-                        },
-                        Action::IncreaseOnionOpacity => {
-                            app.onion_skin_opacity = (app.onion_skin_opacity + 0.1).min(1.0);
-                            app.status_message = Some((format!("Onion Opacity: {:.0}%", app.onion_skin_opacity * 100.0), Instant::now()));
-                        },
-                        Action::DecreaseOnionOpacity => {
-                            app.onion_skin_opacity = (app.onion_skin_opacity - 0.1).max(0.0);
-                            app.status_message = Some((format!("Onion Opacity: {:.0}%", app.onion_skin_opacity * 100.0), Instant::now()));
-                        },
-
-
-                        Action::QuickSelectColorUp => { app.change_palette_selection_2d(0, -1); app.select_color_entry(); },
-                        Action::QuickSelectColorDown => { app.change_palette_selection_2d(0, 1); app.select_color_entry(); },
-                        Action::QuickSelectColorLeft => { app.change_palette_selection_2d(-1, 0); app.select_color_entry(); },
-                        Action::QuickSelectColorRight => { app.change_palette_selection_2d(1, 0); app.select_color_entry(); },
-                        Action::QuickSelectToolLeft => { app.change_tool_selection(-1); app.select_tool_entry(); },
-                        Action::QuickSelectToolRight => { app.change_tool_selection(1); app.select_tool_entry(); },
-                        Action::AdjustSymmetryNegative => match &mut app.symmetry_mode {
-                            crate::SymmetryMode::Vertical(x) => *x = x.saturating_sub(1),
-                            crate::SymmetryMode::Horizontal(y) => *y = y.saturating_add(1).min(app.canvas_height.saturating_sub(1) as u16),
-                            crate::SymmetryMode::DiagonalForward(c) => *c -= 1,
-                            crate::SymmetryMode::DiagonalBackward(c) => *c -= 1,
-                            _ => {}
-                        },
-                        Action::AdjustSymmetryPositive => match &mut app.symmetry_mode {
-                            crate::SymmetryMode::Vertical(x) => *x = x.saturating_add(1).min(app.canvas_width.saturating_sub(1) as u16),
-                            crate::SymmetryMode::Horizontal(y) => *y = y.saturating_sub(1),
-                            crate::SymmetryMode::DiagonalForward(c) => *c += 1,
-                            crate::SymmetryMode::DiagonalBackward(c) => *c += 1,
-                            _ => {}
-                        },
-                            Action::Draw => {
-                                if !app.is_space_held {
-                                    app.is_space_held = true;
-                                    if app.protect_stroke {
-                                        app.drawn_pixels_in_stroke.clear();
-                                    }
-                                    app.use_current_tool();
-                                    app.last_apply_time = Some(Local::now());
-                                }
-                            },
-                
-                        Action::Quit => app.quit(),
-                    }
-                }
+            if app.is_renaming_layer {
+                handle_layer_rename_key(app, key);
+            } else if key.code == KeyCode::Esc && key.modifiers.is_empty() && !app.selection.is_empty() {
+                app.clear_selection();
+            } else if !matches!(key.code, KeyCode::Modifier(_)) {
+            let bound_action = app.keybindings.map.iter()
+                .find(|(_, &binding)| binding.code == key.code && binding.modifiers == key.modifiers)
+                .map(|(action, _)| *action);
+            if let Some(action) = bound_action {
+                execute_action(app, action)?;
+            }
             }
         },
         AppMode::Keybindings => match key.code {
@@ -406,7 +641,7 @@ pub fn handle_key_event(app: &mut App, key: KeyEvent) -> Result<()> {
                     else { app.mode = AppMode::Drawing; }
                 },
                 KeyCode::Up => app.config_selection_index = app.config_selection_index.saturating_sub(1),
-                KeyCode::Down => if app.config_selection_index < total_settings - 1 { app.config_selection_index += 1; },
+                KeyCode::Down if app.config_selection_index < total_settings - 1 => app.config_selection_index += 1,
                 KeyCode::Left => {
                     setting.decrement_value(app);
                     app.config_change_has_occured = true;
@@ -418,6 +653,35 @@ pub fn handle_key_event(app: &mut App, key: KeyEvent) -> Result<()> {
                 _ => {}
             }
         },
+        AppMode::ConfirmPaletteSwitch => match key.code {
+            KeyCode::Left | KeyCode::Right => app.confirm_selection_yes = !app.confirm_selection_yes,
+            KeyCode::Enter => app.confirm_palette_switch(app.confirm_selection_yes),
+            KeyCode::Esc => {
+                app.pending_palette_switch = None;
+                app.mode = AppMode::Drawing;
+            },
+            _ => {}
+        },
+        AppMode::ConfirmQuit => match key.code {
+            KeyCode::Left => app.quit_choice = app.quit_choice.previous(),
+            KeyCode::Right => app.quit_choice = app.quit_choice.next(),
+            KeyCode::Enter => app.confirm_quit(app.quit_choice),
+            KeyCode::Esc => app.confirm_quit(QuitChoice::No),
+            _ => {}
+        },
+        AppMode::UndoHistory => match key.code {
+            KeyCode::Up => app.undo_history_selection = app.undo_history_selection.saturating_sub(1),
+            KeyCode::Down => app.undo_history_selection = (app.undo_history_selection + 1).min(app.undo_stack.len() + app.redo_stack.len()),
+            KeyCode::Enter => app.jump_to_undo_history(app.undo_history_selection),
+            KeyCode::Esc => app.mode = AppMode::Drawing,
+            _ => {}
+        },
+        AppMode::ConfirmRestoreAutosave => match key.code {
+            KeyCode::Left | KeyCode::Right => app.confirm_selection_yes = !app.confirm_selection_yes,
+            KeyCode::Enter => app.confirm_restore_autosave(app.confirm_selection_yes),
+            KeyCode::Esc => app.confirm_restore_autosave(false),
+            _ => {}
+        },
         AppMode::ConfirmConfigSave => match key.code {
             KeyCode::Left | KeyCode::Right => app.confirm_selection_yes = !app.confirm_selection_yes,
             KeyCode::Enter => {
@@ -545,10 +809,10 @@ AppMode::ScriptEditor => {
             KeyCode::Enter => {
                 if app.confirm_selection_yes {
                     app.keybindings.save().unwrap_or_default();
-                    app.status_message = Some(("Keybindings saved.".to_string(), Instant::now()));
+                    app.set_status("Keybindings saved.");
                 } else {
                     app.keybindings = Keybindings::load();
-                    app.status_message = Some(("Keybinding changes discarded.".to_string(), Instant::now()));
+                    app.set_status("Keybinding changes discarded.");
                 }
                 app.keybinding_change_has_occured = false;
                 app.mode = AppMode::Drawing;
@@ -566,7 +830,8 @@ AppMode::ScriptEditor => {
             }
             KeyCode::Char('n') | KeyCode::Esc => {
                 app.pending_save_path = None;
-                app.status_message = Some(("Save cancelled.".to_string(), Instant::now()));
+                app.embed_palettes_once = false;
+                app.set_status("Save cancelled.");
                 app.mode = AppMode::Drawing;
             }
             _ => {}
@@ -584,7 +849,7 @@ AppMode::ScriptEditor => {
                 KeyCode::Enter => {
                     stdout().execute(Hide)?;
                     let command_to_run = app.input_buffer.trim().to_string();
-                    if !command_to_run.is_empty() && app.command_history.get(0) != Some(&command_to_run) {
+                    if !command_to_run.is_empty() && app.command_history.first() != Some(&command_to_run) {
                         app.command_history.insert(0, command_to_run.clone());
                     }
                     app.mode = AppMode::Drawing;
@@ -593,7 +858,7 @@ AppMode::ScriptEditor => {
                     app.suggestion_index = 0;
                     app.history_index = 0;
                     app.suggestion_active = false;
-                    execute_command(app, &command_to_run);
+                    execute_command_line(app, &command_to_run);
                 },
                 KeyCode::Char(c) => {
                     app.input_buffer.insert(app.command_cursor_pos, c);
@@ -602,16 +867,14 @@ AppMode::ScriptEditor => {
                     app.suggestion_active = false; // NEW: Typing deactivates suggestion selection
                     app.history_index = 0;
                 },
-                KeyCode::Backspace => {
-                    if app.command_cursor_pos > 0 {
-                        let current_pos = app.command_cursor_pos;
-                        let prev_pos = app.input_buffer[..current_pos].grapheme_indices(true).last().map_or(0, |(i, _)| i);
-                        app.input_buffer.drain(prev_pos..current_pos);
-                        app.command_cursor_pos = prev_pos;
-                        app.suggestion_active = false; // This deactivates selection mode
-                        app.suggestion_index = 0;      // This resets the selection to the top
-                        app.history_index = 0;
-                    }
+                KeyCode::Backspace if app.command_cursor_pos > 0 => {
+                    let current_pos = app.command_cursor_pos;
+                    let prev_pos = app.input_buffer[..current_pos].grapheme_indices(true).next_back().map_or(0, |(i, _)| i);
+                    app.input_buffer.drain(prev_pos..current_pos);
+                    app.command_cursor_pos = prev_pos;
+                    app.suggestion_active = false; // This deactivates selection mode
+                    app.suggestion_index = 0;      // This resets the selection to the top
+                    app.history_index = 0;
                 },
                 KeyCode::Left => {
                     let current_pos = app.command_cursor_pos;
@@ -660,16 +923,7 @@ AppMode::ScriptEditor => {
                 KeyCode::Tab => {
                     let suggestions = app.get_suggestions(&app.input_buffer);
                     if app.suggestion_active && !suggestions.is_empty() {
-                        let selected_suggestion = &suggestions[app.suggestion_index];
-                        
-                        let new_input = if app.input_buffer.starts_with("load ") {
-                            format!("load {}", selected_suggestion)
-                        } else if app.input_buffer.starts_with("colorpalette:") {
-                            format!("colorpalette:{}", selected_suggestion)
-                        } else {
-                            selected_suggestion.clone()
-                        };
-                        app.input_buffer = new_input;
+                        app.input_buffer = app.complete_with(&app.input_buffer, &suggestions[app.suggestion_index]);
                         app.command_cursor_pos = app.input_buffer.len();
                         app.suggestion_active = false;
                         app.suggestion_index = 0;
@@ -705,11 +959,19 @@ AppMode::ScriptEditor => {
                     }
                     app.mode = AppMode::Drawing;
                 },
+                // Shift moves the highlighted entry itself instead of the highlight.
+                KeyCode::Up if key.modifiers.contains(event::KeyModifiers::SHIFT) => app.shift_palette_entry(0, -1),
+                KeyCode::Down if key.modifiers.contains(event::KeyModifiers::SHIFT) => app.shift_palette_entry(0, 1),
+                KeyCode::Left if key.modifiers.contains(event::KeyModifiers::SHIFT) => app.shift_palette_entry(-1, 0),
+                KeyCode::Right if key.modifiers.contains(event::KeyModifiers::SHIFT) => app.shift_palette_entry(1, 0),
                 KeyCode::Up => app.change_palette_selection_2d(0, -1),
                 KeyCode::Down => app.change_palette_selection_2d(0, 1),
                 KeyCode::Left => app.change_palette_selection_2d(-1, 0),
                 KeyCode::Right => app.change_palette_selection_2d(1, 0),
                 KeyCode::Enter => app.select_color_entry(),
+                KeyCode::Delete => { app.remove_palette_entry(app.palette_index); },
+                KeyCode::Char('n') => color_editor::open_color_editor(app, app.color_palette[app.palette_index]),
+                KeyCode::Char('e') => color_editor::edit_palette_color(app, app.palette_index, false),
                 _ => {}
             }
         }
@@ -743,8 +1005,8 @@ AppMode::ScriptEditor => {
     AppMode::ResizingWidth | AppMode::ResizingHeight => {
         match key.code {
             KeyCode::Enter => match app.mode {
-                AppMode::ResizingWidth => { if let Ok(width) = app.input_buffer.parse::<usize>() { if width > 0 { app.temp_width = width; app.mode = AppMode::ResizingHeight; app.input_buffer.clear(); } } },
-                AppMode::ResizingHeight => { if let Ok(height) = app.input_buffer.parse::<usize>() { if height > 0 { app.resize_canvas(app.temp_width, height); app.mode = AppMode::Drawing; } } },
+                AppMode::ResizingWidth => { if let Ok(width) = app.input_buffer.parse::<usize>() { if (1..=MAX_CANVAS_SIZE).contains(&width) { app.temp_width = width; app.mode = AppMode::ResizingHeight; app.input_buffer.clear(); } } },
+                AppMode::ResizingHeight => { if let Ok(height) = app.input_buffer.parse::<usize>() { if (1..=MAX_CANVAS_SIZE).contains(&height) { app.resize_canvas(app.temp_width, height); app.mode = AppMode::Drawing; } } },
                 _ => {}
             },
             KeyCode::Esc => app.mode = AppMode::Drawing,
@@ -758,4 +1020,39 @@ AppMode::ScriptEditor => {
 
     }
     Ok(())
+}
+
+// Edits the Layers panel name input, moving by whole graphemes like the command prompt.
+fn handle_layer_rename_key(app: &mut App, key: KeyEvent) {
+    let pos = app.layer_name_cursor_pos;
+    match key.code {
+        KeyCode::Enter => app.commit_layer_rename(),
+        KeyCode::Esc => app.cancel_layer_rename(),
+        KeyCode::Char(c) if !key.modifiers.intersects(event::KeyModifiers::CONTROL | event::KeyModifiers::ALT) => {
+            app.layer_input_buffer.insert(pos, c);
+            app.layer_name_cursor_pos += c.len_utf8();
+        }
+        KeyCode::Backspace if pos > 0 => {
+            let prev_pos = app.layer_input_buffer[..pos].grapheme_indices(true).next_back().map_or(0, |(i, _)| i);
+            app.layer_input_buffer.drain(prev_pos..pos);
+            app.layer_name_cursor_pos = prev_pos;
+        }
+        KeyCode::Delete => {
+            if let Some(grapheme) = app.layer_input_buffer[pos..].graphemes(true).next() {
+                let end = pos + grapheme.len();
+                app.layer_input_buffer.drain(pos..end);
+            }
+        }
+        KeyCode::Left => {
+            app.layer_name_cursor_pos = app.layer_input_buffer[..pos].grapheme_indices(true).next_back().map_or(0, |(i, _)| i);
+        }
+        KeyCode::Right => {
+            if let Some(grapheme) = app.layer_input_buffer[pos..].graphemes(true).next() {
+                app.layer_name_cursor_pos = pos + grapheme.len();
+            }
+        }
+        KeyCode::Home => app.layer_name_cursor_pos = 0,
+        KeyCode::End => app.layer_name_cursor_pos = app.layer_input_buffer.len(),
+        _ => {}
+    }
 }
\ No newline at end of file
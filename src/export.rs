@@ -0,0 +1,569 @@
+// export.rs
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, Rgba, RgbaImage};
+use crate::file_browser::BrowserMode;
+use ratatui::prelude::*;
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use crate::{App, Canvas, Layer, Pixel, SerializableColor, file_browser, utils};
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ExportSettings {
+    pub path: String,
+    pub scale: u32,
+    pub transparent: bool,
+    pub mode: ExportLayerMode,
+    // Older sessions predate custom backgrounds and exported over black.
+    #[serde(default = "black")]
+    pub background: SerializableColor,
+    #[serde(default)]
+    pub selection_only: bool,
+    #[serde(default)]
+    pub autocrop: bool,
+}
+
+/// The largest sprite sheet `export sheet` will write, in total pixels (4096x4096).
+pub const MAX_SHEET_PIXELS: u64 = 4096 * 4096;
+
+fn black() -> SerializableColor {
+    SerializableColor(0, 0, 0)
+}
+
+
+impl Serialize for ExportLayerMode {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            ExportLayerMode::United => serializer.serialize_str("United"),
+            ExportLayerMode::Separate => serializer.serialize_str("Separate"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ExportLayerMode {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        match s.as_str() {
+            "United" => Ok(ExportLayerMode::United),
+            "Separate" => Ok(ExportLayerMode::Separate),
+            _ => Ok(ExportLayerMode::United),
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum ExportLayerMode {
+    United,
+    Separate,
+}
+
+/// How `import image` shrinks a picture: sample one source pixel per canvas pixel, or
+/// average every source pixel it covers.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ImportResample {
+    Nearest,
+    Box,
+}
+
+impl App {
+
+    /// Parses an export background: any color `parse_color` takes, such as `white`,
+    /// `#RRGGBB` or `rgb(r,g,b)`.
+    pub fn parse_export_background(value: &str) -> std::result::Result<Color, String> {
+        App::parse_color(value)
+    }
+
+    pub fn export_active_layer_png(&mut self, path: &str) -> bool {
+        let layer_name = self.layers[self.active_layer_index].name.clone();
+        let canvas = self.get_active_canvas();
+        // Raw export: 1x scale, layer opacity ignored. PNG alpha is 8-bit, so per-pixel alpha
+        // is rounded to the nearest 1/255; alphas already on that grid round-trip exactly.
+        let img = RgbaImage::from_fn(self.canvas_width as u32, self.canvas_height as u32, |x, y| {
+            let pixel = canvas[y as usize][x as usize];
+            if pixel.alpha == 0.0 { return Rgba([0, 0, 0, 0]); }
+            let (r, g, b) = utils::to_rgb(pixel.color.into());
+            Rgba([r, g, b, (pixel.alpha * 255.0).round() as u8])
+        });
+
+        if let Err(e) = img.save(path) {
+            self.set_status(format!("Error exporting layer: {}", e));
+            return false;
+        }
+        self.set_status(format!("Exported '{}' to {}", layer_name, path));
+        true
+    }
+
+    pub fn import_active_layer_png(&mut self, path: &std::path::Path) -> bool {
+        let img = match image::open(path) {
+            Ok(i) => i.into_rgba8(),
+            Err(e) => {
+                self.set_status(format!("Error opening image: {}", e));
+                return false;
+            }
+        };
+
+        if img.width() as usize != self.canvas_width || img.height() as usize != self.canvas_height {
+            self.set_status(format!(
+                "Image is {}x{}, expected {}x{}.",
+                img.width(), img.height(), self.canvas_width, self.canvas_height
+            ));
+            return false;
+        }
+
+        if self.active_layer_locked() || self.active_layer_alpha_locked() { return false; }
+        self.save_state_for_undo("Import layer");
+        let canvas = self.get_active_canvas_mut();
+        for (x, y, px) in img.enumerate_pixels() {
+            let [r, g, b, a] = px.0;
+            canvas[y as usize][x as usize] = if a == 0 {
+                Pixel::default()
+            } else {
+                Pixel { color: SerializableColor(r, g, b), alpha: a as f32 / 255.0 }
+            };
+        }
+        self.sync_canvas_from_layers();
+        let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("file");
+        self.set_status(format!("Imported {} into {}", file_name, self.layers[self.active_layer_index].name));
+        true
+    }
+
+    /// Loads a PNG/JPEG onto the active layer (or a new layer on top of it) at the
+    /// top-left corner, as one undo step. The image is shrunk by `downscale`, then to fit
+    /// the canvas when `fit`; whatever still overhangs is cropped. Transparent source
+    /// pixels leave the layer as it was. Colors snap to the palette when snap_to_palette is on.
+    pub fn import_image(&mut self, path: &std::path::Path, downscale: u32, fit: bool, resample: ImportResample, new_layer: bool) -> bool {
+        let img = match image::open(path) {
+            Ok(i) => i.into_rgba8(),
+            Err(e) => {
+                self.set_status(format!("Error opening image: {}", e));
+                return false;
+            }
+        };
+
+        let downscale = downscale.max(1);
+        let (mut width, mut height) = (img.width().div_ceil(downscale), img.height().div_ceil(downscale));
+        let (canvas_w, canvas_h) = (self.canvas_width as u32, self.canvas_height as u32);
+        if fit {
+            (width, height) = fit_within(width, height, canvas_w, canvas_h);
+        }
+        let img = resample_image(&img, width, height, resample);
+
+        if !new_layer && self.active_layer_locked() { return false; }
+        if new_layer { self.add_new_layer(); } else { self.save_state_for_undo("Import image"); }
+        let snap = self.snap_to_palette;
+        let mut placed = Vec::new();
+        for (x, y, px) in img.enumerate_pixels() {
+            let [r, g, b, a] = px.0;
+            if a == 0 || x >= canvas_w || y >= canvas_h { continue; }
+            let color = if snap { self.find_closest_palette_color(Color::Rgb(r, g, b)) } else { Color::Rgb(r, g, b) };
+            placed.push((x as usize, y as usize, Pixel { color: color.into(), alpha: a as f32 / 255.0 }));
+        }
+        let layer = &mut self.layers[self.active_layer_index];
+        for (x, y, pixel) in placed {
+            layer.put_pixel(x, y, pixel);
+        }
+        self.sync_canvas_from_layers();
+        let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("file");
+        self.set_status(format!("Imported {} ({}x{}) into {}", file_name, width, height, self.layers[self.active_layer_index].name));
+        true
+    }
+
+    /// Loads an image as the reference underlay, shrunk to fit the canvas with box
+    /// averaging. It sits beneath every layer and stays out of the composite and exports.
+    pub fn set_reference(&mut self, path: &std::path::Path) -> bool {
+        let img = match image::open(path) {
+            Ok(i) => i.into_rgba8(),
+            Err(e) => {
+                self.set_status(format!("Error opening image: {}", e));
+                return false;
+            }
+        };
+        let (width, height) = fit_within(img.width(), img.height(), self.canvas_width as u32, self.canvas_height as u32);
+        let img = resample_image(&img, width, height, ImportResample::Box);
+        self.reference = Some(Canvas::from_rows((0..height).map(|y| (0..width).map(|x| {
+            let [r, g, b, a] = img.get_pixel(x, y).0;
+            if a == 0 { Pixel::default() } else { Pixel { color: SerializableColor(r, g, b), alpha: a as f32 / 255.0 } }
+        }).collect()).collect()));
+        self.reference_visible = true;
+        let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("file");
+        self.set_status(format!("Reference set to {} ({}x{})", file_name, width, height));
+        true
+    }
+
+/// Writes the image as a PNG. Unless `transparent`, pixels are composited over `background`.
+/// With `selection_only`, just the selection's bounding box is written and pixels outside
+/// the selection are transparent. With `autocrop`, each image is cropped to its
+/// non-transparent pixels before scaling.
+pub fn export_to_png(&mut self, path: Option<String>, scale: u32, transparent: bool, background: Color, selection_only: bool, autocrop: bool) -> bool {
+        let Some(filename) = path else {
+            self.set_status("Export failed: No filename provided.");
+            return false;
+        };
+
+        let scale = if scale == 0 { 1 } else { scale };
+        let region = if selection_only {
+            match crate::app::bounding_box(self.selection.iter().copied()) {
+                Some((min_x, min_y, max_x, max_y)) => (min_x, min_y, max_x - min_x + 1, max_y - min_y + 1),
+                None => {
+                    self.set_status("Export failed: nothing is selected.");
+                    return false;
+                }
+            }
+        } else {
+            (0, 0, self.canvas_width, self.canvas_height)
+        };
+        let selection = &self.selection;
+        let masked = |x: usize, y: usize| selection_only && !selection.contains(&(x, y));
+        // The part of `canvas` to write: the region, or with autocrop just its visible
+        // pixels (None if there are none).
+        let bounds = |canvas: &Canvas, opacity: f32| {
+            if !autocrop {
+                return Some(region);
+            }
+            let (origin_x, origin_y, width, height) = region;
+            let points = (origin_y..origin_y + height).flat_map(|y| (origin_x..origin_x + width).map(move |x| (x, y)));
+            crate::app::bounding_box(points.filter(|&(x, y)| !masked(x, y) && canvas[y][x].alpha * opacity > 0.0))
+                .map(|(min_x, min_y, max_x, max_y)| (min_x, min_y, max_x - min_x + 1, max_y - min_y + 1))
+        };
+        let render = |canvas: &Canvas, opacity: f32, (origin_x, origin_y, width, height): (usize, usize, usize, usize)| {
+            RgbaImage::from_fn(width as u32 * scale, height as u32 * scale, |px, py| {
+                let x = origin_x + (px / scale) as usize;
+                let y = origin_y + (py / scale) as usize;
+                if masked(x, y) { return Rgba([0, 0, 0, 0]); }
+                let pixel = canvas[y][x];
+
+                if transparent {
+                    if pixel.alpha == 0.0 { return Rgba([0, 0, 0, 0]); }
+                    let (r, g, b) = utils::to_rgb(pixel.color.into());
+                    let alpha = (pixel.alpha * opacity * 255.0).round() as u8;
+                    Rgba([r, g, b, alpha])
+                } else {
+                    let final_color = utils::blend_colors(background, pixel.color.into(), pixel.alpha * opacity, self.linear_blending);
+                    let (r, g, b) = utils::to_rgb(final_color);
+                    Rgba([r, g, b, 255])
+                }
+            })
+        };
+        
+        match self.export_layer_mode {
+            ExportLayerMode::United => {
+                // Composite fresh from the layers (visibility and opacity included)
+                // rather than trusting the display canvas to be in sync.
+                let all: Vec<usize> = (0..self.layers.len()).collect();
+                let composite = self.composite_layers(&all);
+                let Some(crop) = bounds(&composite, 1.0) else {
+                    self.set_status("Export failed: nothing to export, the image is empty.");
+                    return false;
+                };
+                let img = render(&composite, 1.0, crop);
+
+                if let Err(e) = img.save(&filename) {
+                    self.set_status(format!("Error exporting file: {}", e));
+                    return false;
+                }
+                self.set_status(format!("Exported to {} ({}x{})", filename, img.width(), img.height()));
+            }
+            ExportLayerMode::Separate => {
+                let base_path = PathBuf::from(&filename);
+                let parent = base_path.parent().unwrap_or(std::path::Path::new("."));
+                let stem = base_path.file_stem().and_then(|s| s.to_str()).unwrap_or("export");
+                
+                // With autocrop, layers with nothing to show are skipped.
+                let mut exported = 0;
+                for (idx, layer) in self.layers.iter().enumerate() {
+                    if !layer.visible {
+                        continue;
+                    }
+                    let Some(crop) = bounds(&layer.canvas, layer.opacity) else { continue };
+                    
+                    let layer_filename = parent.join(format!("{}_{}.png", stem, idx + 1));
+                    let img = render(&layer.canvas, layer.opacity, crop);
+
+                    if let Err(e) = img.save(&layer_filename) {
+                        self.set_status(format!("Error exporting layer {}: {}", idx + 1, e));
+                        return false;
+                    }
+                    exported += 1;
+                }
+                if exported == 0 && autocrop {
+                    self.set_status("Export failed: nothing to export, every visible layer is empty.");
+                    return false;
+                }
+                let size = if autocrop { "cropped".to_string() } else { format!("{}x{}", region.2 as u32 * scale, region.3 as u32 * scale) };
+                self.set_status(format!("Exported {} layers ({})", exported, size));
+}
+}
+        self.last_export = Some(ExportSettings { path: filename, scale, transparent, mode: self.export_layer_mode, background: background.into(), selection_only, autocrop });
+        true
+}
+
+    /// Writes every visible layer as one frame of a looping GIF, bottom layer first.
+    /// Frames go over `background`, or keep 1-bit transparency without one (pixels
+    /// under half alpha drop out). The encoder quantizes frames past 256 colors.
+    pub fn export_to_gif(&mut self, path: &str, scale: u32, delay_ms: u32, background: Option<Color>) -> bool {
+        let scale = scale.max(1);
+        let frames: Vec<Frame> = self.layers.iter().rev().filter(|l| l.visible).map(|layer| {
+            let img = RgbaImage::from_fn(self.canvas_width as u32 * scale, self.canvas_height as u32 * scale, |px, py| {
+                let pixel = layer.canvas[(py / scale) as usize][(px / scale) as usize];
+                let alpha = pixel.alpha * layer.opacity;
+                match background {
+                    Some(bg) => {
+                        let (r, g, b) = utils::to_rgb(utils::blend_colors(bg, pixel.color.into(), alpha, self.linear_blending));
+                        Rgba([r, g, b, 255])
+                    }
+                    None if alpha < 0.5 => Rgba([0, 0, 0, 0]),
+                    None => {
+                        let (r, g, b) = utils::to_rgb(pixel.color.into());
+                        Rgba([r, g, b, 255])
+                    }
+                }
+            });
+            Frame::from_parts(img, 0, 0, Delay::from_numer_denom_ms(delay_ms, 1))
+        }).collect();
+        if frames.is_empty() {
+            self.set_status("Export failed: no visible layers.");
+            return false;
+        }
+
+        let frame_count = frames.len();
+        let result = std::fs::File::create(path).map_err(image::ImageError::IoError).and_then(|file| {
+            let mut encoder = GifEncoder::new(std::io::BufWriter::new(file));
+            encoder.set_repeat(Repeat::Infinite)?;
+            encoder.encode_frames(frames)
+        });
+        if let Err(e) = result {
+            self.set_status(format!("Error exporting GIF: {}", e));
+            return false;
+        }
+        let size_kb = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0) as f64 / 1024.0;
+        self.set_status(format!("Exported {} frames to {} ({:.1} KB)", frame_count, path, size_kb));
+        true
+    }
+
+    /// Writes the composited canvas as ANSI escape text: two pixels per cell with `▀`
+    /// (top pixel in the foreground, bottom in the background), or two background-
+    /// colored spaces per pixel when `plain`, like the editor. Colors go through
+    /// `translate_color`, so Ansi256 mode emits 256-color indices. Transparent pixels
+    /// reset the colors so the art overlays the terminal background.
+    pub fn export_to_ansi(&mut self, path: &str, plain: bool) -> bool {
+        let all: Vec<usize> = (0..self.layers.len()).collect();
+        let composite = self.composite_layers(&all);
+        let color_at = |pixel: &Pixel| (pixel.alpha > 0.0)
+            .then(|| self.translate_color(utils::blend_colors(Color::Black, pixel.color.into(), pixel.alpha, self.linear_blending)));
+
+        let mut text = String::new();
+        if plain {
+            for row in composite.rows() {
+                for pixel in row {
+                    match color_at(pixel) {
+                        Some(color) => text.push_str(&format!("{}  ", ansi_sgr(color, true))),
+                        None => text.push_str("\x1b[0m  "),
+                    }
+                }
+                text.push_str("\x1b[0m\n");
+            }
+        } else {
+            let rows: Vec<&[Pixel]> = composite.rows().collect();
+            for rows in rows.chunks(2) {
+                for x in 0..self.canvas_width {
+                    let top = color_at(&rows[0][x]);
+                    let bottom = rows.get(1).and_then(|row| color_at(&row[x]));
+                    text.push_str("\x1b[0m");
+                    match (top, bottom) {
+                        (Some(top), Some(bottom)) => text.push_str(&format!("{}{}▀", ansi_sgr(top, false), ansi_sgr(bottom, true))),
+                        (Some(top), None) => text.push_str(&format!("{}▀", ansi_sgr(top, false))),
+                        (None, Some(bottom)) => text.push_str(&format!("{}▄", ansi_sgr(bottom, false))),
+                        (None, None) => text.push(' '),
+                    }
+                }
+                text.push_str("\x1b[0m\n");
+            }
+        }
+
+        if let Err(e) = std::fs::write(path, &text) {
+            self.set_status(format!("Error exporting ANSI text: {}", e));
+            return false;
+        }
+        self.set_status(format!("Exported to {} ({:.1} KB)", path, text.len() as f64 / 1024.0));
+        true
+    }
+
+    /// Lays every visible layer out as a tile of one PNG, top of the layer list first,
+    /// left to right in rows of `columns`. `gutter` puts a 1px gap between tiles. Tiles
+    /// go over `background`, or keep their transparency without one. Refuses sheets
+    /// past `MAX_SHEET_PIXELS`.
+    pub fn export_sprite_sheet(&mut self, path: &str, columns: u32, scale: u32, gutter: bool, background: Option<Color>) -> bool {
+        let scale = scale.max(1);
+        let tiles: Vec<&Layer> = self.layers.iter().filter(|l| l.visible).collect();
+        if tiles.is_empty() {
+            self.set_status("Export failed: no visible layers.");
+            return false;
+        }
+
+        let columns = columns.clamp(1, tiles.len() as u32) as u64;
+        let rows = (tiles.len() as u64).div_ceil(columns);
+        let gap = gutter as u64;
+        let (tile_w, tile_h) = (self.canvas_width as u64 * scale as u64, self.canvas_height as u64 * scale as u64);
+        let (sheet_w, sheet_h) = (columns * (tile_w + gap) - gap, rows * (tile_h + gap) - gap);
+        if sheet_w * sheet_h > MAX_SHEET_PIXELS {
+            self.set_status(format!("Export failed: sheet would be {}x{} pixels, over the {} pixel limit.", sheet_w, sheet_h, MAX_SHEET_PIXELS));
+            return false;
+        }
+
+        let empty = match background {
+            Some(bg) => {
+                let (r, g, b) = utils::to_rgb(bg);
+                Rgba([r, g, b, 255])
+            }
+            None => Rgba([0, 0, 0, 0]),
+        };
+        let mut img = RgbaImage::from_pixel(sheet_w as u32, sheet_h as u32, empty);
+        for (i, layer) in tiles.iter().enumerate() {
+            let origin_x = (i as u64 % columns) * (tile_w + gap);
+            let origin_y = (i as u64 / columns) * (tile_h + gap);
+            for py in 0..tile_h {
+                for px in 0..tile_w {
+                    let pixel = layer.canvas[(py / scale as u64) as usize][(px / scale as u64) as usize];
+                    let alpha = pixel.alpha * layer.opacity;
+                    let rgba = match background {
+                        Some(bg) => {
+                            let (r, g, b) = utils::to_rgb(utils::blend_colors(bg, pixel.color.into(), alpha, self.linear_blending));
+                            Rgba([r, g, b, 255])
+                        }
+                        None if alpha == 0.0 => continue,
+                        None => {
+                            let (r, g, b) = utils::to_rgb(pixel.color.into());
+                            Rgba([r, g, b, (alpha * 255.0).round() as u8])
+                        }
+                    };
+                    img.put_pixel((origin_x + px) as u32, (origin_y + py) as u32, rgba);
+                }
+            }
+        }
+
+        if let Err(e) = img.save(path) {
+            self.set_status(format!("Error exporting sprite sheet: {}", e));
+            return false;
+        }
+        self.set_status(format!("Exported {} tiles to {} ({}x{})", tiles.len(), path, sheet_w, sheet_h));
+        true
+    }
+
+    /// Writes the composited canvas as plain text, one `ramp` character per pixel by
+    /// luminance (two when `wide`, to make up for tall terminal cells). See `ascii_char`.
+    pub fn export_to_ascii(&mut self, path: &str, ramp: &[char], invert: bool, wide: bool) -> bool {
+        let all: Vec<usize> = (0..self.layers.len()).collect();
+        let composite = self.composite_layers(&all);
+        let mut text = String::new();
+        for row in composite.rows() {
+            for pixel in row {
+                let c = ascii_char(*pixel, ramp, invert, self.linear_blending);
+                text.push(c);
+                if wide {
+                    text.push(c);
+                }
+            }
+            text.push('\n');
+        }
+
+        if let Err(e) = std::fs::write(path, &text) {
+            self.set_status(format!("Error exporting ASCII text: {}", e));
+            return false;
+        }
+        self.set_status(format!("Exported to {}", path));
+        true
+    }
+
+    // Re-runs the last successful export with the same settings, overwriting the old file.
+    pub fn repeat_last_export(&mut self) {
+        let Some(settings) = self.last_export.clone() else {
+            file_browser::open_browser(self, BrowserMode::Export);
+            return;
+        };
+        let mode_before = self.export_layer_mode;
+        self.export_layer_mode = settings.mode;
+        let exported = self.export_to_png(Some(settings.path.clone()), settings.scale, settings.transparent, settings.background.into(), settings.selection_only, settings.autocrop);
+        self.export_layer_mode = mode_before;
+        if exported {
+            self.set_status(format!("Re-exported to {}", settings.path));
+        }
+    }
+}
+
+// The largest size with the aspect ratio of `width` x `height` that fits the bounds.
+// Never enlarges.
+fn fit_within(width: u32, height: u32, max_width: u32, max_height: u32) -> (u32, u32) {
+    if width <= max_width && height <= max_height {
+        return (width, height);
+    }
+    let ratio = (max_width as f32 / width as f32).min(max_height as f32 / height as f32);
+    (((width as f32 * ratio).round() as u32).clamp(1, max_width.max(1)), ((height as f32 * ratio).round() as u32).clamp(1, max_height.max(1)))
+}
+
+// Shrinks `img` to `width` x `height`. Box averaging weights colors by alpha, so
+// transparent pixels don't darken the edges they border.
+fn resample_image(img: &RgbaImage, width: u32, height: u32, resample: ImportResample) -> RgbaImage {
+    if (width, height) == img.dimensions() {
+        return img.clone();
+    }
+    let (src_w, src_h) = img.dimensions();
+    RgbaImage::from_fn(width, height, |x, y| {
+        let (x0, x1) = (x * src_w / width, ((x + 1) * src_w / width).max(x * src_w / width + 1));
+        let (y0, y1) = (y * src_h / height, ((y + 1) * src_h / height).max(y * src_h / height + 1));
+        match resample {
+            ImportResample::Nearest => *img.get_pixel((x0 + x1) / 2, (y0 + y1) / 2),
+            ImportResample::Box => {
+                let (mut sums, mut alpha_sum, mut count) = ([0u64; 3], 0u64, 0u64);
+                for sy in y0..y1 {
+                    for sx in x0..x1 {
+                        let [r, g, b, a] = img.get_pixel(sx, sy).0;
+                        for (sum, channel) in sums.iter_mut().zip([r, g, b]) {
+                            *sum += channel as u64 * a as u64;
+                        }
+                        alpha_sum += a as u64;
+                        count += 1;
+                    }
+                }
+                if alpha_sum == 0 {
+                    return Rgba([0, 0, 0, 0]);
+                }
+                let [r, g, b] = sums.map(|sum| (sum as f64 / alpha_sum as f64).round() as u8);
+                Rgba([r, g, b, (alpha_sum as f64 / count as f64).round() as u8])
+            }
+        }
+    })
+}
+
+/// The default `export ascii` ramp, darkest first.
+pub const DEFAULT_ASCII_RAMP: &str = " .:-=+*#%@";
+
+/// Maps a pixel's luminance (over black, as the editor shows it) to a character of
+/// `ramp`, darkest first, or the other way round when `invert`. Transparent pixels
+/// are always a space.
+pub fn ascii_char(pixel: Pixel, ramp: &[char], invert: bool, linear: bool) -> char {
+    if pixel.alpha == 0.0 || ramp.is_empty() {
+        return ' ';
+    }
+    let (r, g, b) = utils::to_rgb(utils::blend_colors(Color::Black, pixel.color.into(), pixel.alpha, linear));
+    let luminance = (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32) / 255.0;
+    let luminance = if invert { 1.0 - luminance } else { luminance };
+    ramp[(luminance * (ramp.len() - 1) as f32).round() as usize]
+}
+
+// The SGR sequence selecting `color` as the foreground, or the background when `background`.
+fn ansi_sgr(color: Color, background: bool) -> String {
+    let layer = if background { 48 } else { 38 };
+    match color {
+        Color::Indexed(index) => format!("\x1b[{};5;{}m", layer, index),
+        color => {
+            let (r, g, b) = utils::to_rgb(color);
+            format!("\x1b[{};2;{};{};{}m", layer, r, g, b)
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{App, AppMode, BrowserFocus};
+use crate::{utils, App, AppMode, BrowserFocus, ImportResample};
 use crossterm::event::{KeyCode, KeyEvent, MouseButton, MouseEvent, MouseEventKind};
 use ratatui::{
     prelude::*,
@@ -15,6 +15,10 @@ pub enum BrowserMode {
     ImportPalette,
     Export,
     GeneratePaletteFromImage(bool),
+    ExportLayer,
+    ImportLayer,
+    // The scale input holds the downscale factor here.
+    ImportImage { new_layer: bool, resample: ImportResample },
 }
 
 // Entry point to open the browser.
@@ -27,6 +31,9 @@ pub fn open_browser(app: &mut App, mode: BrowserMode) {
     if matches!(mode, BrowserMode::Save | BrowserMode::Export) {
         let timestamp = chrono::Local::now().format("%Y-%m-%d_%H%M%S");
         app.browser_input_buffer = format!("project_{}", timestamp);
+    } else if matches!(mode, BrowserMode::ExportLayer) {
+        let timestamp = chrono::Local::now().format("%Y-%m-%d_%H%M%S");
+        app.browser_input_buffer = format!("layer_{}", timestamp);
     } else {
         app.browser_input_buffer.clear();
     }
@@ -47,6 +54,12 @@ fn read_directory(app: &mut App, path: &Path) {
     app.browser_entries.clear();
     app.browser_list_state.select(Some(0));
 
+    // Loading pins the recent projects above every directory listing.
+    if matches!(app.browser_mode, Some(BrowserMode::Load)) {
+        app.browser_entries.extend(crate::project::read_recent_projects());
+    }
+    app.browser_pinned = app.browser_entries.len();
+
     // Add ".." to go up a directory, if possible.
     if let Some(parent) = path.parent() {
         if parent != path {
@@ -90,10 +103,13 @@ pub fn handle_browser_input(app: &mut App, key_event: Option<KeyEvent>, mouse_ev
 // Renders the file browser UI.
 pub fn draw_browser(f: &mut Frame, app: &mut App) {
     let is_export_mode = matches!(app.browser_mode, Some(BrowserMode::Export));
-    let is_save_or_export = is_export_mode || matches!(app.browser_mode, Some(BrowserMode::Save));
+    let is_save_or_export = is_export_mode || matches!(app.browser_mode, Some(BrowserMode::Save | BrowserMode::ExportLayer));
+    let is_image_import = matches!(app.browser_mode, Some(BrowserMode::ImportImage { .. }));
+    // Generating a palette uses the same box for the number of colors.
+    let is_palette_from_image = matches!(app.browser_mode, Some(BrowserMode::GeneratePaletteFromImage(_)));
 
     // --- Layout ---
-    let constraints = if is_save_or_export {
+    let constraints = if is_save_or_export || is_image_import || is_palette_from_image {
         vec![Constraint::Min(1), Constraint::Length(3), Constraint::Length(3)]
     } else {
         vec![Constraint::Min(1), Constraint::Length(3)]
@@ -103,8 +119,9 @@ pub fn draw_browser(f: &mut Frame, app: &mut App) {
     // --- List Rendering ---
     let list_chunk = main_chunks[0];
     let list_border_style = if app.browser_focus == BrowserFocus::List { Style::default().fg(Color::Yellow) } else { Style::default() };
-    let items: Vec<ListItem> = app.browser_entries.iter().map(|path| {
-        let name = if path.to_str() == Some("..") { "📁 ..".to_string() }
+    let items: Vec<ListItem> = app.browser_entries.iter().enumerate().map(|(i, path)| {
+        let name = if i < app.browser_pinned { format!("🕘 {}", path.display()) }
+        else if path.to_str() == Some("..") { "📁 ..".to_string() }
         else if path.is_dir() { format!("📁 {}", path.file_name().unwrap_or_default().to_string_lossy()) }
         else { format!("📄 {}", path.file_name().unwrap_or_default().to_string_lossy()) };
         ListItem::new(name)
@@ -120,11 +137,12 @@ pub fn draw_browser(f: &mut Frame, app: &mut App) {
         let input_area = main_chunks[1];
         let input_chunks = Layout::default()
             .direction(Direction::Horizontal)
-            .constraints([Constraint::Min(10), Constraint::Length(10)]) // Flexible Name, Fixed Scale
+            .constraints([Constraint::Min(10), Constraint::Length(10), Constraint::Length(16)]) // Flexible Name, Fixed Scale and Background
             .split(input_area);
         
         let name_chunk = input_chunks[0];
         let scale_chunk = input_chunks[1];
+        let background_chunk = input_chunks[2];
 
         // RENDER FILENAME INPUT
         let name_border_style = if app.browser_focus == BrowserFocus::NameInput { Style::default().fg(Color::Yellow) } else { Style::default() };
@@ -138,6 +156,13 @@ pub fn draw_browser(f: &mut Frame, app: &mut App) {
             let scale_input = Paragraph::new(app.browser_scale_buffer.as_str())
                 .block(Block::default().borders(Borders::ALL).title(" Scale ").border_style(scale_border_style));
             f.render_widget(scale_input, scale_chunk);
+
+            // Empty means a transparent background.
+            let background_border_style = if app.browser_focus == BrowserFocus::BackgroundInput { Style::default().fg(Color::Yellow) } else { Style::default() };
+            let background_text = if app.browser_background_buffer.is_empty() && app.browser_focus != BrowserFocus::BackgroundInput { "none" } else { app.browser_background_buffer.as_str() };
+            let background_input = Paragraph::new(background_text)
+                .block(Block::default().borders(Borders::ALL).title(" Background ").border_style(background_border_style));
+            f.render_widget(background_input, background_chunk);
         }
 
         // --- Set Cursor ---
@@ -146,8 +171,23 @@ pub fn draw_browser(f: &mut Frame, app: &mut App) {
             BrowserFocus::ScaleInput if is_export_mode => {
                 f.set_cursor(scale_chunk.x + app.browser_scale_buffer.len() as u16 + 1, scale_chunk.y + 1);
             }
+            BrowserFocus::BackgroundInput if is_export_mode => {
+                f.set_cursor(background_chunk.x + app.browser_background_buffer.len() as u16 + 1, background_chunk.y + 1);
+            }
             _ => {}
         }
+    } else if is_image_import || is_palette_from_image {
+        let scale_chunk = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Length(14), Constraint::Min(0)])
+            .split(main_chunks[1])[0];
+        let scale_border_style = if app.browser_focus == BrowserFocus::ScaleInput { Style::default().fg(Color::Yellow) } else { Style::default() };
+        let scale_input = Paragraph::new(app.browser_scale_buffer.as_str())
+            .block(Block::default().borders(Borders::ALL).title(if is_palette_from_image { " Colors " } else { " Downscale " }).border_style(scale_border_style));
+        f.render_widget(scale_input, scale_chunk);
+        if app.browser_focus == BrowserFocus::ScaleInput {
+            f.set_cursor(scale_chunk.x + app.browser_scale_buffer.len() as u16 + 1, scale_chunk.y + 1);
+        }
     }
     
     // --- Help Text ---
@@ -160,22 +200,29 @@ pub fn draw_browser(f: &mut Frame, app: &mut App) {
 // --- Internal Helper Functions ---
 
 fn handle_browser_keyboard(app: &mut App, key: KeyEvent) {
-    let is_save_or_export = matches!(app.browser_mode, Some(BrowserMode::Save | BrowserMode::Export));
+    let is_save_or_export = matches!(app.browser_mode, Some(BrowserMode::Save | BrowserMode::Export | BrowserMode::ExportLayer));
     let is_export_mode = matches!(app.browser_mode, Some(BrowserMode::Export));
+    let is_image_import = matches!(app.browser_mode, Some(BrowserMode::ImportImage { .. } | BrowserMode::GeneratePaletteFromImage(_)));
 
     // --- Tab Focus Cycling ---
     if key.code == KeyCode::Tab {
-        if is_export_mode { // Cycle through all three: List -> Name -> Scale -> List
+        if is_export_mode { // Cycle through all four: List -> Name -> Scale -> Background -> List
             app.browser_focus = match app.browser_focus {
                 BrowserFocus::List => BrowserFocus::NameInput,
                 BrowserFocus::NameInput => BrowserFocus::ScaleInput,
-                BrowserFocus::ScaleInput => BrowserFocus::List,
+                BrowserFocus::ScaleInput => BrowserFocus::BackgroundInput,
+                BrowserFocus::BackgroundInput => BrowserFocus::List,
             };
         } else if is_save_or_export { // Cycle through two: List -> Name -> List
             app.browser_focus = match app.browser_focus {
                 BrowserFocus::List => BrowserFocus::NameInput,
                 BrowserFocus::NameInput => BrowserFocus::List,
-                BrowserFocus::ScaleInput => BrowserFocus::List, // Should not happen, but handle it
+                BrowserFocus::ScaleInput | BrowserFocus::BackgroundInput => BrowserFocus::List, // Should not happen, but handle it
+            };
+        } else if is_image_import { // Cycle through two: List -> Downscale (or Colors) -> List
+            app.browser_focus = match app.browser_focus {
+                BrowserFocus::List => BrowserFocus::ScaleInput,
+                _ => BrowserFocus::List,
             };
         }
         return;
@@ -185,7 +232,8 @@ fn handle_browser_keyboard(app: &mut App, key: KeyEvent) {
     match app.browser_focus {
         BrowserFocus::List => handle_list_input(app, key),
         BrowserFocus::NameInput => handle_name_input(app, key),
-        BrowserFocus::ScaleInput if is_export_mode => handle_scale_input(app, key),
+        BrowserFocus::ScaleInput if is_export_mode || is_image_import => handle_scale_input(app, key),
+        BrowserFocus::BackgroundInput if is_export_mode => handle_background_input(app, key),
         _ => handle_list_input(app, key), // Default to list input if something is out of sync
     }
 }
@@ -195,13 +243,11 @@ fn handle_browser_mouse(app: &mut App, mouse: MouseEvent) {
         match mouse.kind {
             MouseEventKind::ScrollUp => navigate_list(app, -1),
             MouseEventKind::ScrollDown => navigate_list(app, 1),
-            MouseEventKind::Down(MouseButton::Left) => {
-                if mouse.row >= area.y && mouse.row < area.bottom() {
-                    let index = (mouse.row - area.y) as usize + app.browser_list_state.offset();
-                    if index < app.browser_entries.len() {
-                        app.browser_list_state.select(Some(index));
-                        on_select(app);
-                    }
+            MouseEventKind::Down(MouseButton::Left) if mouse.row >= area.y && mouse.row < area.bottom() => {
+                let index = (mouse.row - area.y) as usize + app.browser_list_state.offset();
+                if index < app.browser_entries.len() {
+                    app.browser_list_state.select(Some(index));
+                    on_select(app);
                 }
             }
             _ => {}
@@ -241,12 +287,29 @@ fn on_select(app: &mut App) {
             // It's a file, handle based on mode
             match app.browser_mode {
                 Some(BrowserMode::Load) => app.load_project(&selected_path),
-                Some(BrowserMode::ImportPalette) => app.load_and_store_palette(&selected_path.to_string_lossy()),
-                Some(BrowserMode::GeneratePaletteFromImage(add)) => app.generate_palette_from_image(&selected_path, add),
+                Some(BrowserMode::ImportPalette) => { app.load_and_store_palette(&selected_path.to_string_lossy()); },
+                Some(BrowserMode::GeneratePaletteFromImage(add)) => match app.browser_scale_buffer.parse::<usize>() {
+                    Ok(count) if (4..=64).contains(&count) => app.generate_palette_from_image(&selected_path, add, count),
+                    _ => {
+                        app.browser_error = Some("Colors must be between 4 and 64.".to_string());
+                        return;
+                    }
+                },
+                Some(BrowserMode::ImportLayer) => { app.import_active_layer_png(&selected_path); },
+                Some(BrowserMode::ImportImage { new_layer, resample }) => {
+                    let downscale = app.browser_scale_buffer.parse::<u32>().unwrap_or(1);
+                    if !app.import_image(&selected_path, downscale, false, resample, new_layer) {
+                        app.browser_error = app.status_message.as_ref().map(|(message, _)| message.clone());
+                        return;
+                    }
+                }
 
                 _ => return, // In Save/Export mode, selecting a file does nothing.
             }
-            app.mode = AppMode::Drawing; // Close browser on successful action
+            // Close browser on successful action, unless it opened a dialog.
+            if app.mode == AppMode::FileBrowser {
+                app.mode = AppMode::Drawing;
+            }
         }
     }
 }
@@ -273,23 +336,46 @@ fn on_confirm_directory(app: &mut App) {
         None => return,
     };
 
-    let mut filename = app.browser_input_buffer.clone();
+    let extension = match mode {
+        // A typed `.consolet.json` or the plain_project_save default picks the plain format.
+        BrowserMode::Save if crate::project::is_plain_project(Path::new(&app.browser_input_buffer)) => "consolet.json",
+        BrowserMode::Save if app.plain_project_save && !app.browser_input_buffer.to_lowercase().ends_with(".consolet") => "consolet.json",
+        BrowserMode::Save => "consolet",
+        BrowserMode::Export | BrowserMode::ExportLayer => "png",
+        _ => return,
+    };
 
+    // Same rules as `export -o`: "~" expands, nested folders are created, bad names are caught here.
+    let path = match utils::normalize_output_path(&app.browser_current_dir, &app.browser_input_buffer, extension) {
+        Ok(path) => path,
+        Err(e) => {
+            app.browser_error = Some(e);
+            return;
+        }
+    };
+    if let Err(e) = utils::create_parent_dirs(&path) {
+        app.browser_error = Some(format!("Cannot create directory: {}", e));
+        return;
+    }
 
     match mode {
-        BrowserMode::Save => {
-            if !filename.ends_with(".consolet") {
-                filename.push_str(".consolet");
-            }
-            app.save_project(&app.browser_current_dir.join(filename), true);
-        },
+        BrowserMode::Save => { app.save_project(&path, true); },
         BrowserMode::Export => {
-            if !filename.ends_with(".png") {
-                filename.push_str(".png");
-            }
             let scale = app.browser_scale_buffer.parse::<u32>().unwrap_or(1);
-app.export_to_png(Some(app.browser_current_dir.join(filename).to_string_lossy().to_string()), scale, true);
+            let background = if app.browser_background_buffer.is_empty() {
+                None
+            } else {
+                match App::parse_export_background(&app.browser_background_buffer) {
+                    Ok(color) => Some(color),
+                    Err(e) => {
+                        app.browser_error = Some(format!("Invalid background '{}'. {}", app.browser_background_buffer, e));
+                        return;
+                    }
+                }
+            };
+            app.export_to_png(Some(path.to_string_lossy().to_string()), scale, background.is_none(), background.unwrap_or(Color::Black), false, false);
         },
+        BrowserMode::ExportLayer => { app.export_active_layer_png(&path.to_string_lossy()); },
         _ => return,
     }
     app.mode = AppMode::Drawing;
@@ -322,10 +408,22 @@ fn handle_name_input(app: &mut App, key: KeyEvent) {
     }
 }
 
+fn handle_background_input(app: &mut App, key: KeyEvent) {
+    match key.code {
+        KeyCode::Char(c) if c.is_ascii_alphanumeric() || c == '#' => app.browser_background_buffer.push(c),
+        KeyCode::Backspace => { app.browser_background_buffer.pop(); },
+        KeyCode::Enter => on_confirm_directory(app),
+        KeyCode::Esc => app.mode = AppMode::Drawing,
+        _ => handle_list_input(app, key),
+    }
+}
+
 fn handle_scale_input(app: &mut App, key: KeyEvent) {
     match key.code {
         KeyCode::Char(c) if c.is_ascii_digit() => app.browser_scale_buffer.push(c),
         KeyCode::Backspace => { app.browser_scale_buffer.pop(); },
+        // When importing or generating a palette, Enter takes the highlighted image with the typed factor.
+        KeyCode::Enter if matches!(app.browser_mode, Some(BrowserMode::ImportImage { .. } | BrowserMode::GeneratePaletteFromImage(_))) => on_select(app),
         KeyCode::Enter => on_confirm_directory(app),
         KeyCode::Esc => app.mode = AppMode::Drawing,
         // If another key is pressed, pass it to the main handler
@@ -14,8 +14,11 @@ pub fn get_default_help_text() -> &'static str {
     \tExample: save my_art.consolet -a 5\n\n\
     load <name.consolet>  - Load a project. Searches default folder if no path is given.\n\n\
     export            - Export the canvas to a PNG. Args: -o \"path\", -u {scale}, -bg\n\
-    \tExample: export -u 10 -o \"art.png\"\n\n\
+    \tExample: export -u 10 -o \"art.png\"\n\
+    reexport          - Repeat the last export with the same settings (Ctrl+E).\n\n\
     import palette <path> - Import a .consolet palette file for later use.\n\
+    layer export <path>   - Write the active layer to a PNG (1x, 8-bit alpha).\n\
+    layer import <path>   - Replace the active layer with a PNG of the same size.\n\
     colorpalette:<name>   - Switch to a loaded palette (e.g., colorpalette:default).\n\
     colorpalette:<name>   - Switch to a loaded palette (e.g., colorpalette:default).\n\
     savepalette:<name>    - Save the current set of colors as a new palette.\n\
@@ -15,6 +15,7 @@ pub enum Action {
     MoveCursorRight,
     OpenCommandPrompt,
     OpenColorPicker,
+    OpenColorEditor,
     OpenToolPicker,
     PanViewUp,
     PanViewDown,
@@ -24,6 +25,7 @@ pub enum Action {
     ZoomOut,
     Undo,
     Redo,
+    ShowUndoHistory,
     IncreasePenSize,
     DecreasePenSize,
     IncreaseOpacity,
@@ -39,19 +41,48 @@ pub enum Action {
     QuickSelectColorRight,
     QuickSelectToolLeft,
     QuickSelectToolRight,
+    QuickSelectRecent,
     AdjustSymmetryNegative, // Represents 'j' key
     AdjustSymmetryPositive, // Represents 'k' key
     Spray,
     SelectLayerUp,
     SelectLayerDown,
+    SelectLayerUnderCursor,
     AddLayer,
     DeleteLayer,
     ToggleLayerVisibility,
+    CycleLayerBlendMode,
+    ToggleLayerLock,
+    ToggleAlphaLock,
+    RenameLayer,
+    MergeDown,
+    MergeUp,
+    MergeVisible,
+    Flatten,
+    PlayAnimation,
     MoveLayerUp,
     MoveLayerDown,
     ToggleOnionSkin,
     IncreaseOnionOpacity,
     DecreaseOnionOpacity,
+    RepeatLastExport,
+    RepeatLastCommand,
+    OpenCommandPalette,
+    SnapCursorToTile,
+    DrawLine,
+    SetCloneSource,
+    ShiftLayerUp,
+    ShiftLayerDown,
+    ShiftLayerLeft,
+    ShiftLayerRight,
+    MagicWand,
+    ToggleColorInfo,
+    AdjustSymmetryYNegative,
+    AdjustSymmetryYPositive,
+    SetSymmetryAtCursor,
+    FlipCanvasHorizontal,
+    ToggleTilePreview,
+    ToggleReference,
 }
 
 
@@ -117,6 +148,7 @@ impl Default for Keybindings {
         map.insert(Action::ZoomOut, Keybinding { code: KeyCode::Char('-'), modifiers: KeyModifiers::NONE });
         map.insert(Action::OpenCommandPrompt, Keybinding { code: KeyCode::Esc, modifiers: KeyModifiers::NONE });
         map.insert(Action::OpenColorPicker, Keybinding { code: KeyCode::Char('c'), modifiers: KeyModifiers::NONE });
+        map.insert(Action::OpenColorEditor, Keybinding { code: KeyCode::Char('c'), modifiers: KeyModifiers::ALT });
         map.insert(Action::OpenToolPicker, Keybinding { code: KeyCode::Char('t'), modifiers: KeyModifiers::NONE });
         map.insert(Action::IncreasePenSize, Keybinding { code: KeyCode::Char(']'), modifiers: KeyModifiers::NONE });
         map.insert(Action::DecreasePenSize, Keybinding { code: KeyCode::Char('['), modifiers: KeyModifiers::NONE });
@@ -124,6 +156,7 @@ impl Default for Keybindings {
         map.insert(Action::DecreaseOpacity, Keybinding { code: KeyCode::Char('o'), modifiers: KeyModifiers::NONE });
         map.insert(Action::Undo, Keybinding { code: KeyCode::Char('z'), modifiers: KeyModifiers::CONTROL });
         map.insert(Action::Redo, Keybinding { code: KeyCode::Char('y'), modifiers: KeyModifiers::CONTROL });
+        map.insert(Action::ShowUndoHistory, Keybinding { code: KeyCode::Char('Z'), modifiers: KeyModifiers::SHIFT });
         map.insert(Action::CycleSymmetry, Keybinding { code: KeyCode::Char('s'), modifiers: KeyModifiers::NONE });
         map.insert(Action::PickColor, Keybinding { code: KeyCode::Char('r'), modifiers: KeyModifiers::NONE });
         map.insert(Action::Fill, Keybinding { code: KeyCode::Char('f'), modifiers: KeyModifiers::NONE });
@@ -135,18 +168,45 @@ impl Default for Keybindings {
         map.insert(Action::QuickSelectColorRight, Keybinding { code: KeyCode::Right, modifiers: KeyModifiers::CONTROL });
         map.insert(Action::QuickSelectToolLeft, Keybinding { code: KeyCode::Left, modifiers: KeyModifiers::SHIFT });
         map.insert(Action::QuickSelectToolRight, Keybinding { code: KeyCode::Right, modifiers: KeyModifiers::SHIFT });
+        map.insert(Action::QuickSelectRecent, Keybinding { code: KeyCode::Char('q'), modifiers: KeyModifiers::NONE });
         map.insert(Action::AdjustSymmetryNegative, Keybinding { code: KeyCode::Char('m'), modifiers: KeyModifiers::NONE });
         map.insert(Action::AdjustSymmetryPositive, Keybinding { code: KeyCode::Char('n'), modifiers: KeyModifiers::NONE });
         map.insert(Action::SelectLayerUp, Keybinding { code: KeyCode::Up, modifiers: KeyModifiers::ALT });
         map.insert(Action::SelectLayerDown, Keybinding { code: KeyCode::Down, modifiers: KeyModifiers::ALT });
+        map.insert(Action::SelectLayerUnderCursor, Keybinding { code: KeyCode::Char('s'), modifiers: KeyModifiers::ALT });
         map.insert(Action::AddLayer, Keybinding { code: KeyCode::Char('a'), modifiers: KeyModifiers::ALT });
         map.insert(Action::DeleteLayer, Keybinding { code: KeyCode::Char('d'), modifiers: KeyModifiers::ALT });
         map.insert(Action::ToggleLayerVisibility, Keybinding { code: KeyCode::Char('v'), modifiers: KeyModifiers::ALT });
+        map.insert(Action::CycleLayerBlendMode, Keybinding { code: KeyCode::Char('b'), modifiers: KeyModifiers::ALT });
+        map.insert(Action::ToggleLayerLock, Keybinding { code: KeyCode::Char('l'), modifiers: KeyModifiers::ALT });
+        map.insert(Action::ToggleAlphaLock, Keybinding { code: KeyCode::Char('L'), modifiers: KeyModifiers::SHIFT | KeyModifiers::ALT });
+        map.insert(Action::RenameLayer, Keybinding { code: KeyCode::F(2), modifiers: KeyModifiers::NONE });
+        map.insert(Action::MergeDown, Keybinding { code: KeyCode::Char('m'), modifiers: KeyModifiers::ALT });
+        map.insert(Action::MergeUp, Keybinding { code: KeyCode::Char('M'), modifiers: KeyModifiers::SHIFT | KeyModifiers::ALT });
+        map.insert(Action::PlayAnimation, Keybinding { code: KeyCode::Char('p'), modifiers: KeyModifiers::ALT });
         map.insert(Action::MoveLayerUp, Keybinding { code: KeyCode::Char('k'), modifiers: KeyModifiers::ALT });
         map.insert(Action::MoveLayerDown, Keybinding { code: KeyCode::Char('j'), modifiers: KeyModifiers::ALT });
         map.insert(Action::ToggleOnionSkin, Keybinding { code: KeyCode::Char('i'), modifiers: KeyModifiers::NONE });
         map.insert(Action::IncreaseOnionOpacity, Keybinding { code: KeyCode::Char('u'), modifiers: KeyModifiers::NONE });
         map.insert(Action::DecreaseOnionOpacity, Keybinding { code: KeyCode::Char('y'), modifiers: KeyModifiers::NONE });
+        map.insert(Action::RepeatLastExport, Keybinding { code: KeyCode::Char('e'), modifiers: KeyModifiers::CONTROL });
+        map.insert(Action::RepeatLastCommand, Keybinding { code: KeyCode::Char('.'), modifiers: KeyModifiers::NONE });
+        map.insert(Action::OpenCommandPalette, Keybinding { code: KeyCode::Char('p'), modifiers: KeyModifiers::CONTROL });
+        map.insert(Action::SnapCursorToTile, Keybinding { code: KeyCode::Char('t'), modifiers: KeyModifiers::CONTROL });
+        map.insert(Action::DrawLine, Keybinding { code: KeyCode::Char('g'), modifiers: KeyModifiers::NONE });
+        map.insert(Action::SetCloneSource, Keybinding { code: KeyCode::Char('x'), modifiers: KeyModifiers::NONE });
+        map.insert(Action::ShiftLayerUp, Keybinding { code: KeyCode::Up, modifiers: KeyModifiers::SHIFT | KeyModifiers::ALT });
+        map.insert(Action::ShiftLayerDown, Keybinding { code: KeyCode::Down, modifiers: KeyModifiers::SHIFT | KeyModifiers::ALT });
+        map.insert(Action::ShiftLayerLeft, Keybinding { code: KeyCode::Left, modifiers: KeyModifiers::SHIFT | KeyModifiers::ALT });
+        map.insert(Action::ShiftLayerRight, Keybinding { code: KeyCode::Right, modifiers: KeyModifiers::SHIFT | KeyModifiers::ALT });
+        map.insert(Action::MagicWand, Keybinding { code: KeyCode::Char('w'), modifiers: KeyModifiers::NONE });
+        map.insert(Action::ToggleColorInfo, Keybinding { code: KeyCode::Char('v'), modifiers: KeyModifiers::NONE });
+        map.insert(Action::AdjustSymmetryYNegative, Keybinding { code: KeyCode::Char('M'), modifiers: KeyModifiers::SHIFT });
+        map.insert(Action::AdjustSymmetryYPositive, Keybinding { code: KeyCode::Char('N'), modifiers: KeyModifiers::SHIFT });
+        map.insert(Action::SetSymmetryAtCursor, Keybinding { code: KeyCode::Char('S'), modifiers: KeyModifiers::SHIFT });
+        map.insert(Action::FlipCanvasHorizontal, Keybinding { code: KeyCode::Char('H'), modifiers: KeyModifiers::SHIFT });
+        map.insert(Action::ToggleTilePreview, Keybinding { code: KeyCode::Char('T'), modifiers: KeyModifiers::SHIFT });
+        map.insert(Action::ToggleReference, Keybinding { code: KeyCode::Char('R'), modifiers: KeyModifiers::SHIFT });
     Self { map }
     }
 }
\ No newline at end of file
@@ -0,0 +1,25 @@
+// lib.rs
+
+pub mod app;
+pub mod canvas;
+pub mod color_editor;
+pub mod command_palette;
+pub mod commands;
+pub mod config;
+pub mod controller;
+pub mod export;
+pub mod file_browser;
+pub mod font;
+pub mod help_sheet;
+pub mod keybindings;
+pub mod palette;
+pub mod project;
+pub mod script_handler;
+pub mod ui;
+pub mod utils;
+
+pub use app::{App, AppMode, BlendMode, BrowserFocus, CanvasScrollAction, ColorInfo, ColorMode, Config, DitherPattern, FillMode, HighlighterMode, Layer, LayerFocus, MinimapCache, MinimapMode, PaletteFile, PaletteMenuPosition, PaletteSort, PendingPaletteSwitch, PenShape, PickSource, Pixel, PixelChanges, QuitChoice, ResizeAnchor, SerializableColor, SnapToPaletteMode, SymmetryMode, UndoEntry, UndoStep, DEFAULT_SHADE_FACTOR, MAX_BRUSH_SIZE, MAX_CANVAS_SIZE, MESSAGE_LOG_LEN, PIXEL_WIDTH, RECENT_COLORS};
+pub use canvas::Canvas;
+pub use commands::{execute_command, execute_command_line};
+pub use export::{ExportLayerMode, ExportSettings, ImportResample};
+pub use project::{ProjectFile, SessionMetadata, PROJECT_VERSION};
@@ -2,7 +2,14 @@
 use ratatui::prelude::Color;
 
 #[derive(Clone, Copy, PartialEq, Debug)]
-pub enum Tool { Lighter, Darker, Blur }
+pub enum Tool { Lighter, Darker, Blur, Line, Rectangle, Ellipse, Dither, Clone, Replace }
+
+impl Tool {
+    /// Shape tools draw with the selected color or tool instead of replacing it.
+    pub fn is_shape(self) -> bool {
+        matches!(self, Tool::Line | Tool::Rectangle | Tool::Ellipse)
+    }
+}
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum PaletteEntry {
@@ -18,26 +25,33 @@ pub fn get_default_tool_palette() -> Vec<PaletteEntry> {
         PaletteEntry::Tool(Tool::Lighter),
         PaletteEntry::Tool(Tool::Darker),
         PaletteEntry::Tool(Tool::Blur),
+        PaletteEntry::Tool(Tool::Line),
+        PaletteEntry::Tool(Tool::Rectangle),
+        PaletteEntry::Tool(Tool::Ellipse),
+        PaletteEntry::Tool(Tool::Dither),
+        PaletteEntry::Tool(Tool::Clone),
+        PaletteEntry::Tool(Tool::Replace),
     ]
 }
 
 
 
 
-fn hsv_to_rgb(h: f32, s: f32, v: f32) -> (u8, u8, u8) {
+/// Hue in degrees (0..360), saturation and value in 0..=1.
+pub fn hsv_to_rgb(h: f32, s: f32, v: f32) -> (u8, u8, u8) {
     let c = v * s;
     let x = c * (1.0 - ((h / 60.0) % 2.0 - 1.0).abs());
     let m = v - c;
 
-    let (r_prime, g_prime, b_prime) = if h >= 0.0 && h < 60.0 {
+    let (r_prime, g_prime, b_prime) = if (0.0..60.0).contains(&h) {
         (c, x, 0.0)
-    } else if h >= 60.0 && h < 120.0 {
+    } else if (60.0..120.0).contains(&h) {
         (x, c, 0.0)
-    } else if h >= 120.0 && h < 180.0 {
+    } else if (120.0..180.0).contains(&h) {
         (0.0, c, x)
-    } else if h >= 180.0 && h < 240.0 {
+    } else if (180.0..240.0).contains(&h) {
         (0.0, x, c)
-    } else if h >= 240.0 && h < 300.0 {
+    } else if (240.0..300.0).contains(&h) {
         (x, 0.0, c)
     } else {
         (c, 0.0, x)
@@ -50,6 +64,131 @@ fn hsv_to_rgb(h: f32, s: f32, v: f32) -> (u8, u8, u8) {
     )
 }
 
+/// The inverse of `hsv_to_rgb`. Grays get hue 0.
+pub fn rgb_to_hsv(r: u8, g: u8, b: u8) -> (f32, f32, f32) {
+    let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let delta = max - r.min(g).min(b);
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let saturation = if max == 0.0 { 0.0 } else { delta / max };
+    (hue, saturation, max)
+}
+
+/// Palette file formats, told apart by extension: GIMP's `.gpl`, `.hex` with one color
+/// per line, and anything else as the JSON `.consolet` format.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PaletteFormat { Consolet, Gpl, Hex }
+
+impl PaletteFormat {
+    pub fn from_path(path: &std::path::Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()).map(str::to_lowercase).as_deref() {
+            Some("gpl") => PaletteFormat::Gpl,
+            Some("hex") => PaletteFormat::Hex,
+            _ => PaletteFormat::Consolet,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            PaletteFormat::Consolet => "consolet",
+            PaletteFormat::Gpl => "gpl",
+            PaletteFormat::Hex => "hex",
+        }
+    }
+}
+
+/// Reads a GIMP palette. Blank lines, `#` comments and the header lines are skipped;
+/// every other line is "R G B" with an optional color name after it, which is dropped.
+pub fn parse_gpl(text: &str) -> Result<Vec<Color>, String> {
+    let mut colors = Vec::new();
+    for (number, line) in text.trim_start_matches('\u{feff}').lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.eq_ignore_ascii_case("GIMP Palette") || line.starts_with("Name:") || line.starts_with("Columns:") {
+            continue;
+        }
+        let channels: Option<Vec<u8>> = line.split_whitespace().take(3).map(|c| c.parse().ok()).collect();
+        match channels.as_deref() {
+            Some(&[r, g, b]) => colors.push(Color::Rgb(r, g, b)),
+            _ => return Err(format!("line {}: expected 'R G B [name]', found '{}'", number + 1, line)),
+        }
+    }
+    Ok(colors)
+}
+
+/// Reads a `.hex` palette: one RRGGBB color per line, with or without a leading '#'.
+/// Blank lines and lines starting with ';' or '//' are skipped.
+pub fn parse_hex_palette(text: &str) -> Result<Vec<Color>, String> {
+    let mut colors = Vec::new();
+    for (number, line) in text.trim_start_matches('\u{feff}').lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with("//") {
+            continue;
+        }
+        let hex = line.strip_prefix('#').unwrap_or(line);
+        let channel = |i: usize| hex.get(i..i + 2).and_then(|c| u8::from_str_radix(c, 16).ok());
+        match (hex.len(), channel(0), channel(2), channel(4)) {
+            (6, Some(r), Some(g), Some(b)) => colors.push(Color::Rgb(r, g, b)),
+            _ => return Err(format!("line {}: expected RRGGBB, found '{}'", number + 1, line)),
+        }
+    }
+    Ok(colors)
+}
+
+/// Writes `colors` as a GIMP palette. Named terminal colors keep their name.
+pub fn to_gpl(name: &str, colors: &[Color]) -> String {
+    let mut text = format!("GIMP Palette\nName: {}\n#\n", name);
+    for &color in colors {
+        let (r, g, b) = crate::utils::to_rgb(color);
+        match color {
+            Color::Rgb(..) | Color::Indexed(_) => text.push_str(&format!("{:>3} {:>3} {:>3}\n", r, g, b)),
+            named => text.push_str(&format!("{:>3} {:>3} {:>3}\t{:?}\n", r, g, b, named)),
+        }
+    }
+    text
+}
+
+/// Writes `colors` as a `.hex` palette, one lowercase rrggbb per line.
+pub fn to_hex_palette(colors: &[Color]) -> String {
+    colors.iter().map(|&color| {
+        let (r, g, b) = crate::utils::to_rgb(color);
+        format!("{:02x}{:02x}{:02x}\n", r, g, b)
+    }).collect()
+}
+
+// The terminal's named colors first, so `red` stays the palette's Red, then common CSS colors.
+const NAMED_COLORS: &[(&str, Color)] = &[
+    ("black", Color::Black), ("red", Color::Red), ("green", Color::Green), ("yellow", Color::Yellow),
+    ("blue", Color::Blue), ("magenta", Color::Magenta), ("cyan", Color::Cyan), ("gray", Color::Gray),
+    ("darkgray", Color::DarkGray), ("lightred", Color::LightRed), ("lightgreen", Color::LightGreen),
+    ("lightyellow", Color::LightYellow), ("lightblue", Color::LightBlue), ("lightmagenta", Color::LightMagenta),
+    ("lightcyan", Color::LightCyan), ("white", Color::White),
+    ("orange", Color::Rgb(255, 165, 0)), ("teal", Color::Rgb(0, 128, 128)), ("crimson", Color::Rgb(220, 20, 60)),
+    ("navy", Color::Rgb(0, 0, 128)), ("maroon", Color::Rgb(128, 0, 0)), ("olive", Color::Rgb(128, 128, 0)),
+    ("purple", Color::Rgb(128, 0, 128)), ("lime", Color::Rgb(0, 255, 0)), ("silver", Color::Rgb(192, 192, 192)),
+    ("pink", Color::Rgb(255, 192, 203)), ("brown", Color::Rgb(165, 42, 42)), ("gold", Color::Rgb(255, 215, 0)),
+    ("indigo", Color::Rgb(75, 0, 130)), ("violet", Color::Rgb(238, 130, 238)), ("coral", Color::Rgb(255, 127, 80)),
+    ("salmon", Color::Rgb(250, 128, 114)), ("tomato", Color::Rgb(255, 99, 71)), ("turquoise", Color::Rgb(64, 224, 208)),
+    ("skyblue", Color::Rgb(135, 206, 235)), ("steelblue", Color::Rgb(70, 130, 180)), ("forestgreen", Color::Rgb(34, 139, 34)),
+    ("chartreuse", Color::Rgb(127, 255, 0)), ("khaki", Color::Rgb(240, 230, 140)), ("tan", Color::Rgb(210, 180, 140)),
+    ("chocolate", Color::Rgb(210, 105, 30)), ("sienna", Color::Rgb(160, 82, 45)), ("beige", Color::Rgb(245, 245, 220)),
+    ("ivory", Color::Rgb(255, 255, 240)), ("lavender", Color::Rgb(230, 230, 250)), ("plum", Color::Rgb(221, 160, 221)),
+    ("orchid", Color::Rgb(218, 112, 214)), ("slategray", Color::Rgb(112, 128, 144)),
+];
+
+/// Looks up a color by name, ignoring case, `_` and `-`: `orange`, `Light-Blue`, `dark_gray`.
+pub fn named_color(name: &str) -> Option<Color> {
+    let name: String = name.chars().filter(|c| !matches!(c, '_' | '-')).collect::<String>().to_lowercase();
+    NAMED_COLORS.iter().find(|(known, _)| *known == name).map(|&(_, color)| color)
+}
+
 
 
 pub fn get_default_color_palette() -> Vec<PaletteEntry> {
@@ -0,0 +1,577 @@
+// project.rs
+
+use flate2::write::GzEncoder;
+use flate2::read::GzDecoder;
+use flate2::Compression;
+use std::io::{Write, Read};
+use crate::palette::PaletteEntry;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::{utils, App, AppMode, BlendMode, Canvas, Layer, PenShape, SerializableColor, SymmetryMode, ExportSettings};
+
+/// The project format written by this version. 1 is the original per-pixel layout;
+/// 2 may run-length encode canvases (see `canvas_encoding`).
+pub const PROJECT_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize)]
+pub struct ProjectFile {
+    // Missing in files written before versioning, which are all version 1.
+    #[serde(default = "first_version")]
+    pub version: u32,
+    pub width: usize,
+    pub height: usize,
+    pub canvas: Canvas,
+    pub palette: Vec<SerializableColor>,
+    pub layers: Option<Vec<Layer>>,
+    pub active_layer_index: Option<usize>,
+    pub session: Option<SessionMetadata>,
+    // Named palettes saved along with the project, by name. Missing in older files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub palettes: Option<BTreeMap<String, Vec<SerializableColor>>>,
+}
+
+// Per-project state that isn't part of the artwork itself.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct SessionMetadata {
+    pub last_export: Option<ExportSettings>,
+    pub tile_size: Option<(usize, usize)>,
+    pub brush_path: Option<String>,
+    pub reference: Option<Canvas>,
+    pub reference_opacity: Option<f32>,
+    // View and tool state, restored unless restore_session is off.
+    pub zoom_level: Option<u16>,
+    pub view_offset: Option<(i32, i32)>,
+    pub cursor_pos: Option<(u16, u16)>,
+    pub symmetry_mode: Option<SymmetryMode>,
+    pub pen_size: Option<u16>,
+    pub pen_shape: Option<PenShape>,
+    pub opacity: Option<f32>,
+    pub palette_name: Option<String>,
+    pub recent_colors: Option<Vec<SerializableColor>>,
+}
+
+impl ProjectFile {
+    // Hand-edited files can hold canvases of another size than the project's, which
+    // would panic on the first draw. Describes the first one found.
+    fn size_mismatch(&self) -> Option<String> {
+        let size = (self.width, self.height);
+        let wrong = |what: String, canvas: &Canvas| (canvas.size() != size).then(|| {
+            format!("{} is {}x{}, but the project is {}x{}.", what, canvas.width(), canvas.height(), size.0, size.1)
+        });
+        match &self.layers {
+            Some(layers) if layers.is_empty() => Some("The project has no layers.".to_string()),
+            Some(layers) => layers.iter().find_map(|layer| wrong(format!("Layer '{}'", layer.name), &layer.canvas)),
+            None => wrong("The canvas".to_string(), &self.canvas),
+        }.or_else(|| {
+            let reference = self.session.as_ref()?.reference.as_ref()?;
+            wrong("The reference image".to_string(), reference)
+        })
+    }
+}
+
+impl App {
+
+pub fn save_project(&mut self, path: &Path, set_as_current: bool) -> bool {
+    let result = self.write_project(path);
+    self.embed_palettes_once = false;
+    match result {
+        Ok(()) => {
+            if set_as_current { self.project_path = Some(path.to_path_buf()); }
+            self.dirty = false;
+            remember_recent_project(path);
+            let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("file");
+            self.set_status(format!("Saved to {}", file_name));
+            true
+        }
+        Err(e) => {
+            self.set_status(e);
+            false
+        }
+    }
+}
+
+// Serializes the project and writes it to `path`: gzip-compressed, or pretty-printed
+// plain JSON for `.consolet.json`.
+fn write_project(&self, path: &Path) -> Result<(), String> {
+    let data = self.project_bytes(is_plain_project(path))?;
+    std::fs::write(path, data).map_err(|_| "Error writing file.".to_string())
+}
+
+/// How many bytes saving the project would write now, in the format of its current
+/// file (or the plain_project_save default for a new one).
+pub fn estimated_project_size(&self) -> Result<usize, String> {
+    let plain = self.project_path.as_deref().map_or(self.plain_project_save, is_plain_project);
+    self.project_bytes(plain).map(|data| data.len())
+}
+
+// The project file's contents: pretty-printed JSON when `plain`, otherwise gzip-compressed.
+fn project_bytes(&self, plain: bool) -> Result<Vec<u8>, String> {
+    let current_palette: Vec<SerializableColor> = self.color_palette.iter().filter_map(|entry| {
+        if let PaletteEntry::Color(c) = entry { Some((*c).into()) } else { None }
+    }).collect();
+
+    let project_file = ProjectFile {
+        version: PROJECT_VERSION,
+        width: self.canvas_width,
+        height: self.canvas_height,
+        canvas: self.canvas.clone(),
+        palette: current_palette,
+        layers: Some(self.layers.clone().into()),
+        active_layer_index: Some(self.active_layer_index),
+        session: Some(SessionMetadata {
+            last_export: self.last_export.clone(),
+            tile_size: self.tile_size,
+            brush_path: self.brush_path.clone(),
+            reference: self.reference.clone(),
+            reference_opacity: Some(self.reference_opacity),
+            zoom_level: Some(self.zoom_level),
+            view_offset: Some((self.view_offset_x, self.view_offset_y)),
+            cursor_pos: Some(self.cursor_pos),
+            symmetry_mode: Some(self.symmetry_mode),
+            pen_size: Some(self.pen_size),
+            pen_shape: Some(self.pen_shape),
+            opacity: Some(self.opacity),
+            palette_name: Some(self.active_palette_name.clone()),
+            recent_colors: Some(self.recent_colors.iter().map(|&c| c.into()).collect()),
+        }),
+        palettes: (self.embed_palettes || self.embed_palettes_once).then(|| self.palettes_in_use()),
+    };
+
+    if plain {
+        return serde_json::to_vec_pretty(&project_file).map_err(|_| "Error serializing project.".to_string());
+    }
+    let json_data = serde_json::to_string(&project_file).map_err(|_| "Error serializing project.".to_string())?;
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(json_data.as_bytes())
+        .and_then(|_| encoder.finish())
+        .map_err(|_| "Error compressing data.".to_string())
+}
+
+// The active palette and every other loaded palette with a color on the canvas. The
+// built-in default palette is left out since every install has it.
+fn palettes_in_use(&self) -> BTreeMap<String, Vec<SerializableColor>> {
+    let used: HashSet<(u8, u8, u8)> = self.layers.iter()
+        .flat_map(|layer| layer.canvas.pixels())
+        .filter(|pixel| pixel.alpha > 0.0)
+        .map(|pixel| utils::to_rgb(pixel.color.into()))
+        .collect();
+    self.loaded_palettes.iter()
+        .filter(|(name, _)| name.as_str() != "default")
+        .filter_map(|(name, entries)| {
+            let colors: Vec<SerializableColor> = entries.iter().filter_map(|entry| {
+                if let PaletteEntry::Color(c) = entry { Some((*c).into()) } else { None }
+            }).collect();
+            let in_use = *name == self.active_palette_name || entries.iter().any(|entry| matches!(entry, PaletteEntry::Color(c) if used.contains(&utils::to_rgb(*c))));
+            (in_use && !colors.is_empty()).then(|| (name.clone(), colors))
+        })
+        .collect()
+}
+
+/// Writes the project to its next autosave rotation instead of over the project file.
+/// The first autosave of a session takes the oldest rotation; later ones cycle. Each
+/// one goes to a temporary file first, so a failed write leaves the rotation intact.
+/// Rotations past `autosave_rotations` are removed.
+pub fn autosave(&mut self) {
+    let Some(project) = self.project_path.clone() else { return };
+    let rotations = self.autosave_rotations.max(1) as usize;
+    let slot = match self.next_autosave_slot {
+        Some(slot) if slot <= rotations => slot,
+        // Missing rotations count as oldest.
+        _ => (1..=rotations)
+            .min_by_key(|&n| std::fs::metadata(autosave_path(&project, n)).and_then(|m| m.modified()).ok())
+            .unwrap_or(1),
+    };
+    let path = autosave_path(&project, slot);
+    let temp_path = path.with_extension("consolet.tmp");
+
+    let result = self.write_project(&temp_path)
+        .and_then(|_| std::fs::rename(&temp_path, &path).map_err(|e| format!("Error replacing autosave: {}", e)));
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&temp_path);
+        self.set_status(format!("Autosave failed: {}", e));
+        return;
+    }
+    for (n, old) in autosave_files(&project) {
+        if n > rotations {
+            let _ = std::fs::remove_file(old);
+        }
+    }
+    self.next_autosave_slot = Some(slot % rotations + 1);
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("file");
+    self.set_status(format!("Autosaved to {}", file_name));
+}
+
+/// Loads the newest autosave of the current project in its place. The project path
+/// stays on the main file, so the next `save` writes there.
+pub fn restore_autosave(&mut self) {
+    let Some(project) = self.project_path.clone() else {
+        self.set_status("No project is open.");
+        return;
+    };
+    let Some(autosave) = autosave_files(&project).into_iter().map(|(_, path)| path).max_by_key(|path| modified(path)) else {
+        self.set_status(format!("No autosave found for {}.", project.display()));
+        return;
+    };
+    let autosave_interval = self.autosave_interval;
+    self.load_project(&autosave);
+    self.project_path = Some(project);
+    self.autosave_interval = autosave_interval;
+    // The project file itself still has the older state.
+    self.dirty = true;
+    let file_name = autosave.file_name().and_then(|n| n.to_str()).unwrap_or("file");
+    self.set_status(format!("Restored {}. Save to keep it.", file_name));
+}
+
+/// Lists the recent projects in a popup, newest first.
+pub fn show_recent_projects(&mut self) {
+    let recent = read_recent_projects();
+    if recent.is_empty() {
+        self.set_status("No recent projects.");
+        return;
+    }
+    let lines = recent.iter().enumerate().map(|(i, path)| format!("{:>3}  {}", i + 1, path.display())).collect();
+    self.info_popup = Some((" Recent Projects ".to_string(), lines));
+    self.info_popup_scroll = 0;
+    self.mode = AppMode::InfoPopup;
+}
+
+// Resolves the ConfirmRestoreAutosave dialog.
+pub fn confirm_restore_autosave(&mut self, restore: bool) {
+    self.mode = AppMode::Drawing;
+    if self.pending_autosave.take().is_some() && restore {
+        self.restore_autosave();
+    }
+}
+
+pub fn load_project(&mut self, path: &PathBuf) {
+    self.load_project_with(path, false);
+}
+
+/// Loads the project at `path`. Palettes embedded in it are added to the loaded
+/// palettes; same-named local ones are kept unless `replace_palettes` is set.
+pub fn load_project_with(&mut self, path: &PathBuf, replace_palettes: bool) -> bool {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) => { self.set_status(format!("Error reading file: {}", e)); return false; }
+    };
+
+    // Sniff the gzip magic bytes rather than trusting the extension.
+    let mut json_data = String::new();
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        if GzDecoder::new(bytes.as_slice()).read_to_string(&mut json_data).is_err() {
+            self.set_status("File is not a valid compressed project.");
+            return false;
+        }
+    } else {
+        match String::from_utf8(bytes) {
+            Ok(text) => json_data = text,
+            Err(_) => { self.set_status("File is not a valid project."); return false; }
+        }
+    }
+
+    match serde_json::from_str::<ProjectFile>(&json_data) {
+        Ok(project_file) if project_file.version > PROJECT_VERSION => {
+            self.set_status(format!("Project format {} is newer than this version of consolet supports ({}).", project_file.version, PROJECT_VERSION));
+            false
+        }
+        Ok(project_file) => {
+            if let Some(mismatch) = project_file.size_mismatch() {
+                self.set_status(format!("Cannot load project: {}", mismatch));
+                return false;
+            }
+            self.canvas_width = project_file.width;
+            self.canvas_height = project_file.height;
+            self.canvas = project_file.canvas;
+            
+            if let Some(layers) = project_file.layers {
+                self.layers = layers.into();
+                self.active_layer_index = project_file.active_layer_index.unwrap_or(0);
+                if self.active_layer_index >= self.layers.len() {
+                    self.active_layer_index = 0;
+                }
+            } else {
+                self.layers = [Layer {
+                    name: "Layer 1".to_string(),
+                    canvas: self.canvas.clone(),
+                    visible: true,
+                    opacity: 1.0,
+                    locked: false,
+                    blend_mode: BlendMode::Normal,
+                    alpha_locked: false,
+                }].into();
+                self.active_layer_index = 0;
+            }
+            self.sync_canvas_from_layers();
+            let session = project_file.session.unwrap_or_default();
+            if self.restore_session {
+                self.restore_session_state(&session);
+            }
+            self.last_export = session.last_export;
+            self.tile_size = session.tile_size;
+            self.reference = session.reference;
+            self.reference_opacity = session.reference_opacity.unwrap_or(0.3);
+            self.reference_visible = true;
+            let loaded_palette: Vec<PaletteEntry> = project_file.palette.into_iter()
+                .map(|sc| PaletteEntry::Color(sc.into()))
+                .collect();
+            self.color_palette = loaded_palette;
+            self.palette_index = 0;
+            self.palette_scroll_state = 0;
+            self.project_path = Some(path.clone());
+            self.undo_stack.clear();
+            self.redo_stack.clear();
+            self.undo_history_trimmed = false;
+            self.autosave_interval = None;
+            self.dirty = false;
+            let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("file");
+            let palette_note = self.restore_embedded_palettes(project_file.palettes, replace_palettes);
+            self.set_status(format!("Loaded {}{}", file_name, palette_note));
+            if !is_autosave(path) {
+                remember_recent_project(path);
+            }
+            self.clear_brush();
+            if let Some(brush_path) = session.brush_path {
+                if let Err(e) = self.load_brush(&brush_path) {
+                    self.set_status(format!("Loaded {}, but the custom brush could not be restored: {}", file_name, e));
+                }
+            }
+            self.next_autosave_slot = None;
+            if let Some(autosave) = newer_autosave(path) {
+                self.pending_autosave = Some(autosave);
+                self.confirm_selection_yes = true;
+                self.mode = AppMode::ConfirmRestoreAutosave;
+            }
+            true
+        }
+        Err(e) => {
+            self.set_status(format!("Error parsing project file: {}", e));
+            false
+        }
+    }
+}
+
+// Adds the palettes a project carries to `loaded_palettes`, returning a note for the
+// load message about any that clashed with local ones of the same name.
+fn restore_embedded_palettes(&mut self, palettes: Option<BTreeMap<String, Vec<SerializableColor>>>, replace: bool) -> String {
+    let mut kept = Vec::new();
+    let mut replaced = Vec::new();
+    for (name, colors) in palettes.unwrap_or_default() {
+        let entries: Vec<PaletteEntry> = colors.into_iter().map(|sc| PaletteEntry::Color(sc.into())).collect();
+        match self.loaded_palettes.get(&name) {
+            Some(local) if *local == entries => {}
+            Some(_) if !replace => { kept.push(name); continue; }
+            Some(_) => replaced.push(name.clone()),
+            None => {}
+        }
+        self.loaded_palettes.insert(name, entries);
+    }
+    if !kept.is_empty() {
+        format!(" (kept local palette{} {}; load with --force to use the project's)", if kept.len() == 1 { "" } else { "s" }, kept.join(", "))
+    } else if !replaced.is_empty() {
+        format!(" (replaced local palette{} {})", if replaced.len() == 1 { "" } else { "s" }, replaced.join(", "))
+    } else {
+        String::new()
+    }
+}
+
+// Puts back whatever view and tool state the session recorded; anything missing
+// keeps its current value.
+fn restore_session_state(&mut self, session: &SessionMetadata) {
+    if let Some(zoom) = session.zoom_level {
+        self.zoom_level = (zoom.max(2) / 2) * 2;
+    }
+    if let Some((x, y)) = session.view_offset {
+        self.view_offset_x = x;
+        self.view_offset_y = y;
+    }
+    if let Some((x, y)) = session.cursor_pos {
+        self.cursor_pos = (
+            x.min(self.canvas_width.saturating_sub(1) as u16),
+            y.min(self.canvas_height.saturating_sub(1) as u16),
+        );
+    }
+    if let Some(mode) = session.symmetry_mode {
+        self.symmetry_mode = mode;
+    }
+    if let Some(size) = session.pen_size {
+        self.pen_size = size.max(1);
+    }
+    if let Some(shape) = session.pen_shape {
+        self.pen_shape = shape;
+    }
+    if let Some(opacity) = session.opacity {
+        self.opacity = opacity.clamp(0.0, 1.0);
+    }
+    if let Some(name) = &session.palette_name {
+        self.active_palette_name = name.clone();
+    }
+    if let Some(colors) = &session.recent_colors {
+        self.recent_colors = colors.iter().take(crate::RECENT_COLORS).map(|&c| c.into()).collect();
+        self.recent_color_cursor = 0;
+    }
+}
+}
+
+fn first_version() -> u32 { 1 }
+
+/// Reads and writes canvases either as rows of pixels or, when that is smaller, as
+/// `{"width", "height", "runs": [[pixel, count], ...]}` runs over the pixels in row
+/// order. Both are accepted on load, so older projects keep working.
+pub mod canvas_encoding {
+    use crate::{Canvas, Pixel};
+    use serde::de::{self, MapAccess, SeqAccess, Visitor};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::fmt;
+
+    #[derive(Serialize, Deserialize)]
+    struct RunLengthCanvas {
+        width: usize,
+        height: usize,
+        runs: Vec<(Pixel, u32)>,
+    }
+
+    fn runs(canvas: &Canvas) -> Vec<(Pixel, u32)> {
+        let mut runs: Vec<(Pixel, u32)> = Vec::new();
+        for &pixel in canvas.pixels() {
+            match runs.last_mut() {
+                Some((last, count)) if *last == pixel => *count += 1,
+                _ => runs.push((pixel, 1)),
+            }
+        }
+        runs
+    }
+
+    // Mostly uniform canvases are written as runs. Busier ones keep the nested rows
+    // older versions wrote, which stay readable as JSON.
+    pub fn serialize<S: Serializer>(canvas: &Canvas, serializer: S) -> Result<S::Ok, S::Error> {
+        let runs = runs(canvas);
+        // A run is a pixel plus a count, a few bytes more than the pixel alone.
+        if runs.len() * 4 < canvas.pixels().len() * 3 {
+            RunLengthCanvas { width: canvas.width(), height: canvas.height(), runs }.serialize(serializer)
+        } else {
+            serializer.collect_seq(canvas.rows())
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Canvas, D::Error> {
+        deserializer.deserialize_any(CanvasVisitor)
+    }
+
+    struct CanvasVisitor;
+
+    impl<'de> Visitor<'de> for CanvasVisitor {
+        type Value = Canvas;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("rows of pixels or a run-length encoded canvas")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut rows = Vec::new();
+            while let Some(row) = seq.next_element::<Vec<Pixel>>()? {
+                rows.push(row);
+            }
+            Ok(Canvas::from_rows(rows))
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+            let rle = RunLengthCanvas::deserialize(de::value::MapAccessDeserializer::new(map))?;
+            let total: u64 = rle.runs.iter().map(|&(_, count)| count as u64).sum();
+            if total != (rle.width * rle.height) as u64 {
+                return Err(de::Error::custom(format!("run-length canvas has {} pixels, expected {}x{}", total, rle.width, rle.height)));
+            }
+            let pixels = rle.runs.into_iter().flat_map(|(pixel, count)| std::iter::repeat_n(pixel, count as usize)).collect();
+            Canvas::from_pixels(rle.width, rle.height, pixels).ok_or_else(|| de::Error::custom("run-length canvas size mismatch"))
+        }
+    }
+}
+
+/// Where autosave rotation `n` (from 1) of the project at `project` goes:
+/// `<name>.autosave-<n>.consolet` next to it.
+pub fn autosave_path(project: &Path, n: usize) -> PathBuf {
+    let stem = project_stem(project);
+    project.with_file_name(format!("{}.autosave-{}.consolet", stem, n))
+}
+
+// Every autosave rotation of `project` on disk, with its number.
+fn autosave_files(project: &Path) -> Vec<(usize, PathBuf)> {
+    let stem = project_stem(project);
+    let prefix = format!("{}.autosave-", stem);
+    let dir = project.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let Ok(entries) = std::fs::read_dir(dir) else { return Vec::new() };
+    entries.filter_map(Result::ok).filter_map(|entry| {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let n = name.strip_prefix(&prefix)?.strip_suffix(".consolet")?.parse().ok()?;
+        Some((n, project.with_file_name(name)))
+    }).collect()
+}
+
+/// Whether `path` names an uncompressed, pretty-printed project.
+pub fn is_plain_project(path: &Path) -> bool {
+    path.to_string_lossy().to_lowercase().ends_with(".consolet.json")
+}
+
+// The project's file name without `.consolet` or `.consolet.json`.
+fn project_stem(project: &Path) -> &str {
+    let name = project.file_name().and_then(|n| n.to_str()).unwrap_or("project");
+    name.strip_suffix(".consolet.json").or_else(|| name.strip_suffix(".consolet")).unwrap_or(name)
+}
+
+fn is_autosave(path: &Path) -> bool {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    name.strip_suffix(".consolet")
+        .and_then(|rest| rest.rsplit_once(".autosave-"))
+        .is_some_and(|(_, n)| n.parse::<usize>().is_ok())
+}
+
+fn modified(path: &Path) -> Option<std::time::SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// The newest autosave of `project`, if it was written after the project file.
+pub fn newer_autosave(project: &Path) -> Option<PathBuf> {
+    let project_time = modified(project)?;
+    autosave_files(project).into_iter()
+        .map(|(_, path)| path)
+        .filter_map(|path| Some((modified(&path)?, path)))
+        .filter(|(time, _)| *time > project_time)
+        .max_by_key(|(time, _)| *time)
+        .map(|(_, path)| path)
+}
+
+pub const MAX_RECENT_PROJECTS: usize = 10;
+
+fn recent_projects_path() -> std::io::Result<PathBuf> {
+    Ok(crate::utils::get_or_create_app_dir()?.join("recent_projects.json"))
+}
+
+/// The recently saved or loaded projects, newest first. Files that no longer exist
+/// are dropped, and paths leading to the same file are listed once.
+pub fn read_recent_projects() -> Vec<PathBuf> {
+    let stored: Vec<PathBuf> = recent_projects_path().ok()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default();
+    let mut recent: Vec<PathBuf> = Vec::new();
+    for path in stored.into_iter().filter_map(|path| std::fs::canonicalize(path).ok()) {
+        if !recent.contains(&path) {
+            recent.push(path);
+        }
+    }
+    recent.truncate(MAX_RECENT_PROJECTS);
+    recent
+}
+
+// Moves `path` to the front of the recent projects list. Failing to record it is
+// not worth interrupting a save or load over, so errors are ignored.
+fn remember_recent_project(path: &Path) {
+    let Ok(path) = std::fs::canonicalize(path) else { return };
+    let mut recent = read_recent_projects();
+    recent.retain(|p| *p != path);
+    recent.insert(0, path);
+    recent.truncate(MAX_RECENT_PROJECTS);
+    if let (Ok(file), Ok(json)) = (recent_projects_path(), serde_json::to_string_pretty(&recent)) {
+        let _ = std::fs::write(file, json);
+    }
+}
@@ -1,28 +1,56 @@
+use crate::palette::Tool;
 use crate::App;
 use ratatui::prelude::*;
 use ratatui::widgets::{Block, Borders, Clear, Paragraph};
 
 use std::io::Result;
 use std::path::PathBuf;
-use std::time::Instant;
 use serde::Deserialize;
+use std::collections::HashMap;
 
 
 
 
 
-// A command can be either a simple string or a symmetry block
-#[derive(Deserialize)]
-#[serde(untagged)]
+// A command is a simple string, a symmetry block or a repeat block. Blocks hold
+// commands of their own, so they nest.
 enum ScriptCommand {
     Simple(String),
-    SymmetryBlock(SymmetryBlock),
+    SymmetryBlock(SymmetryInfo, Vec<ScriptCommand>),
+    Repeat(RepeatInfo, Vec<ScriptCommand>),
 }
 
 #[derive(Deserialize)]
 struct SymmetryBlock {
     symmetry: SymmetryInfo,
-    commands: Vec<String>,
+    commands: Vec<serde_json::Value>,
+}
+
+// {"repeat": N, "dx": 1, "dy": 0, "commands": [...]} runs its commands N times,
+// moving every coordinate by (dx * i, dy * i) on pass i.
+#[derive(Deserialize)]
+struct RepeatBlock {
+    repeat: u32,
+    #[serde(default)]
+    dx: i32,
+    #[serde(default)]
+    dy: i32,
+    commands: Vec<serde_json::Value>,
+}
+
+struct RepeatInfo {
+    count: u32,
+    dx: i32,
+    dy: i32,
+}
+
+// A script is a list of commands, or {"vars": {"name": value}, "commands": [...]}
+// whose `$name`s are replaced in every command string.
+#[derive(Deserialize)]
+struct ScriptFile {
+    #[serde(default)]
+    vars: serde_json::Map<String, serde_json::Value>,
+    commands: Vec<serde_json::Value>,
 }
 
 #[derive(Deserialize)]
@@ -48,7 +76,7 @@ pub fn load_script_for_editing(app: &mut App) {
     let path = match get_script_path() {
         Ok(p) => p,
         Err(_) => {
-            app.status_message = Some(("Could not access script path.".to_string(), Instant::now()));
+            app.set_status("Could not access script path.");
             return;
         }
     };
@@ -71,12 +99,12 @@ pub fn save_script(app: &mut App) {
         let content: String = app.script_content_lines.join("\n");
         if serde_json::from_str::<serde_json::Value>(&content).is_ok() {
             if std::fs::write(path, content).is_ok() {
-                app.status_message = Some(("Script saved.".to_string(), Instant::now()));
+                app.set_status("Script saved.");
             } else {
-                app.status_message = Some(("Error saving script.".to_string(), Instant::now()));
+                app.set_status("Error saving script.");
             }
         } else {
-            app.status_message = Some(("Invalid JSON. Could not save script.".to_string(), Instant::now()));
+            app.set_status("Invalid JSON. Could not save script.");
         }
     }
 }
@@ -87,55 +115,186 @@ pub fn clear_script(app: &mut App) {
     app.script_cursor_line = 0;
     app.script_cursor_char_pos = 0;
     app.script_scroll_state = 0;
-    app.status_message = Some(("Script cleared.".to_string(), Instant::now()));
+    app.set_status("Script cleared.");
 }
 
 // The core engine that parses and executes the drawing script
 pub fn parse_and_execute_script(app: &mut App) {
     let path = match get_script_path() {
         Ok(p) => p,
-        Err(_) => { app.status_message = Some(("Could not access script path.".to_string(), Instant::now())); return; }
+        Err(_) => { app.set_status("Could not access script path."); return; }
     };
     let content = match std::fs::read_to_string(path) {
         Ok(c) => c,
-        Err(_) => { app.status_message = Some(("command_draw.json not found.".to_string(), Instant::now())); return; }
+        Err(_) => { app.set_status("command_draw.json not found."); return; }
+    };
+    let commands = match serde_json::from_str::<serde_json::Value>(&content) {
+        Ok(script) => parse_script(script),
+        Err(e) => { app.set_status(format!("Invalid JSON in script: {}", e)); return; }
     };
-    let commands = match serde_json::from_str::<Vec<ScriptCommand>>(&content) {
+    let commands = match commands {
         Ok(c) => c,
-        Err(e) => { app.status_message = Some((format!("Invalid JSON in script: {}", e), Instant::now())); return; }
+        Err(e) => { app.set_status(e); return; }
     };
 
-    app.save_state_for_undo();
+    // `layer` commands can move the script onto any layer, so all of them go in its undo step.
+    app.save_layers_for_undo(&(0..app.layers.len()).collect::<Vec<_>>(), "Script");
     let mut operations_performed = 0;
     let original_symmetry = app.symmetry_mode; // Save the user's current symmetry setting
+    // The script's pen_size, opacity and layer commands only last until it ends.
+    let (original_selection, original_pen_size, original_opacity, original_layer) = (app.current_selection, app.pen_size, app.opacity, app.active_layer_index);
+    // Scripts start from a 1px pen at full opacity, whatever the user was drawing with.
+    app.pen_size = 1;
+    app.opacity = 1.0;
+
+    // Commands outside symmetry blocks draw with symmetry off.
+    run_commands(app, &commands, (0, 0), crate::SymmetryMode::Off, &mut operations_performed);
+
+    app.symmetry_mode = original_symmetry; // IMPORTANT: Restore the user's original symmetry setting
+    app.current_selection = original_selection;
+    app.pen_size = original_pen_size;
+    app.opacity = original_opacity;
+    // `layer:<x> active` keeps its switch, as it always has.
+    if switches_layers(&commands) {
+        app.active_layer_index = original_layer.min(app.layers.len().saturating_sub(1));
+    }
+    app.set_status(format!("Script executed. {} operations performed.", operations_performed));
+}
 
+// Checks the whole script before anything is drawn. Errors name the block by its
+// position, counting from 1, with nested blocks as e.g. 3.2.
+fn parse_script(script: serde_json::Value) -> std::result::Result<Vec<ScriptCommand>, String> {
+    let file = match script {
+        serde_json::Value::Array(commands) => ScriptFile { vars: serde_json::Map::new(), commands },
+        other => serde_json::from_value::<ScriptFile>(other).map_err(|e| format!("Invalid script: {}", e))?,
+    };
+    let vars = file.vars.into_iter()
+        .map(|(name, value)| match value {
+            serde_json::Value::String(text) => (name, text),
+            other => (name, other.to_string()),
+        })
+        .collect();
+    parse_commands(file.commands, "", &vars)
+}
+
+fn parse_commands(values: Vec<serde_json::Value>, parent: &str, vars: &HashMap<String, String>) -> std::result::Result<Vec<ScriptCommand>, String> {
+    values.into_iter().enumerate().map(|(i, value)| {
+        let path = format!("{}{}", parent, i + 1);
+        let error = |message: String| format!("Script block {}: {}", path, message);
+        match value {
+            serde_json::Value::String(command) => substitute_vars(&command, vars).map(ScriptCommand::Simple).map_err(error),
+            serde_json::Value::Object(ref fields) if fields.contains_key("repeat") => {
+                let block = serde_json::from_value::<RepeatBlock>(value).map_err(|e| error(e.to_string()))?;
+                let info = RepeatInfo { count: block.repeat, dx: block.dx, dy: block.dy };
+                Ok(ScriptCommand::Repeat(info, parse_commands(block.commands, &format!("{}.", path), vars)?))
+            }
+            serde_json::Value::Object(ref fields) if fields.contains_key("symmetry") => {
+                let block = serde_json::from_value::<SymmetryBlock>(value).map_err(|e| error(e.to_string()))?;
+                Ok(ScriptCommand::SymmetryBlock(block.symmetry, parse_commands(block.commands, &format!("{}.", path), vars)?))
+            }
+            _ => Err(error("expected a command string, a repeat block or a symmetry block".to_string())),
+        }
+    }).collect()
+}
+
+// Whether the script uses `layer <index|name>` anywhere.
+fn switches_layers(commands: &[ScriptCommand]) -> bool {
+    commands.iter().any(|command| match command {
+        ScriptCommand::Simple(cmd_str) => cmd_str.trim().starts_with("layer "),
+        ScriptCommand::SymmetryBlock(_, block) | ScriptCommand::Repeat(_, block) => switches_layers(block),
+    })
+}
+
+// Replaces each `$name` with its value from the script's "vars".
+fn substitute_vars(command: &str, vars: &HashMap<String, String>) -> std::result::Result<String, String> {
+    let mut result = String::new();
+    let mut rest = command;
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let len = after.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(after.len());
+        let name = &after[..len];
+        match vars.get(name) {
+            Some(value) => result.push_str(value),
+            None if name.is_empty() => return Err("'$' must be followed by a variable name".to_string()),
+            None => return Err(format!("unknown variable ${}", name)),
+        }
+        rest = &after[len..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn run_commands(app: &mut App, commands: &[ScriptCommand], offset: (i32, i32), symmetry: crate::SymmetryMode, operations_performed: &mut i32) {
     for command in commands {
         match command {
             ScriptCommand::Simple(cmd_str) => {
-                // For simple commands, temporarily turn symmetry OFF
-                app.symmetry_mode = crate::SymmetryMode::Off;
-                execute_single_command_string(app, &cmd_str, &mut operations_performed);
-            },
-            ScriptCommand::SymmetryBlock(block) => {
-                // For a symmetry block, set the specified symmetry mode
-                let new_mode = match block.symmetry.mode.as_str() {
-                    "vertical" => crate::SymmetryMode::Vertical(block.symmetry.coordinate as u16),
-                    "horizontal" => crate::SymmetryMode::Horizontal(block.symmetry.coordinate as u16),
-                    "diagonal_forward" => crate::SymmetryMode::DiagonalForward(block.symmetry.coordinate),
-                    "diagonal_backward" => crate::SymmetryMode::DiagonalBackward(block.symmetry.coordinate),
+                app.symmetry_mode = symmetry;
+                execute_single_command_string(app, &offset_coordinates(cmd_str, offset), operations_performed);
+            }
+            ScriptCommand::SymmetryBlock(info, block) => {
+                let new_mode = match info.mode.as_str() {
+                    "vertical" => crate::SymmetryMode::Vertical(info.coordinate as u16),
+                    "horizontal" => crate::SymmetryMode::Horizontal(info.coordinate as u16),
+                    "diagonal_forward" => crate::SymmetryMode::DiagonalForward(info.coordinate),
+                    "diagonal_backward" => crate::SymmetryMode::DiagonalBackward(info.coordinate),
+                    "quad" => crate::SymmetryMode::Quad(app.canvas_width as u16 / 2, app.canvas_height as u16 / 2),
+                    "radial" => crate::SymmetryMode::Radial(app.radial_segments, app.canvas_width as u16 / 2, app.canvas_height as u16 / 2),
                     _ => crate::SymmetryMode::Off,
                 };
-                app.symmetry_mode = new_mode;
-                // Execute all commands within this block using that symmetry
-                for cmd_str in &block.commands {
-                    execute_single_command_string(app, cmd_str, &mut operations_performed);
+                run_commands(app, block, offset, new_mode, operations_performed);
+            }
+            ScriptCommand::Repeat(info, block) => {
+                for i in 0..info.count as i32 {
+                    let pass_offset = (offset.0 + info.dx * i, offset.1 + info.dy * i);
+                    run_commands(app, block, pass_offset, symmetry, operations_performed);
                 }
             }
         }
     }
+}
 
-    app.symmetry_mode = original_symmetry; // IMPORTANT: Restore the user's original symmetry setting
-    app.status_message = Some((format!("Script executed. {} operations performed.", operations_performed), Instant::now()));
+// Stamps the brush along a shape the way the Line, Rectangle and Ellipse tools do,
+// counting each stamped pixel as an operation.
+fn draw_script_shape(app: &mut App, color: Color, tool: Tool, from: (i32, i32), to: (i32, i32), filled: bool, operations_performed: &mut i32) {
+    app.current_selection = crate::palette::PaletteEntry::Color(color);
+    // Like the tools, overlapping stamps of one shape don't stack opacity.
+    if app.protect_stroke { app.drawn_pixels_in_stroke.clear(); }
+    for (x, y) in app.shape_points(tool, from, to, filled) {
+        app.apply_brush(x, y);
+        *operations_performed += 1;
+    }
+    if app.protect_stroke { app.drawn_pixels_in_stroke.clear(); }
+}
+
+// Moves every `x,y` and `x,y-x,y` argument of a drawing command by `offset`.
+// Points that would land left of or above the canvas are dropped.
+fn offset_coordinates(cmd_str: &str, (dx, dy): (i32, i32)) -> String {
+    if (dx, dy) == (0, 0) || cmd_str.trim().starts_with("layer") {
+        return cmd_str.to_string();
+    }
+    let shift = |point: &str| -> Option<Option<String>> {
+        let (x, y) = point.split_once(',')?;
+        let (x, y) = (x.parse::<i32>().ok()? + dx, y.parse::<i32>().ok()? + dy);
+        Some((u16::try_from(x).is_ok() && u16::try_from(y).is_ok()).then(|| format!("{},{}", x, y)))
+    };
+    let mut parts = cmd_str.split_whitespace();
+    let mut result: Vec<String> = parts.next().map(String::from).into_iter().collect();
+    for part in parts {
+        let shifted = match part.split_once('-') {
+            Some((start, end)) => match (shift(start), shift(end)) {
+                (Some(start), Some(end)) => Some(start.zip(end).map(|(start, end)| format!("{}-{}", start, end))),
+                _ => None,
+            },
+            None => shift(part),
+        };
+        match shifted {
+            Some(Some(moved)) => result.push(moved),
+            Some(None) => {}
+            None => result.push(part.to_string()),
+        }
+    }
+    result.join(" ")
 }
 
 // Renders the UI for the script editor
@@ -198,22 +357,53 @@ fn execute_single_command_string(app: &mut App, cmd_str: &str, operations_perfor
          })
     };
 
+    // "layer:<name|#> visible=false", "layer:\"Line art\" active", ... switch or adjust layers mid-script.
+    if let Some(rest) = cmd_str.trim().strip_prefix("layer:") {
+        if let [target, verb] = crate::utils::split_args(rest).as_slice() {
+            if app.apply_layer_verb(target, verb).is_ok() {
+                *operations_performed += 1;
+            }
+        }
+        return;
+    }
+
+    // "layer <index|name>" makes that layer the one later commands draw on.
+    if let Some(rest) = cmd_str.trim().strip_prefix("layer ") {
+        if let [target] = crate::utils::split_args(rest).as_slice() {
+            if app.apply_layer_verb(target, "active").is_ok() {
+                *operations_performed += 1;
+            }
+        }
+        return;
+    }
+
     let parts: Vec<&str> = cmd_str.split_whitespace().collect();
     if parts.len() < 2 { return; }
 
     let command_part = parts[0];
     let coordinate_parts = &parts[1..];
 
+    // Brush settings for the rest of the script.
+    match (command_part, coordinate_parts) {
+        ("pen_size", [size]) => {
+            if let Ok(size) = size.parse::<u16>() { app.pen_size = size.max(1); }
+            return;
+        }
+        ("opacity", [opacity]) => {
+            if let Ok(opacity) = opacity.parse::<f32>() { app.opacity = opacity.clamp(0.0, 1.0); }
+            return;
+        }
+        _ => {}
+    }
+
     // --- Corrected if/else Structure ---
 
     if let Some((cmd, value)) = command_part.split_once(':') {
+        let filled = coordinate_parts.get(1) == Some(&"filled");
         // This block handles commands WITH a color value, like "apply_color:" or "fill:"
         if cmd == "apply_color" {
-            if let Some(color) = App::parse_hex_color(value) {
-                let original_selection = app.current_selection;
-                let original_opacity = app.opacity;
+            if let Ok(color) = App::parse_color(value) {
                 app.current_selection = crate::palette::PaletteEntry::Color(color);
-                app.opacity = 1.0; // Scripts should always draw at full opacity
 
                 for coord_str in coordinate_parts {
                     if let Some((start_str, end_str)) = coord_str.split_once('-') {
@@ -230,12 +420,23 @@ fn execute_single_command_string(app: &mut App, cmd_str: &str, operations_perfor
                         *operations_performed += 1;
                     }
                 }
-                app.current_selection = original_selection;
-                app.opacity = original_opacity;
+            }
+        } else if let (Some(tool), Some(range)) = (match cmd { "line" => Some(Tool::Line), "rect" => Some(Tool::Rectangle), _ => None }, coordinate_parts.first()) {
+            // "line:<color> x1,y1-x2,y2" and "rect:<color> x1,y1-x2,y2 [filled]"
+            let corners = range.split_once('-').and_then(|(start, end)| Some((parse_coord(start)?, parse_coord(end)?)));
+            if let (Ok(color), Some(((x1, y1), (x2, y2)))) = (App::parse_color(value), corners) {
+                draw_script_shape(app, color, tool, (x1 as i32, y1 as i32), (x2 as i32, y2 as i32), filled && tool == Tool::Rectangle, operations_performed);
+            }
+        } else if cmd == "circle" && coordinate_parts.len() >= 2 {
+            // "circle:<color> cx,cy r [filled]"
+            if let (Ok(color), Some((cx, cy)), Ok(r)) = (App::parse_color(value), parse_coord(coordinate_parts[0]), coordinate_parts[1].parse::<i32>()) {
+                let (cx, cy) = (cx as i32, cy as i32);
+                let filled = coordinate_parts.get(2) == Some(&"filled");
+                draw_script_shape(app, color, Tool::Ellipse, (cx - r, cy - r), (cx + r, cy + r), filled, operations_performed);
             }
         } else if cmd == "fill" && !coordinate_parts.is_empty() {
             if let Some((x, y)) = parse_coord(coordinate_parts[0]) {
-                if let Some(color) = App::parse_hex_color(value) {
+                if let Ok(color) = App::parse_color(value) {
                     app.fill_from_point(x as usize, y as usize, color, 1.0);
                     *operations_performed += 1;
                 }
@@ -0,0 +1,1086 @@
+// ui.rs
+
+use crate::palette::{PaletteEntry, Tool};
+use crate::commands::COMMANDS;
+use ratatui::{
+    prelude::*,
+    text::{Line, Span},
+    widgets::{block::Title, Block, Borders, Clear, List, ListItem, ListState, Paragraph},
+};
+use crate::keybindings::Action;
+use strum::IntoEnumIterator;
+use unicode_segmentation::UnicodeSegmentation;
+use crate::{App, AppMode, BlendMode, ColorInfo, HighlighterMode, MinimapCache, MinimapMode, PaletteMenuPosition, QuitChoice, SymmetryMode, PIXEL_WIDTH, color_editor, command_palette, config, file_browser, help_sheet, script_handler, utils};
+
+
+pub fn ui(frame: &mut Frame, app: &mut App) {
+    if let AppMode::HelpScreen = app.mode {
+        draw_help_screen(frame, app);
+        return;
+    }
+
+    if let AppMode::Keybindings = app.mode {
+        draw_keybindings_screen(frame, app);
+        return;
+    }
+
+
+    if let AppMode::ConfigEditor = app.mode {
+        config::draw_config_screen(frame, app);
+        return;
+    }
+
+    if let AppMode::ScriptEditor = app.mode {
+        script_handler::draw_script_editor(frame, app);
+        return;
+    }
+
+    if let AppMode::FileBrowser = app.mode {
+        file_browser::draw_browser(frame, app);
+        return;
+    }
+
+
+    if let AppMode::ConfirmConfigSave = app.mode {
+        draw_confirmation_dialog(frame, app, "Save configuration changes?");
+        return;
+    }
+    if let AppMode::ConfirmScriptSave = app.mode {
+        draw_confirmation_dialog(frame, app, "Save script changes?");
+        return;
+    }
+
+    if let AppMode::ConfirmKeybindingSave = app.mode {
+        // Draw the main UI first to have a background
+        // ... (your existing main UI drawing logic) ...
+        draw_confirmation_dialog(frame, app, "Save keybinding changes?");
+        return;
+    }
+
+
+    const MIN_CANVAS_WIDTH: u16 = 20;
+    const MIN_CANVAS_HEIGHT: u16 = 10;
+    const SIDE_PANEL_WIDTH: u16 = 22;
+
+app.is_side_panel_visible = frame.size().width > MIN_CANVAS_WIDTH + SIDE_PANEL_WIDTH && frame.size().height > MIN_CANVAS_HEIGHT;
+
+let main_layout = Layout::default().direction(Direction::Vertical).constraints([Constraint::Min(0), Constraint::Length(3)]).split(frame.size());
+let content_area = main_layout[0];
+let bottom_bar_area = main_layout[1];
+
+let (canvas_panel_area, palette_area_option) = if app.is_side_panel_visible {
+    let constraints_left = [Constraint::Max(SIDE_PANEL_WIDTH), Constraint::Min(0)];
+    let constraints_right = [Constraint::Min(0), Constraint::Max(SIDE_PANEL_WIDTH)];
+    
+    let top_layout = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(if app.palette_menu_position == PaletteMenuPosition::Left {
+            constraints_left
+        } else {
+            constraints_right
+        })
+        .split(content_area);
+
+    if app.palette_menu_position == PaletteMenuPosition::Left {
+        (top_layout[1], Some(top_layout[0]))
+    } else {
+        (top_layout[0], Some(top_layout[1]))
+    }
+} else {
+    (content_area, None)
+};
+
+let canvas_container_block = Block::default().borders(Borders::ALL).title(Title::from(" Canvas ").alignment(Alignment::Center));
+let pixel_area = canvas_container_block.inner(canvas_panel_area);
+frame.render_widget(canvas_container_block, canvas_panel_area);
+
+if app.last_pixel_area.is_none() {
+    app.last_pixel_area = Some(pixel_area);
+}
+
+if app.last_pixel_area.is_none_or(|last| last.width != pixel_area.width || last.height != pixel_area.height)
+    && app.canvas_width > 0 && app.canvas_height > 0 {
+    let max_zoom_x = pixel_area.width / app.canvas_width as u16;
+    let max_zoom_y = (pixel_area.height * PIXEL_WIDTH) / app.canvas_height as u16;
+    let mut new_zoom = max_zoom_x.min(max_zoom_y);
+    new_zoom = new_zoom.clamp(2, app.max_zoom * 2);
+    new_zoom = (new_zoom / 2) * 2;
+    app.zoom_level = new_zoom;
+    app.view_offset_x = 0;
+    app.view_offset_y = 0;
+}
+app.last_pixel_area = Some(pixel_area);
+
+app.clamp_view_offsets(pixel_area.width, pixel_area.height);
+
+let pixel_render_height = (app.zoom_level / PIXEL_WIDTH).max(1);
+let canvas_screen_width = (app.canvas_width as u16).saturating_mul(app.zoom_level);
+let canvas_screen_height = (app.canvas_height as u16).saturating_mul(pixel_render_height);
+let canvas_area_x = pixel_area.x + pixel_area.width.saturating_sub(canvas_screen_width) / 2;
+let canvas_area_y = pixel_area.y + pixel_area.height.saturating_sub(canvas_screen_height) / 2;
+let centered_canvas_rect = Rect::new(canvas_area_x, canvas_area_y, canvas_screen_width, canvas_screen_height);
+app.last_centered_canvas_rect = Some(centered_canvas_rect);
+
+// --- Tile Preview: the 8 wrapped copies around the canvas, clipped to the panel ---
+if app.tile_preview && app.canvas_width > 0 && app.canvas_height > 0 {
+    let (width, height) = (app.canvas_width as i32, app.canvas_height as i32);
+    let (zoom, block_h) = (app.zoom_level as i32, pixel_render_height as i32);
+    let (rect_x, rect_y) = (centered_canvas_rect.x as i32, centered_canvas_rect.y as i32);
+    // Only walk the canvas coordinates that can land inside the panel.
+    let first_x = (app.view_offset_x + (pixel_area.left() as i32 - rect_x).div_euclid(zoom)).max(-width);
+    let last_x = (app.view_offset_x + (pixel_area.right() as i32 - rect_x).div_euclid(zoom)).min(2 * width - 1);
+    let first_y = (app.view_offset_y + (pixel_area.top() as i32 - rect_y).div_euclid(block_h)).max(-height);
+    let last_y = (app.view_offset_y + (pixel_area.bottom() as i32 - rect_y).div_euclid(block_h)).min(2 * height - 1);
+    for tile_y in first_y..=last_y {
+        for tile_x in first_x..=last_x {
+            if (0..width).contains(&tile_x) && (0..height).contains(&tile_y) {
+                continue; // The editable canvas is drawn below.
+            }
+            let (canvas_x, canvas_y) = (tile_x.rem_euclid(width) as usize, tile_y.rem_euclid(height) as usize);
+            let pixel = app.canvas[canvas_y][canvas_x];
+            let backdrop = transparency_backdrop(app, canvas_x, canvas_y);
+            if pixel.alpha == 0.0 && backdrop.is_none() {
+                continue;
+            }
+            let left = (rect_x + (tile_x - app.view_offset_x) * zoom).max(pixel_area.left() as i32);
+            let top = (rect_y + (tile_y - app.view_offset_y) * block_h).max(pixel_area.top() as i32);
+            let right = (rect_x + (tile_x - app.view_offset_x + 1) * zoom).min(pixel_area.right() as i32);
+            let bottom = (rect_y + (tile_y - app.view_offset_y + 1) * block_h).min(pixel_area.bottom() as i32);
+            if left < right && top < bottom {
+                let color = utils::blend_colors(backdrop.unwrap_or(Color::Black), pixel.color.into(), pixel.alpha, app.linear_blending);
+                fill_cells(frame, Rect::new(left as u16, top as u16, (right - left) as u16, (bottom - top) as u16), app.translate_color(color));
+            }
+        }
+    }
+}
+
+// --- Correct, Symmetrical Border Drawing ---
+let border_rect = Rect {
+    x: centered_canvas_rect.x.saturating_sub(1),
+    y: centered_canvas_rect.y.saturating_sub(1),
+    width: centered_canvas_rect.width + 2,
+    height: centered_canvas_rect.height + 2,
+};
+let clipped_border_area = pixel_area.intersection(border_rect);
+frame.render_widget(
+    Block::default().borders(Borders::ALL).border_style(Style::default().fg(Color::DarkGray)),
+    clipped_border_area,
+);
+
+// --- Canvas Content Drawing ---
+let draw_area = pixel_area.intersection(centered_canvas_rect);
+let onion_layers = if app.onion_skin_enabled && app.playing.is_none() { onion_skin_layers(app) } else { Vec::new() };
+for screen_y in (draw_area.top()..draw_area.bottom()).step_by(pixel_render_height as usize) {
+    for screen_x_start in (draw_area.left()..draw_area.right()).step_by(app.zoom_level as usize) {
+        let canvas_x_i32 = app.view_offset_x + ((screen_x_start - centered_canvas_rect.x) / app.zoom_level) as i32;
+        let canvas_y_i32 = app.view_offset_y + ((screen_y - centered_canvas_rect.y) / pixel_render_height) as i32;
+
+        if canvas_x_i32 >= 0 && canvas_x_i32 < app.canvas_width as i32 && canvas_y_i32 >= 0 && canvas_y_i32 < app.canvas_height as i32 {
+            let (canvas_x, canvas_y) = (canvas_x_i32 as usize, canvas_y_i32 as usize);
+            // Playback shows the current frame's layer on its own, hidden or not.
+            let mut pixel = match app.layers.get(app.playback_frame).filter(|_| app.playing.is_some()) {
+                Some(layer) => {
+                    let pixel = layer.canvas[canvas_y][canvas_x];
+                    crate::Pixel { alpha: pixel.alpha * layer.opacity, ..pixel }
+                }
+                None => app.canvas[canvas_y][canvas_x],
+            };
+            
+            if let Some(onion) = onion_skin_at(app, &onion_layers, canvas_x, canvas_y) {
+                if pixel.alpha == 0.0 {
+                    pixel = onion;
+                } else {
+                    pixel.color = utils::blend_colors(pixel.color.into(), onion.color.into(), onion.alpha * 0.3, app.linear_blending).into();
+                }
+            }
+            
+            let backdrop = transparency_backdrop(app, canvas_x, canvas_y);
+            let mut final_color = if pixel.alpha > 0.0 { utils::blend_colors(backdrop.unwrap_or(Color::Black), pixel.color.into(), pixel.alpha, app.linear_blending) } else { backdrop.unwrap_or(Color::Reset) };
+            
+            if let Some((grid_w, grid_h)) = app.grid_spacing() {
+                if canvas_x.is_multiple_of(grid_w) || canvas_y.is_multiple_of(grid_h) {
+                    final_color = utils::blend_colors(final_color, Color::DarkGray, 0.35, app.linear_blending);
+                }
+            }
+
+            // For diagonal lines, we still blend the background
+            match app.symmetry_mode {
+                SymmetryMode::DiagonalForward(c) if canvas_y_i32 == canvas_x_i32 + c => { final_color = utils::blend_colors(final_color, Color::Yellow, 0.4, app.linear_blending); }
+                SymmetryMode::DiagonalBackward(c) if canvas_y_i32 == -canvas_x_i32 + c => { final_color = utils::blend_colors(final_color, Color::Yellow, 0.4, app.linear_blending); }
+                _ => {}
+            }
+            
+            let block_width = app.zoom_level.min(draw_area.right() - screen_x_start);
+            let block_height = pixel_render_height.min(draw_area.bottom() - screen_y);
+            fill_cells(frame, Rect::new(screen_x_start, screen_y, block_width, block_height), app.translate_color(final_color));
+
+            // Pixel grid: the top row and left column of each pixel's block.
+            if app.pixel_grid && app.zoom_level / 2 >= app.pixel_grid_min_zoom {
+                let is_major = |coord: usize| app.major_grid_spacing > 0 && coord.is_multiple_of(app.major_grid_spacing as usize);
+                let line_color = |major: bool| app.translate_color(if major {
+                    utils::blend_colors(final_color, Color::Gray, 0.5, app.linear_blending)
+                } else {
+                    utils::blend_colors(final_color, Color::DarkGray, 0.3, app.linear_blending)
+                });
+                if pixel_render_height > 1 {
+                    fill_cells(frame, Rect::new(screen_x_start, screen_y, block_width, 1), line_color(is_major(canvas_y)));
+                }
+                fill_cells(frame, Rect::new(screen_x_start, screen_y, 1, block_height), line_color(is_major(canvas_x)));
+            }
+        }
+    }
+}
+
+// --- New, Thin Symmetry Line Overlay Drawing ---
+// Quad symmetry shows both the vertical and the horizontal line.
+let (vertical_line, horizontal_line) = match app.symmetry_mode {
+    SymmetryMode::Vertical(line_x) => (Some(line_x), None),
+    SymmetryMode::Horizontal(line_y) => (None, Some(line_y)),
+    SymmetryMode::Quad(line_x, line_y) => (Some(line_x), Some(line_y)),
+    _ => (None, None),
+};
+if let Some(line_x) = vertical_line {
+    let mut line_screen_x = centered_canvas_rect.x + (line_x * app.zoom_level);
+    // For even-width canvases, the true center is between pixels. Shift the visual line left to appear on the boundary.
+    if app.canvas_width.is_multiple_of(2) {
+        line_screen_x = line_screen_x.saturating_sub(1);
+    }
+    if line_screen_x >= draw_area.left() && line_screen_x < draw_area.right() {
+        for y in draw_area.top()..draw_area.bottom() {
+            frame.render_widget(Paragraph::new("┃").style(Style::default().fg(Color::Blue)), Rect::new(line_screen_x, y, 1, 1));
+        }
+    }
+}
+if let Some(line_y) = horizontal_line {
+    let mut line_screen_y = centered_canvas_rect.y + (line_y * pixel_render_height);
+    // For even-height canvases, shift the visual line up to appear on the boundary.
+    if app.canvas_height.is_multiple_of(2) {
+        line_screen_y = line_screen_y.saturating_sub(1);
+    }
+    if line_screen_y >= draw_area.top() && line_screen_y < draw_area.bottom() {
+        for x in draw_area.left()..draw_area.right() {
+            frame.render_widget(Paragraph::new("━").style(Style::default().fg(Color::Blue)), Rect::new(x, line_screen_y, 1, 1));
+        }
+    }
+}
+if let SymmetryMode::Radial(_, center_x, center_y) = app.symmetry_mode {
+    let screen_x = (center_x as i32 - app.view_offset_x) * app.zoom_level as i32 + centered_canvas_rect.x as i32;
+    let screen_y = (center_y as i32 - app.view_offset_y) * pixel_render_height as i32 + centered_canvas_rect.y as i32;
+    let marker_rect = Rect::new(screen_x.max(0) as u16, screen_y.max(0) as u16, 1, 1);
+    if screen_x >= 0 && screen_y >= 0 && marker_rect.intersects(draw_area) {
+        frame.render_widget(Paragraph::new("✛").style(Style::default().fg(Color::Blue)), marker_rect);
+    }
+}
+// Diagonals are handled by blending above.
+
+// The tile preview already fills the panel around the canvas.
+let should_draw_minimap = !app.tile_preview && match app.minimap_mode {
+    MinimapMode::On => true,
+    MinimapMode::Off => false,
+    MinimapMode::Auto => app.canvas_width >= 100 && app.canvas_height >= 100,
+};
+
+app.last_minimap_area = None;
+if should_draw_minimap && pixel_area.width > 20 && pixel_area.height > 10 {
+    let minimap_width = (pixel_area.width / 4).max(10);
+    let minimap_height = (pixel_area.height / 3).max(5);
+    let minimap_area = Rect::new(
+        pixel_area.right() - minimap_width,
+        pixel_area.bottom() - minimap_height,
+        minimap_width,
+        minimap_height,
+    );
+    frame.render_widget(Clear, minimap_area);
+    draw_minimap(frame, app, minimap_area);
+}
+// Magic wand selection: outline each selected pixel on the sides that face unselected ones.
+if !app.selection.is_empty() {
+    let selected = |x: i32, y: i32| x >= 0 && y >= 0 && app.selection.contains(&(x as usize, y as usize));
+    for &(x, y) in &app.selection {
+        let (x, y) = (x as i32, y as i32);
+        let mut borders = Borders::NONE;
+        if !selected(x, y - 1) { borders |= Borders::TOP; }
+        if !selected(x, y + 1) { borders |= Borders::BOTTOM; }
+        if !selected(x - 1, y) { borders |= Borders::LEFT; }
+        if !selected(x + 1, y) { borders |= Borders::RIGHT; }
+        if borders == Borders::NONE { continue; }
+        let screen_x = (x - app.view_offset_x) * app.zoom_level as i32 + centered_canvas_rect.x as i32;
+        let screen_y = (y - app.view_offset_y) * pixel_render_height as i32 + centered_canvas_rect.y as i32;
+        if screen_x < 0 || screen_y < 0 { continue; }
+        let cell_rect = Rect::new(screen_x as u16, screen_y as u16, app.zoom_level, pixel_render_height).intersection(pixel_area);
+        if !cell_rect.is_empty() {
+            frame.render_widget(Block::default().borders(borders).border_style(Style::default().fg(app.translate_color(Color::Cyan))), cell_rect);
+        }
+    }
+}
+// Live preview of the shape's outline between its anchor and the cursor, one cell per block
+// it will cover. Everything but lines is dashed: cells alternate in pairs by angle around the
+// shape's center, which works the same for straight edges and curves.
+if let Some(anchor) = app.shape_anchor.filter(|_| app.mode == AppMode::Drawing) {
+    let block = app.pixel_block.max(1) as i32;
+    let tool = app.shape_tool.unwrap_or(Tool::Line);
+    let preview_color = match app.current_selection {
+        PaletteEntry::Color(c) => c,
+        PaletteEntry::Tool(_) => Color::Yellow,
+    };
+    let points = app.shape_points(tool, (anchor.0 as i32, anchor.1 as i32), (app.cursor_pos.0 as i32, app.cursor_pos.1 as i32), false);
+    let center = ((anchor.0 as f32 + app.cursor_pos.0 as f32) / 2.0, (anchor.1 as f32 + app.cursor_pos.1 as f32) / 2.0);
+    let dashes = (points.len() / 2).max(1) as f32;
+    for (x, y) in points {
+        let angle = (y as f32 - center.1).atan2(x as f32 - center.0) + std::f32::consts::PI;
+        if tool != Tool::Line && (angle / std::f32::consts::TAU * dashes) as usize % 2 == 1 { continue; }
+        let screen_x = (x as i32 - app.view_offset_x) * app.zoom_level as i32 + centered_canvas_rect.x as i32;
+        let screen_y = (y as i32 - app.view_offset_y) * pixel_render_height as i32 + centered_canvas_rect.y as i32;
+        if screen_x < 0 || screen_y < 0 { continue; }
+        let cell_rect = Rect::new(screen_x as u16, screen_y as u16, block as u16 * app.zoom_level, block as u16 * pixel_render_height).intersection(pixel_area);
+        if !cell_rect.is_empty() {
+            fill_cells(frame, cell_rect, app.translate_color(preview_color));
+        }
+    }
+}
+if let AppMode::Drawing = app.mode {
+    let cursor_screen_x = ((app.cursor_pos.0 as i32 - app.view_offset_x) * app.zoom_level as i32) + centered_canvas_rect.x as i32;
+    let cursor_screen_y = ((app.cursor_pos.1 as i32 - app.view_offset_y) * pixel_render_height as i32) + centered_canvas_rect.y as i32;
+    if (app.cursor_pos.0 as usize) < app.canvas_width && (app.cursor_pos.1 as usize) < app.canvas_height {
+        let block = app.pixel_block.max(1) as i32;
+        let offset = app.pen_size as i32 / 2 * block;
+        let brush_start_canvas_x = app.cursor_pos.0 as i32 / block * block - offset;
+        let brush_start_canvas_y = app.cursor_pos.1 as i32 / block * block - offset;
+        let brush_start_screen_x = ((brush_start_canvas_x - app.view_offset_x) * app.zoom_level as i32) + centered_canvas_rect.x as i32;
+        let brush_start_screen_y = ((brush_start_canvas_y - app.view_offset_y) * pixel_render_height as i32) + centered_canvas_rect.y as i32;
+        let brush_screen_width = app.pen_size * block as u16 * app.zoom_level;
+        let brush_screen_height = app.pen_size * block as u16 * pixel_render_height;
+        let brush_outline_rect = Rect::new(brush_start_screen_x as u16, brush_start_screen_y as u16, brush_screen_width, brush_screen_height);
+        let brush_outline_block = Block::default().borders(Borders::ALL).border_style(Style::default().fg(app.translate_color(Color::Yellow)));
+        if let Some(mask) = app.scaled_brush_mask() {
+            // Outline the mask's shape: mark each covered cell on the sides that face uncovered ones.
+            let covered = |x: i32, y: i32| y >= 0 && x >= 0 && mask.get(y as usize).and_then(|row| row.get(x as usize)).is_some_and(|&v| v >= 0.5);
+            let mask_start_x = app.cursor_pos.0 as i32 / block - mask[0].len() as i32 / 2;
+            let mask_start_y = app.cursor_pos.1 as i32 / block - mask.len() as i32 / 2;
+            for (my, row) in mask.iter().enumerate() {
+                for mx in 0..row.len() {
+                    let (mx, my) = (mx as i32, my as i32);
+                    if !covered(mx, my) { continue; }
+                    let mut borders = Borders::NONE;
+                    if !covered(mx, my - 1) { borders |= Borders::TOP; }
+                    if !covered(mx, my + 1) { borders |= Borders::BOTTOM; }
+                    if !covered(mx - 1, my) { borders |= Borders::LEFT; }
+                    if !covered(mx + 1, my) { borders |= Borders::RIGHT; }
+                    if borders == Borders::NONE { continue; }
+                    let screen_x = ((mask_start_x + mx) * block - app.view_offset_x) * app.zoom_level as i32 + centered_canvas_rect.x as i32;
+                    let screen_y = ((mask_start_y + my) * block - app.view_offset_y) * pixel_render_height as i32 + centered_canvas_rect.y as i32;
+                    if screen_x < 0 || screen_y < 0 { continue; }
+                    let cell_rect = Rect::new(screen_x as u16, screen_y as u16, block as u16 * app.zoom_level, block as u16 * pixel_render_height).intersection(pixel_area);
+                    if !cell_rect.is_empty() {
+                        frame.render_widget(Block::default().borders(borders).border_style(Style::default().fg(app.translate_color(Color::Yellow))), cell_rect);
+                    }
+                }
+            }
+        } else if brush_outline_rect.intersects(pixel_area) { frame.render_widget(brush_outline_block, brush_outline_rect); }
+        let center_cursor_rect = Rect::new(cursor_screen_x as u16, cursor_screen_y as u16, app.zoom_level, pixel_render_height);
+        if center_cursor_rect.intersects(pixel_area) {
+            match app.current_selection {
+                PaletteEntry::Color(c) => {
+                    let original_pixel = app.canvas[app.cursor_pos.1 as usize][app.cursor_pos.0 as usize];
+                    let original_color: Color = original_pixel.color.into();
+                    let display_color = utils::blend_colors(original_color, c, app.opacity, app.linear_blending);
+                    frame.render_widget(Block::default().bg(app.translate_color(display_color)), center_cursor_rect);
+                }
+                PaletteEntry::Tool(tool) => {
+                    let original_pixel = app.canvas[app.cursor_pos.1 as usize][app.cursor_pos.0 as usize];
+                    let original_color: Color = original_pixel.color.into();
+                    if original_pixel.alpha == 0.0 {
+                        frame.render_widget(Block::default().bg(original_color), center_cursor_rect);
+                        if app.highlighter_enabled && app.highlighter_mode == HighlighterMode::Underscore {
+                            let underscore_rect = Rect::new(center_cursor_rect.x, center_cursor_rect.bottom().saturating_sub(1), center_cursor_rect.width, 1);
+                            let p = Paragraph::new("_".repeat(app.zoom_level as usize)).style(Style::default().fg(app.translate_color(Color::Yellow)));
+                            frame.render_widget(p, underscore_rect);
+                        }
+                    } else {
+                        let final_color = match tool {
+                            Tool::Lighter => utils::blend_colors(original_color, Color::White, app.shade_factor, app.linear_blending),
+                            Tool::Darker => utils::blend_colors(original_color, Color::Black, app.shade_factor, app.linear_blending),
+                            Tool::Line | Tool::Rectangle | Tool::Ellipse | Tool::Dither | Tool::Clone | Tool::Replace => original_color,
+                            Tool::Blur => app.calculate_blur_at(app.cursor_pos.0 as usize, app.cursor_pos.1 as usize, app.opacity).color.into(),
+                        };
+                        if app.highlighter_enabled {
+                            match app.highlighter_mode {
+                                HighlighterMode::Underscore => {
+                                    frame.render_widget(Block::default().bg(original_color), center_cursor_rect);
+                                    let underscore_rect = Rect::new(center_cursor_rect.x, center_cursor_rect.bottom().saturating_sub(1), center_cursor_rect.width, 1);
+                                    let p = Paragraph::new("_".repeat(app.zoom_level as usize)).style(Style::default().fg(app.translate_color(Color::Yellow)).bg(app.translate_color(original_color)));
+                                    frame.render_widget(p, underscore_rect);
+                                }
+                                HighlighterMode::Blend => {
+                                    let display_color = utils::blend_colors(original_color, final_color, app.highlighter_value, app.linear_blending);
+                                    frame.render_widget(Block::default().bg(app.translate_color(display_color)), center_cursor_rect);
+                                }
+                            }
+                        } else { frame.render_widget(Block::default().bg(app.translate_color(final_color)), center_cursor_rect); }
+                    }
+                }
+            }
+        }
+        // A one-line readout just right of the cursor, or left of it near the right edge.
+        if app.color_info == ColorInfo::Cursor {
+            let text = format!(" {} ", app.color_info_text());
+            let width = (Span::raw(text.as_str()).width() as u16).min(pixel_area.width);
+            let right = cursor_screen_x + app.zoom_level as i32 + 1;
+            let x = if right + width as i32 <= pixel_area.right() as i32 { right } else { cursor_screen_x - 1 - width as i32 };
+            let y = if cursor_screen_y > pixel_area.y as i32 { cursor_screen_y - 1 } else { cursor_screen_y + pixel_render_height as i32 };
+            let x = x.clamp(pixel_area.x as i32, (pixel_area.right() - width) as i32) as u16;
+            let y = y.clamp(pixel_area.y as i32, pixel_area.bottom().saturating_sub(1) as i32) as u16;
+            let style = Style::default().fg(app.translate_color(Color::White)).bg(app.translate_color(Color::DarkGray));
+            frame.render_widget(Paragraph::new(text).style(style), Rect::new(x, y, width, 1));
+        }
+        // Marks where the clone tool samples from, moving with the cursor once the offset is locked.
+        if let (PaletteEntry::Tool(Tool::Clone), Some((source_x, source_y))) = (app.current_selection, app.clone_sample_pos()) {
+            let source_screen_x = (source_x - app.view_offset_x) * app.zoom_level as i32 + centered_canvas_rect.x as i32;
+            let source_screen_y = (source_y - app.view_offset_y) * pixel_render_height as i32 + centered_canvas_rect.y as i32;
+            if source_screen_x >= 0 && source_screen_y >= 0 {
+                let source_rect = Rect::new(source_screen_x as u16, source_screen_y as u16, app.zoom_level, pixel_render_height).intersection(pixel_area);
+                if !source_rect.is_empty() {
+                    frame.render_widget(Paragraph::new("+").alignment(Alignment::Center).style(Style::default().fg(app.translate_color(Color::Cyan)).add_modifier(Modifier::BOLD)), source_rect);
+                }
+            }
+        }
+    }
+}
+
+if let Some(palette_area) = palette_area_option {
+    // Tools wrap onto as many rows as they need.
+    let tool_columns = (palette_area.width.saturating_sub(2) / 3).max(1) as usize;
+    let tool_rows = app.tool_palette.len().div_ceil(tool_columns) as u16;
+    // The recent colors strip only takes room once something has been painted.
+    let recent_height = if app.recent_colors.is_empty() { 0 } else { 3 };
+    let palette_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(2 + tool_rows), Constraint::Min(8), Constraint::Length(recent_height), Constraint::Length(8)])
+        .split(palette_area);
+    
+    let tool_area = palette_layout[0];
+    let color_area = palette_layout[1];
+    let recent_area = palette_layout[2];
+    let layer_area = palette_layout[3];
+    
+    let tool_block = Block::default().borders(Borders::ALL).title(Title::from(" Tools ").alignment(Alignment::Center)).border_style(match app.mode { AppMode::ToolPicker => Style::default().fg(app.translate_color(Color::Yellow)), _ => Style::default() });
+    let actual_tool_area = tool_block.inner(tool_area);
+    frame.render_widget(tool_block, tool_area);
+    app.last_tool_area = Some(actual_tool_area);
+    
+    for (i, entry) in app.tool_palette.iter().enumerate() {
+        let is_selected = i == app.tool_index;
+        let symbol = if is_selected { ">" } else { " " };
+        let item_text = match entry {
+            PaletteEntry::Tool(Tool::Lighter) => Span::styled(format!("{}L", symbol), Style::default().bg(if is_selected { app.translate_color(Color::DarkGray) } else { Color::Reset })),
+            PaletteEntry::Tool(Tool::Darker) => Span::styled(format!("{}D", symbol), Style::default().bg(if is_selected { app.translate_color(Color::DarkGray) } else { Color::Reset })),
+            PaletteEntry::Tool(Tool::Blur) => Span::styled(format!("{}B", symbol), Style::default().bg(if is_selected { app.translate_color(Color::DarkGray) } else { Color::Reset })),
+            PaletteEntry::Tool(Tool::Clone) => Span::styled(format!("{}C", symbol), Style::default().bg(if is_selected { app.translate_color(Color::DarkGray) } else { Color::Reset })),
+            PaletteEntry::Tool(Tool::Line) => Span::styled(format!("{}/", symbol), Style::default().bg(if is_selected { app.translate_color(Color::DarkGray) } else { Color::Reset }).fg(if app.shape_tool == Some(Tool::Line) { app.translate_color(Color::Yellow) } else { Color::Reset })),
+            PaletteEntry::Tool(Tool::Rectangle) => Span::styled(format!("{}#", symbol), Style::default().bg(if is_selected { app.translate_color(Color::DarkGray) } else { Color::Reset }).fg(if app.shape_tool == Some(Tool::Rectangle) { app.translate_color(Color::Yellow) } else { Color::Reset })),
+            PaletteEntry::Tool(Tool::Ellipse) => Span::styled(format!("{}O", symbol), Style::default().bg(if is_selected { app.translate_color(Color::DarkGray) } else { Color::Reset }).fg(if app.shape_tool == Some(Tool::Ellipse) { app.translate_color(Color::Yellow) } else { Color::Reset })),
+            PaletteEntry::Tool(Tool::Dither) => Span::styled(format!("{}%", symbol), Style::default().bg(if is_selected { app.translate_color(Color::DarkGray) } else { Color::Reset }).fg(if app.dither_enabled { app.translate_color(Color::Yellow) } else { Color::Reset })),
+            PaletteEntry::Tool(Tool::Replace) => Span::styled(format!("{}R", symbol), Style::default().bg(if is_selected { app.translate_color(Color::DarkGray) } else { Color::Reset }).fg(if app.replace_mode { app.translate_color(Color::Yellow) } else { Color::Reset })),
+            _ => Span::raw(""),
+        };
+        let x = actual_tool_area.x + (i % tool_columns * 3) as u16;
+        let y = actual_tool_area.y + (i / tool_columns) as u16;
+        frame.render_widget(Paragraph::new(item_text), Rect::new(x, y, 3, 1).intersection(actual_tool_area));
+    }
+
+    let color_block = Block::default().borders(Borders::ALL).title(Title::from(" Colors ").alignment(Alignment::Center)).border_style(match app.mode { AppMode::ColorPicker => Style::default().fg(app.translate_color(Color::Yellow)), _ => Style::default() });
+    let actual_color_area = color_block.inner(color_area);
+    frame.render_widget(color_block, color_area);
+    app.last_palette_area = Some(actual_color_area);
+    
+    let columns = (actual_color_area.width / 3).max(1) as usize;
+    let rows = actual_color_area.height as usize;
+    
+    for i in app.palette_scroll_state..app.color_palette.len() {
+        let entry = &app.color_palette[i];
+        let row = (i - app.palette_scroll_state) / columns;
+        let col = i % columns;
+        if row >= rows { break; }
+        let is_selected = i == app.palette_index;
+        let symbol = if is_selected { ">" } else { " " };
+        let item_text = match entry {
+            PaletteEntry::Color(c) => Span::styled(
+                format!("{}█", symbol),
+                Style::default().fg(app.translate_color(*c)).bg(if is_selected { app.translate_color(Color::DarkGray) } else { Color::Reset }),
+            ),
+            _ => Span::raw(""),
+        };
+        let x = actual_color_area.x + (col * 3) as u16;
+        let y = actual_color_area.y + row as u16;
+        frame.render_widget(Paragraph::new(item_text), Rect::new(x, y, 3, 1));
+    }
+
+    app.last_recent_colors_area = None;
+    if recent_height > 0 {
+        let recent_block = Block::default().borders(Borders::ALL).title(Title::from(" Recent ").alignment(Alignment::Center));
+        let actual_recent_area = recent_block.inner(recent_area);
+        frame.render_widget(recent_block, recent_area);
+        app.last_recent_colors_area = Some(actual_recent_area);
+        let fits = (actual_recent_area.width / 3) as usize;
+        for (i, color) in app.recent_colors.iter().take(fits).enumerate() {
+            let is_selected = app.current_selection == PaletteEntry::Color(*color);
+            let item_text = Span::styled(
+                format!("{}█", if is_selected { ">" } else { " " }),
+                Style::default().fg(app.translate_color(*color)).bg(if is_selected { app.translate_color(Color::DarkGray) } else { Color::Reset }),
+            );
+            let x = actual_recent_area.x + (i * 3) as u16;
+            frame.render_widget(Paragraph::new(item_text), Rect::new(x, actual_recent_area.y, 3, 1).intersection(actual_recent_area));
+        }
+    }
+
+    let layer_block = Block::default()
+        .borders(Borders::ALL)
+        .title(Title::from(" Layers ").alignment(Alignment::Center));
+    let actual_layer_area = layer_block.inner(layer_area);
+    frame.render_widget(layer_block, layer_area);
+    app.last_layer_area = Some(actual_layer_area);
+    
+    let visible_rows = actual_layer_area.height.saturating_sub(2) as usize;
+    let start_idx = app.layer_scroll_state;
+    let end_idx = (start_idx + visible_rows).min(app.layers.len());
+    
+    for (list_idx, layer_idx) in (start_idx..end_idx).enumerate() {
+        let layer = &app.layers[layer_idx];
+        let is_selected = layer_idx == app.active_layer_index;
+        let symbol = if is_selected { ">" } else { " " };
+        let visibility = if layer.visible { "â—" } else { "â—‹" };
+        let prefix = format!("{}{} ", symbol, visibility);
+        let mut markers = String::new();
+        if layer.locked {
+            markers.push_str(" 🔒");
+        }
+        if layer.alpha_locked {
+            markers.push_str(" α");
+        }
+        if layer.blend_mode != BlendMode::Normal {
+            markers.push_str(&format!(" [{}]", layer.blend_mode.abbreviation()));
+        }
+        // Opacity sits at the right edge as a percentage and a three-cell bar; the name
+        // gives up whatever room is left so the markers and opacity always show.
+        let filled = (layer.opacity * 3.0).round() as usize;
+        let opacity_text = format!(" {:>3}% {}{}", (layer.opacity * 100.0).round() as u32, "█".repeat(filled), "░".repeat(3 - filled));
+        let row_width = actual_layer_area.width as usize;
+        let fixed_width = Span::raw(prefix.as_str()).width() + Span::raw(markers.as_str()).width() + Span::raw(opacity_text.as_str()).width();
+        let left = format!("{}{}{}", prefix, utils::elide(&layer.name, row_width.saturating_sub(fixed_width)), markers);
+        let padding = row_width.saturating_sub(Span::raw(left.as_str()).width() + Span::raw(opacity_text.as_str()).width());
+        let text = format!("{}{}{}", left, " ".repeat(padding), opacity_text);
+        let mut style = if is_selected {
+            Style::default().bg(app.translate_color(Color::DarkGray))
+        } else {
+            Style::default()
+        };
+        // While a row is dragged, the row it would land on is underlined.
+        if app.layer_drag.is_some_and(|(from, to)| to == layer_idx && to != from) {
+            style = style.add_modifier(Modifier::UNDERLINED);
+        }
+        let y = actual_layer_area.y + list_idx as u16;
+        if is_selected && app.is_renaming_layer && y < actual_layer_area.bottom() {
+            // The name is swapped for the rename input; the markers come back once it closes.
+            let prefix_width = prefix.graphemes(true).count() as u16;
+            let input = Line::from(vec![
+                Span::raw(prefix),
+                Span::styled(app.layer_input_buffer.as_str(), Style::default().fg(app.translate_color(Color::Yellow))),
+            ]);
+            frame.render_widget(
+                Paragraph::new(input).style(style),
+                Rect::new(actual_layer_area.x, y, actual_layer_area.width, 1)
+            );
+            let cursor_offset = app.layer_input_buffer[..app.layer_name_cursor_pos].graphemes(true).count() as u16;
+            let cursor_x = (actual_layer_area.x + prefix_width + cursor_offset).min(actual_layer_area.right().saturating_sub(1));
+            frame.set_cursor(cursor_x, y);
+            continue;
+        }
+        if y < actual_layer_area.bottom() {
+            frame.render_widget(
+                Paragraph::new(text).style(style),
+                Rect::new(actual_layer_area.x, y, actual_layer_area.width, 1)
+            );
+        }
+    }
+    
+    if app.onion_skin_enabled {
+        let onion_y = actual_layer_area.bottom().saturating_sub(2);
+        if onion_y >= actual_layer_area.y {
+            let onion_text = format!("Onion: {:.0}% -{} +{}", app.onion_skin_opacity * 100.0, app.onion_skin_frames_before, app.onion_skin_frames_after);
+            frame.render_widget(
+                Paragraph::new(onion_text).style(Style::default().fg(app.translate_color(Color::Cyan))),
+                Rect::new(actual_layer_area.x, onion_y, actual_layer_area.width, 1)
+            );
+        }
+    }
+
+
+
+
+
+}
+    if let AppMode::Command = app.mode {
+        draw_command_screen(frame, app);
+    } else {
+
+        if let Some((_, timestamp)) = &app.status_message {
+            if timestamp.elapsed() > std::time::Duration::from_secs(2) {
+                app.status_message = None;
+            }
+        }
+
+        let symmetry_text = match app.symmetry_mode {
+            SymmetryMode::Off => "Off".to_string(),
+            SymmetryMode::Horizontal(y) => format!("Horizontal @ Y={}", y),
+            SymmetryMode::Vertical(x) => format!("Vertical @ X={}", x),
+            SymmetryMode::DiagonalForward(c) => format!("Diag-Fwd @ c={}", c),
+            SymmetryMode::DiagonalBackward(c) => format!("Diag-Bwd @ c={}", c),
+            SymmetryMode::Quad(x, y) => format!("Quad @ X={}, Y={}", x, y),
+            SymmetryMode::Radial(n, x, y) => format!("Radial x{} @ {},{}", n, x, y),
+        };
+        let help_text = if let Some((msg, _)) = &app.status_message { msg.clone() } else {
+            match app.mode {
+                AppMode::Drawing if app.playing.is_some() => format!("Playing {}fps — frame {}/{} | any key stops", app.playing.unwrap_or_default(), app.playback_frame + 1, app.layers.len()),
+                AppMode::Drawing => format!("({}, {}) | Pen: {} | Opacity: {:.0}% | Zoom: {}x | Symmetry:[{}]{}", app.cursor_pos.0, app.cursor_pos.1, app.pen_size, app.opacity * 100.0, app.zoom_level / 2, symmetry_text, if app.flat_mode { " | FLAT" } else { "" })
+                    + &if app.pixel_block > 1 { format!(" | BLK {}", app.pixel_block) } else { String::new() }
+                    + &if app.stabilizer > 0 { format!(" | STB {}", app.stabilizer) } else { String::new() }
+                    + &if app.dither_enabled { format!(" | DITHER {}%", app.dither_pattern.percent()) } else { String::new() }
+                    + if app.tile_preview { " | TILE" } else { "" }
+                    + &if app.color_info == ColorInfo::StatusBar { format!(" | {}", app.color_info_text()) } else { String::new() },
+                AppMode::ResizingWidth => format!("New Width ({}x{}): {}", app.canvas_width, app.canvas_height, app.input_buffer),
+                AppMode::ResizingHeight => format!("New Height ({}x{}): {}", app.temp_width, app.input_buffer, app.input_buffer),
+                AppMode::ConfirmOverwrite => "File exists. Overwrite? (y/n)".to_string(),
+                AppMode::ColorPicker => {
+                    let key_str = app.keybindings.map.get(&Action::OpenColorPicker)
+                        .map(utils::format_keybinding)
+                        .unwrap_or_else(|| "N/A".to_string());
+                    format!("Arrows: Navigate | Enter: Select | n: New Color | e: Edit | Esc/{}: Back", key_str)
+                },
+                AppMode::ToolPicker => {
+                    let key_str = app.keybindings.map.get(&Action::OpenToolPicker)
+                        .map(utils::format_keybinding)
+                        .unwrap_or_else(|| "N/A".to_string());
+                    format!("Arrows: Navigate | Enter: Select | Esc/{}: Back", key_str)
+                },
+                _ => "".to_string(),
+            }
+        };
+        let help_block = Block::default().borders(Borders::ALL).title(Title::from(" Controls ").alignment(Alignment::Center));
+        frame.render_widget(Paragraph::new(help_text).block(help_block), bottom_bar_area);
+    }
+
+    if let AppMode::CommandPalette = app.mode {
+        command_palette::draw_command_palette(frame, app);
+    }
+
+    if let (AppMode::InfoPopup, Some((title, lines))) = (&app.mode, &app.info_popup) {
+        let area = utils::centered_rect(80, 60, frame.size());
+        let block = Block::default().title(format!("{}(Esc to Close) ", title)).borders(Borders::ALL).border_style(Style::default().fg(app.translate_color(Color::Yellow)));
+        let paragraph = Paragraph::new(lines.join("\n")).block(block).scroll((app.info_popup_scroll, 0));
+        frame.render_widget(Clear, area);
+        frame.render_widget(paragraph, area);
+    }
+
+    if let AppMode::UndoHistory = app.mode {
+        draw_undo_history(frame, app);
+    }
+
+    if let AppMode::ColorEditor = app.mode {
+        color_editor::draw_color_editor(frame, app);
+    }
+
+    if let AppMode::ConfirmQuit = app.mode {
+        let selected = match app.quit_choice { QuitChoice::Yes => 0, QuitChoice::No => 1, QuitChoice::Save => 2 };
+        draw_dialog(frame, "You have unsaved changes.\nQuit without saving?", &["Yes", "No", "Save"], selected);
+    }
+
+    if let AppMode::ConfirmRestoreAutosave = app.mode {
+        let name = app.pending_autosave.as_ref().and_then(|p| p.file_name()).map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        draw_confirmation_dialog(frame, app, &format!("{} is newer than the project.\nRestore it?", name));
+    }
+
+    if let AppMode::ConfirmPaletteSwitch = app.mode {
+        let message = match &app.pending_palette_switch {
+            Some(pending) => {
+                let shown: Vec<String> = pending.missing_colors.iter().take(10).map(|c| {
+                    let (r, g, b) = utils::to_rgb(*c);
+                    format!("#{:02X}{:02X}{:02X}", r, g, b)
+                }).collect();
+                let more = pending.missing_colors.len().saturating_sub(shown.len());
+                let color_lines: Vec<String> = shown.chunks(5).map(|chunk| chunk.join(" ")).collect();
+                format!(
+                    "{} colors on the canvas are not in '{}':\n{}{}\n\nSwitch and append used colors?\n(No switches anyway, Esc cancels)",
+                    pending.missing_colors.len(), pending.name, color_lines.join("\n"),
+                    if more > 0 { format!("\n...and {} more", more) } else { String::new() }
+                )
+            }
+            None => String::new(),
+        };
+        draw_confirmation_dialog(frame, app, &message);
+    }
+}
+
+
+
+
+
+
+
+// func
+
+
+// Paints the background of every cell in `area`. The canvas has one such rectangle per
+// pixel, so this writes to the buffer directly rather than building a Block for each.
+fn fill_cells(frame: &mut Frame, area: Rect, color: Color) {
+    let area = area.intersection(frame.size());
+    frame.buffer_mut().set_style(area, Style::default().bg(color));
+}
+
+// Earlier layers are tinted red and later ones green-blue, as in animation tools.
+const ONION_BEFORE_TINT: Color = Color::Rgb(255, 60, 60);
+const ONION_AFTER_TINT: Color = Color::Rgb(60, 200, 255);
+
+/// The visible layers onion skinning shows around the active one, farthest first, each
+/// with its tint and an opacity that drops with every step away.
+fn onion_skin_layers(app: &App) -> Vec<(usize, Color, f32)> {
+    let active = app.active_layer_index;
+    let mut steps: Vec<(usize, usize, Color)> = (1..=app.onion_skin_frames_before as usize)
+        .filter_map(|step| Some((active.checked_sub(step)?, step, ONION_BEFORE_TINT)))
+        .chain((1..=app.onion_skin_frames_after as usize).map(|step| (active + step, step, ONION_AFTER_TINT)))
+        .filter(|&(index, _, _)| app.layers.get(index).is_some_and(|layer| layer.visible))
+        .collect();
+    steps.sort_by_key(|&(_, step, _)| std::cmp::Reverse(step));
+    steps.into_iter().map(|(index, step, tint)| (index, tint, app.onion_skin_opacity / step as f32)).collect()
+}
+
+/// The tinted onion layers at (x, y) composited together, or None where all are empty.
+fn onion_skin_at(app: &App, onion_layers: &[(usize, Color, f32)], x: usize, y: usize) -> Option<crate::Pixel> {
+    let mut onion = crate::Pixel::default();
+    for &(index, tint, opacity) in onion_layers {
+        let pixel = app.layers[index].canvas[y][x];
+        if pixel.alpha == 0.0 { continue; }
+        let tinted = utils::blend_colors(pixel.color.into(), tint, 0.5, app.linear_blending);
+        onion = utils::composite_over(onion, utils::blend_colors(Color::Black, tinted, pixel.alpha, app.linear_blending).into(), opacity, app.linear_blending);
+    }
+    (onion.alpha > 0.0).then_some(onion)
+}
+
+/// What shows through a transparent canvas pixel at (x, y): the checker cell with the
+/// reference image blended over it, or None when neither is on.
+fn transparency_backdrop(app: &App, x: usize, y: usize) -> Option<Color> {
+    let checker = app.transparency_checker.then(|| {
+        let size = app.checker_size.max(1) as usize;
+        if (x / size + y / size).is_multiple_of(2) { app.checker_light } else { app.checker_dark }
+    });
+    let reference = app.reference.as_ref()
+        .filter(|_| app.reference_visible)
+        .and_then(|reference| reference.get(x, y))
+        .filter(|pixel| pixel.alpha > 0.0);
+    match reference {
+        Some(pixel) => Some(utils::blend_colors(checker.unwrap_or(Color::Black), pixel.color.into(), pixel.alpha * app.reference_opacity, app.linear_blending)),
+        None => checker,
+    }
+}
+
+fn draw_command_screen(frame: &mut Frame, app: &App) {
+    let input_bar_area = Rect {
+        x: frame.size().x,
+        y: frame.size().height.saturating_sub(3),
+        width: frame.size().width,
+        height: 3,
+    };
+    let input_text = vec![Line::from(vec![Span::raw("> "), Span::raw(app.input_buffer.as_str())])];
+    let input_paragraph = Paragraph::new(input_text).block(Block::default().borders(Borders::ALL).title("Command Mode"));
+    
+    frame.render_widget(Clear, input_bar_area);
+    frame.render_widget(input_paragraph, input_bar_area);
+    let cursor_offset = app.input_buffer[..app.command_cursor_pos].graphemes(true).count() as u16;
+    frame.set_cursor(input_bar_area.x + 2 + cursor_offset, input_bar_area.y + 1);
+
+    let suggestions = app.get_suggestions_with_matches(&app.input_buffer);
+
+
+    if !suggestions.is_empty() {
+        let max_suggestion_width = suggestions.iter().map(|(s, _)| s.chars().count()).max().unwrap_or(0);
+        let box_width = (max_suggestion_width + 4) as u16;
+        let box_height = (suggestions.len() + 2) as u16;
+        let suggestions_area = Rect {
+            x: input_bar_area.x + 2,
+            y: input_bar_area.y.saturating_sub(box_height),
+            width: box_width,
+            height: box_height,
+        };
+
+        let suggestion_items: Vec<Line> = suggestions.iter().enumerate()
+            .map(|(i, (s, matched))| {
+                let selected = app.suggestion_active && i == app.suggestion_index;
+                let style = if selected { 
+                    Style::default().fg(app.translate_color(Color::Black)).bg(app.translate_color(Color::Yellow)) 
+                } else { 
+                    Style::default() 
+                };
+                // The characters that matched what was typed stand out.
+                let match_style = if selected {
+                    style.add_modifier(Modifier::BOLD | Modifier::UNDERLINED)
+                } else {
+                    Style::default().fg(app.translate_color(Color::Yellow)).add_modifier(Modifier::BOLD)
+                };
+
+                Line::from(s.chars().enumerate()
+                    .map(|(j, c)| Span::styled(c.to_string(), if matched.contains(&j) { match_style } else { style }))
+                    .collect::<Vec<_>>())
+            })
+            .collect();
+        
+        let suggestions_paragraph = Paragraph::new(suggestion_items).block(Block::default().borders(Borders::ALL).title("Suggestions"));
+        frame.render_widget(suggestions_paragraph, suggestions_area);
+
+        
+        let mut info_text: Option<Text> = None;
+        let command_name_to_show = if app.suggestion_active && !suggestions.is_empty() {
+            let s = &suggestions[app.suggestion_index].0;
+            let name = s.split_once(' ').map(|(c, _)| c).unwrap_or(s);
+            // Values and paths belong to the command already typed.
+            if COMMANDS.iter().any(|c| c.name == name) { name } else { app.input_buffer.split(['=', ' ']).next().unwrap_or(&app.input_buffer) }
+        } else {
+            app.input_buffer.split_once('=').map(|(c, _)| c).unwrap_or(&app.input_buffer)
+        };
+
+        if let Some(cmd) = COMMANDS.iter().find(|c| c.name == command_name_to_show) {
+            info_text = Some(Text::from(vec![
+                Line::from(Span::styled(cmd.name, Style::default().bold())),
+                Line::from(cmd.description),
+                Line::from(Span::styled(format!("Usage: {}", cmd.usage), Style::default().fg(app.translate_color(Color::Yellow)))),
+                Line::from(Span::styled(format!("Example: {}", cmd.example), Style::default().fg(app.translate_color(Color::Cyan)))),
+            ]));
+        }
+
+        if let Some(text) = info_text {
+            let box_height = 6;
+            let info_area = Rect {
+                x: input_bar_area.x,
+                y: suggestions_area.y.saturating_sub(box_height),
+                width: frame.size().width,
+                height: box_height,
+            };
+            let info_paragraph = Paragraph::new(text)
+                .block(Block::default().borders(Borders::ALL).title("Command Info"))
+                .wrap(ratatui::widgets::Wrap { trim: true });
+            frame.render_widget(info_paragraph, info_area);
+        }
+
+    }
+}
+fn draw_help_screen(frame: &mut Frame, app: &mut App) {
+    let help_text = match utils::get_help_sheet_path() {
+        Ok(path) => {
+            match std::fs::read_to_string(&path) {
+                Ok(content) => content, // File exists, use its content
+                Err(_) => { // File doesn't exist or is unreadable
+                    let default_content = help_sheet::get_default_help_text();
+                    // Attempt to create it for next time
+                    let _ = std::fs::write(path, default_content);
+                    // Use the default content for this session
+                    default_content.to_string()
+                }
+            }
+        },
+        Err(_) => "Error: Could not determine help sheet path.".to_string(),
+    };
+
+    let block = Block::default().title(" Help ").borders(Borders::ALL).border_style(Style::default().fg(app.translate_color(Color::Yellow)));
+    let paragraph = Paragraph::new(help_text)
+        .block(block)
+        .wrap(ratatui::widgets::Wrap { trim: false })
+        .scroll((app.help_scroll, 0));
+
+    let area = utils::centered_rect(80, 90, frame.size());
+    frame.render_widget(Clear, area);
+    frame.render_widget(paragraph, area);
+}
+
+
+fn draw_minimap(frame: &mut Frame, app: &mut App, area: Rect) {
+    let block = Block::default().borders(Borders::ALL).title("Minimap");
+    let inner_area = block.inner(area);
+    frame.render_widget(block, area);
+    app.last_minimap_area = Some(inner_area);
+
+    if app.canvas_width == 0 || app.canvas_height == 0 || inner_area.width < 1 || inner_area.height < 1 {
+        return;
+    }
+
+    let scale_x = app.canvas_width as f32 / inner_area.width as f32;
+    let scale_y = app.canvas_height as f32 / (inner_area.height as f32 * 2.0);
+
+    let Some(last_pixel_area) = app.last_pixel_area else { return };
+    let pixel_render_height = (app.zoom_level / PIXEL_WIDTH).max(1);
+    let visible_pixels_x = (last_pixel_area.width as f32 / app.zoom_level as f32) as i32;
+    let visible_pixels_y = (last_pixel_area.height as f32 / pixel_render_height as f32) as i32;
+
+    // Scanning the canvas is the expensive part, so the colors are only rebuilt when
+    // the composite or the minimap size changed since the last frame.
+    let size = (inner_area.width, inner_area.height);
+    let layer_key: Vec<(bool, u32)> = app.layers.iter().map(|layer| (layer.visible, layer.opacity.to_bits())).collect();
+    let stale = app.minimap_cache.as_ref().is_none_or(|cache| {
+        cache.canvas_generation != app.canvas_generation || cache.size != size || cache.layer_key != layer_key
+    });
+    if stale {
+        let get_color_for_region = |start_x: usize, end_x: usize, start_y: usize, end_y: usize| -> Option<Color> {
+            for y in start_y..end_y.min(app.canvas_height) {
+                for x in start_x..end_x.min(app.canvas_width) {
+                    if app.canvas[y][x].alpha > 0.0 {
+                        let pixel = app.canvas[y][x];
+                        return Some(utils::blend_colors(Color::Black, pixel.color.into(), pixel.alpha, app.linear_blending));
+                    }
+                }
+            }
+            None
+        };
+        let mut cells = Vec::with_capacity(inner_area.width as usize * inner_area.height as usize);
+        for my in 0..inner_area.height {
+            for mx in 0..inner_area.width {
+                let region_start_x = (mx as f32 * scale_x) as usize;
+                let region_end_x = ((mx + 1) as f32 * scale_x) as usize;
+                let region_start_y_top = (my as f32 * 2.0 * scale_y) as usize;
+                let region_end_y_top = ((my as f32 * 2.0 + 1.0) * scale_y) as usize;
+                let region_start_y_bot = ((my as f32 * 2.0 + 1.0) * scale_y) as usize;
+                let region_end_y_bot = ((my as f32 * 2.0 + 2.0) * scale_y) as usize;
+                cells.push((
+                    get_color_for_region(region_start_x, region_end_x, region_start_y_top, region_end_y_top).unwrap_or(Color::Reset),
+                    get_color_for_region(region_start_x, region_end_x, region_start_y_bot, region_end_y_bot).unwrap_or(Color::Reset),
+                ));
+            }
+        }
+        app.minimap_cache = Some(MinimapCache { canvas_generation: app.canvas_generation, layer_key, size, cells });
+    }
+    let Some(cache) = &app.minimap_cache else { return };
+
+    // The viewport highlight follows panning and zooming, so it is worked out every frame.
+    let viewport_left = app.view_offset_x;
+    let viewport_right = app.view_offset_x + visible_pixels_x;
+    let viewport_top = app.view_offset_y;
+    let viewport_bottom = app.view_offset_y + visible_pixels_y;
+    for my in 0..inner_area.height {
+        for mx in 0..inner_area.width {
+            let (mut top_color, mut bottom_color) = cache.cells[my as usize * inner_area.width as usize + mx as usize];
+            let region_left = (mx as f32 * scale_x) as i32;
+            let region_right = ((mx + 1) as f32 * scale_x) as i32;
+            let region_start_y_top = (my as f32 * 2.0 * scale_y) as usize;
+            let region_end_y_top = ((my as f32 * 2.0 + 1.0) * scale_y) as usize;
+            let region_start_y_bot = ((my as f32 * 2.0 + 1.0) * scale_y) as usize;
+            let region_end_y_bot = ((my as f32 * 2.0 + 2.0) * scale_y) as usize;
+
+            let is_top_in_view = region_start_y_top < viewport_bottom as usize 
+                && region_end_y_top > viewport_top as usize
+                && region_left < viewport_right
+                && region_right > viewport_left;
+
+            let is_bot_in_view = region_start_y_bot < viewport_bottom as usize 
+                && region_end_y_bot > viewport_top as usize
+                && region_left < viewport_right
+                && region_right > viewport_left;
+
+            if is_top_in_view { top_color = app.translate_color(utils::blend_colors(top_color, Color::Yellow, 0.4, app.linear_blending)); }
+            if is_bot_in_view { bottom_color = app.translate_color(utils::blend_colors(bottom_color, Color::Yellow, 0.4, app.linear_blending)); }
+
+            let style = Style::default().fg(app.translate_color(top_color)).bg(app.translate_color(bottom_color));
+            frame.buffer_mut().get_mut(inner_area.x + mx, inner_area.y + my).set_symbol("▀").set_style(style);
+        }
+    }
+}
+
+
+fn draw_keybindings_screen(frame: &mut Frame, app: &mut App) {
+    let area = utils::centered_rect(60, 80, frame.size());
+    frame.render_widget(Clear, area);
+    let block = Block::default().title(" Keybindings (Enter to Change, Esc to Exit) ").borders(Borders::ALL);
+    let inner_area = block.inner(area);
+    frame.render_widget(block, area);
+
+    if app.is_changing_keybinding {
+        let waiting_area = utils::centered_rect(40, 20, frame.size());
+        let text = Paragraph::new("Press any key combination...\n(Press Esc to cancel)")
+            .alignment(Alignment::Center)
+            .block(Block::default().borders(Borders::ALL).title("Waiting for Input"));
+        frame.render_widget(Clear, waiting_area);
+        frame.render_widget(text, waiting_area);
+        return;
+    }
+
+    let mut items = vec![];
+    for (i, action) in Action::iter().enumerate() {
+        let keybinding = app.keybindings.map.get(&action);
+        let key_str = keybinding.map(utils::format_keybinding).unwrap_or_else(|| "Unbound".to_string());
+        let line = Line::from(vec![
+            Span::styled(format!("{:<25}", action.to_string()), Style::default()),
+            Span::raw(key_str),
+        ]);
+        let style = if i == app.keybindings_selection_index {
+            Style::default().bg(app.translate_color(Color::Yellow)).fg(app.translate_color(Color::Black))
+        } else {
+            Style::default()
+        };
+        items.push(line.style(style));
+    }
+
+    let list = Paragraph::new(items)
+        .block(Block::default())
+        .scroll((app.keybindings_scroll_state, 0));
+    frame.render_widget(list, inner_area);
+}
+
+fn draw_confirmation_dialog(frame: &mut Frame, app: &mut App, message: &str) {
+    let selected = if app.confirm_selection_yes { 0 } else { 1 };
+    draw_dialog(frame, message, &["Yes", "No"], selected);
+}
+
+// Lists the starting state and every undo and redo step. The current state is marked;
+// steps that redo would replay are dimmed.
+fn draw_undo_history(frame: &mut Frame, app: &App) {
+    let area = utils::centered_rect(60, 60, frame.size());
+    let current = app.undo_stack.len();
+    let start = if app.undo_history_trimmed { "Oldest kept state" } else { "Start" };
+    let rows = std::iter::once(format!("          {}", start))
+        .chain(app.undo_history_steps().map(|step| format!("{}  {}", step.time.format("%H:%M:%S"), step.label)));
+    let items: Vec<ListItem> = rows.enumerate().map(|(i, row)| {
+        let marker = if i == current { "● " } else { "  " };
+        let style = if i > current { Style::default().fg(app.translate_color(Color::DarkGray)) } else { Style::default() };
+        ListItem::new(format!("{}{}", marker, row)).style(style)
+    }).collect();
+    let title = if current + app.redo_stack.len() == 0 { " Undo History: nothing to undo yet (Esc to Close) " } else { " Undo History (Enter: Jump, Esc: Close) " };
+    let list = List::new(items)
+        .block(Block::default().title(title).borders(Borders::ALL).border_style(Style::default().fg(app.translate_color(Color::Yellow))))
+        .highlight_style(Style::default().bg(app.translate_color(Color::Blue)).add_modifier(Modifier::BOLD));
+    let mut state = ListState::default().with_selected(Some(app.undo_history_selection));
+    frame.render_widget(Clear, area);
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+// A message above a row of buttons, with `selected` highlighted.
+fn draw_dialog(frame: &mut Frame, message: &str, buttons: &[&str], selected: usize) {
+    // Longer messages (e.g. lists of colors) get a taller dialog.
+    let area = if message.lines().count() > 2 { utils::centered_rect(50, 50, frame.size()) } else { utils::centered_rect(30, 20, frame.size()) };
+    frame.render_widget(Clear, area);
+    let block = Block::default().title(" Confirmation ").borders(Borders::ALL);
+    let inner_area = block.inner(area);
+    frame.render_widget(block, area);
+
+    let text = Paragraph::new(message).alignment(Alignment::Center);
+
+    let mut spans = Vec::new();
+    for (i, label) in buttons.iter().enumerate() {
+        if i > 0 { spans.push(Span::raw(" / ")); }
+        let style = if i == selected { Style::default().reversed() } else { Style::default() };
+        spans.push(Span::styled(format!(" {} ", label), style));
+    }
+    let buttons = Line::from(spans).alignment(Alignment::Center);
+    
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(inner_area);
+
+    frame.render_widget(text, layout[0]);
+    frame.render_widget(buttons, layout[1]);
+}
@@ -1,8 +1,12 @@
-use crate::{Block, Borders, Clear, PaletteFile, Paragraph, SerializableColor, palette, stdout};
+use crate::{BlendMode, PaletteFile, Pixel, SerializableColor, palette};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph};
+use std::io::stdout;
 
 use ratatui::prelude::*;
 use std::io::Result;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+use std::collections::HashMap;
 
 #[cfg(not(windows))]
 use crossterm::event::{Event, KeyCode};
@@ -26,13 +30,114 @@ pub fn get_or_create_app_dir() -> Result<PathBuf> {
     Ok(app_dir)
 }
 
-pub fn blend_colors(c1: Color, c2: Color, factor: f32) -> Color {
+// Blending runs per pixel per frame, so both conversions are table lookups.
+const LINEAR_TO_SRGB_STEPS: usize = 4096;
+
+fn srgb_to_linear_table() -> &'static [f32; 256] {
+    static TABLE: OnceLock<[f32; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [0.0; 256];
+        for (i, value) in table.iter_mut().enumerate() {
+            let c = i as f32 / 255.0;
+            *value = if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) };
+        }
+        table
+    })
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    static TABLE: OnceLock<Vec<u8>> = OnceLock::new();
+    let table = TABLE.get_or_init(|| {
+        (0..LINEAR_TO_SRGB_STEPS).map(|i| {
+            let l = i as f32 / (LINEAR_TO_SRGB_STEPS - 1) as f32;
+            let c = if l <= 0.0031308 { l * 12.92 } else { 1.055 * l.powf(1.0 / 2.4) - 0.055 };
+            (c * 255.0).round() as u8
+        }).collect()
+    });
+    table[(value.clamp(0.0, 1.0) * (LINEAR_TO_SRGB_STEPS - 1) as f32).round() as usize]
+}
+
+/// Mixes `c1` towards `c2` by `factor`, in linear light when `linear` (the app's
+/// `linear_blending` setting) and in plain sRGB otherwise.
+pub fn blend_colors(c1: Color, c2: Color, factor: f32, linear: bool) -> Color {
     let (r1, g1, b1) = to_rgb(c1);
     let (r2, g2, b2) = to_rgb(c2);
-    let r = (r1 as f32 * (1.0 - factor) + r2 as f32 * factor).round() as u8;
-    let g = (g1 as f32 * (1.0 - factor) + g2 as f32 * factor).round() as u8;
-    let b = (b1 as f32 * (1.0 - factor) + b2 as f32 * factor).round() as u8;
-    Color::Rgb(r, g, b)
+    if factor <= 0.0 { return Color::Rgb(r1, g1, b1); }
+    if factor >= 1.0 { return Color::Rgb(r2, g2, b2); }
+    let mix: fn(u8, u8, f32) -> u8 = if linear {
+        |a, b, t| {
+            let table = srgb_to_linear_table();
+            linear_to_srgb(table[a as usize] * (1.0 - t) + table[b as usize] * t)
+        }
+    } else {
+        |a, b, t| (a as f32 * (1.0 - t) + b as f32 * t).round() as u8
+    };
+    Color::Rgb(mix(r1, r2, factor), mix(g1, g2, factor), mix(b1, b2, factor))
+}
+
+/// Standard "over" compositing of a source color/alpha on top of `dest`.
+pub fn composite_over(dest: Pixel, src_color: SerializableColor, src_alpha: f32, linear: bool) -> Pixel {
+    if src_alpha <= 0.0 { return dest; }
+    if dest.alpha == 0.0 {
+        return Pixel { color: src_color, alpha: src_alpha };
+    }
+    let final_alpha = src_alpha + dest.alpha * (1.0 - src_alpha);
+    let factor = src_alpha / final_alpha;
+    let final_color = blend_colors(dest.color.into(), src_color.into(), factor, linear);
+    Pixel { color: final_color.into(), alpha: final_alpha }
+}
+
+/// Like `composite_over`, but the source color is first combined with the backdrop by
+/// `mode`. As in the W3C compositing model the blended color is weighted by the
+/// backdrop's alpha, so blend modes only act where something is underneath.
+pub fn composite_blended(dest: Pixel, src_color: SerializableColor, src_alpha: f32, mode: BlendMode, linear: bool) -> Pixel {
+    if mode == BlendMode::Normal || dest.alpha == 0.0 {
+        return composite_over(dest, src_color, src_alpha, linear);
+    }
+    let (br, bg, bb) = to_rgb(dest.color.into());
+    let (sr, sg, sb) = to_rgb(src_color.into());
+    let blended = Color::Rgb(mode.blend_channel(br, sr), mode.blend_channel(bg, sg), mode.blend_channel(bb, sb));
+    let mixed = blend_colors(src_color.into(), blended, dest.alpha, linear);
+    composite_over(dest, mixed.into(), src_alpha, linear)
+}
+
+/// Euclidean distance between two colors in RGB space (0 to about 441.7).
+pub fn color_distance(a: Color, b: Color) -> f32 {
+    let ((ar, ag, ab), (br, bg, bb)) = (to_rgb(a), to_rgb(b));
+    let (dr, dg, db) = (ar as f32 - br as f32, ag as f32 - bg as f32, ab as f32 - bb as f32);
+    (dr * dr + dg * dg + db * db).sqrt()
+}
+
+/// Deterministic pseudo-random value in [-1, 1) for a pixel, mixed from a seed (SplitMix64).
+pub fn pixel_noise(seed: u64, x: usize, y: usize) -> f32 {
+    let mut z = seed ^ (((x as u64) << 32) | y as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    (z >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+}
+
+/// Rotates the hue of a color by `hue_shift` degrees and moves its HSV brightness by
+/// `value_shift` (clamped to 0..=1).
+pub fn jitter_color(color: Color, hue_shift: f32, value_shift: f32) -> Color {
+    let (r, g, b) = to_rgb(color);
+    let (hue, saturation, max) = palette::rgb_to_hsv(r, g, b);
+
+    let hue = (hue + hue_shift).rem_euclid(360.0);
+    let value = (max + value_shift).clamp(0.0, 1.0);
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0).rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = value - chroma;
+    let channel = |c: f32| ((c + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+    Color::Rgb(channel(r), channel(g), channel(b))
 }
 
 pub fn to_rgb(c: Color) -> (u8, u8, u8) {
@@ -48,6 +153,277 @@ pub fn to_rgb(c: Color) -> (u8, u8, u8) {
     }
 }
 
+/// Xiaolin Wu's line algorithm. Returns every touched pixel with its coverage
+/// (0.0-1.0), so callers can write partial alpha along the edges. Endpoints are
+/// pixel centers and are always fully covered.
+pub fn wu_line(x0: i32, y0: i32, x1: i32, y1: i32) -> Vec<(i32, i32, f32)> {
+    let steep = (y1 - y0).abs() > (x1 - x0).abs();
+    let (mut x0, mut y0, mut x1, mut y1) = if steep { (y0, x0, y1, x1) } else { (x0, y0, x1, y1) };
+    if x0 > x1 {
+        std::mem::swap(&mut x0, &mut x1);
+        std::mem::swap(&mut y0, &mut y1);
+    }
+
+    let dx = (x1 - x0) as f32;
+    let gradient = if dx == 0.0 { 1.0 } else { (y1 - y0) as f32 / dx };
+    let mut points = Vec::new();
+    let mut plot = |a: i32, b: i32, c: f32| {
+        if c > 0.0 {
+            if steep { points.push((b, a, c)); } else { points.push((a, b, c)); }
+        }
+    };
+
+    for x in x0..=x1 {
+        if x == x0 || x == x1 {
+            plot(x, if x == x0 { y0 } else { y1 }, 1.0);
+            continue;
+        }
+        let intery = y0 as f32 + gradient * (x - x0) as f32;
+        let ipart = intery.floor();
+        let fpart = intery - ipart;
+        plot(x, ipart as i32, 1.0 - fpart);
+        plot(x, ipart as i32 + 1, fpart);
+    }
+    points
+}
+
+/// Bresenham's line algorithm. Returns every pixel from `(x0, y0)` to `(x1, y1)`
+/// inclusive, in drawing order, with no diagonal gaps or doubled steps.
+pub fn bresenham_line(x0: i32, y0: i32, x1: i32, y1: i32) -> Vec<(i32, i32)> {
+    let dx = (x1 - x0).abs();
+    let dy = -(y1 - y0).abs();
+    let step_x = if x0 < x1 { 1 } else { -1 };
+    let step_y = if y0 < y1 { 1 } else { -1 };
+    let (mut x, mut y) = (x0, y0);
+    let mut error = dx + dy;
+    let mut points = Vec::with_capacity(dx.max(-dy) as usize + 1);
+    loop {
+        points.push((x, y));
+        if x == x1 && y == y1 { break; }
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+    points
+}
+
+/// Midpoint ellipse inscribed in the box with corners `(x0, y0)` and `(x1, y1)`,
+/// both inclusive, after Zingl's rectangle variant so even-sized boxes stay
+/// symmetric. Filled ellipses span each row between its outline pixels.
+pub fn ellipse_points(x0: i32, y0: i32, x1: i32, y1: i32, filled: bool) -> Vec<(i32, i32)> {
+    let (mut x0, mut x1) = (x0.min(x1) as i64, x0.max(x1) as i64);
+    let (top, bottom) = (y0.min(y1) as i64, y0.max(y1) as i64);
+    let (a, b) = (x1 - x0, bottom - top);
+    let b1 = b & 1;
+    let mut dx = 4 * (1 - a) * b * b;
+    let mut dy = 4 * (b1 + 1) * a * a;
+    let mut err = dx + dy + b1 * a * a;
+    let mut y0 = top + (b + 1) / 2;
+    let mut y1 = y0 - b1;
+    let (step_a, step_b) = (8 * a * a, 8 * b * b);
+
+    let mut outline = Vec::new();
+    loop {
+        outline.extend([(x1, y0), (x0, y0), (x0, y1), (x1, y1)]);
+        let doubled = 2 * err;
+        if doubled <= dy {
+            y0 += 1;
+            y1 -= 1;
+            dy += step_a;
+            err += dy;
+        }
+        if doubled >= dx || 2 * err > dy {
+            x0 += 1;
+            x1 -= 1;
+            dx += step_b;
+            err += dx;
+        }
+        if x0 > x1 { break; }
+    }
+    // Very flat ellipses stop early; finish their tips.
+    while y0 - y1 <= b {
+        outline.extend([(x0 - 1, y0), (x1 + 1, y0), (x0 - 1, y1), (x1 + 1, y1)]);
+        y0 += 1;
+        y1 -= 1;
+    }
+
+    let mut points: Vec<(i32, i32)> = outline.into_iter().map(|(x, y)| (x as i32, y as i32)).collect();
+    if filled {
+        let mut spans: HashMap<i32, (i32, i32)> = HashMap::new();
+        for &(x, y) in &points {
+            let span = spans.entry(y).or_insert((x, x));
+            *span = (span.0.min(x), span.1.max(x));
+        }
+        points = spans.into_iter().flat_map(|(y, (left, right))| (left..=right).map(move |x| (x, y))).collect();
+    }
+    points.sort_unstable_by_key(|&(x, y)| (y, x));
+    points.dedup();
+    points
+}
+
+/// The antialiased counterpart of `ellipse_points`: every pixel the ellipse touches,
+/// with coverage (0.0-1.0) falling off with the pixel center's distance from the curve.
+/// Pixels on the curve are fully covered, and so is the inside of a filled ellipse.
+/// A box one pixel thin is a line.
+pub fn ellipse_coverage(x0: i32, y0: i32, x1: i32, y1: i32, filled: bool) -> Vec<(i32, i32, f32)> {
+    let (left, right, top, bottom) = (x0.min(x1), x0.max(x1), y0.min(y1), y0.max(y1));
+    if left == right || top == bottom {
+        return wu_line(x0, y0, x1, y1);
+    }
+    let (center_x, center_y) = ((left + right) as f32 / 2.0, (top + bottom) as f32 / 2.0);
+    let (rx, ry) = ((right - left) as f32 / 2.0, (bottom - top) as f32 / 2.0);
+    let mut points = Vec::new();
+    for y in top..=bottom {
+        for x in left..=right {
+            let (dx, dy) = (x as f32 - center_x, y as f32 - center_y);
+            // Distance from the curve to first order, negative inside.
+            let level = (dx / rx).powi(2) + (dy / ry).powi(2) - 1.0;
+            let gradient = 2.0 * ((dx / (rx * rx)).powi(2) + (dy / (ry * ry)).powi(2)).sqrt();
+            let distance = if gradient > 0.0 { level / gradient } else { -rx.min(ry) };
+            let coverage = if filled { 1.0 - distance } else { 1.0 - distance.abs() };
+            if coverage > 0.0 {
+                points.push((x, y, coverage.min(1.0)));
+            }
+        }
+    }
+    points
+}
+
+/// Case-insensitive subsequence match. Returns a score (higher is better) and the
+/// char positions in `candidate` that matched, or None if `query` doesn't fit.
+/// Matches at the start, after a separator, at camelCase humps and runs of
+/// consecutive characters score higher.
+pub fn fuzzy_match(query: &str, candidate: &str) -> Option<(i32, Vec<usize>)> {
+    let chars: Vec<char> = candidate.chars().collect();
+    let mut matched: Vec<usize> = Vec::new();
+    let mut score = 0;
+    let mut pos = 0;
+
+    for qc in query.chars().map(|c| c.to_ascii_lowercase()) {
+        while pos < chars.len() && chars[pos].to_ascii_lowercase() != qc {
+            pos += 1;
+        }
+        if pos == chars.len() { return None; }
+
+        score += 1;
+        if pos == 0 {
+            score += 8;
+        } else {
+            let prev = chars[pos - 1];
+            if !prev.is_alphanumeric() || (prev.is_lowercase() && chars[pos].is_uppercase()) {
+                score += 5;
+            }
+        }
+        if let Some(&last) = matched.last() {
+            if last + 1 == pos { score += 4; } else { score -= (pos - last - 1).min(5) as i32; }
+        }
+        matched.push(pos);
+        pos += 1;
+    }
+    // Prefer shorter candidates when everything else is equal.
+    score -= (chars.len() - matched.len()) as i32 / 4;
+    Some((score, matched))
+}
+
+/// Turns a user-typed output path into the file that will actually be written:
+/// strips quotes, expands `~`, resolves relative paths against `base_dir`,
+/// rejects names the platform can't store and appends `.{extension}` unless the
+/// path already ends with it in any case ("ART.PNG" stays as is).
+pub fn normalize_output_path(base_dir: &Path, input: &str, extension: &str) -> std::result::Result<PathBuf, String> {
+    let trimmed = input.replace('"', "");
+    let trimmed = trimmed.trim();
+    if trimmed.is_empty() {
+        return Err("Filename cannot be empty.".to_string());
+    }
+
+    let mut path = base_dir.join(shellexpand::tilde(trimmed).as_ref());
+    if trimmed.ends_with(['/', std::path::MAIN_SEPARATOR]) || path.file_name().is_none() {
+        return Err(format!("'{}' is not a file name.", trimmed));
+    }
+
+    for component in path.components() {
+        if let Component::Normal(part) = component {
+            let part = part.to_string_lossy();
+            if let Some(c) = part.chars().find(|&c| is_illegal_filename_char(c)) {
+                return Err(format!("Invalid character '{}' in '{}'.", c.escape_default(), part));
+            }
+        }
+    }
+
+    // Compared on the whole name so double extensions like "consolet.json" work too.
+    let has_extension = path.file_name().is_some_and(|name| name.to_string_lossy().to_lowercase().ends_with(&format!(".{}", extension.to_lowercase())));
+    if !has_extension {
+        let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(format!(".{}", extension));
+        path.set_file_name(file_name);
+    }
+    Ok(path)
+}
+
+#[cfg(windows)]
+fn is_illegal_filename_char(c: char) -> bool {
+    c.is_control() || matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*')
+}
+
+#[cfg(not(windows))]
+fn is_illegal_filename_char(c: char) -> bool {
+    c == '\0'
+}
+
+/// Creates any missing parent directories of `path`.
+pub fn create_parent_dirs(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() && !parent.exists() => std::fs::create_dir_all(parent),
+        _ => Ok(()),
+    }
+}
+
+/// Splits a command line on whitespace, keeping double-quoted runs together
+/// (quotes removed), so `layer "Line art" active` yields three arguments.
+pub fn split_args(input: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut has_arg = false;
+    for c in input.chars() {
+        match c {
+            '"' => { in_quotes = !in_quotes; has_arg = true; }
+            c if c.is_whitespace() && !in_quotes => {
+                if has_arg { args.push(std::mem::take(&mut current)); }
+                has_arg = false;
+            }
+            c => { current.push(c); has_arg = true; }
+        }
+    }
+    if has_arg { args.push(current); }
+    args
+}
+
+/// Splits `clear; resize 64x64` into its commands. Semicolons inside double quotes
+/// stay put, as does `\;`, which becomes a plain `;`. Empty commands are dropped.
+pub fn split_commands(input: &str) -> Vec<String> {
+    let mut commands = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&';') => current.push(chars.next().unwrap_or(';')),
+            '"' => { in_quotes = !in_quotes; current.push(c); }
+            ';' if !in_quotes => commands.push(std::mem::take(&mut current)),
+            c => current.push(c),
+        }
+    }
+    commands.push(current);
+    commands.into_iter().map(|command| command.trim().to_string()).filter(|command| !command.is_empty()).collect()
+}
+
 pub fn export_default_palettes_if_missing() -> std::io::Result<()> {
     let palettes_dir = get_or_create_app_dir()?.join("palettes");
     for (name, generator) in palette::get_built_in_palettes() {
@@ -152,6 +528,24 @@ pub fn format_keybinding(kb: &crate::keybindings::Keybinding) -> String {
     parts.join(" + ")
 }
 
+/// Cuts text to at most max_width terminal columns, ending with "…" when anything was
+/// cut. Works on whole graphemes, so wide characters and combining marks stay intact.
+pub fn elide(text: &str, max_width: usize) -> String {
+    use unicode_segmentation::UnicodeSegmentation;
+    if Span::raw(text).width() <= max_width { return text.to_string(); }
+    if max_width == 0 { return String::new(); }
+    let mut elided = String::new();
+    let mut width = 0;
+    for grapheme in text.graphemes(true) {
+        let grapheme_width = Span::raw(grapheme).width();
+        if width + grapheme_width + 1 > max_width { break; }
+        elided.push_str(grapheme);
+        width += grapheme_width;
+    }
+    elided.push('…');
+    elided
+}
+
 pub fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)
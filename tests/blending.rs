@@ -0,0 +1,50 @@
+mod common;
+
+use common::test_app;
+use consolet::utils::{blend_colors, to_rgb};
+use consolet::{execute_command, Pixel, SerializableColor};
+use ratatui::style::Color;
+
+fn luminance(c: Color) -> f32 {
+    let (r, g, b) = to_rgb(c);
+    0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32
+}
+
+#[test]
+fn linear_blending_brightens_midpoints_and_keeps_endpoints() {
+    let red = Color::Rgb(255, 0, 0);
+    let green = Color::Rgb(0, 255, 0);
+    let odd = Color::Rgb(13, 77, 201);
+
+    for linear in [false, true] {
+        assert_eq!(blend_colors(red, odd, 0.0, linear), red);
+        assert_eq!(blend_colors(red, odd, 1.0, linear), odd);
+        assert_eq!(blend_colors(odd, green, 0.0, linear), odd);
+        assert_eq!(blend_colors(odd, green, 1.0, linear), green);
+    }
+
+    let srgb_mid = blend_colors(red, green, 0.5, false);
+    let linear_mid = blend_colors(red, green, 0.5, true);
+
+    assert_eq!(srgb_mid, Color::Rgb(128, 128, 0));
+    assert_eq!(linear_mid, Color::Rgb(188, 188, 0));
+    assert!(luminance(linear_mid) > luminance(srgb_mid) * 1.3);
+}
+
+#[test]
+fn linear_blending_is_per_app() {
+    let half_green = Pixel { color: SerializableColor(0, 255, 0), alpha: 0.5 };
+    let mut srgb = test_app(1, 1);
+    let mut linear = test_app(1, 1);
+    assert!(execute_command(&mut linear, "linear_blending=true"));
+    assert!(!srgb.linear_blending);
+
+    for app in [&mut srgb, &mut linear] {
+        execute_command(app, "add_layer");
+        app.layers[1].canvas[0][0] = Pixel { color: SerializableColor(255, 0, 0), alpha: 1.0 };
+        app.layers[0].canvas[0][0] = half_green;
+    }
+
+    assert_eq!(srgb.composite_pixel(0, 0).color, SerializableColor(128, 128, 0));
+    assert_eq!(linear.composite_pixel(0, 0).color, SerializableColor(188, 188, 0));
+}
@@ -0,0 +1,808 @@
+mod common;
+
+use common::{active_pixel, painted, select_color, test_app};
+use consolet::{execute_command, AppMode, SerializableColor, SymmetryMode};
+
+// Scripts are read from one file in the data directory, so tests that write it take turns.
+static SCRIPT_FILE: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+#[test]
+fn undo_restores_canvas_after_clear() {
+    let mut app = test_app(8, 8);
+    app.save_state_for_undo("Test");
+    app.apply_brush(3, 3);
+    assert_eq!(painted(&app), vec![(3, 3)]);
+
+    execute_command(&mut app, "clear");
+    assert!(painted(&app).is_empty());
+
+    execute_command(&mut app, "undo");
+    assert_eq!(painted(&app), vec![(3, 3)]);
+    assert_eq!(active_pixel(&app, 3, 3).color, SerializableColor(255, 0, 0));
+}
+
+#[test]
+fn tile_fill_repeats_copied_tile() {
+    let mut app = test_app(8, 8);
+    execute_command(&mut app, "tilesize 4x4");
+    app.apply_brush(1, 2);
+    app.cursor_pos = (0, 0);
+    execute_command(&mut app, "tile copy");
+    execute_command(&mut app, "tile fill");
+
+    assert_eq!(painted(&app), vec![(1, 2), (5, 2), (1, 6), (5, 6)]);
+}
+
+#[test]
+fn setter_commands_clamp_to_range() {
+    let mut app = test_app(8, 8);
+    execute_command(&mut app, "pixel_block=3");
+    assert_eq!(app.pixel_block, 3);
+    execute_command(&mut app, "stabilizer=99");
+    assert_ne!(app.stabilizer, 99);
+    execute_command(&mut app, "flat_mode=true");
+    assert!(app.flat_mode);
+}
+
+#[test]
+fn script_draws_onto_named_layers() {
+    let _script_file = SCRIPT_FILE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut app = test_app(8, 8);
+    execute_command(&mut app, "add_layer");
+    execute_command(&mut app, "rename_layer=Ink");
+    let script = r##"["layer:ink active", "apply_color:#00FF00 1,1-2,1", "layer:\"Layer 1\" active", "apply_color:#0000FF 5,5", "apply_color:rgb(255,128,0) 2,2", "fill:crimson 7,7"]"##;
+    std::fs::write(consolet::script_handler::get_script_path().unwrap(), script).unwrap();
+
+    execute_command(&mut app, "draw_script");
+
+    let ink = &app.layers[0];
+    assert_eq!(ink.name, "Ink");
+    assert_eq!(ink.canvas[1][1].color, SerializableColor(0, 255, 0));
+    assert_eq!(ink.canvas[1][2].color, SerializableColor(0, 255, 0));
+    assert_eq!(ink.canvas[5][5].alpha, 0.0);
+    assert_eq!(app.layers[1].canvas[5][5].color, SerializableColor(0, 0, 255));
+    assert_eq!(app.layers[1].canvas[2][2].color, SerializableColor(255, 128, 0));
+    assert_eq!(app.layers[1].canvas[7][7].color, SerializableColor(220, 20, 60));
+    assert_eq!(app.active_layer_index, 1);
+}
+
+#[test]
+fn layer_command_addresses_layers_by_position_and_prefix() {
+    let mut app = test_app(4, 4);
+    execute_command(&mut app, "rename_layer=Background");
+    execute_command(&mut app, "add_layer");
+    app.layers[0].name = "Line art".to_string();
+    execute_command(&mut app, "add_layer");
+    execute_command(&mut app, "rename_layer=Lights");
+
+    execute_command(&mut app, "layer 3 opacity=0.5");
+    assert_eq!(app.layers[2].opacity, 0.5);
+
+    execute_command(&mut app, "layer \"line art\" visible=false");
+    assert!(!app.layers[1].visible);
+
+    execute_command(&mut app, "layer back active");
+    assert_eq!(app.active_layer_index, 2);
+
+    execute_command(&mut app, "layer li active");
+    assert_eq!(app.active_layer_index, 2, "an ambiguous prefix must not switch layers");
+    assert!(app.status_message.as_ref().unwrap().0.contains("ambiguous"));
+
+    execute_command(&mut app, "layer list");
+    assert!(app.mode == AppMode::InfoPopup);
+    let (_, lines) = app.info_popup.as_ref().unwrap();
+    assert_eq!(lines.len(), 4);
+}
+
+#[test]
+fn merge_visible_keeps_hidden_layers() {
+    let mut app = test_app(4, 4);
+    app.apply_brush(0, 0);
+    execute_command(&mut app, "add_layer");
+    select_color(&mut app, 0, 0, 255);
+    app.apply_brush(1, 1);
+    execute_command(&mut app, "add_layer");
+    execute_command(&mut app, "rename_layer=Hidden");
+    execute_command(&mut app, "layer hidden visible=false");
+    let before = app.canvas.clone();
+
+    execute_command(&mut app, "merge_visible");
+
+    assert_eq!(app.layers.len(), 2);
+    assert_eq!(app.layers[0].name, "Hidden");
+    assert!(!app.layers[0].visible);
+    common::assert_canvas_eq(&app.canvas, &before);
+}
+
+#[test]
+fn flatten_refuses_locked_layers_without_force() {
+    let mut app = test_app(4, 4);
+    execute_command(&mut app, "add_layer");
+    app.layers[1].locked = true;
+
+    execute_command(&mut app, "flatten");
+    assert_eq!(app.layers.len(), 2);
+
+    execute_command(&mut app, "flatten --force");
+    assert_eq!(app.layers.len(), 1);
+}
+
+#[test]
+fn replace_color_keeps_alpha_and_undoes_across_layers_in_one_step() {
+    let mut app = test_app(6, 6);
+    app.opacity = 0.5;
+    app.apply_brush(1, 1);
+    app.opacity = 1.0;
+    app.apply_brush(2, 2);
+    select_color(&mut app, 0, 0, 255);
+    app.apply_brush(3, 3);
+    app.add_new_layer();
+    select_color(&mut app, 255, 0, 0);
+    app.apply_brush(4, 4);
+
+    execute_command(&mut app, "replace_color=#FF0000,#00AA00");
+    assert_eq!(active_pixel(&app, 4, 4).color, SerializableColor(0, 170, 0));
+    assert_eq!(app.layers[1].canvas[2][2].color, SerializableColor(255, 0, 0), "other layers are untouched");
+
+    execute_command(&mut app, "replace_color #FF0000,#00AA00 --all-layers");
+    let bottom = &app.layers[1].canvas;
+    assert_eq!((bottom[1][1].color, bottom[1][1].alpha), (SerializableColor(0, 170, 0), 0.5));
+    assert_eq!(bottom[2][2].color, SerializableColor(0, 170, 0));
+    assert_eq!(bottom[3][3].color, SerializableColor(0, 0, 255));
+    assert!(app.status_message.as_ref().unwrap().0.starts_with("Replaced 2 pixels"));
+
+    execute_command(&mut app, "undo");
+    assert_eq!(app.layers[1].canvas[1][1].color, SerializableColor(255, 0, 0));
+    assert_eq!(app.layers[1].canvas[2][2].color, SerializableColor(255, 0, 0));
+    assert_eq!(active_pixel(&app, 4, 4).color, SerializableColor(0, 170, 0), "only the --all-layers step was undone");
+}
+
+#[test]
+fn replace_tool_swaps_clicked_color_for_selection() {
+    let mut app = test_app(6, 6);
+    app.apply_brush(0, 0);
+    app.apply_brush(5, 5);
+    select_color(&mut app, 0, 0, 255);
+    app.replace_mode = true;
+    app.cursor_pos = (5, 5);
+    app.use_current_tool();
+
+    assert_eq!(active_pixel(&app, 0, 0).color, SerializableColor(0, 0, 255));
+    assert_eq!(painted(&app).len(), 2);
+}
+
+#[test]
+fn outline_borders_opaque_content_outside_inside_and_on_a_new_layer() {
+    let mut app = test_app(7, 7);
+    app.pen_size = 3;
+    app.pen_shape = consolet::PenShape::Square;
+    app.apply_brush(3, 3); // a 3x3 square at 2..=4
+    app.pen_size = 1;
+    select_color(&mut app, 0, 0, 255);
+
+    execute_command(&mut app, "outline");
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Outlined 12 pixels.");
+    assert_eq!(active_pixel(&app, 1, 3).color, SerializableColor(0, 0, 255));
+    assert_eq!(active_pixel(&app, 1, 1).alpha, 0.0, "corners need --diagonal");
+    app.undo();
+    assert_eq!(painted(&app).len(), 9);
+
+    execute_command(&mut app, "outline --diagonal");
+    assert_eq!(painted(&app).len(), 25);
+    app.undo();
+
+    execute_command(&mut app, "outline --inside");
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Outlined 8 pixels.");
+    assert_eq!(active_pixel(&app, 3, 3).color, SerializableColor(255, 0, 0));
+    assert_eq!(active_pixel(&app, 2, 2).color, SerializableColor(0, 0, 255));
+    app.undo();
+
+    let layer_count = app.layers.len();
+    execute_command(&mut app, "outline --new-layer");
+    assert_eq!(app.layers.len(), layer_count + 1);
+    assert_eq!(painted(&app).len(), 12, "only the outline is on the new layer");
+}
+
+#[test]
+fn shift_layer_wraps_or_clips_and_undoes() {
+    let mut app = test_app(4, 3);
+    app.apply_brush(3, 0);
+    execute_command(&mut app, "shift_layer=1,-1");
+    assert_eq!(painted(&app), vec![(0, 2)], "wraps by default");
+
+    app.undo();
+    assert_eq!(painted(&app), vec![(3, 0)]);
+
+    execute_command(&mut app, "shift_layer=1,0 --clip");
+    assert!(painted(&app).is_empty());
+
+    app.undo();
+    app.add_new_layer();
+    app.apply_brush(0, 0);
+    execute_command(&mut app, "shift_layer=0,1 --all");
+    assert_eq!(painted(&app), vec![(0, 1)]);
+    let bottom = app.layers.iter().find(|layer| layer.canvas[1][3].alpha > 0.0);
+    assert!(bottom.is_some(), "the other layer moved as well");
+}
+
+#[test]
+fn text_stamps_glyphs_clips_or_wraps_and_undoes_in_one_step() {
+    let mut app = test_app(10, 12);
+    let undo_depth = app.undo_stack.len();
+    execute_command(&mut app, "text \"I~\" 1,1");
+    // The 3x5 'I': a full top and bottom bar with a stem in the middle.
+    assert_eq!(painted(&app).len(), 3 + 3 + 3);
+    assert_eq!(active_pixel(&app, 1, 1).alpha, 1.0);
+    assert_eq!(active_pixel(&app, 2, 3).alpha, 1.0);
+    assert_eq!(active_pixel(&app, 1, 3).alpha, 0.0);
+    assert_eq!(app.undo_stack.len(), undo_depth + 1);
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Skipped unsupported characters: ~");
+    app.undo();
+    assert!(painted(&app).is_empty());
+
+    execute_command(&mut app, "text=lll 0,0");
+    assert!(painted(&app).iter().all(|&(_, y)| y < 5), "clipped at the right edge");
+    assert!(active_pixel(&app, 8, 4).alpha > 0.0, "the third glyph is partly drawn");
+    app.undo();
+
+    execute_command(&mut app, "text=LLL 0,0 --wrap");
+    assert!(active_pixel(&app, 8, 4).alpha == 0.0);
+    assert!(active_pixel(&app, 0, 10).alpha > 0.0, "the third glyph moved to the next line");
+}
+
+#[test]
+fn text_snaps_to_the_palette() {
+    let mut app = test_app(4, 6);
+    app.snap_to_palette = true;
+    select_color(&mut app, 1, 2, 3);
+    execute_command(&mut app, "text=. 0,0");
+    let dot = active_pixel(&app, 1, 4);
+    assert_eq!(dot.alpha, 1.0);
+    let in_palette = app.color_palette.iter().any(|entry| matches!(entry, consolet::palette::PaletteEntry::Color(c) if SerializableColor::from(*c) == dot.color));
+    assert!(in_palette);
+}
+
+#[test]
+fn magic_wand_selects_contiguous_color_for_delete_fill_and_move() {
+    let mut app = test_app(6, 3);
+    app.apply_brush(0, 0);
+    app.apply_brush(1, 0);
+    app.apply_brush(4, 0); // same color, not connected
+    app.magic_wand(0, 0);
+    assert_eq!(app.selection.len(), 2);
+
+    select_color(&mut app, 0, 0, 255);
+    execute_command(&mut app, "selection fill");
+    assert_eq!(active_pixel(&app, 1, 0).color, SerializableColor(0, 0, 255));
+    assert_eq!(active_pixel(&app, 4, 0).color, SerializableColor(255, 0, 0));
+
+    execute_command(&mut app, "selection move 0,2");
+    assert_eq!(active_pixel(&app, 0, 0).alpha, 0.0);
+    assert_eq!(active_pixel(&app, 1, 2).color, SerializableColor(0, 0, 255));
+    assert!(app.selection.contains(&(1, 2)));
+
+    execute_command(&mut app, "selection delete");
+    assert_eq!(painted(&app), vec![(4, 0)], "the moved pixels are gone, the unselected one stays");
+    app.undo();
+    app.undo();
+    assert_eq!(active_pixel(&app, 1, 0).color, SerializableColor(0, 0, 255));
+
+    execute_command(&mut app, "selection clear");
+    assert!(app.selection.is_empty());
+}
+
+#[test]
+fn selection_mask_limits_drawing_and_erasing() {
+    let mut app = test_app(4, 4);
+    app.apply_brush(1, 1);
+    app.magic_wand(1, 1);
+    app.selection_mask = true;
+    app.pen_size = 4;
+    app.pen_shape = consolet::PenShape::Square;
+
+    select_color(&mut app, 0, 255, 0);
+    app.apply_brush(1, 1);
+    assert_eq!(painted(&app), vec![(1, 1)]);
+    assert_eq!(active_pixel(&app, 1, 1).color, SerializableColor(0, 255, 0));
+
+    app.selection_mask = false;
+    app.apply_brush(1, 1);
+    app.selection_mask = true;
+    app.erase_brush(1, 1);
+    assert_eq!(active_pixel(&app, 1, 1).alpha, 0.0);
+    assert_eq!(active_pixel(&app, 0, 0).alpha, 1.0, "unselected pixels survive the eraser");
+}
+
+#[test]
+fn symmetry_command_and_cursor_action_set_the_axis() {
+    let mut app = test_app(20, 10);
+    execute_command(&mut app, "symmetry=vertical:3");
+    assert_eq!(app.symmetry_mode, SymmetryMode::Vertical(3));
+    execute_command(&mut app, "symmetry=horizontal:50");
+    assert_eq!(app.symmetry_mode, SymmetryMode::Horizontal(9), "clamped to the canvas");
+    execute_command(&mut app, "symmetry=diag_bwd:-4");
+    assert_eq!(app.symmetry_mode, SymmetryMode::DiagonalBackward(-4));
+    execute_command(&mut app, "symmetry=sideways:2");
+    assert_eq!(app.symmetry_mode, SymmetryMode::DiagonalBackward(-4), "bad input changes nothing");
+
+    app.cursor_pos = (7, 2);
+    app.set_symmetry_at_cursor();
+    assert_eq!(app.symmetry_mode, SymmetryMode::DiagonalBackward(9));
+    execute_command(&mut app, "symmetry=diag_fwd:0");
+    app.set_symmetry_at_cursor();
+    assert_eq!(app.symmetry_mode, SymmetryMode::DiagonalForward(-5));
+    execute_command(&mut app, "symmetry=vertical:0");
+    app.set_symmetry_at_cursor();
+    assert_eq!(app.symmetry_mode, SymmetryMode::Vertical(7));
+
+    execute_command(&mut app, "symmetry=off");
+    assert_eq!(app.symmetry_mode, SymmetryMode::Off);
+    app.set_symmetry_at_cursor();
+    assert_eq!(app.symmetry_mode, SymmetryMode::Off);
+}
+
+#[test]
+fn palette_entries_can_be_removed_and_reordered() {
+    use consolet::controller::handle_key_event;
+    use consolet::palette::PaletteEntry;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use ratatui::{layout::Rect, style::Color};
+    let colors = |app: &consolet::App| app.color_palette.iter().map(|entry| match entry { PaletteEntry::Color(c) => *c, _ => Color::Reset }).collect::<Vec<_>>();
+    let status = |app: &consolet::App| app.status_message.as_ref().unwrap().0.clone();
+
+    let mut app = test_app(4, 4);
+    app.color_palette = [Color::Rgb(255, 0, 0), Color::Rgb(250, 10, 10), Color::Rgb(0, 255, 0), Color::Rgb(0, 0, 255), Color::Rgb(0, 0, 250)]
+        .into_iter().map(PaletteEntry::Color).collect();
+    app.palette_index = 4;
+
+    execute_command(&mut app, "palette_remove=#ff0000");
+    assert_eq!(status(&app), "Removed palette color 1 (#FF0000).");
+    assert!(app.current_selection == PaletteEntry::Color(Color::Rgb(250, 10, 10)), "the closest color takes over");
+    assert_eq!(app.palette_index, 3, "the highlight stays on the same color");
+    execute_command(&mut app, "palette_remove=#123456");
+    assert_eq!(status(&app), "#123456 is not in the palette.");
+    execute_command(&mut app, "palette_remove 4");
+    assert_eq!(colors(&app), [Color::Rgb(250, 10, 10), Color::Rgb(0, 255, 0), Color::Rgb(0, 0, 255)]);
+    assert_eq!(app.palette_index, 2);
+
+    execute_command(&mut app, "palette_move=1,3");
+    assert_eq!(colors(&app), [Color::Rgb(0, 255, 0), Color::Rgb(0, 0, 255), Color::Rgb(250, 10, 10)]);
+    assert_eq!(app.palette_index, 2);
+    execute_command(&mut app, "palette_move=1,9");
+    assert_eq!(status(&app), "No palette color at position 9.");
+    execute_command(&mut app, "palette_move=1");
+    assert_eq!(status(&app), "Usage: palette_move=<from>,<to>");
+
+    // In the color picker, Shift+arrows carry the highlighted color and Delete removes it.
+    app.last_palette_area = Some(Rect::new(0, 0, 12, 4));
+    app.mode = AppMode::ColorPicker;
+    handle_key_event(&mut app, KeyEvent::new(KeyCode::Left, KeyModifiers::SHIFT)).unwrap();
+    assert_eq!(colors(&app), [Color::Rgb(0, 255, 0), Color::Rgb(250, 10, 10), Color::Rgb(0, 0, 255)]);
+    assert_eq!(app.palette_index, 1);
+    for _ in 0..3 { handle_key_event(&mut app, KeyEvent::new(KeyCode::Delete, KeyModifiers::NONE)).unwrap(); }
+    assert_eq!(colors(&app), [Color::Rgb(0, 255, 0)]);
+    assert_eq!(status(&app), "Cannot remove the last palette color.");
+    assert!(app.current_selection == PaletteEntry::Color(Color::Rgb(0, 255, 0)));
+}
+
+#[test]
+fn palette_sort_and_dedupe_keep_the_highlighted_color() {
+    use consolet::palette::{PaletteEntry, Tool};
+    use ratatui::style::Color;
+    let mut app = test_app(4, 4);
+    app.color_palette = vec![
+        PaletteEntry::Color(Color::Rgb(0, 0, 255)),
+        PaletteEntry::Tool(Tool::Dither),
+        PaletteEntry::Color(Color::Rgb(200, 200, 200)),
+        PaletteEntry::Color(Color::Rgb(255, 0, 0)),
+        PaletteEntry::Color(Color::Rgb(20, 20, 20)),
+        PaletteEntry::Color(Color::Rgb(0, 255, 0)),
+    ];
+    app.palette_index = 3;
+
+    execute_command(&mut app, "palette_sort=hue");
+    assert_eq!(app.color_palette, [
+        PaletteEntry::Color(Color::Rgb(20, 20, 20)),
+        PaletteEntry::Tool(Tool::Dither),
+        PaletteEntry::Color(Color::Rgb(200, 200, 200)),
+        PaletteEntry::Color(Color::Rgb(255, 0, 0)),
+        PaletteEntry::Color(Color::Rgb(0, 255, 0)),
+        PaletteEntry::Color(Color::Rgb(0, 0, 255)),
+    ]);
+    assert_eq!(app.palette_index, 3);
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Sorted 5 colors by hue.");
+    execute_command(&mut app, "palette_sort brightness");
+    assert!(app.color_palette[5] == PaletteEntry::Color(Color::Rgb(200, 200, 200)));
+    assert!(app.color_palette[app.palette_index] == PaletteEntry::Color(Color::Rgb(255, 0, 0)));
+    execute_command(&mut app, "palette_sort=value");
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Usage: palette_sort=<hue|brightness|saturation>");
+
+    app.color_palette = [Color::Rgb(10, 10, 10), Color::Red, Color::Rgb(255, 0, 0), Color::Rgb(12, 10, 10), Color::Rgb(250, 0, 0)]
+        .into_iter().map(PaletteEntry::Color).collect();
+    app.palette_index = 2;
+    execute_command(&mut app, "palette_dedupe");
+    assert_eq!(app.color_palette.len(), 4, "named and RGB red are the same color");
+    assert_eq!(app.palette_index, 1, "the highlight moves to the copy that was kept");
+    execute_command(&mut app, "palette_dedupe 5");
+    assert_eq!(app.color_palette, [PaletteEntry::Color(Color::Rgb(10, 10, 10)), PaletteEntry::Color(Color::Red)]);
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Removed 2 duplicate colors.");
+    execute_command(&mut app, "palette_dedupe -1");
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Usage: palette_dedupe [tolerance]");
+}
+
+#[test]
+fn built_in_palettes_are_offered_and_palette_files_override_them() {
+    // test_app points the data directory at the scratch one; palette files are read
+    // when an App is created, so the second one sees the override.
+    test_app(1, 1);
+    let palettes_dir = consolet::utils::get_or_create_app_dir().unwrap().join("palettes");
+    std::fs::write(palettes_dir.join("atari.consolet"), "[[1,2,3],[4,5,6]]").unwrap();
+
+    let mut app = test_app(4, 4);
+    assert!(app.get_suggestions("colorpalette:").iter().any(|name| name == "xterm256"));
+    assert_eq!(app.get_suggestions("colorpalette:web"), vec!["websafe", "websafe_2"]);
+
+    execute_command(&mut app, "colorpalette:xterm256 --force");
+    assert_eq!(app.active_palette_name, "xterm256");
+    assert_eq!(app.color_palette.len(), 256);
+
+    execute_command(&mut app, "colorpalette:atari --force");
+    assert_eq!(app.color_palette.len(), 2, "the palette file wins over the built-in");
+
+    execute_command(&mut app, "colorpalette --list");
+    assert!(app.mode == AppMode::InfoPopup);
+    let (title, lines) = app.info_popup.as_ref().unwrap();
+    assert_eq!(title, " Palettes ");
+    assert!(lines.iter().any(|line| line.starts_with(">") && line.contains("atari") && line.ends_with(" 2")), "{:?}", lines);
+    assert!(lines.iter().any(|line| line.contains("xterm256") && line.ends_with(" 256")), "{:?}", lines);
+}
+
+#[test]
+fn palette_save_waits_for_the_switch_to_be_confirmed() {
+    use consolet::controller::handle_key_event;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    let mut app = test_app(4, 4);
+    // A typed color joins the palette, and xterm256 doesn't have it.
+    execute_command(&mut app, "rgb(12,34,56)");
+    app.apply_brush(0, 0);
+    let default = app.default_palette_name.clone();
+
+    execute_command(&mut app, "colorpalette:xterm256 --save");
+    assert!(app.mode == AppMode::ConfirmPaletteSwitch);
+    handle_key_event(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)).unwrap();
+    assert_eq!(app.default_palette_name, default, "cancelling keeps the old default");
+    assert_ne!(app.status_message.as_ref().unwrap().0, "Configuration saved.");
+
+    execute_command(&mut app, "colorpalette:xterm256 --save");
+    handle_key_event(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)).unwrap();
+    assert_eq!(app.active_palette_name, "xterm256");
+    assert_eq!(app.default_palette_name, "xterm256");
+}
+
+#[test]
+fn chained_commands_run_in_order_and_stop_at_the_first_failure() {
+    use consolet::controller::handle_key_event;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    assert_eq!(consolet::utils::split_commands(r#"clear; layer rename "a;b" ;; flip\;x ; "#), vec!["clear", r#"layer rename "a;b""#, "flip;x"]);
+
+    let mut app = test_app(8, 8);
+    app.apply_brush(1, 1);
+    let line = "clear; colorpalette:ansi --force; resize 16x12";
+    handle_key_event(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)).unwrap();
+    assert!(app.mode == AppMode::Command);
+    app.input_buffer = line.to_string();
+    handle_key_event(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)).unwrap();
+    assert!(painted(&app).is_empty());
+    assert_eq!(app.active_palette_name, "ansi");
+    assert_eq!((app.canvas_width, app.canvas_height), (16, 12));
+    assert_eq!(app.command_history, vec![line.to_string()], "the chain is one history entry");
+
+    assert!(!execute_command(&mut app, "no_such_command"));
+    assert!(!consolet::execute_command_line(&mut app, "checker_size=4; checker_size=99; resize 20x20"));
+    assert_eq!(app.checker_size, 4);
+    assert_eq!((app.canvas_width, app.canvas_height), (16, 12), "commands after the failure are skipped");
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Command 2 of 3 (checker_size=99) failed: Value out of range (1-16).");
+
+    // A single command keeps its own message.
+    assert!(!consolet::execute_command_line(&mut app, "palette_sort=rainbow"));
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Usage: palette_sort=<hue|brightness|saturation>");
+}
+
+#[test]
+fn handler_failures_stop_a_command_chain() {
+    let mut app = test_app(8, 8);
+    app.set_status("Ready.");
+    assert!(!execute_command(&mut app, "tile paste"));
+    assert_eq!(app.status_message.as_ref().unwrap().0, "No tile copied. Use 'tile copy' first.");
+    let logged: Vec<&str> = app.message_log.iter().rev().take(2).map(|(_, message)| message.as_str()).collect();
+    assert_eq!(logged, ["No tile copied. Use 'tile copy' first.", "Ready."], "the failure is logged once");
+
+    std::fs::create_dir_all(common::scratch_dir()).unwrap();
+    let not_a_dir = common::scratch_dir().join("chain_not_a_dir");
+    std::fs::write(&not_a_dir, "").unwrap();
+
+    let failing = [
+        format!("export -o {}", not_a_dir.join("x.png").display()),
+        format!("layer import {}", not_a_dir.join("missing.png").display()),
+        "move_to_layer=Nowhere".to_string(),
+        "tile paste".to_string(),
+        "text=".to_string(),
+    ];
+    for command in failing {
+        app.apply_brush(1, 1);
+        assert!(!consolet::execute_command_line(&mut app, &format!("{}; clear", command)), "{}", command);
+        assert_eq!(painted(&app), vec![(1, 1)], "'{}' failed, so clear must not run", command);
+        assert!(app.status_message.as_ref().unwrap().0.starts_with(&format!("Command 1 of 2 ({}) failed: ", command)));
+    }
+}
+
+#[test]
+fn suggestions_match_fuzzily_and_complete_values_and_paths() {
+    use consolet::controller::handle_key_event;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    let mut app = test_app(8, 8);
+    assert_eq!(app.get_suggestions("exprt").first().map(String::as_str), Some("export"));
+    let (name, matched) = &app.get_suggestions_with_matches("exprt")[0];
+    assert_eq!((name.as_str(), matched.as_slice()), ("export", &[0, 1, 2, 4, 5][..]));
+
+    assert_eq!(app.get_suggestions("penShape="), vec!["circular", "square"]);
+    assert_eq!(app.get_suggestions("palette_sort=br"), vec!["brightness"]);
+    assert!(app.get_suggestions("stabilizer=").is_empty(), "ranges have no values to list");
+    assert_eq!(app.complete_with("penShape=sq", "square"), "penShape=square");
+
+    let dir = common::scratch_dir().join("suggest");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("sprites")).unwrap();
+    std::fs::write(dir.join("warm.gpl"), "").unwrap();
+    std::fs::write(dir.join(".hidden"), "").unwrap();
+    let base = format!("{}/", dir.display());
+    assert_eq!(app.get_suggestions(&format!("import palette {}", base)), vec![format!("{}sprites/", base), format!("{}warm.gpl", base)]);
+    assert_eq!(app.get_suggestions(&format!("export --scale 2 -o {}wrm", base)), vec![format!("{}warm.gpl", base)]);
+    assert!(app.get_suggestions(&format!("import palette {}.", base)).contains(&format!("{}.hidden", base)), "typing a dot shows hidden files");
+
+    handle_key_event(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)).unwrap();
+    app.input_buffer = format!("import palette {}wa", base);
+    app.suggestion_active = true;
+    app.suggestion_index = 0;
+    handle_key_event(&mut app, KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE)).unwrap();
+    assert_eq!(app.input_buffer, format!("import palette {}warm.gpl", base));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn resize_takes_a_size_directly_and_setters_take_relative_steps() {
+    let mut app = test_app(8, 8);
+    assert!(execute_command(&mut app, "resize 20x12"));
+    assert_eq!((app.canvas_width, app.canvas_height), (20, 12));
+    assert!(execute_command(&mut app, "resize 32"));
+    assert_eq!((app.canvas_width, app.canvas_height), (32, 32));
+    assert!(!execute_command(&mut app, "resize 2000x10"));
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Canvas size must be 1-1024 on each side.");
+    assert!(!execute_command(&mut app, "resize 0"));
+    assert!(!execute_command(&mut app, "resize big"));
+    assert!(app.status_message.as_ref().unwrap().0.starts_with("Usage: resize {<W>x<H>|<size>}"));
+    assert_eq!((app.canvas_width, app.canvas_height), (32, 32));
+
+    execute_command(&mut app, "spraySize=10");
+    assert!(execute_command(&mut app, "spraySize=+2"));
+    assert_eq!(app.spray_size, 12);
+    execute_command(&mut app, "spraySize=-100");
+    assert_eq!(app.spray_size, 1, "relative steps clamp to the setter's range");
+
+    execute_command(&mut app, "layer_opacity=0.5");
+    assert!(execute_command(&mut app, "layer_opacity=-0.1"));
+    assert_eq!(app.layers[app.active_layer_index].opacity, 0.4);
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Set layer_opacity to 0.4");
+
+    assert!(!execute_command(&mut app, "spraySize=+x"));
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Invalid value. Usage: spraySize={1-50} (or spraySize=+N / spraySize=-N to adjust it)");
+
+    // Every numeric setter knows its current value, so none rejects a step of zero.
+    for cmd in consolet::commands::COMMANDS.iter() {
+        if matches!(cmd.command_type, consolet::commands::CommandType::SetterU16(..) | consolet::commands::CommandType::SetterF32(..)) {
+            assert!(execute_command(&mut app, &format!("{}=+0", cmd.name)), "{} can't be adjusted", cmd.name);
+        }
+    }
+}
+
+#[test]
+fn colors_can_be_typed_as_rgb_short_hex_or_names() {
+    use consolet::palette::PaletteEntry;
+    use consolet::App;
+    use ratatui::style::Color;
+
+    assert_eq!(App::parse_color("#f80"), Ok(Color::Rgb(255, 136, 0)));
+    assert_eq!(App::parse_color("rgb(255, 128, 0)"), Ok(Color::Rgb(255, 128, 0)));
+    assert_eq!(App::parse_color("Light-Blue"), Ok(Color::LightBlue));
+    assert_eq!(App::parse_color("rgb(300,0,0)"), Err("Color component 300 is out of range (0-255).".to_string()));
+    assert!(App::parse_color("rgb(1,2)").unwrap_err().starts_with("rgb() takes 3 components, got 2."));
+    assert!(App::parse_color("#12").unwrap_err().starts_with("Invalid hex color: #12."));
+    assert!(App::parse_color("fuchsiaish").unwrap_err().starts_with("Unknown color: fuchsiaish."));
+    assert_eq!(App::parse_hex_color("f80"), None, "the short form needs its '#'");
+
+    let mut app = test_app(8, 8);
+    let palette_len = app.color_palette.len();
+    assert!(execute_command(&mut app, "rgb(255, 128, 0)"));
+    assert_eq!(app.current_selection, PaletteEntry::Color(Color::Rgb(255, 128, 0)));
+    assert_eq!(app.color_palette.len(), palette_len + 1);
+    assert!(execute_command(&mut app, "teal"));
+    assert_eq!(app.current_selection, PaletteEntry::Color(Color::Rgb(0, 128, 128)));
+    assert!(execute_command(&mut app, "#0f0"));
+    assert_eq!(app.current_selection, PaletteEntry::Color(Color::Rgb(0, 255, 0)));
+    assert!(!execute_command(&mut app, "rgb(0,256,0)"));
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Color component 256 is out of range (0-255).");
+    assert!(!execute_command(&mut app, "tealish"));
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Unknown command: tealish");
+
+    app.apply_brush(1, 1);
+    assert!(execute_command(&mut app, "replace_color=#0f0,rgb(1,2,3)"));
+    assert_eq!(active_pixel(&app, 1, 1).color, SerializableColor(1, 2, 3));
+    assert!(!execute_command(&mut app, "replace_color=rgb(1,2,3),nope"));
+    assert!(app.status_message.as_ref().unwrap().0.starts_with("Unknown color: nope."));
+
+    assert!(execute_command(&mut app, "checker_light=rgb(200,200,200)"));
+    assert_eq!(app.checker_light, Color::Rgb(200, 200, 200));
+    assert!(!execute_command(&mut app, "checker_light=rgb(999,0,0)"));
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Color component 999 is out of range (0-255).");
+    assert_eq!(app.checker_light, Color::Rgb(200, 200, 200));
+    assert!(!execute_command(&mut app, "checker_dark=#12"));
+    assert!(app.status_message.as_ref().unwrap().0.starts_with("Invalid hex color: #12."));
+
+    assert!(!execute_command(&mut app, "export -o x.png -bgcolor rgb(1,2,300)"));
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Error: Invalid background color 'rgb(1,2,300)'. Color component 300 is out of range (0-255).");
+}
+
+#[test]
+fn status_messages_are_logged_and_the_last_command_can_be_repeated() {
+    use consolet::controller::handle_key_event;
+    use consolet::MESSAGE_LOG_LEN;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    let mut app = test_app(8, 8);
+    let repeat = KeyEvent::new(KeyCode::Char('.'), KeyModifiers::NONE);
+    handle_key_event(&mut app, repeat).unwrap();
+    assert_eq!(app.status_message.as_ref().unwrap().0, "No command to repeat yet.");
+
+    execute_command(&mut app, "spraySize=10");
+    handle_key_event(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)).unwrap();
+    app.input_buffer = "spraySize=+3".to_string();
+    handle_key_event(&mut app, KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)).unwrap();
+    assert_eq!(app.spray_size, 13);
+    handle_key_event(&mut app, repeat).unwrap();
+    handle_key_event(&mut app, repeat).unwrap();
+    assert_eq!(app.spray_size, 19);
+    assert!(app.mode == AppMode::Drawing, "repeating doesn't open the prompt");
+
+    let logged: Vec<&str> = app.message_log.iter().map(|(_, message)| message.as_str()).collect();
+    assert_eq!(logged, ["No command to repeat yet.", "Set spraySize to 10", "Set spraySize to 13", "Set spraySize to 16", "Set spraySize to 19"]);
+
+    for i in 0..MESSAGE_LOG_LEN {
+        app.set_status(format!("message {}", i));
+    }
+    assert_eq!(app.message_log.len(), MESSAGE_LOG_LEN);
+    assert_eq!(app.message_log.front().unwrap().1, "message 0");
+
+    execute_command(&mut app, "messages");
+    assert!(app.mode == AppMode::InfoPopup);
+    let (title, lines) = app.info_popup.as_ref().unwrap();
+    assert_eq!(title, " Messages ");
+    assert!(lines[0].ends_with(&format!("  message {}", MESSAGE_LOG_LEN - 1)), "newest first");
+}
+
+#[test]
+fn stats_count_layer_pixels_colors_and_off_palette_colors() {
+    let mut app = test_app(8, 4);
+    app.color_palette = vec![consolet::palette::PaletteEntry::Color(ratatui::style::Color::Rgb(255, 0, 0))];
+    app.apply_brush(0, 0);
+    app.apply_brush(1, 0);
+    execute_command(&mut app, "add_layer");
+    select_color(&mut app, 0, 0, 255);
+    app.apply_brush(2, 2);
+    execute_command(&mut app, "add_layer");
+    select_color(&mut app, 0, 255, 0);
+    app.apply_brush(3, 3);
+    execute_command(&mut app, "layer 1 visible=false");
+
+    execute_command(&mut app, "stats");
+    assert!(app.mode == AppMode::InfoPopup);
+    let (title, lines) = app.info_popup.clone().unwrap();
+    assert_eq!(title, " Canvas Stats ");
+    assert_eq!(lines[0], "Canvas     8x4 (32 pixels)");
+    assert_eq!(lines[1], "Layers     3");
+    assert!(lines[2].ends_with("      1 px (hidden)"), "{}", lines[2]);
+    assert!(lines[4].ends_with("      2 px"), "{}", lines[4]);
+    assert_eq!(lines[5], "Colors     2 distinct on visible layers (2 without alpha)");
+    assert_eq!(lines[6], "Palette    1 color not in the palette:");
+    assert_eq!(lines[7], "  #0000FF");
+    assert!(lines[8].starts_with("File size  about "), "{}", lines[8]);
+}
+
+#[test]
+fn script_repeat_blocks_offset_coordinates_and_vars_are_substituted() {
+    let _script_file = SCRIPT_FILE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut app = test_app(16, 8);
+    let script = r##"{
+        "vars": { "post": "#8B4513", "top": 1 },
+        "commands": [
+            { "repeat": 3, "dx": 4, "commands": ["apply_color:$post 0,$top-0,2"] },
+            { "symmetry": { "mode": "vertical", "coordinate": 7 }, "commands": [
+                { "repeat": 2, "dy": 2, "commands": ["apply_color:teal 1,4"] }
+            ] }
+        ]
+    }"##;
+    let script_path = consolet::script_handler::get_script_path().unwrap();
+    std::fs::write(&script_path, script).unwrap();
+    execute_command(&mut app, "draw_script");
+
+    let brown = SerializableColor(0x8B, 0x45, 0x13);
+    for x in [0, 4, 8] {
+        for y in 1..=2 {
+            assert_eq!(active_pixel(&app, x, y).color, brown, "post at {},{}", x, y);
+        }
+    }
+    assert_eq!(active_pixel(&app, 12, 1).alpha, 0.0);
+    let teal = SerializableColor(0, 128, 128);
+    for (x, y) in [(1, 4), (1, 6), (12, 4), (12, 6)] {
+        assert_eq!(active_pixel(&app, x, y).color, teal, "mirrored repeat at {},{}", x, y);
+    }
+
+    std::fs::write(&script_path, r#"["erase 0,0", {"symmetry": {"mode": "vertical", "coordinate": 3}, "commands": ["erase 1,1", {"repeat": "x", "commands": []}]}]"#).unwrap();
+    assert!(execute_command(&mut app, "draw_script"));
+    assert!(app.status_message.as_ref().unwrap().0.starts_with("Script block 2.2: invalid type"), "{}", app.status_message.as_ref().unwrap().0);
+    std::fs::write(&script_path, r#"{"commands": ["apply_color:$nope 0,0"]}"#).unwrap();
+    execute_command(&mut app, "draw_script");
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Script block 1: unknown variable $nope");
+    assert_eq!(active_pixel(&app, 0, 1).color, brown, "nothing runs when the script has an error");
+}
+
+#[test]
+fn script_shapes_and_brush_settings_apply_until_the_script_ends() {
+    let _script_file = SCRIPT_FILE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut app = test_app(16, 16);
+    execute_command(&mut app, "add_layer");
+    execute_command(&mut app, "rename_layer=Ink");
+    execute_command(&mut app, "layer 2 active");
+    app.pen_size = 5;
+    let script = r##"[
+        "line:#FF0000 0,0-3,3",
+        "rect:#00FF00 5,0-8,2",
+        "rect:#0000FF 10,0-12,2 filled",
+        "layer Ink",
+        "opacity 0.5",
+        "circle:#FFFFFF 8,10 3",
+        "circle:#FFFFFF 2,12 1 filled",
+        "pen_size 2",
+        "apply_color:#FF00FF 14,14"
+    ]"##;
+    std::fs::write(consolet::script_handler::get_script_path().unwrap(), script).unwrap();
+    let before: Vec<_> = app.layers.iter().map(|layer| layer.canvas.clone()).collect();
+    execute_command(&mut app, "draw_script");
+
+    let base = &app.layers[1].canvas;
+    for i in 0..4 {
+        assert_eq!(base[i][i].color, SerializableColor(255, 0, 0));
+    }
+    assert_eq!(base[0][6].color, SerializableColor(0, 255, 0));
+    assert_eq!(base[1][6].alpha, 0.0, "rectangles are outlines unless filled");
+    assert_eq!(base[1][11].color, SerializableColor(0, 0, 255));
+    assert_eq!(base[10][8].alpha, 0.0, "shapes after `layer Ink` go on that layer");
+
+    let ink = &app.layers[0].canvas;
+    assert_eq!(ink[10][5].color, SerializableColor(255, 255, 255));
+    assert_eq!(ink[10][5].alpha, 0.5);
+    assert_eq!(ink[10][8].alpha, 0.0, "the circle is an outline");
+    assert_eq!(ink[12][2].alpha, 0.5, "a filled circle covers its center");
+    let dab = (13..16).flat_map(|y| (13..16).map(move |x| (x, y))).filter(|&(x, y)| ink[y][x].alpha > 0.0).count();
+    assert!(dab > 1, "pen_size 2 stamps more than one pixel");
+
+    assert_eq!(app.active_layer_index, 1);
+    assert_eq!((app.pen_size, app.opacity), (5, 1.0));
+    assert!(app.status_message.as_ref().unwrap().0.starts_with("Script executed."));
+
+    // One undo takes back what the script drew on every layer.
+    app.undo();
+    assert!(app.layers.iter().map(|layer| &layer.canvas).eq(before.iter()));
+}
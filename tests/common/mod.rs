@@ -0,0 +1,70 @@
+// Shared helpers for the integration tests.
+#![allow(dead_code)]
+
+use consolet::palette::PaletteEntry;
+use consolet::{App, Canvas, Pixel};
+use ratatui::style::Color;
+use std::path::PathBuf;
+use std::sync::Once;
+
+/// Per-test-binary scratch directory under target/tmp.
+pub fn scratch_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(env!("CARGO_CRATE_NAME"))
+}
+
+/// Points the app data directory (palettes, keybindings, config, scripts) at the
+/// scratch directory so tests never read or write the real user profile.
+fn isolate_data_dir() {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| {
+        let dir = scratch_dir();
+        std::fs::create_dir_all(&dir).unwrap();
+        std::env::set_var("XDG_DATA_HOME", dir.join("data"));
+        std::env::set_var("HOME", &dir);
+    });
+}
+
+/// A fresh App with a transparent `width`x`height` canvas and a solid color selected.
+pub fn test_app(width: usize, height: usize) -> App {
+    isolate_data_dir();
+    let mut app = App::new();
+    app.resize_canvas(width, height);
+    app.pen_size = 1;
+    app.opacity = 1.0;
+    // Stroke protection only makes sense between mouse-down and mouse-up.
+    app.protect_stroke = false;
+    app.current_selection = PaletteEntry::Color(Color::Rgb(255, 0, 0));
+    app
+}
+
+pub fn select_color(app: &mut App, r: u8, g: u8, b: u8) {
+    app.current_selection = PaletteEntry::Color(Color::Rgb(r, g, b));
+}
+
+pub fn active_pixel(app: &App, x: usize, y: usize) -> Pixel {
+    app.layers[app.active_layer_index].canvas[y][x]
+}
+
+/// Coordinates of every non-transparent pixel on the active layer.
+pub fn painted(app: &App) -> Vec<(usize, usize)> {
+    let canvas = &app.layers[app.active_layer_index].canvas;
+    let mut points = Vec::new();
+    for (y, row) in canvas.rows().enumerate() {
+        for (x, pixel) in row.iter().enumerate() {
+            if pixel.alpha > 0.0 {
+                points.push((x, y));
+            }
+        }
+    }
+    points
+}
+
+pub fn assert_canvas_eq(actual: &Canvas, expected: &Canvas) {
+    assert_eq!(actual.size(), expected.size(), "canvas sizes differ");
+    for (y, (a_row, e_row)) in actual.rows().zip(expected.rows()).enumerate() {
+        for (x, (a, e)) in a_row.iter().zip(e_row).enumerate() {
+            assert_eq!(a.color, e.color, "color differs at ({}, {})", x, y);
+            assert!((a.alpha - e.alpha).abs() < 1e-6, "alpha differs at ({}, {}): {} vs {}", x, y, a.alpha, e.alpha);
+        }
+    }
+}
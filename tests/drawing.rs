@@ -0,0 +1,950 @@
+mod common;
+
+use common::{active_pixel, painted, select_color, test_app};
+use consolet::palette::{PaletteEntry, Tool};
+use consolet::{DitherPattern, FillMode};
+use consolet::utils::{bresenham_line, ellipse_points};
+
+#[test]
+fn antialiased_45_degree_line_is_fully_opaque() {
+    let mut app = test_app(8, 8);
+    app.antialias = true;
+    app.draw_shape(Tool::Line, (0, 0), (5, 5));
+    assert_eq!(painted(&app), (0..=5).map(|i| (i, i)).collect::<Vec<_>>());
+    assert!((0..=5).all(|i| active_pixel(&app, i, i).alpha == 1.0));
+}
+
+#[test]
+fn antialiased_shallow_line_ramps_alpha() {
+    let mut app = test_app(12, 6);
+    app.antialias = true;
+    app.draw_shape(Tool::Line, (0, 0), (10, 3));
+    assert_eq!(active_pixel(&app, 0, 0).alpha, 1.0);
+    assert_eq!(active_pixel(&app, 10, 3).alpha, 1.0);
+    let canvas = &app.layers[0].canvas;
+    assert!(canvas.pixels().iter().any(|p| p.alpha > 0.0 && p.alpha < 1.0));
+    for x in 1..10 {
+        let total: f32 = (0..6).map(|y| canvas[y][x].alpha).sum();
+        assert!((total - 1.0).abs() < 1e-4, "column {} alpha sums to {}", x, total);
+    }
+
+    // Off by default: the same line is hard-edged.
+    let mut crisp = test_app(12, 6);
+    crisp.draw_shape(Tool::Line, (0, 0), (10, 3));
+    assert!(crisp.layers[0].canvas.pixels().iter().all(|p| p.alpha == 0.0 || p.alpha == 1.0));
+}
+
+#[test]
+fn antialiased_lines_snap_the_color_but_keep_the_coverage_alpha() {
+    let mut smooth = test_app(12, 6);
+    smooth.antialias = true;
+    select_color(&mut smooth, 250, 10, 130);
+    smooth.draw_shape(Tool::Line, (0, 0), (10, 3));
+
+    let mut snapped = test_app(12, 6);
+    snapped.antialias = true;
+    snapped.snap_to_palette = true;
+    select_color(&mut snapped, 250, 10, 130);
+    snapped.draw_shape(Tool::Line, (0, 0), (10, 3));
+
+    let palette: Vec<consolet::SerializableColor> = snapped.color_palette.iter()
+        .filter_map(|entry| if let PaletteEntry::Color(c) = entry { Some((*c).into()) } else { None })
+        .collect();
+    // Partly covered edge pixels are blended, then snapped.
+    let mut edges = 0;
+    for (a, b) in smooth.layers[0].canvas.pixels().iter().zip(snapped.layers[0].canvas.pixels()) {
+        assert_eq!(a.alpha, b.alpha);
+        if b.alpha > 0.0 && b.alpha < 1.0 {
+            assert!(palette.contains(&b.color), "{:?} is not in the palette", b.color);
+            assert_ne!(a.color, b.color);
+            edges += 1;
+        }
+    }
+    assert!(edges > 0);
+}
+
+#[test]
+fn antialiased_brush_has_soft_edges() {
+    let mut app = test_app(16, 16);
+    app.antialias = true;
+    app.pen_size = 7;
+    app.apply_brush(8, 8);
+
+    assert_eq!(active_pixel(&app, 8, 8).alpha, 1.0);
+    let canvas = &app.layers[app.active_layer_index].canvas;
+    assert!(canvas.pixels().iter().any(|p| p.alpha > 0.0 && p.alpha < 1.0));
+}
+
+// A 2x2 brush: full, half and no strength, with alpha scaling the gray.
+fn write_test_brush(name: &str) -> std::path::PathBuf {
+    std::fs::create_dir_all(common::scratch_dir()).unwrap();
+    let path = common::scratch_dir().join(name);
+    let img = image::GrayAlphaImage::from_raw(2, 2, vec![255, 255, 255, 128, 128, 255, 0, 255]).unwrap();
+    img.save(&path).unwrap();
+    path
+}
+
+#[test]
+fn custom_brushes_scale_their_mask_by_opacity_and_pen_size() {
+    let path = write_test_brush("brush_mask.png");
+    let mut app = test_app(10, 10);
+    app.opacity = 0.5;
+    app.pen_size = 2;
+    assert!(consolet::execute_command(&mut app, &format!("brush load {}", path.display())));
+    assert_eq!(app.brush_path.as_deref(), Some(std::fs::canonicalize(&path).unwrap().to_str().unwrap()));
+    app.apply_brush(4, 4);
+    let half = 0.5 * 128.0 / 255.0;
+    assert_eq!(active_pixel(&app, 3, 3).alpha, 0.5);
+    assert!((active_pixel(&app, 4, 3).alpha - half).abs() < 1e-6);
+    assert!((active_pixel(&app, 3, 4).alpha - half).abs() < 1e-6);
+    assert_eq!(active_pixel(&app, 4, 4).alpha, 0.0, "black leaves the canvas alone");
+    assert_eq!(painted(&app).len(), 3);
+
+    // Scaled up with nearest neighbour, each mask pixel covers 2x2 cells at pen_size 4.
+    app.layers[0].canvas = consolet::Canvas::new(10, 10);
+    app.pen_size = 4;
+    app.apply_brush(4, 4);
+    for (x, y) in [(2, 2), (3, 3)] {
+        assert_eq!(active_pixel(&app, x, y).alpha, 0.5);
+    }
+    for (x, y) in [(4, 2), (5, 3), (2, 4), (3, 5)] {
+        assert!((active_pixel(&app, x, y).alpha - half).abs() < 1e-6, "({}, {})", x, y);
+    }
+    assert_eq!(painted(&app).len(), 12);
+
+    assert!(consolet::execute_command(&mut app, "brush clear"));
+    assert!(app.brush_mask.is_none() && app.brush_path.is_none());
+    app.layers[0].canvas = consolet::Canvas::new(10, 10);
+    app.pen_size = 1;
+    app.apply_brush(4, 4);
+    assert_eq!(painted(&app), vec![(4, 4)]);
+
+    assert!(!consolet::execute_command(&mut app, "brush load missing_brush.png"));
+    assert!(app.status_message.as_ref().unwrap().0.starts_with("Error loading brush:"));
+}
+
+#[test]
+fn flat_mode_never_writes_partial_alpha() {
+    let mut app = test_app(16, 16);
+    app.flat_mode = true;
+    app.antialias = true;
+    app.pen_size = 7;
+    app.opacity = 0.4;
+    app.apply_brush(5, 5);
+    app.apply_brush(7, 6);
+    app.cursor_pos = (15, 15);
+    app.fill_area();
+    app.current_selection = PaletteEntry::Tool(Tool::Blur);
+    app.apply_brush(6, 6);
+
+    let canvas = &app.layers[app.active_layer_index].canvas;
+    assert!(canvas.pixels().iter().all(|p| p.alpha == 0.0 || p.alpha == 1.0));
+}
+
+#[test]
+fn pixel_block_edits_stay_block_aligned_at_canvas_edges() {
+    // 10 is not a multiple of 4, so the last blocks are clipped.
+    let mut app = test_app(10, 10);
+    app.pixel_block = 4;
+
+    app.apply_brush(9, 9);
+    assert_eq!(painted(&app), vec![(8, 8), (9, 8), (8, 9), (9, 9)]);
+
+    app.erase_brush(9, 9);
+    app.apply_brush(0, 9);
+    assert_eq!(painted(&app), vec![(0, 8), (1, 8), (2, 8), (3, 8), (0, 9), (1, 9), (2, 9), (3, 9)]);
+
+    app.cursor_pos = (3, 3);
+    app.move_cursor(1, 1);
+    assert_eq!(app.cursor_pos, (4, 4));
+    app.move_cursor(1, 1);
+    app.move_cursor(1, 1);
+    assert_eq!(app.cursor_pos, (8, 8), "cursor must stay on a block origin at the edge");
+}
+
+#[test]
+fn pixel_block_fill_treats_blocks_as_units() {
+    let mut app = test_app(10, 10);
+    app.pixel_block = 2;
+    // A wall of blocks at x = 4..6 splits the canvas.
+    for y in (0..10).step_by(2) {
+        app.apply_brush(4, y);
+    }
+    app.fill_from_point(1, 1, ratatui::style::Color::Rgb(0, 255, 0), 1.0);
+
+    let filled: Vec<_> = painted(&app).into_iter().filter(|&(x, y)| active_pixel(&app, x, y).color.1 == 255).collect();
+    assert_eq!(filled.len(), 4 * 10);
+    assert!(filled.iter().all(|&(x, _)| x < 4));
+}
+
+/// Variance of the turning angle between consecutive segments of a path.
+fn curvature_variance(points: &[(u16, u16)]) -> f64 {
+    let mut points: Vec<(f64, f64)> = points.iter().map(|&(x, y)| (x as f64, y as f64)).collect();
+    points.dedup();
+    let angles: Vec<f64> = points.windows(3).map(|w| {
+        let a = (w[1].1 - w[0].1).atan2(w[1].0 - w[0].0);
+        let b = (w[2].1 - w[1].1).atan2(w[2].0 - w[1].0);
+        let mut turn = b - a;
+        while turn > std::f64::consts::PI { turn -= 2.0 * std::f64::consts::PI; }
+        while turn < -std::f64::consts::PI { turn += 2.0 * std::f64::consts::PI; }
+        turn
+    }).collect();
+    let mean = angles.iter().sum::<f64>() / angles.len() as f64;
+    angles.iter().map(|a| (a - mean).powi(2)).sum::<f64>() / angles.len() as f64
+}
+
+fn stroke(app: &mut consolet::App, raw: &[(u16, u16)]) -> Vec<(u16, u16)> {
+    app.begin_stabilized_stroke(raw[0].0, raw[0].1);
+    let mut path = vec![raw[0]];
+    for &(x, y) in &raw[1..] {
+        path.push(app.stabilized_point(x, y));
+    }
+    path.extend(app.flush_stabilizer());
+    path
+}
+
+#[test]
+fn stabilizer_smooths_zigzag_and_reaches_the_end() {
+    let raw: Vec<(u16, u16)> = (0..40).map(|i| (10 + i, if i % 2 == 0 { 20 } else { 23 })).collect();
+    let mut app = test_app(64, 64);
+
+    app.stabilizer = 0;
+    let unsmoothed = stroke(&mut app, &raw);
+    app.stabilizer = 5;
+    let smoothed = stroke(&mut app, &raw);
+
+    assert_eq!(unsmoothed, raw);
+    assert_eq!(smoothed.last(), raw.last(), "flushing must finish the stroke at the release point");
+    assert!(curvature_variance(&smoothed) < curvature_variance(&unsmoothed));
+}
+
+#[test]
+fn fast_drags_paint_a_gapless_line_once_per_pixel() {
+    let mut app = test_app(24, 16);
+    app.opacity = 0.5;
+    app.protect_stroke = true;
+    app.apply_brush(2, 3);
+    app.last_drag_point = Some((2, 3));
+    app.drag_brush_to(9, 5, false);
+    app.drag_brush_to(20, 12, false);
+
+    let mut expected: Vec<(usize, usize)> = bresenham_line(2, 3, 9, 5).into_iter()
+        .chain(bresenham_line(9, 5, 20, 12))
+        .map(|(x, y)| (x as usize, y as usize))
+        .collect();
+    expected.sort_by_key(|&(x, y)| (y, x));
+    expected.dedup();
+    assert_eq!(painted(&app), expected);
+    assert!(expected.iter().all(|&(x, y)| (active_pixel(&app, x, y).alpha - 0.5).abs() < 1e-6),
+        "protect_stroke keeps the shared endpoints from being painted twice");
+
+    app.opacity = 1.0;
+    app.drawn_pixels_in_stroke.clear();
+    app.last_drag_point = Some((2, 3));
+    app.drag_brush_to(20, 12, true);
+    // The press already handled the start point, so only the rest of the line is erased.
+    let erased: Vec<_> = bresenham_line(2, 3, 20, 12).into_iter().skip(1).map(|(x, y)| (x as usize, y as usize)).collect();
+    assert!(erased.iter().all(|&(x, y)| active_pixel(&app, x, y).alpha == 0.0));
+    assert!(active_pixel(&app, 2, 3).alpha > 0.0);
+}
+
+#[test]
+fn bresenham_line_is_gapless_and_inclusive() {
+    let points = bresenham_line(7, 1, 0, 4);
+    assert_eq!(points.first(), Some(&(7, 1)));
+    assert_eq!(points.last(), Some(&(0, 4)));
+    assert_eq!(points.len(), 8);
+    for pair in points.windows(2) {
+        let (dx, dy) = (pair[1].0 - pair[0].0, pair[1].1 - pair[0].1);
+        assert!(dx.abs() <= 1 && dy.abs() <= 1 && (dx, dy) != (0, 0));
+    }
+}
+
+#[test]
+fn line_action_anchors_then_draws_one_undo_step() {
+    let mut app = test_app(12, 12);
+    app.symmetry_mode = consolet::SymmetryMode::Vertical(6);
+    app.cursor_pos = (1, 2);
+    app.anchor_or_draw_shape();
+    assert!(painted(&app).is_empty());
+    assert_eq!(app.shape_anchor, Some((1, 2)));
+
+    app.cursor_pos = (4, 8);
+    app.anchor_or_draw_shape();
+    assert_eq!(app.shape_anchor, None);
+    let pixels = painted(&app);
+    assert_eq!(pixels.len(), 14, "7 line pixels plus their mirror");
+    assert!(pixels.contains(&(1, 2)) && pixels.contains(&(4, 8)));
+    assert!(pixels.contains(&(10, 2)) && pixels.contains(&(7, 8)));
+
+    app.undo();
+    assert!(painted(&app).is_empty());
+}
+
+#[test]
+fn antialiased_line_action_mirrors_its_edge_alpha() {
+    let mut app = test_app(12, 12);
+    app.antialias = true;
+    app.symmetry_mode = consolet::SymmetryMode::Vertical(6);
+    app.cursor_pos = (0, 1);
+    app.anchor_or_draw_shape();
+    app.cursor_pos = (4, 9);
+    app.anchor_or_draw_shape();
+
+    let pixels = painted(&app);
+    assert!(pixels.iter().any(|&(x, y)| active_pixel(&app, x, y).alpha < 1.0), "the edges are soft");
+    for &(x, y) in &pixels {
+        assert_eq!(active_pixel(&app, x, y).alpha, active_pixel(&app, 11 - x, y).alpha, "({}, {})", x, y);
+    }
+    app.undo();
+    assert!(painted(&app).is_empty());
+}
+
+#[test]
+fn rectangle_outline_and_fill_are_single_undo_steps() {
+    let mut app = test_app(10, 10);
+    app.draw_shape(Tool::Rectangle, (6, 5), (2, 1));
+    let outline = painted(&app);
+    assert_eq!(outline.len(), 16);
+    assert!(!outline.contains(&(4, 3)));
+
+    app.fill_shapes = true;
+    app.draw_shape(Tool::Rectangle, (2, 1), (6, 5));
+    assert_eq!(painted(&app).len(), 25);
+
+    app.undo();
+    assert_eq!(painted(&app), outline);
+    app.undo();
+    assert!(painted(&app).is_empty());
+}
+
+#[test]
+fn rectangle_clips_to_canvas() {
+    let mut app = test_app(8, 8);
+    app.pen_size = 3;
+    app.draw_shape(Tool::Rectangle, (-4, -4), (5, 20));
+
+    let pixels = painted(&app);
+    // The right edge at x = 5 is stamped with a 3px brush; the other edges are off-canvas.
+    assert_eq!(pixels.len(), 3 * 8);
+    assert!(pixels.iter().all(|&(x, _)| (4..=6).contains(&x)));
+}
+
+#[test]
+fn ellipse_outline_is_symmetric_and_touches_its_box() {
+    for (w, h) in [(9, 5), (8, 6), (1, 7), (12, 1)] {
+        let points = ellipse_points(0, 0, w, h, false);
+        for &(x, y) in &points {
+            assert!(points.contains(&(w - x, y)) && points.contains(&(x, h - y)), "{}x{} not symmetric at ({}, {})", w, h, x, y);
+        }
+        assert_eq!(points.iter().map(|p| p.0).min(), Some(0));
+        assert_eq!(points.iter().map(|p| p.0).max(), Some(w));
+        assert_eq!(points.iter().map(|p| p.1).min(), Some(0));
+        assert_eq!(points.iter().map(|p| p.1).max(), Some(h));
+    }
+}
+
+#[test]
+fn filled_ellipse_has_no_holes() {
+    let points = ellipse_points(0, 0, 10, 6, true);
+    for y in 0..=6 {
+        let row: Vec<i32> = points.iter().filter(|p| p.1 == y).map(|p| p.0).collect();
+        let (left, right) = (*row.iter().min().unwrap(), *row.iter().max().unwrap());
+        assert_eq!(row.len() as i32, right - left + 1, "gap in row {}", y);
+    }
+}
+
+#[test]
+fn antialiased_ellipses_have_soft_symmetric_edges() {
+    let mut app = test_app(16, 12);
+    app.antialias = true;
+    app.draw_shape(Tool::Ellipse, (1, 1), (13, 9));
+    let alpha = |app: &consolet::App, x: usize, y: usize| active_pixel(app, x, y).alpha;
+    for (x, y) in [(1, 5), (13, 5), (7, 1), (7, 9)] {
+        assert_eq!(alpha(&app, x, y), 1.0, "the curve touches its box at ({}, {})", x, y);
+    }
+    assert_eq!(alpha(&app, 7, 5), 0.0, "an outline leaves the middle empty");
+    let pixels = painted(&app);
+    assert!(pixels.iter().any(|&(x, y)| alpha(&app, x, y) < 1.0), "the edges are soft");
+    for &(x, y) in &pixels {
+        assert!((1..=13).contains(&x) && (1..=9).contains(&y));
+        assert_eq!(alpha(&app, x, y), alpha(&app, 14 - x, y), "({}, {})", x, y);
+        assert_eq!(alpha(&app, x, y), alpha(&app, x, 10 - y), "({}, {})", x, y);
+    }
+    app.undo();
+    assert!(painted(&app).is_empty());
+
+    app.fill_shapes = true;
+    app.draw_shape(Tool::Ellipse, (1, 1), (13, 9));
+    assert_eq!(alpha(&app, 7, 5), 1.0);
+    assert!((2..13).all(|x| alpha(&app, x, 5) == 1.0), "a filled ellipse is solid inside");
+    assert!(painted(&app).iter().any(|&(x, y)| alpha(&app, x, y) < 1.0));
+}
+
+#[test]
+fn perfect_circles_square_the_box() {
+    let mut app = test_app(20, 20);
+    app.perfect_circles = true;
+    let points = app.shape_points(Tool::Ellipse, (10, 10), (4, 8), false);
+    let xs: Vec<u16> = points.iter().map(|p| p.0).collect();
+    let ys: Vec<u16> = points.iter().map(|p| p.1).collect();
+    assert_eq!((xs.iter().min(), xs.iter().max()), (Some(&4), Some(&10)));
+    assert_eq!((ys.iter().min(), ys.iter().max()), (Some(&4), Some(&10)));
+}
+
+#[test]
+fn ellipse_mirrors_with_vertical_symmetry_in_one_undo_step() {
+    let mut app = test_app(20, 10);
+    app.symmetry_mode = consolet::SymmetryMode::Vertical(10);
+    app.draw_shape(Tool::Ellipse, (1, 1), (6, 8));
+
+    let pixels = painted(&app);
+    let left: Vec<_> = pixels.iter().filter(|p| p.0 < 10).collect();
+    let right: Vec<_> = pixels.iter().filter(|p| p.0 >= 10).collect();
+    assert!(!left.is_empty());
+    assert_eq!(left.len(), right.len());
+    assert!(left.iter().all(|&&(x, y)| pixels.contains(&(19 - x, y))));
+
+    app.undo();
+    assert!(painted(&app).is_empty());
+}
+
+#[test]
+fn dither_is_anchored_to_the_canvas() {
+    let mut app = test_app(12, 12);
+    app.pen_size = 5;
+    app.pen_shape = consolet::PenShape::Square;
+    app.dither_enabled = true;
+    app.apply_brush(4, 4);
+    app.apply_brush(5, 6);
+
+    let pixels = painted(&app);
+    assert!(!pixels.is_empty());
+    assert!(pixels.iter().all(|&(x, y)| (x + y) % 2 == 0), "checker cells must have even x + y");
+
+    app.dither_pattern = DitherPattern::Light;
+    app.apply_brush(9, 9);
+    let light: Vec<_> = painted(&app).into_iter().filter(|&(x, y)| x >= 8 && y >= 8).collect();
+    assert_eq!(light.len(), 4);
+    assert!(light.iter().all(|&(x, y)| x % 2 == 0 && y % 2 == 0));
+}
+
+#[test]
+fn denser_dither_patterns_contain_lighter_ones() {
+    for y in 0..4 {
+        for x in 0..4 {
+            if DitherPattern::Light.covers(x, y) { assert!(DitherPattern::Checker.covers(x, y)); }
+            if DitherPattern::Checker.covers(x, y) { assert!(DitherPattern::Dense.covers(x, y)); }
+        }
+    }
+    let count = |p: DitherPattern| (0..4).flat_map(|y| (0..4).map(move |x| (x, y))).filter(|&(x, y)| p.covers(x, y)).count();
+    assert_eq!((count(DitherPattern::Light), count(DitherPattern::Checker), count(DitherPattern::Dense)), (4, 8, 12));
+}
+
+#[test]
+fn clone_copies_with_a_locked_offset_and_skips_off_canvas_sources() {
+    let mut app = test_app(12, 12);
+    app.apply_brush(1, 1);
+    common::select_color(&mut app, 0, 0, 255);
+    app.apply_brush(2, 1);
+
+    app.cursor_pos = (1, 1);
+    app.set_clone_source();
+    app.current_selection = PaletteEntry::Tool(Tool::Clone);
+    app.pen_size = 3;
+    app.pen_shape = consolet::PenShape::Square;
+    app.apply_brush(8, 8);
+    assert_eq!(app.clone_offset, Some((-7, -7)));
+    assert_eq!(active_pixel(&app, 8, 8), active_pixel(&app, 1, 1));
+    assert_eq!(active_pixel(&app, 9, 8), active_pixel(&app, 2, 1));
+
+    // A later stroke keeps the offset, so this samples around (-2, 3), off the canvas.
+    let before = painted(&app);
+    app.apply_brush(5, 10);
+    assert_eq!(painted(&app), before);
+    assert_eq!(app.clone_offset, Some((-7, -7)));
+}
+
+#[test]
+fn clone_samples_merged_image_when_asked() {
+    let mut app = test_app(8, 8);
+    app.apply_brush(1, 1);
+    app.add_new_layer();
+    app.cursor_pos = (1, 1);
+    app.set_clone_source();
+    app.current_selection = PaletteEntry::Tool(Tool::Clone);
+
+    app.apply_brush(5, 5);
+    assert_eq!(active_pixel(&app, 5, 5).alpha, 0.0, "the new layer is empty at the source");
+
+    app.clone_sample_merged = true;
+    app.apply_brush(5, 5);
+    assert_eq!(active_pixel(&app, 5, 5).alpha, 1.0);
+}
+
+#[test]
+fn global_fill_reaches_disconnected_regions() {
+    let mut app = test_app(10, 10);
+    // A full-height wall at x = 4 splits the transparent canvas in two.
+    for y in 0..10 {
+        app.apply_brush(4, y);
+    }
+    let green = ratatui::style::Color::Rgb(0, 255, 0);
+    app.fill_from_point(1, 1, green, 1.0);
+    assert_eq!(active_pixel(&app, 8, 8).alpha, 0.0, "contiguous fill stops at the wall");
+
+    app.undo();
+    app.fill_mode = FillMode::Global;
+    app.fill_from_point(1, 1, green, 1.0);
+    assert_eq!(active_pixel(&app, 8, 8).color.1, 255);
+    assert_eq!(active_pixel(&app, 4, 4).color.0, 255, "the wall keeps its color");
+}
+
+#[test]
+fn fill_tolerance_absorbs_near_colors_but_keeps_transparency_apart() {
+    let mut app = test_app(6, 1);
+    select_color(&mut app, 100, 100, 100);
+    app.apply_brush(0, 0);
+    app.apply_brush(1, 0);
+    select_color(&mut app, 110, 100, 100);
+    app.apply_brush(2, 0);
+    select_color(&mut app, 200, 100, 100);
+    app.apply_brush(3, 0);
+    // x = 4 and 5 stay transparent.
+    let blue = ratatui::style::Color::Rgb(0, 0, 255);
+
+    app.fill_from_point(0, 0, blue, 1.0);
+    assert_eq!(active_pixel(&app, 2, 0).color.0, 110, "exact matching by default");
+
+    app.undo();
+    app.fill_tolerance = 32;
+    app.fill_from_point(0, 0, blue, 1.0);
+    assert_eq!(active_pixel(&app, 2, 0).color.2, 255);
+    assert_eq!(active_pixel(&app, 3, 0).color.0, 200, "too far away to match");
+
+    app.undo();
+    app.fill_tolerance = 255;
+    app.fill_from_point(0, 0, blue, 1.0);
+    assert_eq!(active_pixel(&app, 3, 0).color.2, 255);
+    assert_eq!(active_pixel(&app, 4, 0).alpha, 0.0, "transparent pixels are their own group");
+}
+
+#[test]
+fn fill_mirrors_across_the_symmetry_line_in_one_undo_step() {
+    let mut app = test_app(10, 4);
+    // Walls at x = 2 and x = 7 leave separate pockets on each side.
+    for y in 0..4 {
+        app.apply_brush(2, y);
+        app.apply_brush(7, y);
+    }
+    let undo_depth = app.undo_stack.len();
+    app.symmetry_mode = consolet::SymmetryMode::Vertical(5);
+    select_color(&mut app, 0, 255, 0);
+    app.cursor_pos = (0, 1);
+    app.fill_area();
+
+    assert_eq!(active_pixel(&app, 0, 0).color.1, 255);
+    assert_eq!(active_pixel(&app, 9, 3).color.1, 255, "the mirrored pocket is filled too");
+    assert_eq!(active_pixel(&app, 4, 0).alpha, 0.0, "the middle is untouched");
+    assert_eq!(app.undo_stack.len(), undo_depth + 1);
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Filled 2 regions (symmetry)");
+
+    app.undo();
+    assert_eq!(active_pixel(&app, 9, 3).alpha, 0.0);
+    assert_eq!(active_pixel(&app, 0, 0).alpha, 0.0);
+}
+
+#[test]
+fn color_jitter_varies_pixels_and_redraws_identically_after_undo() {
+    let mut app = test_app(8, 8);
+    select_color(&mut app, 120, 80, 200);
+    app.color_jitter = 0.5;
+    app.jitter_seed = 42;
+    app.pen_size = 8;
+    app.pen_shape = consolet::PenShape::Square;
+    app.use_current_tool();
+    let first = app.layers[0].canvas.clone();
+    let distinct: std::collections::HashSet<_> = first.pixels().iter().map(|p| (p.color.0, p.color.1, p.color.2)).collect();
+    assert!(distinct.len() > 8, "jitter should texture the stroke");
+
+    app.undo();
+    app.use_current_tool();
+    assert_eq!(app.layers[0].canvas, first);
+
+    app.undo();
+    app.color_jitter = 0.0;
+    app.use_current_tool();
+    assert!(app.layers[0].canvas.pixels().iter().filter(|p| p.alpha > 0.0).all(|p| p.color == consolet::SerializableColor(120, 80, 200)));
+}
+
+#[test]
+fn color_jitter_snaps_to_palette_and_leaves_tools_alone() {
+    let mut app = test_app(6, 6);
+    app.snap_to_palette = true;
+    app.color_jitter = 1.0;
+    app.pen_size = 6;
+    app.pen_shape = consolet::PenShape::Square;
+    app.use_current_tool();
+    let palette: Vec<consolet::SerializableColor> = app.color_palette.iter()
+        .filter_map(|entry| if let PaletteEntry::Color(c) = entry { Some((*c).into()) } else { None })
+        .collect();
+    assert!(app.layers[0].canvas.pixels().iter().filter(|p| p.alpha > 0.0).all(|p| palette.contains(&p.color)));
+
+    app.snap_to_palette = false;
+    select_color(&mut app, 100, 100, 100);
+    app.use_current_tool();
+    app.current_selection = PaletteEntry::Tool(Tool::Darker);
+    let before = active_pixel(&app, 2, 2);
+    app.use_current_tool();
+    let after = active_pixel(&app, 2, 2);
+    assert!(after.color.0 < before.color.0 && after.color.0 == after.color.1 && after.color.1 == after.color.2, "Darker keeps the hue");
+}
+
+#[test]
+fn soft_eraser_fades_once_per_stroke_and_clears_near_zero() {
+    let mut app = test_app(4, 4);
+    app.apply_brush(1, 1);
+    app.soft_eraser = true;
+    app.opacity = 0.5;
+    app.protect_stroke = true;
+
+    app.erase_brush(1, 1);
+    app.erase_brush(1, 1);
+    assert_eq!(active_pixel(&app, 1, 1).alpha, 0.5, "protect_stroke fades each pixel once per stroke");
+
+    app.drawn_pixels_in_stroke.clear();
+    app.erase_brush(1, 1);
+    assert_eq!(active_pixel(&app, 1, 1).alpha, 0.25);
+
+    app.opacity = 0.99;
+    app.drawn_pixels_in_stroke.clear();
+    app.erase_brush(1, 1);
+    assert_eq!(active_pixel(&app, 1, 1), consolet::Pixel::default());
+
+    app.soft_eraser = false;
+    app.opacity = 0.5;
+    app.apply_brush(2, 2);
+    app.drawn_pixels_in_stroke.clear();
+    app.erase_brush(2, 2);
+    assert_eq!(active_pixel(&app, 2, 2).alpha, 0.0);
+}
+
+#[test]
+fn blur_radius_widens_the_window_and_clips_at_edges() {
+    let mut app = test_app(7, 1);
+    app.apply_brush(0, 0);
+    app.current_selection = PaletteEntry::Tool(Tool::Blur);
+
+    assert_eq!(app.calculate_blur_at(2, 0, 1.0).alpha, 0.0, "out of reach at radius 1");
+    app.blur_radius = 2;
+    let blurred = app.calculate_blur_at(2, 0, 1.0);
+    assert!((blurred.alpha - 1.0 / 5.0).abs() < 1e-6, "five pixels in the window");
+
+    // At the edge the window holds only the pixels that exist, not wrapped ones.
+    let edge = app.calculate_blur_at(6, 0, 1.0);
+    assert_eq!(edge.alpha, 0.0);
+    let corner = app.calculate_blur_at(0, 0, 1.0);
+    assert!((corner.alpha - 1.0 / 3.0).abs() < 1e-6);
+}
+
+#[test]
+fn spray_follows_pen_shape_and_works_with_tools() {
+    let mut app = test_app(21, 21);
+    app.cursor_pos = (10, 10);
+    app.spray_size = 10;
+    app.spray_speed = 100;
+    app.spray_intensity = 1.0;
+    app.pen_shape = consolet::PenShape::Circular;
+    for _ in 0..20 {
+        app.apply_spray();
+    }
+    let sprayed = painted(&app);
+    assert!(!sprayed.is_empty());
+    assert!(sprayed.iter().all(|&(x, y)| (x as i32 - 10).pow(2) + (y as i32 - 10).pow(2) <= 25), "all dots inside the circle");
+
+    app.spray_falloff = true;
+    app.current_selection = PaletteEntry::Tool(Tool::Darker);
+    let before = active_pixel(&app, 10, 10);
+    for _ in 0..20 {
+        app.apply_spray();
+    }
+    assert!(active_pixel(&app, 10, 10).color.0 < before.color.0, "the Darker tool sprays too");
+}
+
+#[test]
+fn radial_symmetry_repeats_strokes_around_the_center() {
+    let mut app = test_app(10, 10);
+    app.radial_segments = 4;
+    for _ in 0..6 {
+        app.cycle_symmetry_mode();
+    }
+    assert_eq!(app.symmetry_mode, consolet::SymmetryMode::Radial(4, 5, 5));
+
+    app.apply_brush(7, 5);
+    let mut dots = painted(&app);
+    dots.sort();
+    assert_eq!(dots, vec![(2, 4), (4, 7), (5, 2), (7, 5)]);
+
+    app.erase_brush(4, 7);
+    assert!(painted(&app).is_empty(), "erasing one copy erases them all");
+
+    // Copies that would land off the canvas are skipped.
+    app.symmetry_mode = consolet::SymmetryMode::Radial(4, 8, 5);
+    app.apply_brush(5, 5);
+    assert_eq!(painted(&app).len(), 3);
+
+    app.set_radial_segments(6);
+    assert_eq!(app.symmetry_mode, consolet::SymmetryMode::Radial(6, 8, 5));
+}
+
+#[test]
+fn quad_symmetry_mirrors_across_both_lines() {
+    let mut app = test_app(10, 10);
+    for _ in 0..5 {
+        app.cycle_symmetry_mode();
+    }
+    assert_eq!(app.symmetry_mode, consolet::SymmetryMode::Quad(5, 5));
+
+    // Even dimensions: the lines sit between pixels 4 and 5.
+    app.apply_brush(1, 2);
+    let mut dots = painted(&app);
+    dots.sort();
+    assert_eq!(dots, vec![(1, 2), (1, 7), (8, 2), (8, 7)]);
+
+    app.erase_brush(8, 7);
+    assert!(painted(&app).is_empty());
+
+    // Odd dimensions: the lines run through the middle pixels.
+    let mut app = test_app(9, 7);
+    app.symmetry_mode = consolet::SymmetryMode::Quad(4, 3);
+    app.apply_brush(1, 1);
+    let mut dots = painted(&app);
+    dots.sort();
+    assert_eq!(dots, vec![(1, 1), (1, 5), (7, 1), (7, 5)]);
+}
+
+#[test]
+fn pixel_diff_undo_matches_full_snapshots() {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    let mut rng = StdRng::seed_from_u64(2299);
+    let mut app = test_app(12, 9);
+    // What full-layer snapshots would give: every state, and where undo/redo stand.
+    let mut history = vec![app.layers[0].canvas.clone()];
+    let mut position: usize = 0;
+    let mut clears = 0;
+    for step in 0..300 {
+        match rng.gen_range(0..10) {
+            0..=4 => {
+                select_color(&mut app, rng.gen(), rng.gen(), rng.gen());
+                app.save_state_for_undo("Brush stroke");
+                for _ in 0..rng.gen_range(1..6) {
+                    app.apply_brush(rng.gen_range(0..12), rng.gen_range(0..9));
+                }
+                history.truncate(position + 1);
+                history.push(app.layers[0].canvas.clone());
+                position += 1;
+            }
+            5 => {
+                app.clear_canvas(false);
+                clears += 1;
+                history.truncate(position + 1);
+                history.push(app.layers[0].canvas.clone());
+                position += 1;
+            }
+            6 | 7 => {
+                if !app.undo_stack.is_empty() { position -= 1; }
+                app.undo();
+            }
+            _ => {
+                if !app.redo_stack.is_empty() { position += 1; }
+                app.redo();
+            }
+        }
+        assert!(app.layers[0].canvas == history[position], "step {}", step);
+    }
+    while !app.redo_stack.is_empty() {
+        app.redo();
+        position += 1;
+        assert!(app.layers[0].canvas == history[position]);
+    }
+    // Finished strokes keep only their changed pixels; clears may keep the whole layer.
+    let copies = app.undo_stack.iter().rev().skip(1).filter(|step| matches!(step.entry, consolet::UndoEntry::Pixels(_))).count();
+    assert!(copies <= clears, "{} full copies for {} clears", copies, clears);
+    assert!(app.undo_stack.iter().any(|step| matches!(step.entry, consolet::UndoEntry::PixelDiff(_))));
+    while !app.undo_stack.is_empty() {
+        app.undo();
+        position -= 1;
+        assert!(app.layers[0].canvas == history[position]);
+    }
+}
+
+#[test]
+fn finished_strokes_keep_only_their_changed_pixels() {
+    use consolet::UndoEntry;
+    let changed = |app: &consolet::App| match &app.undo_stack.back().unwrap().entry {
+        UndoEntry::PixelDiff(diffs) => Some(diffs.iter().map(|(_, pixels)| pixels.len()).sum::<usize>()),
+        _ => None,
+    };
+    let mut app = test_app(16, 16);
+    app.cursor_pos = (2, 2);
+    app.use_current_tool();
+    assert_eq!(changed(&app), Some(1));
+    app.draw_shape(Tool::Line, (0, 8), (9, 8));
+    assert_eq!(changed(&app), Some(10));
+    app.cursor_pos = (15, 15);
+    app.fill_area();
+    assert_eq!(changed(&app), None, "a fill over most of the layer keeps the copy");
+
+    // A drag still in progress keeps its full copy until the mouse is released.
+    select_color(&mut app, 10, 20, 30);
+    app.is_mouse_dragging = true;
+    app.save_state_for_undo("Brush stroke");
+    app.apply_brush(0, 0);
+    app.finish_undo_step();
+    assert_eq!(changed(&app), None);
+    app.is_mouse_dragging = false;
+    app.finish_undo_step();
+    assert_eq!(changed(&app), Some(1));
+    app.undo();
+    assert_eq!(active_pixel(&app, 0, 0), active_pixel(&app, 1, 0));
+}
+
+#[test]
+fn undo_history_jumps_back_and_forward_by_label() {
+    let mut app = test_app(8, 8);
+    assert!(app.undo_stack[0].label.ends_with("→8x8"), "{}", app.undo_stack[0].label);
+    let resized = app.layers[0].canvas.clone();
+    app.cursor_pos = (1, 1);
+    app.use_current_tool();
+    let stroked = app.layers[0].canvas.clone();
+    app.cursor_pos = (5, 5);
+    app.fill_area();
+    app.clear_canvas(false);
+    let labels: Vec<&str> = app.undo_history_steps().map(|step| step.label.as_str()).collect();
+    assert_eq!(&labels[1..], ["Brush stroke", "Fill", "Clear layer"]);
+
+    app.show_undo_history();
+    assert_eq!(app.undo_history_selection, 4);
+    app.jump_to_undo_history(2);
+    assert_eq!(app.layers[0].canvas, stroked);
+    assert_eq!(app.undo_stack.len(), 2);
+    assert_eq!(app.redo_stack.len(), 2);
+    // The undone steps stay listed, in the order redo replays them.
+    let labels: Vec<&str> = app.undo_history_steps().map(|step| step.label.as_str()).collect();
+    assert_eq!(&labels[1..], ["Brush stroke", "Fill", "Clear layer"]);
+
+    app.jump_to_undo_history(1);
+    assert_eq!(app.layers[0].canvas, resized);
+    app.jump_to_undo_history(4);
+    assert!(painted(&app).is_empty());
+    assert!(app.redo_stack.is_empty());
+}
+
+#[test]
+fn undo_history_with_nothing_to_undo_keeps_the_canvas() {
+    let mut app = test_app(4, 4);
+    app.undo();
+    assert!(app.undo_stack.is_empty());
+    app.redo_stack.clear();
+    app.show_undo_history();
+    assert_eq!(app.undo_history_selection, 0);
+    assert_eq!(app.undo_history_steps().count(), 0);
+    let size = (app.canvas_width, app.canvas_height);
+    app.jump_to_undo_history(3);
+    assert!(app.mode == consolet::AppMode::Drawing);
+    assert_eq!((app.canvas_width, app.canvas_height), size);
+}
+
+#[test]
+fn brush_strokes_keep_the_composite_in_step_with_a_full_resync() {
+    let mut app = test_app(48, 40);
+    for (r, x) in [(200, 4), (90, 20)] {
+        select_color(&mut app, r, 60, 30);
+        app.pen_size = 14;
+        app.apply_brush(x, 18);
+        app.add_new_layer();
+    }
+    app.layers[1].opacity = 0.6;
+    app.opacity = 0.7;
+    app.pen_size = 20;
+    select_color(&mut app, 20, 120, 220);
+    for x in (6..44).step_by(3) {
+        app.apply_brush(x, 20);
+    }
+    for tool in [Tool::Lighter, Tool::Blur] {
+        app.current_selection = PaletteEntry::Tool(tool);
+        app.apply_brush(24, 24);
+    }
+    app.apply_spray();
+
+    let incremental = app.canvas.clone();
+    app.sync_canvas_from_layers();
+    assert_eq!(incremental, app.canvas);
+}
+
+// The queue-based flood fill the magic wand and fills used before the scanline fill,
+// kept as the reference for which pixels a contiguous fill covers.
+fn reference_fill(app: &consolet::App, start_x: usize, start_y: usize) -> std::collections::HashSet<(usize, usize)> {
+    let block = (app.pixel_block as usize).max(1);
+    let canvas = &app.layers[app.active_layer_index].canvas;
+    let (width, height) = (app.canvas_width, app.canvas_height);
+    let (start_x, start_y) = (start_x / block * block, start_y / block * block);
+    let target = canvas[start_y][start_x];
+    let tolerance = app.fill_tolerance as f32;
+    let matches = |pixel: &consolet::Pixel| {
+        if (pixel.alpha == 0.0) != (target.alpha == 0.0) { return false; }
+        if pixel.alpha == 0.0 { return true; }
+        let alpha_matches = app.flat_mode || (pixel.alpha - target.alpha).abs() * 255.0 <= tolerance;
+        alpha_matches && consolet::utils::color_distance(pixel.color.into(), target.color.into()) <= tolerance
+    };
+    let mut visited = vec![vec![false; width.div_ceil(block)]; height.div_ceil(block)];
+    let mut queue = std::collections::VecDeque::from([(start_x, start_y)]);
+    let mut pixels = std::collections::HashSet::new();
+    while let Some((x, y)) = queue.pop_front() {
+        if visited[y / block][x / block] || !matches(&canvas[y][x]) { continue; }
+        visited[y / block][x / block] = true;
+        pixels.extend((y..(y + block).min(height)).flat_map(|py| (x..(x + block).min(width)).map(move |px| (px, py))));
+        if x >= block { queue.push_back((x - block, y)); }
+        if x + block < width { queue.push_back((x + block, y)); }
+        if y >= block { queue.push_back((x, y - block)); }
+        if y + block < height { queue.push_back((x, y + block)); }
+    }
+    pixels
+}
+
+#[test]
+fn contiguous_fill_covers_the_same_pixels_as_the_reference_fill() {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    let mut rng = StdRng::seed_from_u64(2306);
+    let shades = [(0, 0, 0), (200, 40, 40), (210, 50, 45), (40, 40, 200)];
+    for round in 0..200 {
+        let mut app = test_app(rng.gen_range(1..40), rng.gen_range(1..30));
+        app.pixel_block = rng.gen_range(1..4);
+        app.flat_mode = rng.gen_bool(0.3);
+        app.fill_tolerance = [0, 0, 20, 255][rng.gen_range(0..4)];
+        for row in app.layers[0].canvas.rows_mut() {
+            for pixel in row.iter_mut() {
+                let (r, g, b) = shades[rng.gen_range(0..shades.len())];
+                let alpha = [0.0, 0.5, 1.0][rng.gen_range(0..3)];
+                *pixel = consolet::Pixel { color: consolet::SerializableColor(r, g, b), alpha };
+            }
+        }
+        app.sync_canvas_from_layers();
+        let (x, y) = (rng.gen_range(0..app.canvas_width), rng.gen_range(0..app.canvas_height));
+
+        app.magic_wand(x, y);
+        assert_eq!(app.selection, reference_fill(&app, x, y), "round {}", round);
+
+        let expected = reference_fill(&app, x, y);
+        let before = app.layers[0].canvas.clone();
+        app.selection.clear();
+        select_color(&mut app, 1, 2, 3);
+        app.cursor_pos = (x as u16, y as u16);
+        app.fill_area();
+        let changed: std::collections::HashSet<(usize, usize)> = (0..app.canvas_height)
+            .flat_map(|py| (0..app.canvas_width).map(move |px| (px, py)))
+            .filter(|&(px, py)| app.layers[0].canvas[py][px] != before[py][px])
+            .collect();
+        assert_eq!(changed, expected, "round {}", round);
+    }
+}
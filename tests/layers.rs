@@ -0,0 +1,988 @@
+mod common;
+
+use common::{active_pixel, assert_canvas_eq, painted, scratch_dir, select_color, test_app};
+use consolet::palette::PaletteEntry;
+use consolet::{execute_command, App, BlendMode, Canvas, PickSource, Pixel, SerializableColor, SymmetryMode};
+use ratatui::style::Color;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+#[test]
+fn layer_png_round_trip_preserves_alpha() {
+    let mut rng = StdRng::seed_from_u64(2159);
+    let mut app = test_app(9, 7);
+    for y in 0..7 {
+        for x in 0..9 {
+            // Every 8-bit alpha step, with 0 and 255 always present.
+            let k: u8 = match (x, y) {
+                (0, 0) => 0,
+                (1, 0) => 255,
+                _ => rng.gen(),
+            };
+            app.layers[0].canvas[y][x] = if k == 0 {
+                Pixel::default()
+            } else {
+                Pixel { color: SerializableColor(rng.gen(), rng.gen(), rng.gen()), alpha: k as f32 / 255.0 }
+            };
+        }
+    }
+    let original = app.layers[0].canvas.clone();
+    std::fs::create_dir_all(scratch_dir()).unwrap();
+    let path = scratch_dir().join("round_trip.png");
+
+    assert!(execute_command(&mut app, &format!("layer export {}", path.display())));
+    execute_command(&mut app, "clear");
+    assert!(execute_command(&mut app, &format!("layer import {}", path.display())));
+
+    assert_eq!(app.layers[0].canvas, original);
+}
+
+#[test]
+fn united_export_composites_every_visible_layer() {
+    let mut app = test_app(4, 2);
+    select_color(&mut app, 255, 0, 0);
+    app.apply_brush(0, 0);
+    execute_command(&mut app, "add_layer");
+    select_color(&mut app, 0, 0, 255);
+    app.apply_brush(3, 1);
+    app.layers[0].opacity = 0.5;
+    std::fs::create_dir_all(scratch_dir()).unwrap();
+
+    let path = scratch_dir().join("united.png");
+    app.export_layer_mode = consolet::ExportLayerMode::United;
+    assert!(app.export_to_png(Some(path.to_string_lossy().into_owned()), 1, true, Color::Black, false, false));
+    let img = image::open(&path).unwrap().to_rgba8();
+    assert_eq!(img.get_pixel(0, 0).0, [255, 0, 0, 255], "the lower layer is kept");
+    assert_eq!(img.get_pixel(3, 1).0, [0, 0, 255, 128], "the top layer at half opacity");
+    assert_eq!(img.get_pixel(1, 0).0[3], 0);
+
+    assert!(app.export_to_png(Some(path.to_string_lossy().into_owned()), 1, false, Color::Black, false, false));
+    let img = image::open(&path).unwrap().to_rgba8();
+    assert_eq!(img.get_pixel(3, 1).0, [0, 0, 128, 255], "blended over black");
+}
+
+#[test]
+fn export_composites_over_a_custom_background() {
+    let mut app = test_app(2, 1);
+    select_color(&mut app, 0, 0, 255);
+    app.opacity = 0.5;
+    app.apply_brush(0, 0);
+    std::fs::create_dir_all(scratch_dir()).unwrap();
+    let path = scratch_dir().join("bgcolor.png");
+
+    execute_command(&mut app, &format!("export -o {} -bgcolor white", path.display()));
+    let img = image::open(&path).unwrap().to_rgba8();
+    assert_eq!(img.get_pixel(0, 0).0, [128, 128, 255, 255]);
+    assert_eq!(img.get_pixel(1, 0).0, [255, 255, 255, 255]);
+
+    // Separate mode writes <stem>_<n>.png per visible layer with the same background.
+    execute_command(&mut app, "export_mode=separate");
+    execute_command(&mut app, &format!("export -o {} -bgcolor #00FF00", path.display()));
+    let img = image::open(scratch_dir().join("bgcolor_1.png")).unwrap().to_rgba8();
+    assert_eq!(img.get_pixel(1, 0).0, [0, 255, 0, 255]);
+
+    execute_command(&mut app, &format!("export -o {} -bgcolor #GGG", path.display()));
+    assert!(app.status_message.as_ref().unwrap().0.contains("Invalid background color"));
+}
+
+#[test]
+fn reexport_repeats_the_last_export_settings() {
+    use consolet::ExportLayerMode;
+    let mut app = test_app(2, 1);
+    select_color(&mut app, 0, 0, 255);
+    app.opacity = 0.5;
+    app.apply_brush(0, 0);
+    let dir = scratch_dir().join("reexport");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("sprite.png");
+    let layer_file = dir.join("sprite_1.png");
+
+    execute_command(&mut app, "export_mode=separate");
+    assert!(execute_command(&mut app, &format!("export -o {} -u 2 -bgcolor white", path.display())));
+    std::fs::remove_file(&layer_file).unwrap();
+    execute_command(&mut app, "export_mode=united");
+    select_color(&mut app, 255, 0, 0);
+    app.opacity = 1.0;
+    app.apply_brush(1, 0);
+
+    assert!(execute_command(&mut app, "reexport"));
+    assert_eq!(app.status_message.as_ref().unwrap().0, format!("Re-exported to {}", path.display()));
+    assert!(!path.exists(), "the export is repeated in separate mode");
+    let img = image::open(&layer_file).unwrap().to_rgba8();
+    assert_eq!(img.dimensions(), (4, 2), "at the same scale");
+    assert_eq!(img.get_pixel(1, 1).0, [128, 128, 255, 255], "over the same background");
+    assert_eq!(img.get_pixel(2, 0).0, [255, 0, 0, 255], "with the current pixels");
+    assert!(app.export_layer_mode == ExportLayerMode::United, "the export mode setting is left alone");
+
+    // Ctrl+E does the same.
+    std::fs::remove_file(&layer_file).unwrap();
+    consolet::controller::execute_action(&mut app, consolet::keybindings::Action::RepeatLastExport).unwrap();
+    assert!(layer_file.exists());
+}
+
+#[test]
+fn reexport_opens_the_export_browser_before_the_first_export() {
+    let mut app = test_app(2, 1);
+    assert!(app.last_export.is_none());
+    execute_command(&mut app, "reexport");
+    assert!(app.mode == consolet::AppMode::FileBrowser);
+    assert!(matches!(app.browser_mode, Some(consolet::file_browser::BrowserMode::Export)));
+}
+
+#[test]
+fn gif_export_writes_a_frame_per_visible_layer() {
+    use image::AnimationDecoder;
+
+    let mut app = test_app(3, 2);
+    app.apply_brush(0, 0);
+    execute_command(&mut app, "add_layer");
+    select_color(&mut app, 0, 0, 255);
+    app.apply_brush(2, 1);
+    execute_command(&mut app, "add_layer");
+    app.layers[0].visible = false;
+    std::fs::create_dir_all(scratch_dir()).unwrap();
+    let path = scratch_dir().join("frames.gif");
+
+    execute_command(&mut app, &format!("export gif -o {} -d 200 -u 2", path.display()));
+    assert!(app.status_message.as_ref().unwrap().0.starts_with("Exported 2 frames"), "{:?}", app.status_message);
+    let file = std::io::BufReader::new(std::fs::File::open(&path).unwrap());
+    let frames = image::codecs::gif::GifDecoder::new(file).unwrap().into_frames().collect_frames().unwrap();
+    assert_eq!(frames.len(), 2, "the hidden layer is skipped");
+    assert_eq!(frames[0].delay().numer_denom_ms(), (200, 1));
+    let (first, second) = (frames[0].buffer(), frames[1].buffer());
+    assert_eq!(first.dimensions(), (6, 4));
+    assert_eq!(first.get_pixel(0, 0).0, [255, 0, 0, 255], "bottom layer first");
+    assert_eq!(first.get_pixel(5, 3).0[3], 0);
+    assert_eq!(second.get_pixel(5, 3).0, [0, 0, 255, 255]);
+
+    execute_command(&mut app, &format!("export gif -o {} -bgcolor white", path.display()));
+    let file = std::io::BufReader::new(std::fs::File::open(&path).unwrap());
+    let frames = image::codecs::gif::GifDecoder::new(file).unwrap().into_frames().collect_frames().unwrap();
+    assert_eq!(frames[0].buffer().get_pixel(2, 1).0, [255, 255, 255, 255]);
+}
+
+#[test]
+fn ansi_export_writes_half_blocks_and_plain_cells() {
+    let mut app = test_app(2, 3);
+    app.apply_brush(0, 0);
+    select_color(&mut app, 0, 0, 255);
+    app.apply_brush(0, 1);
+    app.apply_brush(1, 2);
+    std::fs::create_dir_all(scratch_dir()).unwrap();
+    let path = scratch_dir().join("art.ans");
+
+    execute_command(&mut app, &format!("export ansi -o {}", path.display()));
+    assert!(app.status_message.as_ref().unwrap().0.starts_with("Exported to"), "{:?}", app.status_message);
+    let text = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 2, "two pixel rows per line");
+    assert_eq!(lines[0], "\x1b[0m\x1b[38;2;255;0;0m\x1b[48;2;0;0;255m▀\x1b[0m \x1b[0m");
+    assert_eq!(lines[1], "\x1b[0m \x1b[0m\x1b[38;2;0;0;255m▀\x1b[0m");
+
+    execute_command(&mut app, "colorMode=Ansi256");
+    execute_command(&mut app, &format!("export ansi -o {} --plain", path.display()));
+    let text = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], "\x1b[48;5;196m  \x1b[0m  \x1b[0m");
+}
+
+#[test]
+fn sprite_sheet_tiles_visible_layers_top_first() {
+    let mut app = test_app(2, 2);
+    app.apply_brush(0, 0);
+    execute_command(&mut app, "add_layer");
+    select_color(&mut app, 0, 0, 255);
+    app.apply_brush(1, 1);
+    execute_command(&mut app, "add_layer");
+    execute_command(&mut app, "add_layer");
+    app.layers[0].visible = false;
+    std::fs::create_dir_all(scratch_dir()).unwrap();
+    let path = scratch_dir().join("sheet.png");
+
+    execute_command(&mut app, &format!("export sheet -o {} -cols 1 -gutter -bgcolor white", path.display()));
+    assert!(app.status_message.as_ref().unwrap().0.starts_with("Exported 3 tiles"), "{:?}", app.status_message);
+    let img = image::open(&path).unwrap().to_rgba8();
+    assert_eq!(img.dimensions(), (2, 8));
+    assert_eq!(img.get_pixel(0, 0).0, [255, 255, 255, 255], "the empty top layer comes first");
+    assert_eq!(img.get_pixel(1, 4).0, [0, 0, 255, 255]);
+    assert_eq!(img.get_pixel(0, 6).0, [255, 0, 0, 255], "the bottom layer comes last");
+
+    execute_command(&mut app, &format!("export sheet -o {} -u 2", path.display()));
+    let img = image::open(&path).unwrap().to_rgba8();
+    assert_eq!(img.dimensions(), (12, 4), "columns never exceed the tile count");
+    assert_eq!(img.get_pixel(0, 0).0[3], 0);
+
+    execute_command(&mut app, &format!("export sheet -o {} -u 2000", path.display()));
+    assert!(app.status_message.as_ref().unwrap().0.contains("12000x4000"), "{:?}", app.status_message);
+}
+
+#[test]
+fn ascii_char_maps_luminance_onto_the_ramp() {
+    use consolet::export::ascii_char;
+    use consolet::{Pixel, SerializableColor};
+
+    let ramp: Vec<char> = " .:@".chars().collect();
+    let pixel = |r, g, b, alpha| Pixel { color: SerializableColor(r, g, b), alpha };
+    assert_eq!(ascii_char(pixel(255, 255, 255, 1.0), &ramp, false, false), '@');
+    assert_eq!(ascii_char(pixel(0, 0, 0, 1.0), &ramp, false, false), ' ');
+    assert_eq!(ascii_char(pixel(128, 128, 128, 1.0), &ramp, false, false), ':');
+    assert_eq!(ascii_char(pixel(0, 255, 0, 1.0), &ramp, false, false), ':', "green weighs more than blue");
+    assert_eq!(ascii_char(pixel(0, 0, 255, 1.0), &ramp, false, false), ' ');
+    assert_eq!(ascii_char(pixel(255, 255, 255, 0.5), &ramp, false, false), ':', "partial alpha darkens over black");
+    assert_eq!(ascii_char(pixel(255, 255, 255, 1.0), &ramp, true, false), ' ');
+    assert_eq!(ascii_char(pixel(0, 0, 0, 1.0), &ramp, true, false), '@');
+    assert_eq!(ascii_char(pixel(0, 0, 0, 0.0), &ramp, true, false), ' ', "transparent is always a space");
+}
+
+#[test]
+fn ascii_export_writes_one_line_per_row() {
+    let mut app = test_app(3, 2);
+    select_color(&mut app, 255, 255, 255);
+    app.apply_brush(0, 0);
+    app.apply_brush(2, 1);
+    std::fs::create_dir_all(scratch_dir()).unwrap();
+    let path = scratch_dir().join("art.txt");
+
+    execute_command(&mut app, &format!("export ascii -o {}", path.display()));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "@  \n  @\n");
+
+    execute_command(&mut app, &format!("export ascii -o {} --charset \" .#\" --wide", path.display()));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "##    \n    ##\n");
+
+    execute_command(&mut app, &format!("export ascii -o {} --charset", path.display()));
+    assert!(app.status_message.as_ref().unwrap().0.starts_with("Usage: export ascii"));
+}
+
+#[test]
+fn image_import_downscales_and_keeps_alpha() {
+    let mut app = test_app(4, 4);
+    std::fs::create_dir_all(scratch_dir()).unwrap();
+    let path = scratch_dir().join("import.png");
+    // An 8x4 image: a red left half, and a right half of white rows between transparent ones.
+    image::RgbaImage::from_fn(8, 4, |x, y| match (x < 4, y % 2 == 0) {
+        (true, _) => image::Rgba([255, 0, 0, 255]),
+        (false, true) => image::Rgba([255, 255, 255, 255]),
+        (false, false) => image::Rgba([0, 0, 0, 0]),
+    }).save(&path).unwrap();
+
+    execute_command(&mut app, &format!("import image {}", path.display()));
+    assert!(app.status_message.as_ref().unwrap().0.contains("(8x4)"), "{:?}", app.status_message);
+    assert_eq!(active_pixel(&app, 3, 3).color, SerializableColor(255, 0, 0), "overhang is cropped");
+
+    app.undo();
+    execute_command(&mut app, &format!("import image {} --fit --box --new-layer", path.display()));
+    assert_eq!(app.layers.len(), 2);
+    assert_eq!(app.active_layer_index, 0);
+    let pixel = active_pixel(&app, 3, 1);
+    assert_eq!(pixel.color, SerializableColor(255, 255, 255), "transparent pixels don't darken the average");
+    assert!((pixel.alpha - 128.0 / 255.0).abs() < 0.01, "{}", pixel.alpha);
+    assert_eq!(active_pixel(&app, 0, 2).alpha, 0.0, "fit keeps the aspect ratio");
+    assert_eq!(app.layers[1].canvas[0][0].alpha, 0.0, "the old layer is untouched");
+
+    app.snap_to_palette = true;
+    app.color_palette = vec![PaletteEntry::Color(Color::Rgb(200, 0, 0))];
+    execute_command(&mut app, &format!("import image {} -s 2", path.display()));
+    assert_eq!(active_pixel(&app, 0, 0).color, SerializableColor(200, 0, 0));
+    assert_eq!(active_pixel(&app, 3, 0).color, SerializableColor(255, 255, 255), "transparent source pixels leave the layer as it was");
+}
+
+#[test]
+fn selection_export_writes_the_masked_bounding_box() {
+    let mut app = test_app(6, 6);
+    for (x, y) in [(1, 1), (2, 1), (2, 2), (3, 3)] {
+        app.apply_brush(x, y);
+    }
+    app.selection = [(1, 1), (2, 2), (3, 3), (2, 1)].into_iter().collect();
+    app.selection.remove(&(2, 1));
+    std::fs::create_dir_all(scratch_dir()).unwrap();
+    let path = scratch_dir().join("selection.png");
+
+    execute_command(&mut app, &format!("export -o {} -u 2 -bgcolor white --selection", path.display()));
+    assert!(app.status_message.as_ref().unwrap().0.ends_with("(6x6)"), "{:?}", app.status_message);
+    let img = image::open(&path).unwrap().to_rgba8();
+    assert_eq!(img.dimensions(), (6, 6));
+    assert_eq!(img.get_pixel(0, 0).0, [255, 0, 0, 255]);
+    assert_eq!(img.get_pixel(2, 0).0[3], 0, "painted but not selected");
+    assert_eq!(img.get_pixel(5, 5).0, [255, 0, 0, 255]);
+    assert_eq!(app.last_export.as_ref().map(|e| e.selection_only), Some(true));
+
+    execute_command(&mut app, "export_mode=separate");
+    execute_command(&mut app, &format!("export -o {} --selection", path.display()));
+    let img = image::open(scratch_dir().join("selection_1.png")).unwrap().to_rgba8();
+    assert_eq!(img.dimensions(), (3, 3));
+
+    app.selection.clear();
+    execute_command(&mut app, &format!("export -o {} --selection", path.display()));
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Export failed: nothing is selected.");
+}
+
+#[test]
+fn autocrop_export_shrinks_to_the_painted_pixels() {
+    let mut app = test_app(5, 4);
+    std::fs::create_dir_all(scratch_dir()).unwrap();
+    let path = scratch_dir().join("autocrop.png");
+
+    execute_command(&mut app, &format!("export -o {} --autocrop", path.display()));
+    assert!(app.status_message.as_ref().unwrap().0.starts_with("Export failed"), "{:?}", app.status_message);
+
+    app.apply_brush(4, 3);
+    execute_command(&mut app, &format!("export -o {} -u 3 --autocrop", path.display()));
+    let img = image::open(&path).unwrap().to_rgba8();
+    assert_eq!(img.dimensions(), (3, 3));
+    assert!(img.pixels().all(|p| p.0 == [255, 0, 0, 255]));
+
+    execute_command(&mut app, "add_layer");
+    execute_command(&mut app, "export_mode=separate");
+    execute_command(&mut app, &format!("export -o {} --autocrop", path.display()));
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Exported 1 layers (cropped)", "the empty layer is skipped");
+    assert_eq!(image::open(scratch_dir().join("autocrop_2.png")).unwrap().to_rgba8().dimensions(), (1, 1));
+}
+
+fn random_layer_stack(rng: &mut StdRng) -> consolet::App {
+    let mut app = test_app(12, 9);
+    let layer_count = rng.gen_range(2..=5);
+    for _ in 1..layer_count {
+        execute_command(&mut app, "add_layer");
+    }
+    for layer in app.layers.iter_mut() {
+        layer.visible = rng.gen_bool(0.75);
+        layer.opacity = rng.gen_range(0.0..=1.0);
+        layer.blend_mode = BlendMode::ALL[rng.gen_range(0..BlendMode::ALL.len())];
+        for pixel in layer.canvas.pixels_mut() {
+            if rng.gen_bool(0.6) {
+                *pixel = Pixel { color: SerializableColor(rng.gen(), rng.gen(), rng.gen()), alpha: rng.gen_range(0.05..=1.0) };
+            }
+        }
+    }
+    app.sync_canvas_from_layers();
+    app
+}
+
+#[test]
+fn flatten_matches_on_screen_composite() {
+    let mut rng = StdRng::seed_from_u64(2168);
+    for _ in 0..25 {
+        let mut app = random_layer_stack(&mut rng);
+        let composite = app.canvas.clone();
+
+        execute_command(&mut app, "flatten");
+
+        assert_eq!(app.layers.len(), 1);
+        assert_canvas_eq(&app.layers[0].canvas, &composite);
+        assert_canvas_eq(&app.canvas, &composite);
+    }
+}
+
+#[test]
+fn merge_visible_matches_on_screen_composite() {
+    let mut rng = StdRng::seed_from_u64(2169);
+    for _ in 0..25 {
+        let mut app = random_layer_stack(&mut rng);
+        let composite = app.canvas.clone();
+        let hidden: Vec<String> = app.layers.iter().filter(|l| !l.visible).map(|l| l.name.clone()).collect();
+        let visible_count = app.layers.len() - hidden.len();
+
+        execute_command(&mut app, "merge_visible");
+
+        if visible_count >= 2 {
+            assert_eq!(app.layers.len(), hidden.len() + 1);
+            assert!(app.layers[app.active_layer_index].visible);
+        }
+        assert_canvas_eq(&app.canvas, &composite);
+        let remaining_hidden: Vec<String> = app.layers.iter().filter(|l| !l.visible).map(|l| l.name.clone()).collect();
+        assert_eq!(remaining_hidden, hidden);
+    }
+}
+
+#[test]
+fn blend_modes_combine_with_the_layers_below() {
+    let mut app = test_app(2, 1);
+    app.add_new_layer();
+    // Layer 1 is the bottom: an opaque backdrop on (0, 0) only.
+    app.layers[1].canvas[0][0] = Pixel { color: SerializableColor(200, 100, 40), alpha: 1.0 };
+    for x in 0..2 {
+        app.layers[0].canvas[0][x] = Pixel { color: SerializableColor(100, 200, 255), alpha: 1.0 };
+    }
+
+    let expected = [
+        (BlendMode::Normal, (100, 200, 255)),
+        (BlendMode::Multiply, (78, 78, 40)),
+        (BlendMode::Screen, (222, 222, 255)),
+        (BlendMode::Overlay, (188, 157, 80)),
+        (BlendMode::Add, (255, 255, 255)),
+        (BlendMode::Subtract, (100, 0, 0)),
+    ];
+    for (mode, (r, g, b)) in expected {
+        execute_command(&mut app, &format!("layer_blend={}", mode.name()));
+        assert_eq!(app.layers[0].blend_mode, mode);
+        assert_eq!(app.canvas[0][0].color, SerializableColor(r, g, b), "{:?}", mode);
+        assert_eq!(app.canvas[0][1].color, SerializableColor(100, 200, 255), "{:?} has nothing to blend with over transparency", mode);
+    }
+
+    // Layer opacity fades the blended result toward the backdrop.
+    execute_command(&mut app, "layer_blend=multiply");
+    execute_command(&mut app, "layer_opacity=0.5");
+    assert_eq!(app.canvas[0][0].color, SerializableColor(139, 89, 40));
+
+    execute_command(&mut app, "layer_blend=dodge");
+    assert_eq!(app.layers[0].blend_mode, BlendMode::Multiply);
+    assert!(app.status_message.as_ref().unwrap().0.starts_with("Unknown blend mode: dodge."));
+    execute_command(&mut app, "layer 2 blend=scr");
+    assert_eq!(app.layers[1].blend_mode, BlendMode::Screen);
+
+    app.undo();
+    app.undo();
+    assert_eq!((app.layers[0].opacity, app.layers[1].blend_mode), (1.0, BlendMode::Normal));
+    app.undo();
+    assert_eq!(app.layers[0].blend_mode, BlendMode::Subtract, "blend mode changes undo one step at a time");
+}
+
+#[test]
+fn merge_down_keeps_the_blended_look() {
+    let mut rng = StdRng::seed_from_u64(2314);
+    for mode in BlendMode::ALL {
+        let mut app = random_layer_stack(&mut rng);
+        app.layers[0].visible = true;
+        app.layers[0].blend_mode = mode;
+        app.layers[1] = consolet::Layer { visible: true, opacity: 1.0, blend_mode: BlendMode::Normal, ..app.layers[1].clone() };
+        // Colors are rounded after every blend, so anything shown beneath the pair would
+        // be rounded in a different order once the pair is merged.
+        app.layers.iter_mut().skip(2).for_each(|layer| layer.visible = false);
+        app.sync_canvas_from_layers();
+        let composite = app.canvas.clone();
+        let (count, lower_name) = (app.layers.len(), app.layers[1].name.clone());
+
+        app.set_active_layer(0);
+        execute_command(&mut app, "merge_down");
+        assert_eq!((app.layers.len(), app.layers[0].name.as_str()), (count - 1, lower_name.as_str()));
+        assert_canvas_eq(&app.canvas, &composite);
+    }
+}
+
+#[test]
+fn merge_up_keeps_the_look_and_the_upper_name() {
+    let mut rng = StdRng::seed_from_u64(2320);
+    for mode in BlendMode::ALL {
+        let mut app = random_layer_stack(&mut rng);
+        app.layers[0].visible = true;
+        app.layers[0].blend_mode = mode;
+        app.layers[1] = consolet::Layer { visible: true, opacity: 1.0, blend_mode: BlendMode::Normal, ..app.layers[1].clone() };
+        // Colors are rounded after every blend, so anything shown beneath the pair would
+        // be rounded in a different order once the pair is merged.
+        app.layers.iter_mut().skip(2).for_each(|layer| layer.visible = false);
+        app.sync_canvas_from_layers();
+        let composite = app.canvas.clone();
+        let (count, upper_name) = (app.layers.len(), app.layers[0].name.clone());
+
+        app.set_active_layer(1);
+        execute_command(&mut app, "merge_up");
+        assert_eq!((app.layers.len(), app.active_layer_index), (count - 1, 0));
+        assert_eq!(app.layers[0].name, upper_name);
+        assert_canvas_eq(&app.canvas, &composite);
+
+        app.undo();
+        assert_eq!(app.layers.len(), count);
+        assert_canvas_eq(&app.canvas, &composite);
+    }
+
+    let mut app = test_app(2, 2);
+    execute_command(&mut app, "merge_up");
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Cannot merge top layer.");
+}
+
+#[test]
+fn move_and_copy_to_layer_carry_pixels_in_one_undo_step() {
+    let mut app = test_app(4, 2);
+    execute_command(&mut app, "rename_layer=Ink");
+    execute_command(&mut app, "add_layer");
+    execute_command(&mut app, "rename_layer=Wrong");
+    select_color(&mut app, 10, 20, 30);
+    app.opacity = 0.5;
+    app.apply_brush(0, 0);
+    app.apply_brush(1, 0);
+    app.opacity = 1.0;
+    app.apply_brush(2, 1);
+    let before: Vec<_> = app.layers.iter().map(|layer| layer.canvas.clone()).collect();
+
+    // With a selection only the selected pixels move, keeping their alpha.
+    app.selection.insert((0, 0));
+    app.selection.insert((3, 1));
+    execute_command(&mut app, "move_to_layer=ink");
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Moved 1 pixels to 'Ink'.");
+    assert_eq!(app.layers[1].canvas[0][0], Pixel { color: SerializableColor(10, 20, 30), alpha: 0.5 });
+    assert_eq!((app.layers[0].canvas[0][0].alpha, app.layers[0].canvas[0][1].alpha), (0.0, 0.5));
+    app.undo();
+    for (layer, canvas) in app.layers.iter().zip(&before) {
+        assert_canvas_eq(&layer.canvas, canvas);
+    }
+
+    // Without one, every painted pixel is copied and the source keeps them.
+    app.clear_selection();
+    execute_command(&mut app, "copy_to_layer=2");
+    assert_canvas_eq(&app.layers[0].canvas, &before[0]);
+    assert_canvas_eq(&app.layers[1].canvas, &before[0]);
+
+    execute_command(&mut app, "move_to_layer=Nope");
+    assert_eq!(app.status_message.as_ref().unwrap().0, "No layer matches 'Nope'.");
+    execute_command(&mut app, "rename_layer=Ink");
+    execute_command(&mut app, "move_to_layer=Ink");
+    assert_eq!(app.status_message.as_ref().unwrap().0, "'Ink' is ambiguous: Ink, Ink.");
+    execute_command(&mut app, "move_to_layer=1");
+    assert_eq!(app.status_message.as_ref().unwrap().0, "The target is the active layer.");
+}
+
+#[test]
+fn locked_layers_refuse_every_edit() {
+    let mut app = test_app(6, 4);
+    app.apply_brush(0, 0);
+    execute_command(&mut app, "add_layer");
+    app.apply_brush(1, 1);
+    execute_command(&mut app, "lock_layer");
+    let locked = app.active_layer_index;
+    assert!(app.layers[locked].locked);
+    let canvases: Vec<_> = app.layers.iter().map(|layer| layer.canvas.clone()).collect();
+    let undo_steps = app.undo_stack.len();
+
+    app.cursor_pos = (3, 2);
+    app.use_current_tool();
+    app.apply_brush(4, 3);
+    app.erase_brush(1, 1);
+    app.erase_at_cursor();
+    app.fill_area();
+    app.apply_spray();
+    app.draw_shape(consolet::palette::Tool::Rectangle, (0, 0), (5, 3));
+    app.stamp_text("A", 0, 0, false);
+    execute_command(&mut app, "clear");
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Layer is locked.");
+    execute_command(&mut app, "merge_down");
+    for (layer, canvas) in app.layers.iter().zip(&canvases) {
+        assert_canvas_eq(&layer.canvas, canvas);
+    }
+    assert_eq!(app.layers.len(), 2, "merge_down refuses a locked layer");
+    assert_eq!(app.undo_stack.len(), undo_steps);
+
+    execute_command(&mut app, "shift_layer=1,0 --all");
+    assert_canvas_eq(&app.layers[locked].canvas, &canvases[locked]);
+    assert_eq!(app.layers[1 - locked].canvas[0][1].alpha, 1.0, "the unlocked layer still shifts");
+
+    execute_command(&mut app, "clear --all");
+    assert_canvas_eq(&app.layers[locked].canvas, &canvases[locked]);
+    assert!(app.layers[1 - locked].canvas.pixels().iter().all(|p| p.alpha == 0.0));
+
+    execute_command(&mut app, &format!("layer {} locked=false", locked + 1));
+    app.apply_brush(4, 3);
+    assert_eq!(active_pixel(&app, 4, 3).alpha, 1.0);
+}
+
+#[test]
+fn alpha_locked_layers_only_recolor_existing_pixels() {
+    let mut app = test_app(4, 4);
+    select_color(&mut app, 200, 0, 0);
+    app.opacity = 0.5;
+    app.apply_brush(1, 1);
+    execute_command(&mut app, "alpha_lock_layer");
+    assert!(app.layers[0].alpha_locked);
+
+    select_color(&mut app, 0, 0, 200);
+    app.opacity = 1.0;
+    app.apply_brush(1, 1);
+    app.apply_brush(2, 1);
+    assert_eq!(active_pixel(&app, 1, 1), Pixel { color: SerializableColor(0, 0, 200), alpha: 0.5 });
+    assert_eq!(active_pixel(&app, 2, 1).alpha, 0.0, "transparent pixels stay transparent");
+
+    app.cursor_pos = (3, 3);
+    app.fill_area();
+    assert_eq!(painted(&app), vec![(1, 1)]);
+
+    app.erase_brush(1, 1);
+    app.erase_at_cursor();
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Layer is alpha-locked.");
+    assert_eq!(active_pixel(&app, 1, 1).alpha, 0.5);
+
+    execute_command(&mut app, "layer 1 alpha_locked=false");
+    app.apply_brush(2, 1);
+    assert_eq!(active_pixel(&app, 2, 1).alpha, 1.0);
+}
+
+// A 6x6 app whose alpha-locked layer holds one half-transparent red pixel at (1, 1),
+// with opaque blue selected.
+fn alpha_locked_app() -> App {
+    let mut app = test_app(6, 6);
+    app.layers[0].canvas[1][1] = Pixel { color: SerializableColor(200, 0, 0), alpha: 0.5 };
+    app.sync_canvas_from_layers();
+    execute_command(&mut app, "alpha_lock_layer");
+    select_color(&mut app, 0, 0, 200);
+    app
+}
+
+const RECOLORED: Pixel = Pixel { color: SerializableColor(0, 0, 200), alpha: 0.5 };
+const UNTOUCHED: Pixel = Pixel { color: SerializableColor(200, 0, 0), alpha: 0.5 };
+
+#[test]
+fn alpha_locked_text_only_recolors_existing_pixels() {
+    let mut app = alpha_locked_app();
+    assert!(execute_command(&mut app, "text=I 0,0"));
+    assert_eq!(painted(&app), vec![(1, 1)]);
+    assert_eq!(active_pixel(&app, 1, 1), RECOLORED);
+}
+
+#[test]
+fn alpha_locked_outline_only_recolors_existing_pixels() {
+    let mut app = alpha_locked_app();
+    execute_command(&mut app, "outline");
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Nothing to outline.");
+    assert_eq!(painted(&app), vec![(1, 1)]);
+    execute_command(&mut app, "outline --inside");
+    assert_eq!(active_pixel(&app, 1, 1), RECOLORED);
+}
+
+#[test]
+fn alpha_locked_selection_fill_only_recolors_existing_pixels() {
+    let mut app = alpha_locked_app();
+    app.selection.extend([(1, 1), (2, 1)]);
+    assert!(execute_command(&mut app, "selection fill"));
+    assert_eq!(painted(&app), vec![(1, 1)]);
+    assert_eq!(active_pixel(&app, 1, 1), RECOLORED);
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Filled 1 selected pixels.");
+}
+
+#[test]
+fn alpha_locked_selection_move_is_refused() {
+    let mut app = alpha_locked_app();
+    app.selection.insert((1, 1));
+    assert!(!execute_command(&mut app, "selection move 1,0"));
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Layer is alpha-locked.");
+    assert_eq!(active_pixel(&app, 1, 1), UNTOUCHED);
+}
+
+#[test]
+fn alpha_locked_layers_are_not_shifted() {
+    let mut app = alpha_locked_app();
+    assert!(!execute_command(&mut app, "shift_layer=1,0"));
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Layer is alpha-locked.");
+    execute_command(&mut app, "shift_layer=1,0 --all");
+    assert_eq!(painted(&app), vec![(1, 1)]);
+}
+
+#[test]
+fn alpha_locked_tile_paste_and_fill_only_recolor_existing_pixels() {
+    let mut app = alpha_locked_app();
+    execute_command(&mut app, "tilesize 2x2");
+    app.tile_clipboard = Some(Canvas::from_rows(vec![vec![Pixel::default(); 2]; 2]));
+    assert!(execute_command(&mut app, "tile paste"));
+    assert_eq!(active_pixel(&app, 1, 1), UNTOUCHED, "a transparent tile does not erase");
+
+    let green = Pixel { color: SerializableColor(0, 200, 0), alpha: 1.0 };
+    app.tile_clipboard = Some(Canvas::from_rows(vec![vec![green; 2]; 2]));
+    assert!(execute_command(&mut app, "tile paste"));
+    assert_eq!(painted(&app), vec![(1, 1)]);
+    assert_eq!(active_pixel(&app, 1, 1), Pixel { alpha: 0.5, ..green });
+
+    app.tile_clipboard = Some(Canvas::from_rows(vec![vec![Pixel { alpha: 1.0, ..RECOLORED }; 2]; 2]));
+    assert!(execute_command(&mut app, "tile fill --blend"));
+    assert_eq!(painted(&app), vec![(1, 1)]);
+    assert_eq!(active_pixel(&app, 1, 1), RECOLORED);
+}
+
+#[test]
+fn alpha_locked_image_import_only_recolors_existing_pixels() {
+    let mut app = alpha_locked_app();
+    std::fs::create_dir_all(scratch_dir()).unwrap();
+    let path = scratch_dir().join("alpha_locked_import.png");
+    image::RgbaImage::from_pixel(6, 6, image::Rgba([0, 0, 200, 255])).save(&path).unwrap();
+
+    assert!(execute_command(&mut app, &format!("import image {}", path.display())));
+    assert_eq!(painted(&app), vec![(1, 1)]);
+    assert_eq!(active_pixel(&app, 1, 1), RECOLORED);
+
+    assert!(!execute_command(&mut app, &format!("layer import {}", path.display())));
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Layer is alpha-locked.");
+    assert_eq!(painted(&app), vec![(1, 1)]);
+}
+
+#[test]
+fn alpha_locked_layers_are_not_cleared() {
+    let mut app = alpha_locked_app();
+    execute_command(&mut app, "clear");
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Layer is alpha-locked.");
+    execute_command(&mut app, "clear --all");
+    assert_eq!(active_pixel(&app, 1, 1), UNTOUCHED);
+}
+
+#[test]
+fn pick_color_reads_the_active_layer_or_the_composite() {
+    let mut app = test_app(4, 4);
+    select_color(&mut app, 10, 20, 30);
+    app.apply_brush(1, 1);
+    app.add_new_layer();
+    app.cursor_pos = (1, 1);
+    select_color(&mut app, 200, 200, 200);
+
+    app.pick_color_at_cursor();
+    assert_eq!(app.current_selection, PaletteEntry::Color(Color::Rgb(200, 200, 200)), "nothing picked from the empty layer");
+    assert!(app.status_message.as_ref().unwrap().0.contains("another layer"));
+
+    app.pick_from = PickSource::Composite;
+    app.pick_color_at_cursor();
+    assert_eq!(app.current_selection, PaletteEntry::Color(Color::Rgb(10, 20, 30)));
+}
+
+#[test]
+fn pick_alpha_sets_opacity_from_the_picked_pixel() {
+    let mut app = test_app(4, 4);
+    app.opacity = 0.4;
+    app.apply_brush(2, 2);
+    app.opacity = 1.0;
+    app.cursor_pos = (2, 2);
+
+    app.pick_color_at_cursor();
+    assert_eq!(app.opacity, 1.0);
+
+    app.pick_alpha = true;
+    app.pick_color_at_cursor();
+    assert!((app.opacity - 0.4).abs() < 1e-6);
+}
+
+#[test]
+fn resize_keeps_artwork_at_the_anchor_and_undoes() {
+    let mut app = test_app(4, 4);
+    select_color(&mut app, 255, 0, 0);
+    app.apply_brush(0, 0);
+    app.apply_brush(3, 3);
+    // The new layer goes on top, at index 0.
+    app.add_new_layer();
+    app.apply_brush(1, 2);
+
+    execute_command(&mut app, "resize 8x6 --anchor=center");
+    assert_eq!((app.canvas_width, app.canvas_height), (8, 6));
+    assert!(app.layers.iter().all(|l| l.canvas.size() == (8, 6)));
+    assert_eq!(painted_on(&app, 1), vec![(2, 1), (5, 4)]);
+    assert_eq!(painted_on(&app, 0), vec![(3, 3)]);
+
+    // Shrinking clips whatever ends up outside.
+    execute_command(&mut app, "resize 3x3 --anchor=-2,-1");
+    assert_eq!(painted_on(&app, 1), vec![(0, 0)]);
+    assert_eq!(painted_on(&app, 0), vec![(1, 2)]);
+
+    app.undo();
+    assert_eq!((app.canvas_width, app.canvas_height), (8, 6));
+    assert_eq!(painted_on(&app, 1), vec![(2, 1), (5, 4)]);
+    app.undo();
+    assert_eq!((app.canvas_width, app.canvas_height), (4, 4));
+    assert_eq!(painted_on(&app, 1), vec![(0, 0), (3, 3)]);
+    app.redo();
+    assert_eq!((app.canvas_width, app.canvas_height), (8, 6));
+    assert_eq!(painted_on(&app, 0), vec![(3, 3)]);
+
+    execute_command(&mut app, "resize 5x5 --clear");
+    assert_eq!((app.canvas_width, app.canvas_height), (5, 5));
+    assert!(painted_on(&app, 1).is_empty() && painted_on(&app, 0).is_empty());
+
+    execute_command(&mut app, "resize 0x5");
+    assert_eq!((app.canvas_width, app.canvas_height), (5, 5), "bad sizes are rejected");
+}
+
+#[test]
+fn resize_undo_brings_back_the_exact_layer_set() {
+    let mut app = test_app(6, 6);
+    app.cursor_pos = (5, 5);
+    app.use_current_tool();
+    execute_command(&mut app, "resize 3x3");
+    assert_eq!(app.cursor_pos, (2, 2));
+    // A layer added at the new size goes away again with the steps that follow the resize.
+    app.add_new_layer();
+    app.undo();
+    app.undo();
+    assert_eq!((app.canvas_width, app.canvas_height), (6, 6));
+    assert_eq!(app.layers.len(), 1);
+    assert_eq!(painted_on(&app, 0), vec![(5, 5)]);
+    app.redo();
+    assert_eq!((app.canvas_width, app.canvas_height), (3, 3));
+    assert!(app.cursor_pos.0 < 3 && app.cursor_pos.1 < 3);
+}
+
+#[test]
+fn clear_all_empties_every_layer_in_one_step() {
+    let mut app = test_app(4, 4);
+    app.use_current_tool();
+    app.add_new_layer();
+    app.cursor_pos = (1, 1);
+    app.use_current_tool();
+
+    execute_command(&mut app, "clear");
+    assert_eq!((painted_on(&app, 0), painted_on(&app, 1)), (vec![], vec![(0, 0)]));
+    app.undo();
+    execute_command(&mut app, "clear --all");
+    assert_eq!(app.status_message.as_ref().unwrap().0, "All layers cleared.");
+    assert!(painted_on(&app, 0).is_empty() && painted_on(&app, 1).is_empty());
+    app.undo();
+    assert_eq!((painted_on(&app, 0), painted_on(&app, 1)), (vec![(1, 1)], vec![(0, 0)]));
+}
+
+#[test]
+fn crop_to_selection_or_content_remaps_the_canvas() {
+    let mut app = test_app(10, 8);
+    execute_command(&mut app, "crop");
+    assert_eq!((app.canvas_width, app.canvas_height), (10, 8), "nothing selected");
+    execute_command(&mut app, "crop --content");
+    assert_eq!((app.canvas_width, app.canvas_height), (10, 8), "nothing drawn");
+
+    app.apply_brush(2, 3);
+    app.apply_brush(6, 5);
+    app.cursor_pos = (9, 4);
+    app.symmetry_mode = SymmetryMode::DiagonalForward(1);
+    execute_command(&mut app, "crop --content");
+    assert_eq!((app.canvas_width, app.canvas_height), (5, 3));
+    assert_eq!(painted_on(&app, 0), vec![(0, 0), (4, 2)]);
+    assert_eq!(app.cursor_pos, (4, 1));
+    // y = x + 1 through (2, 3) becomes y = x + 0 through (0, 0).
+    assert_eq!(app.symmetry_mode, SymmetryMode::DiagonalForward(0));
+
+    app.undo();
+    assert_eq!((app.canvas_width, app.canvas_height), (10, 8));
+    assert_eq!(painted_on(&app, 0), vec![(2, 3), (6, 5)]);
+
+    app.selection = [(1, 1), (3, 4)].into_iter().collect();
+    execute_command(&mut app, "crop");
+    assert_eq!((app.canvas_width, app.canvas_height), (3, 4));
+    assert_eq!(painted_on(&app, 0), vec![(1, 2)]);
+    assert!(app.selection.is_empty());
+}
+
+#[test]
+fn rotate_and_flip_transform_every_layer() {
+    let mut app = test_app(4, 2);
+    app.apply_brush(0, 0);
+    app.add_new_layer();
+    app.apply_brush(3, 1);
+    app.cursor_pos = (1, 0);
+    app.symmetry_mode = SymmetryMode::Vertical(2);
+
+    execute_command(&mut app, "rotate_canvas=90");
+    assert_eq!((app.canvas_width, app.canvas_height), (2, 4));
+    assert_eq!(painted_on(&app, 1), vec![(1, 0)]);
+    assert_eq!(painted_on(&app, 0), vec![(0, 3)]);
+    assert_eq!(app.cursor_pos, (1, 1));
+    assert_eq!(app.symmetry_mode, SymmetryMode::Off);
+
+    execute_command(&mut app, "flip_canvas=h");
+    assert_eq!(painted_on(&app, 1), vec![(0, 0)]);
+    execute_command(&mut app, "flip_canvas=v");
+    assert_eq!(painted_on(&app, 1), vec![(0, 3)]);
+    execute_command(&mut app, "rotate_canvas=270");
+    assert_eq!((app.canvas_width, app.canvas_height), (4, 2));
+    assert_eq!(painted_on(&app, 1), vec![(3, 1)]);
+    assert_eq!(painted_on(&app, 0), vec![(0, 0)]);
+
+    for _ in 0..4 {
+        app.undo();
+    }
+    assert_eq!((app.canvas_width, app.canvas_height), (4, 2));
+    assert_eq!(painted_on(&app, 1), vec![(0, 0)]);
+    assert_eq!(painted_on(&app, 0), vec![(3, 1)]);
+
+    execute_command(&mut app, "rotate_canvas=45");
+    assert_eq!((app.canvas_width, app.canvas_height), (4, 2), "only quarter turns");
+}
+
+fn painted_on(app: &consolet::App, layer: usize) -> Vec<(usize, usize)> {
+    let canvas = &app.layers[layer].canvas;
+    let mut dots: Vec<(usize, usize)> = (0..canvas.height())
+        .flat_map(|y| (0..canvas[y].len()).map(move |x| (x, y)))
+        .filter(|&(x, y)| canvas[y][x].alpha > 0.0)
+        .collect();
+    dots.sort();
+    dots
+}
+
+#[test]
+fn layer_structure_changes_undo_and_redo() {
+    let mut app = test_app(4, 4);
+    app.use_current_tool();
+    app.add_new_layer();
+    app.cursor_pos = (2, 2);
+    app.use_current_tool();
+    assert_eq!(app.layers.len(), 2);
+
+    // Deleting a layer by accident is recoverable.
+    app.delete_active_layer();
+    assert_eq!(app.layers.len(), 1);
+    app.undo();
+    assert_eq!(app.layers.len(), 2);
+    assert_eq!(active_pixel(&app, 2, 2).alpha, 1.0);
+    app.redo();
+    assert_eq!(app.layers.len(), 1);
+    app.undo();
+
+    app.move_layer_down();
+    assert_eq!((app.active_layer_index, app.layers[1].name.as_str()), (1, "Layer 2"));
+    app.undo();
+    assert_eq!((app.active_layer_index, app.layers[0].name.as_str()), (0, "Layer 2"));
+
+    execute_command(&mut app, "layer_opacity=0.25");
+    assert_eq!(app.layers[0].opacity, 0.25);
+    app.undo();
+    assert_eq!(app.layers[0].opacity, 1.0);
+    app.redo();
+    assert_eq!(app.layers[0].opacity, 0.25);
+
+    app.active_layer_index = 0;
+    execute_command(&mut app, "merge_down");
+    assert_eq!(app.layers.len(), 1);
+    app.undo();
+    assert_eq!(app.layers.len(), 2);
+    assert_eq!(app.layers[1].canvas[0][0].alpha, 1.0);
+
+    // Strokes recorded before the structural steps still undo, layer by layer.
+    while app.undo_stack.len() > 1 {
+        app.undo();
+    }
+    app.undo();
+    assert_eq!(app.layers.len(), 1);
+    assert!(app.layers[0].canvas.pixels().iter().all(|p| p.alpha == 0.0));
+}
+
+#[test]
+fn layer_snapshots_count_against_the_undo_memory_cap() {
+    let mut app = test_app(256, 256);
+    for _ in 0..9 {
+        app.add_new_layer();
+    }
+    // Each snapshot of the 10 layers is about 5 MB, so a 16 MB cap keeps three.
+    execute_command(&mut app, "undo_memory_mb=16");
+    for _ in 0..5 {
+        app.move_layer_down();
+        app.move_layer_up();
+    }
+    let total: usize = app.undo_stack.iter().map(|step| step.entry.approximate_size()).sum();
+    assert!(total <= 16 * 1024 * 1024);
+    assert_eq!(app.undo_stack.len(), 3);
+}
+
+#[test]
+fn lowering_the_undo_limit_trims_the_history_right_away() {
+    let mut app = test_app(4, 4);
+    for i in 0..30 {
+        app.cursor_pos = (i % 4, i / 4 % 4);
+        app.use_current_tool();
+    }
+    assert!(app.undo_stack.len() >= 30);
+    execute_command(&mut app, "undo_limit=10");
+    assert_eq!(app.undo_stack.len(), 10);
+    execute_command(&mut app, "undo_limit=5");
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Value out of range (10-1000).");
+
+    for _ in 0..10 {
+        app.undo();
+    }
+    app.undo();
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Nothing to undo (history limit reached)");
+    assert!(app.layers[0].canvas.pixels().iter().any(|p| p.alpha > 0.0), "the oldest strokes can no longer be undone");
+
+    let mut fresh = test_app(4, 4);
+    while !fresh.undo_stack.is_empty() {
+        fresh.undo();
+    }
+    fresh.undo();
+    assert_eq!(fresh.status_message.as_ref().unwrap().0, "Nothing to undo");
+}
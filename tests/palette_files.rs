@@ -0,0 +1,90 @@
+mod common;
+
+use common::{scratch_dir, test_app};
+use consolet::execute_command;
+use consolet::palette::{parse_gpl, parse_hex_palette, to_gpl, to_hex_palette, PaletteEntry};
+use ratatui::style::Color;
+use std::path::PathBuf;
+
+fn sample(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("palettes").join(name)
+}
+
+#[test]
+fn gpl_files_parse_with_comments_blank_lines_and_names() {
+    let text = std::fs::read_to_string(sample("sample.gpl")).unwrap();
+    assert_eq!(parse_gpl(&text).unwrap(), [Color::Rgb(0, 0, 0), Color::Rgb(255, 241, 232), Color::Rgb(29, 43, 83), Color::Rgb(126, 37, 83)]);
+    assert_eq!(parse_gpl("\u{feff}GIMP Palette\r\n1 2 3\r\n").unwrap(), [Color::Rgb(1, 2, 3)]);
+    assert_eq!(parse_gpl("GIMP Palette\n1 2\n").unwrap_err(), "line 2: expected 'R G B [name]', found '1 2'");
+    assert!(parse_gpl("256 0 0 Too Red").is_err());
+}
+
+#[test]
+fn hex_files_parse_with_or_without_hashes() {
+    let text = std::fs::read_to_string(sample("sample.hex")).unwrap();
+    assert_eq!(parse_hex_palette(&text).unwrap(), [Color::Rgb(0, 0, 0), Color::Rgb(255, 0, 77), Color::Rgb(41, 173, 255)]);
+    assert_eq!(parse_hex_palette("00ff00\nnope\n").unwrap_err(), "line 2: expected RRGGBB, found 'nope'");
+    assert!(parse_hex_palette("ff00ff00").is_err());
+}
+
+#[test]
+fn written_palettes_read_back_the_same() {
+    let colors = [Color::White, Color::Rgb(12, 34, 56)];
+    let gpl = to_gpl("Mine", &colors);
+    assert_eq!(gpl, "GIMP Palette\nName: Mine\n#\n255 255 255\tWhite\n 12  34  56\n");
+    assert_eq!(parse_gpl(&gpl).unwrap(), [Color::Rgb(255, 255, 255), Color::Rgb(12, 34, 56)]);
+    assert_eq!(to_hex_palette(&colors), "ffffff\n0c2238\n");
+    assert_eq!(parse_hex_palette(&to_hex_palette(&colors)).unwrap(), [Color::Rgb(255, 255, 255), Color::Rgb(12, 34, 56)]);
+}
+
+#[test]
+fn import_and_export_palette_commands() {
+    let mut app = test_app(4, 4);
+    execute_command(&mut app, &format!("import palette {}", sample("sample.gpl").display()));
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Palette 'sample' imported and saved.");
+    execute_command(&mut app, "colorpalette:sample --force");
+    assert!(app.color_palette[1] == PaletteEntry::Color(Color::Rgb(255, 241, 232)));
+    execute_command(&mut app, &format!("import palette {}", sample("sample.hex").display()));
+    assert_eq!(app.loaded_palettes["sample"].len(), 3, "the .hex file replaces the .gpl one of the same name");
+
+    let dir = scratch_dir().join("exported_palettes");
+    execute_command(&mut app, &format!("exportpalette:{}", dir.join("mine").display()));
+    assert_eq!(app.status_message.as_ref().unwrap().0, format!("Exported 4 colors to {}.", dir.join("mine.gpl").display()));
+    assert_eq!(parse_gpl(&std::fs::read_to_string(dir.join("mine.gpl")).unwrap()).unwrap().len(), 4);
+    execute_command(&mut app, &format!("exportpalette:{} --format=hex", dir.join("mine").display()));
+    assert_eq!(std::fs::read_to_string(dir.join("mine.hex")).unwrap().lines().next(), Some("000000"));
+    execute_command(&mut app, "exportpalette:mine --format=png");
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Usage: exportpalette:<name> [--format=gpl|hex]");
+}
+
+#[test]
+fn image_palettes_have_the_asked_size_sorted_dark_to_light() {
+    let mut app = test_app(4, 4);
+    let path = scratch_dir().join("palette_source.png");
+    let shades = [[250, 250, 250], [10, 10, 10], [200, 30, 30], [30, 200, 30], [30, 30, 200], [128, 128, 128]];
+    image::RgbImage::from_fn(6, 2, |x, _| image::Rgb(shades[x as usize])).save(&path).unwrap();
+
+    app.generate_palette_from_image(&path, false, 16);
+    assert_eq!(app.color_palette.len(), 6, "no more colors than the image has");
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Palette of 6 colors generated from image.");
+    assert!(app.color_palette[0] == PaletteEntry::Color(Color::Rgb(10, 10, 10)));
+    assert!(app.color_palette[5] == PaletteEntry::Color(Color::Rgb(250, 250, 250)));
+    app.generate_palette_from_image(&path, false, 4);
+    assert_eq!(app.color_palette.len(), 4);
+
+    // Big images are sampled rather than clustered pixel by pixel.
+    let big = scratch_dir().join("palette_source_big.png");
+    image::RgbImage::from_fn(1000, 400, |_, y| image::Rgb(if y < 200 { [0, 0, 0] } else { [255, 255, 255] })).save(&big).unwrap();
+    app.generate_palette_from_image(&big, false, 16);
+    assert_eq!(app.color_palette, [PaletteEntry::Color(Color::Rgb(0, 0, 0)), PaletteEntry::Color(Color::Rgb(255, 255, 255))]);
+
+    execute_command(&mut app, "colorpalette_image 24 --add");
+    assert_eq!(app.browser_scale_buffer, "24");
+    app.mode = consolet::AppMode::Drawing;
+    execute_command(&mut app, "paletteColors=8");
+    execute_command(&mut app, "colorpalette_image");
+    assert_eq!(app.browser_scale_buffer, "8");
+    app.mode = consolet::AppMode::Drawing;
+    execute_command(&mut app, "colorpalette_image 3");
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Usage: colorpalette_image [4-64] [--add]");
+}
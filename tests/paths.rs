@@ -0,0 +1,49 @@
+use consolet::utils::{create_parent_dirs, normalize_output_path};
+use std::path::{Path, PathBuf};
+
+#[test]
+fn expands_tilde() {
+    let home = PathBuf::from(shellexpand::tilde("~").into_owned());
+    let path = normalize_output_path(Path::new("/somewhere/else"), "~/art/sprite", "png").unwrap();
+    assert_eq!(path, home.join("art").join("sprite.png"));
+}
+
+#[test]
+fn resolves_nested_relative_paths_against_base() {
+    let base = Path::new("/projects");
+    let path = normalize_output_path(base, "sheets/walk/frame1", "png").unwrap();
+    assert_eq!(path, base.join("sheets").join("walk").join("frame1.png"));
+    let absolute = normalize_output_path(base, "/tmp/out", "png").unwrap();
+    assert_eq!(absolute, PathBuf::from("/tmp/out.png"));
+}
+
+#[test]
+fn appends_extension_case_insensitively() {
+    let base = Path::new("/projects");
+    assert_eq!(normalize_output_path(base, "ART.PNG", "png").unwrap(), base.join("ART.PNG"));
+    assert_eq!(normalize_output_path(base, "art.Png", "png").unwrap(), base.join("art.Png"));
+    assert_eq!(normalize_output_path(base, "art.jpg", "png").unwrap(), base.join("art.jpg.png"));
+    assert_eq!(normalize_output_path(base, "scene", "consolet").unwrap(), base.join("scene.consolet"));
+    assert_eq!(normalize_output_path(base, "\"my art\"", "png").unwrap(), base.join("my art.png"));
+}
+
+#[test]
+fn rejects_empty_and_invalid_names() {
+    let base = Path::new("/projects");
+    assert!(normalize_output_path(base, "", "png").is_err());
+    assert!(normalize_output_path(base, "  ", "png").is_err());
+    assert!(normalize_output_path(base, "folder/", "png").is_err());
+    assert!(normalize_output_path(base, "bad\0name", "png").is_err());
+    #[cfg(windows)]
+    assert!(normalize_output_path(base, "what?.png", "png").is_err());
+}
+
+#[test]
+fn creates_intermediate_directories() {
+    let root = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("paths").join("nested");
+    let _ = std::fs::remove_dir_all(&root);
+    let file = root.join("a").join("b").join("out.png");
+    create_parent_dirs(&file).unwrap();
+    assert!(root.join("a").join("b").is_dir());
+    create_parent_dirs(Path::new("plain.png")).unwrap();
+}
@@ -0,0 +1,449 @@
+mod common;
+
+use common::{scratch_dir, test_app};
+use consolet::palette::PaletteEntry;
+use consolet::{BlendMode, PenShape, Pixel, SymmetryMode};
+use ratatui::style::Color;
+use std::io::Write;
+
+#[test]
+fn session_state_survives_save_and_load() {
+    let mut app = test_app(8, 6);
+    app.zoom_level = 6;
+    app.view_offset_x = 2;
+    app.view_offset_y = 1;
+    app.cursor_pos = (5, 4);
+    app.symmetry_mode = SymmetryMode::Quad(3, 2);
+    app.pen_size = 4;
+    app.pen_shape = PenShape::Square;
+    app.opacity = 0.4;
+    app.active_palette_name = "pico8".to_string();
+    std::fs::create_dir_all(scratch_dir()).unwrap();
+    let path = scratch_dir().join("session.consolet");
+    app.save_project(&path, false);
+
+    let mut loaded = test_app(2, 2);
+    loaded.load_project(&path);
+    assert_eq!((loaded.canvas_width, loaded.canvas_height), (8, 6));
+    assert_eq!(loaded.zoom_level, 6);
+    assert_eq!((loaded.view_offset_x, loaded.view_offset_y), (2, 1));
+    assert_eq!(loaded.cursor_pos, (5, 4));
+    assert_eq!(loaded.symmetry_mode, SymmetryMode::Quad(3, 2));
+    assert_eq!((loaded.pen_size, loaded.pen_shape), (4, PenShape::Square));
+    assert_eq!(loaded.opacity, 0.4);
+    assert_eq!(loaded.active_palette_name, "pico8");
+
+    let mut clean = test_app(2, 2);
+    consolet::execute_command(&mut clean, "restore_session=false");
+    clean.load_project(&path);
+    assert_eq!(clean.pen_size, 1);
+    assert_eq!(clean.symmetry_mode, SymmetryMode::Off);
+}
+
+#[test]
+fn projects_without_a_session_still_load() {
+    std::fs::create_dir_all(scratch_dir()).unwrap();
+    let path = scratch_dir().join("legacy.consolet");
+    let pixel = r#"{"color":[0,0,0],"alpha":0.0}"#;
+    let json = format!(r#"{{"width":2,"height":1,"canvas":[[{pixel},{pixel}]],"palette":[[255,0,0]],"layers":null,"active_layer_index":null}}"#);
+    let mut encoder = flate2::write::GzEncoder::new(std::fs::File::create(&path).unwrap(), flate2::Compression::default());
+    encoder.write_all(json.as_bytes()).unwrap();
+    encoder.finish().unwrap();
+
+    let mut app = test_app(4, 4);
+    app.pen_size = 3;
+    app.load_project(&path);
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Loaded legacy.consolet");
+    assert_eq!((app.canvas_width, app.canvas_height), (2, 1));
+    assert_eq!(app.pen_size, 3, "missing session fields keep the current values");
+}
+
+#[test]
+fn autosave_rotates_backups_and_offers_them_on_load() {
+    let dir = scratch_dir().join("autosave");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let project = dir.join("art.consolet");
+    let mut app = test_app(4, 4);
+    app.save_project(&project, true);
+    consolet::execute_command(&mut app, "autosave_rotations=2");
+    std::fs::write(dir.join("art.autosave-5.consolet"), "stale").unwrap();
+
+    app.apply_brush(1, 1);
+    for _ in 0..3 {
+        app.autosave();
+    }
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Autosaved to art.autosave-1.consolet", "the third autosave wraps around");
+    let mut names: Vec<String> = std::fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).collect();
+    names.sort();
+    assert_eq!(names, ["art.autosave-1.consolet", "art.autosave-2.consolet", "art.consolet"]);
+
+    // The project itself is untouched and now older than its autosaves.
+    let an_hour_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+    std::fs::File::options().write(true).open(&project).unwrap().set_modified(an_hour_ago).unwrap();
+    let mut loaded = test_app(4, 4);
+    loaded.load_project(&project);
+    assert_eq!(loaded.layers[0].canvas[1][1].alpha, 0.0);
+    assert!(loaded.mode == consolet::AppMode::ConfirmRestoreAutosave);
+
+    loaded.confirm_restore_autosave(true);
+    assert!(loaded.mode == consolet::AppMode::Drawing);
+    assert_eq!(loaded.layers[0].canvas[1][1].alpha, 1.0);
+    assert_eq!(loaded.project_path.as_deref(), Some(project.as_path()), "save still targets the project");
+}
+
+#[test]
+fn quit_asks_first_when_there_are_unsaved_changes() {
+    let dir = scratch_dir().join("dirty");
+    std::fs::create_dir_all(&dir).unwrap();
+    let project = dir.join("art.consolet");
+    let mut app = test_app(4, 4);
+    app.save_project(&project, true);
+    app.use_current_tool();
+    assert!(app.dirty);
+    app.quit();
+    assert!(!app.should_quit);
+    assert!(app.mode == consolet::AppMode::ConfirmQuit);
+    app.confirm_quit(consolet::QuitChoice::No);
+    assert!(app.mode == consolet::AppMode::Drawing && !app.should_quit);
+
+    app.save_project(&project, true);
+    app.add_new_layer();
+    app.autosave();
+    assert!(app.dirty, "autosaves leave the project unsaved");
+    app.quit();
+    app.confirm_quit(consolet::QuitChoice::Save);
+    assert!(app.should_quit);
+    assert!(!app.dirty);
+
+    let mut loaded = test_app(4, 4);
+    loaded.load_project(&project);
+    assert_eq!(loaded.layers.len(), 2);
+    assert!(!loaded.dirty);
+    loaded.quit();
+    assert!(loaded.should_quit, "a freshly loaded project quits right away");
+    loaded.should_quit = false;
+    consolet::execute_command(&mut loaded, "#123456");
+    assert!(loaded.dirty, "new palette colors count as changes");
+}
+
+#[test]
+fn plain_and_compressed_projects_round_trip() {
+    let dir = scratch_dir().join("formats");
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut app = test_app(3, 2);
+    app.use_current_tool();
+    app.add_new_layer();
+    app.layers[0].name = "Ink".to_string();
+
+    let compressed = dir.join("art.consolet");
+    let plain = dir.join("art.consolet.json");
+    app.save_project(&compressed, false);
+    app.save_project(&plain, false);
+    assert_eq!(&std::fs::read(&compressed).unwrap()[..2], &[0x1f, 0x8b]);
+    let text = std::fs::read_to_string(&plain).unwrap();
+    assert!(text.starts_with("{\n") && text.contains("\n  \"width\": 3,"), "plain projects are pretty-printed JSON");
+
+    for path in [&compressed, &plain] {
+        let mut loaded = test_app(5, 5);
+        loaded.load_project(path);
+        assert_eq!((loaded.canvas_width, loaded.canvas_height), (3, 2));
+        assert_eq!(loaded.layers.len(), 2);
+        assert_eq!(loaded.layers[0].name, "Ink");
+        assert_eq!(loaded.layers[1].canvas[0][0].alpha, 1.0);
+    }
+
+    // The format is sniffed, not taken from the extension.
+    let renamed = dir.join("renamed.consolet");
+    std::fs::copy(&plain, &renamed).unwrap();
+    let mut loaded = test_app(5, 5);
+    loaded.load_project(&renamed);
+    assert_eq!(loaded.status_message.as_ref().unwrap().0, "Loaded renamed.consolet");
+    assert_eq!(loaded.layers.len(), 2);
+}
+
+#[test]
+fn layer_blend_modes_are_saved_and_default_to_normal() {
+    let dir = scratch_dir().join("blend_modes");
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut app = test_app(2, 2);
+    app.add_new_layer();
+    app.layers[0].blend_mode = BlendMode::Screen;
+    let path = dir.join("blend.consolet.json");
+    app.save_project(&path, false);
+
+    let mut loaded = test_app(2, 2);
+    loaded.load_project(&path);
+    assert_eq!((loaded.layers[0].blend_mode, loaded.layers[1].blend_mode), (BlendMode::Screen, BlendMode::Normal));
+
+    // Projects from before blend modes have no blend_mode on their layers.
+    let mut project: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    for layer in project["layers"].as_array_mut().unwrap() {
+        layer.as_object_mut().unwrap().remove("blend_mode").unwrap();
+    }
+    let legacy = dir.join("legacy.consolet.json");
+    std::fs::write(&legacy, project.to_string()).unwrap();
+    let mut loaded = test_app(2, 2);
+    loaded.load_project(&legacy);
+    assert_eq!(loaded.layers.len(), 2);
+    assert!(loaded.layers.iter().all(|layer| layer.blend_mode == BlendMode::Normal));
+}
+
+#[test]
+fn alpha_lock_is_saved_with_the_layer() {
+    let dir = scratch_dir().join("alpha_lock");
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut app = test_app(2, 2);
+    app.add_new_layer();
+    app.toggle_alpha_lock();
+    let path = dir.join("alpha_lock.consolet.json");
+    app.save_project(&path, false);
+
+    let mut loaded = test_app(2, 2);
+    loaded.load_project(&path);
+    assert_eq!((loaded.layers[0].alpha_locked, loaded.layers[1].alpha_locked), (true, false));
+}
+
+#[test]
+fn save_plain_flag_and_default_pick_the_json_format() {
+    let dir = scratch_dir().join("plain_save");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut app = test_app(2, 2);
+    consolet::execute_command(&mut app, &format!("save flagged --plain -p {}", dir.display()));
+    assert!(dir.join("flagged.consolet.json").exists());
+
+    consolet::execute_command(&mut app, "plain_project_save=true");
+    consolet::execute_command(&mut app, &format!("save by_default -p {}", dir.display()));
+    consolet::execute_command(&mut app, &format!("save explicit.consolet -p {}", dir.display()));
+    assert!(dir.join("by_default.consolet.json").exists());
+    assert!(dir.join("explicit.consolet").exists());
+    assert_eq!(app.project_path.as_deref(), Some(dir.join("explicit.consolet").as_path()));
+
+    // Picking the plain file in the browser loads it like any other project.
+    let mut loaded = test_app(4, 4);
+    consolet::file_browser::open_browser(&mut loaded, consolet::file_browser::BrowserMode::Load);
+    loaded.browser_entries.push(dir.join("by_default.consolet.json"));
+    loaded.browser_list_state.select(Some(loaded.browser_entries.len() - 1));
+    let enter = crossterm::event::KeyEvent::new(crossterm::event::KeyCode::Enter, crossterm::event::KeyModifiers::NONE);
+    consolet::file_browser::handle_browser_input(&mut loaded, Some(enter), None);
+    assert!(loaded.mode == consolet::AppMode::Drawing);
+    assert_eq!((loaded.canvas_width, loaded.canvas_height), (2, 2));
+    assert_eq!(loaded.status_message.as_ref().unwrap().0, "Loaded by_default.consolet.json");
+}
+
+#[test]
+fn mostly_empty_canvases_are_run_length_encoded() {
+    let mut app = test_app(512, 512);
+    for _ in 0..3 {
+        app.add_new_layer();
+    }
+    for (i, layer) in app.layers.iter_mut().enumerate() {
+        for x in 0..40 {
+            layer.canvas[100 + i * 50][x * 3] = consolet::Pixel { color: consolet::SerializableColor(200, i as u8 * 40, 10), alpha: 1.0 };
+        }
+    }
+    app.sync_canvas_from_layers();
+    let dir = scratch_dir().join("rle");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("big.consolet.json");
+    app.save_project(&path, false);
+
+    // What the per-pixel encoding would take for the same canvases.
+    let per_pixel: usize = app.layers.iter().map(|layer| &layer.canvas).chain([&app.canvas])
+        .map(|canvas| serde_json::to_string(&canvas.to_rows()).unwrap().len())
+        .sum();
+    let saved = std::fs::metadata(&path).unwrap().len() as usize;
+    assert!(saved * 10 < per_pixel, "RLE project is {} bytes, per-pixel canvases alone are {}", saved, per_pixel);
+    let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(json["version"], consolet::PROJECT_VERSION);
+    assert_eq!(json["layers"][0]["canvas"]["runs"][0][1], 100 * 512, "everything above the first painted row is one run");
+
+    let mut loaded = test_app(2, 2);
+    loaded.load_project(&path);
+    assert_eq!(loaded.layers.len(), 4);
+    for (loaded_layer, layer) in loaded.layers.iter().zip(&app.layers) {
+        assert!(loaded_layer.canvas == layer.canvas);
+    }
+}
+
+#[test]
+fn run_length_canvases_must_cover_the_whole_canvas() {
+    std::fs::create_dir_all(scratch_dir()).unwrap();
+    let path = scratch_dir().join("short_runs.consolet.json");
+    let pixel = r#"{"color":[0,0,0],"alpha":0.0}"#;
+    std::fs::write(&path, format!(r#"{{"version":2,"width":2,"height":2,"canvas":{{"width":2,"height":2,"runs":[[{pixel},3]]}},"palette":[],"layers":null,"active_layer_index":null}}"#)).unwrap();
+    let mut app = test_app(4, 4);
+    app.load_project(&path);
+    assert!(app.status_message.as_ref().unwrap().0.contains("run-length canvas has 3 pixels, expected 2x2"));
+    assert_eq!((app.canvas_width, app.canvas_height), (4, 4));
+
+    std::fs::write(&path, format!(r#"{{"version":3,"width":1,"height":1,"canvas":[[{pixel}]],"palette":[],"layers":null,"active_layer_index":null}}"#)).unwrap();
+    app.load_project(&path);
+    assert!(app.status_message.as_ref().unwrap().0.starts_with("Project format 3 is newer"));
+}
+
+#[test]
+fn layers_and_references_must_match_the_project_size() {
+    std::fs::create_dir_all(scratch_dir()).unwrap();
+    let path = scratch_dir().join("short_layer.consolet.json");
+    let mut app = test_app(3, 2);
+    app.reference = Some(app.layers[0].canvas.clone());
+    app.save_project(&path, false);
+    let saved: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    let pixel = r#"{"color":[0,0,0],"alpha":0.0}"#;
+    let short_canvas: serde_json::Value = serde_json::from_str(&format!("[[{pixel},{pixel}]]")).unwrap();
+
+    let mut short_layer = saved.clone();
+    short_layer["layers"][0]["canvas"] = short_canvas.clone();
+    std::fs::write(&path, short_layer.to_string()).unwrap();
+    let mut loaded = test_app(4, 4);
+    assert!(!loaded.load_project_with(&path, false));
+    assert_eq!(loaded.status_message.as_ref().unwrap().0, "Cannot load project: Layer 'Layer 1' is 2x1, but the project is 3x2.");
+    assert_eq!((loaded.canvas_width, loaded.canvas_height), (4, 4));
+
+    let mut short_reference = saved.clone();
+    short_reference["session"]["reference"] = short_canvas;
+    std::fs::write(&path, short_reference.to_string()).unwrap();
+    assert!(!loaded.load_project_with(&path, false));
+    assert_eq!(loaded.status_message.as_ref().unwrap().0, "Cannot load project: The reference image is 2x1, but the project is 3x2.");
+
+    let mut no_layers = saved.clone();
+    no_layers["layers"] = serde_json::json!([]);
+    std::fs::write(&path, no_layers.to_string()).unwrap();
+    assert!(!loaded.load_project_with(&path, false));
+    assert_eq!(loaded.status_message.as_ref().unwrap().0, "Cannot load project: The project has no layers.");
+
+    std::fs::write(&path, saved.to_string()).unwrap();
+    assert!(loaded.load_project_with(&path, false));
+    assert_eq!(loaded.reference.as_ref().map(|r| r.size()), Some((3, 2)));
+}
+
+#[test]
+fn busy_canvases_are_saved_as_rows_of_pixels() {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    let mut rng = StdRng::seed_from_u64(2307);
+    let mut app = test_app(5, 3);
+    for pixel in app.layers[0].canvas.pixels_mut() {
+        *pixel = consolet::Pixel { color: consolet::SerializableColor(rng.gen(), rng.gen(), rng.gen()), alpha: 1.0 };
+    }
+    app.sync_canvas_from_layers();
+    let dir = scratch_dir().join("rows");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("noise.consolet.json");
+    app.save_project(&path, false);
+
+    let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    let rows = json["layers"][0]["canvas"].as_array().unwrap();
+    assert_eq!(rows.len(), 3);
+    assert!(rows.iter().all(|row| row.as_array().unwrap().len() == 5));
+    assert_eq!(json["layers"][0]["canvas"][2][4]["color"][0], app.layers[0].canvas[2][4].color.0);
+
+    let mut loaded = test_app(2, 2);
+    loaded.load_project(&path);
+    assert!(loaded.layers[0].canvas == app.layers[0].canvas);
+}
+
+#[test]
+fn canvases_from_ragged_rows_take_the_first_row_width() {
+    let red = consolet::Pixel { color: consolet::SerializableColor(255, 0, 0), alpha: 1.0 };
+    let canvas = consolet::Canvas::from_rows(vec![vec![red; 3], vec![red; 1], vec![red; 5]]);
+    assert_eq!(canvas.size(), (3, 3));
+    assert_eq!(canvas[1], [red, consolet::Pixel::default(), consolet::Pixel::default()]);
+    assert_eq!(canvas.get(2, 2), Some(&red));
+    assert_eq!(canvas.get(3, 0), None);
+    assert_eq!(canvas.to_rows()[2].len(), 3);
+}
+
+#[test]
+fn embedded_palettes_travel_with_the_project() {
+    let dir = scratch_dir().join("embedded_palettes");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let colors = |rgbs: &[(u8, u8, u8)]| rgbs.iter().map(|&(r, g, b)| PaletteEntry::Color(Color::Rgb(r, g, b))).collect::<Vec<_>>();
+
+    let mut app = test_app(2, 2);
+    app.loaded_palettes.insert("warm".to_string(), colors(&[(200, 40, 10), (250, 180, 0)]));
+    app.loaded_palettes.insert("cool".to_string(), colors(&[(0, 80, 200)]));
+    app.loaded_palettes.insert("unused".to_string(), colors(&[(1, 2, 3)]));
+    app.active_palette_name = "warm".to_string();
+    app.layers[0].canvas[0][0] = Pixel { color: Color::Rgb(0, 80, 200).into(), alpha: 1.0 };
+
+    consolet::execute_command(&mut app, &format!("save plain --plain -p {}", dir.display()));
+    let json = std::fs::read_to_string(dir.join("plain.consolet.json")).unwrap();
+    assert!(!json.contains("\"palettes\""), "palettes are only embedded on request");
+
+    consolet::execute_command(&mut app, &format!("save art --embed-palettes -p {}", dir.display()));
+    let path = dir.join("art.consolet");
+    assert!(!app.embed_palettes_once, "the flag only applies to that save");
+
+    let mut loaded = test_app(2, 2);
+    loaded.loaded_palettes.insert("cool".to_string(), colors(&[(9, 9, 9)]));
+    consolet::execute_command(&mut loaded, &format!("load {}", path.display()));
+    assert_eq!(loaded.loaded_palettes["warm"], colors(&[(200, 40, 10), (250, 180, 0)]));
+    assert_eq!(loaded.loaded_palettes["cool"], colors(&[(9, 9, 9)]), "same-named local palettes are kept");
+    assert!(!loaded.loaded_palettes.contains_key("unused"));
+    assert_eq!(loaded.status_message.as_ref().unwrap().0, "Loaded art.consolet (kept local palette cool; load with --force to use the project's)");
+
+    consolet::execute_command(&mut loaded, &format!("load {} --force", path.display()));
+    assert_eq!(loaded.loaded_palettes["cool"], colors(&[(0, 80, 200)]));
+    assert_eq!(loaded.status_message.as_ref().unwrap().0, "Loaded art.consolet (replaced local palette cool)");
+
+    // The config default embeds them on every save.
+    consolet::execute_command(&mut app, "embed_palettes=true");
+    consolet::execute_command(&mut app, &format!("save plain --plain -f -p {}", dir.display()));
+    let json = std::fs::read_to_string(dir.join("plain.consolet.json")).unwrap();
+    assert!(json.contains("\"warm\"") && json.contains("\"cool\""));
+}
+
+#[test]
+fn custom_brush_is_restored_with_the_project() {
+    let dir = scratch_dir().join("brush_project");
+    std::fs::create_dir_all(&dir).unwrap();
+    let brush = dir.join("soft.png");
+    image::GrayAlphaImage::from_raw(2, 1, vec![255, 255, 128, 255]).unwrap().save(&brush).unwrap();
+    let path = dir.join("brushed.consolet");
+
+    // Loaded by a relative path, the brush is saved with an absolute one.
+    let relative = brush.strip_prefix(std::env::current_dir().unwrap()).unwrap();
+    let mut app = test_app(4, 4);
+    app.load_brush(relative.to_str().unwrap()).unwrap();
+    assert!(std::path::Path::new(app.brush_path.as_ref().unwrap()).is_absolute());
+    app.save_project(&path, true);
+
+    let mut loaded = test_app(4, 4);
+    loaded.load_project(&path);
+    assert_eq!(loaded.status_message.as_ref().unwrap().0, "Loaded brushed.consolet");
+    assert_eq!(loaded.brush_mask, app.brush_mask);
+    assert_eq!(loaded.brush_path, app.brush_path);
+
+    std::fs::remove_file(&brush).unwrap();
+    loaded.load_project(&path);
+    assert!(loaded.status_message.as_ref().unwrap().0.starts_with("Loaded brushed.consolet, but the custom brush could not be restored:"));
+    assert!(loaded.brush_mask.is_none() && loaded.brush_path.is_none());
+}
+
+#[test]
+fn last_export_survives_save_and_load() {
+    let dir = scratch_dir().join("last_export");
+    std::fs::create_dir_all(&dir).unwrap();
+    let export_path = dir.join("out.png");
+    let path = dir.join("exported.consolet");
+    let mut app = test_app(3, 2);
+    app.apply_brush(1, 1);
+    assert!(consolet::execute_command(&mut app, &format!("export -o {} -u 3 -bgcolor #102030 --autocrop", export_path.display())));
+    app.save_project(&path, true);
+
+    let mut loaded = test_app(3, 2);
+    loaded.load_project(&path);
+    let settings = loaded.last_export.as_ref().expect("last export is restored");
+    assert_eq!(settings.path, export_path.display().to_string());
+    assert_eq!(settings.scale, 3);
+    assert!(!settings.transparent);
+    assert_eq!(settings.background, consolet::SerializableColor(16, 32, 48));
+    assert!(settings.autocrop && !settings.selection_only);
+
+    std::fs::remove_file(&export_path).unwrap();
+    assert!(consolet::execute_command(&mut loaded, "reexport"));
+    assert_eq!(image::open(&export_path).unwrap().to_rgba8().dimensions(), (3, 3), "the restored settings are used");
+}
@@ -0,0 +1,36 @@
+mod common;
+
+use common::{scratch_dir, test_app};
+
+#[test]
+fn saves_and_loads_are_remembered_newest_first() {
+    let dir = scratch_dir().join("recent");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("nested")).unwrap();
+    let mut app = test_app(4, 4);
+    for i in 0..12 {
+        app.save_project(&dir.join(format!("art{}.consolet", i)), true);
+    }
+    let recent = consolet::project::read_recent_projects();
+    assert_eq!(recent.len(), consolet::project::MAX_RECENT_PROJECTS);
+    assert_eq!(recent[0].file_name().unwrap(), "art11.consolet");
+    assert_eq!(recent[9].file_name().unwrap(), "art2.consolet");
+
+    // The same file reached another way is moved up, not listed twice.
+    app.load_project(&dir.join("nested").join("..").join("art5.consolet"));
+    let recent = consolet::project::read_recent_projects();
+    assert_eq!(recent[0], std::fs::canonicalize(dir.join("art5.consolet")).unwrap());
+    assert_eq!(recent.iter().filter(|p| p.ends_with("art5.consolet")).count(), 1);
+
+    // Deleted projects drop out, and autosaves are not recorded.
+    std::fs::remove_file(dir.join("art11.consolet")).unwrap();
+    app.autosave();
+    app.restore_autosave();
+    let recent = consolet::project::read_recent_projects();
+    assert!(recent.iter().all(|p| !p.ends_with("art11.consolet") && !p.to_string_lossy().contains(".autosave-")));
+
+    let suggestions = app.get_suggestions("recent art1");
+    assert_eq!(suggestions, [format!("load {}", recent.iter().find(|p| p.ends_with("art10.consolet")).unwrap().display())]);
+    consolet::execute_command(&mut app, &suggestions[0]);
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Loaded art10.consolet");
+}
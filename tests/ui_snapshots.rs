@@ -0,0 +1,723 @@
+mod common;
+
+use common::{scratch_dir, select_color, test_app};
+use consolet::{command_palette, controller::handle_mouse_event, ui::ui, App, AppMode, MinimapMode};
+use crossterm::event::{KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use ratatui::{backend::TestBackend, Terminal};
+use std::path::PathBuf;
+
+/// Renders one frame and returns it as plain text, one line per terminal row.
+fn render(app: &mut App, width: u16, height: u16) -> String {
+    let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+    terminal.draw(|frame| ui(frame, app)).unwrap();
+    let buffer = terminal.backend().buffer();
+    let mut text = String::new();
+    for y in 0..buffer.area.height {
+        let row: String = (0..buffer.area.width).map(|x| buffer.get(x, y).symbol()).collect();
+        text.push_str(row.trim_end());
+        text.push('\n');
+    }
+    text
+}
+
+/// Compares against tests/snapshots/<name>.txt. Run with UPDATE_SNAPSHOTS=1 to
+/// (re)write the snapshot after an intentional UI change.
+fn assert_snapshot(name: &str, actual: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("snapshots").join(format!("{}.txt", name));
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("missing snapshot {}; run with UPDATE_SNAPSHOTS=1", path.display()));
+    assert!(expected == actual, "snapshot '{}' differs.\n--- expected\n{}\n--- actual\n{}", name, expected, actual);
+}
+
+#[test]
+fn drawing_mode() {
+    let mut app = test_app(12, 8);
+    app.apply_brush(2, 2);
+    app.apply_brush(3, 3);
+    app.cursor_pos = (5, 4);
+    assert_snapshot("drawing_mode", &render(&mut app, 80, 24));
+}
+
+#[test]
+fn drawing_mode_status_flags() {
+    let mut app = test_app(12, 8);
+    app.flat_mode = true;
+    app.pixel_block = 2;
+    app.stabilizer = 4;
+    let screen = render(&mut app, 120, 24);
+    assert!(screen.contains("FLAT | BLK 2 | STB 4"), "{}", screen);
+}
+
+#[test]
+fn command_palette() {
+    let mut app = test_app(12, 8);
+    command_palette::open_command_palette(&mut app);
+    app.command_palette_query = "layer".to_string();
+    assert!(app.mode == AppMode::CommandPalette);
+    assert_snapshot("command_palette", &render(&mut app, 80, 24));
+}
+
+#[test]
+fn layer_list_popup() {
+    let mut app = test_app(12, 8);
+    app.apply_brush(1, 1);
+    consolet::execute_command(&mut app, "add_layer");
+    consolet::execute_command(&mut app, "layer list");
+    assert_snapshot("layer_list_popup", &render(&mut app, 80, 24));
+}
+
+#[test]
+fn tile_preview_repeats_the_canvas_around_it() {
+    let mut app = test_app(4, 4);
+    app.apply_brush(0, 0);
+    app.tile_preview = true;
+    let screen = render(&mut app, 80, 24);
+    assert!(screen.contains("| TILE"), "{}", screen);
+
+    let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+    terminal.draw(|frame| ui(frame, &mut app)).unwrap();
+    let rect = app.last_centered_canvas_rect.unwrap();
+    let buffer = terminal.backend().buffer();
+    let red = ratatui::style::Color::Rgb(255, 0, 0);
+    // Pixel (0, 0) of the copies to the right of and below the canvas.
+    assert_eq!(buffer.get(rect.x + rect.width, rect.y).bg, red);
+    assert_eq!(buffer.get(rect.x, rect.y + rect.height).bg, red);
+    // Its transparent neighbor (1, 0) stays empty.
+    assert_eq!(buffer.get(rect.x + rect.width + app.zoom_level, rect.y).bg, ratatui::style::Color::Reset);
+}
+
+#[test]
+fn transparency_checker_fills_transparent_pixels() {
+    let mut app = test_app(4, 4);
+    consolet::execute_command(&mut app, "transparency_checker=true");
+    consolet::execute_command(&mut app, "checker_size=2");
+    consolet::execute_command(&mut app, "checker_light=#CCCCCC");
+    app.opacity = 0.5;
+    app.apply_brush(3, 3);
+    // Keep the cursor preview off the cells checked below.
+    app.cursor_pos = (0, 3);
+
+    let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+    terminal.draw(|frame| ui(frame, &mut app)).unwrap();
+    let rect = app.last_centered_canvas_rect.unwrap();
+    let buffer = terminal.backend().buffer();
+    let cell = |x: u16, y: u16| buffer.get(rect.x + x * app.zoom_level, rect.y + y * (app.zoom_level / 2)).bg;
+    assert_eq!(cell(0, 0), ratatui::style::Color::Rgb(204, 204, 204));
+    assert_eq!(cell(1, 1), ratatui::style::Color::Rgb(204, 204, 204), "cells are 2 pixels wide");
+    assert_eq!(cell(2, 0), ratatui::style::Color::Rgb(102, 102, 102));
+    // Half-transparent red over the light cell at (3, 3), not over black.
+    assert_eq!(cell(3, 3), ratatui::style::Color::Rgb(230, 102, 102));
+}
+
+#[test]
+fn reference_image_shows_through_transparent_pixels_only() {
+    let mut app = test_app(4, 4);
+    std::fs::create_dir_all(scratch_dir()).unwrap();
+    let path = scratch_dir().join("reference.png");
+    // Twice the canvas size, so it is shrunk to fit.
+    image::RgbaImage::from_pixel(8, 8, image::Rgba([0, 0, 200, 255])).save(&path).unwrap();
+    consolet::execute_command(&mut app, &format!("reference {}", path.display()));
+    consolet::execute_command(&mut app, "reference_opacity=0.5");
+    app.apply_brush(3, 3);
+    app.cursor_pos = (0, 3);
+
+    let draw = |app: &mut App| {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        terminal.draw(|frame| ui(frame, app)).unwrap();
+        let rect = app.last_centered_canvas_rect.unwrap();
+        let (buffer, zoom) = (terminal.backend().buffer().clone(), app.zoom_level);
+        move |x: u16, y: u16| buffer.get(rect.x + x * zoom, rect.y + y * (zoom / 2)).bg
+    };
+    let cell = draw(&mut app);
+    assert_eq!(cell(0, 0), ratatui::style::Color::Rgb(0, 0, 100));
+    assert_eq!(cell(3, 3), ratatui::style::Color::Rgb(255, 0, 0), "opaque pixels cover it");
+    assert!(app.canvas.pixels().iter().filter(|p| p.alpha > 0.0).count() == 1, "the composite leaves it out");
+
+    consolet::controller::execute_action(&mut app, consolet::keybindings::Action::ToggleReference).unwrap();
+    let cell = draw(&mut app);
+    assert_eq!(cell(0, 0), ratatui::style::Color::Reset);
+}
+
+#[test]
+fn pixel_grid_appears_from_the_minimum_zoom() {
+    let mut app = test_app(16, 4);
+    consolet::execute_command(&mut app, "grid=true");
+    consolet::execute_command(&mut app, "gridSpacing=8");
+    app.cursor_pos = (15, 3);
+    app.zoom_level = 4;
+    let draw = |app: &mut App| {
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        terminal.draw(|frame| ui(frame, app)).unwrap();
+        terminal.backend().buffer().clone()
+    };
+
+    let buffer = draw(&mut app);
+    let rect = app.last_centered_canvas_rect.unwrap();
+    assert_eq!(buffer.get(rect.x, rect.y).bg, ratatui::style::Color::Reset, "below gridMinZoom");
+
+    app.zoom_level = 8;
+    let buffer = draw(&mut app);
+    let rect = app.last_centered_canvas_rect.unwrap();
+    let (minor, major) = (buffer.get(rect.x + 8, rect.y + 1).bg, buffer.get(rect.x + 64, rect.y + 1).bg);
+    assert_ne!(minor, ratatui::style::Color::Reset);
+    assert_ne!(major, minor, "every 8th pixel line is brighter");
+    assert_eq!(buffer.get(rect.x + 9, rect.y + 1).bg, ratatui::style::Color::Reset, "inside a pixel");
+}
+
+#[test]
+fn minimap_shows_the_canvas_as_half_blocks() {
+    let mut app = test_app(120, 100);
+    app.pen_size = 8;
+    app.apply_brush(60, 50);
+    let screen = render(&mut app, 120, 40);
+    assert!(screen.contains("Minimap"), "{}", screen);
+    assert!(screen.contains("▀▀▀"), "{}", screen);
+}
+
+#[test]
+fn minimap_is_rebuilt_only_when_the_picture_changes() {
+    let mut app = test_app(120, 100);
+    app.pen_size = 8;
+    app.apply_brush(60, 50);
+    let cells = |app: &App| app.minimap_cache.as_ref().unwrap().cells.as_ptr();
+    render(&mut app, 120, 40);
+    let first = cells(&app);
+    app.view_offset_x += 3;
+    render(&mut app, 120, 40);
+    assert_eq!(cells(&app), first, "panning only moves the highlight");
+
+    app.layers[0].opacity = 0.5;
+    render(&mut app, 120, 40);
+    let after_opacity = cells(&app);
+    assert_ne!(after_opacity, first);
+
+    app.apply_brush(10, 10);
+    render(&mut app, 120, 40);
+    assert_ne!(cells(&app), after_opacity);
+}
+
+#[test]
+fn middle_and_shift_left_drags_pan_without_moving_the_cursor() {
+    let mut app = test_app(64, 64);
+    render(&mut app, 100, 40);
+    app.zoom_level = 8;
+    app.cursor_pos = (3, 4);
+    render(&mut app, 100, 40);
+    let rect = app.last_centered_canvas_rect.unwrap();
+    let mouse = |kind, column, row, modifiers| MouseEvent { kind, column, row, modifiers };
+    let (column, row) = (rect.x + 40, rect.y + 10);
+
+    handle_mouse_event(&mut app, mouse(MouseEventKind::Down(MouseButton::Middle), column, row, KeyModifiers::NONE));
+    handle_mouse_event(&mut app, mouse(MouseEventKind::Drag(MouseButton::Middle), column - 24, row - 8, KeyModifiers::NONE));
+    assert_eq!((app.view_offset_x, app.view_offset_y), (3, 2), "dragging left and up reveals more of the right and bottom");
+    handle_mouse_event(&mut app, mouse(MouseEventKind::Up(MouseButton::Middle), column - 24, row - 8, KeyModifiers::NONE));
+    assert!(app.pan_anchor.is_none());
+
+    handle_mouse_event(&mut app, mouse(MouseEventKind::Down(MouseButton::Left), column, row, KeyModifiers::SHIFT));
+    handle_mouse_event(&mut app, mouse(MouseEventKind::Drag(MouseButton::Left), column + 500, row + 500, KeyModifiers::SHIFT));
+    handle_mouse_event(&mut app, mouse(MouseEventKind::Up(MouseButton::Left), column + 500, row + 500, KeyModifiers::SHIFT));
+    assert_eq!((app.view_offset_x, app.view_offset_y), (0, 0), "pans are clamped to the canvas");
+
+    assert_eq!(app.cursor_pos, (3, 4));
+    assert!(app.layers[0].canvas.pixels().iter().all(|p| p.alpha == 0.0), "panning never paints");
+}
+
+#[test]
+fn hovering_moves_the_cursor_only_over_the_canvas() {
+    let mut app = test_app(32, 32);
+    render(&mut app, 100, 40);
+    let rect = app.last_centered_canvas_rect.unwrap();
+    let undo_steps = app.undo_stack.len();
+    let hover = |column, row| MouseEvent { kind: MouseEventKind::Moved, column, row, modifiers: KeyModifiers::NONE };
+
+    let zoom = app.zoom_level;
+    handle_mouse_event(&mut app, hover(rect.x + 3 * zoom, rect.y + zoom));
+    assert_eq!(app.cursor_pos, (3, 2));
+    let palette = app.last_palette_area.unwrap();
+    handle_mouse_event(&mut app, hover(palette.x, palette.y));
+    assert_eq!(app.cursor_pos, (3, 2), "hovering the palette leaves the canvas cursor alone");
+
+    app.hover_cursor = false;
+    handle_mouse_event(&mut app, hover(rect.x, rect.y));
+    assert_eq!(app.cursor_pos, (3, 2));
+    assert_eq!(app.undo_stack.len(), undo_steps);
+    assert!(app.layers[0].canvas.pixels().iter().all(|p| p.alpha == 0.0), "hovering never paints");
+}
+
+#[test]
+fn clicking_and_dragging_the_minimap_moves_the_view() {
+    let mut app = test_app(200, 200);
+    render(&mut app, 120, 40);
+    app.zoom_level = 8;
+    render(&mut app, 120, 40);
+    let minimap = app.last_minimap_area.expect("the minimap is drawn for large canvases");
+    let pixel_area = app.last_pixel_area.unwrap();
+    let (visible_x, visible_y) = ((pixel_area.width / 8) as i32, (pixel_area.height / 4) as i32);
+    let mouse = |kind, column, row| MouseEvent { kind, column, row, modifiers: KeyModifiers::NONE };
+
+    let (center_column, center_row) = (minimap.x + minimap.width / 2, minimap.y + minimap.height / 2);
+    handle_mouse_event(&mut app, mouse(MouseEventKind::Down(MouseButton::Left), center_column, center_row));
+    let expected_x = ((minimap.width / 2) as f32 + 0.5) * 200.0 / minimap.width as f32;
+    let expected_y = ((minimap.height / 2) as f32 + 0.5) * 200.0 / minimap.height as f32;
+    assert_eq!(app.view_offset_x, expected_x as i32 - visible_x / 2);
+    assert_eq!(app.view_offset_y, expected_y as i32 - visible_y / 2, "each minimap row covers two canvas-pixel rows of the scaled canvas");
+
+    // Dragging past the minimap behaves like dragging over its edge cells.
+    for ((outside_column, outside_row), (edge_column, edge_row)) in [((0, 0), (minimap.x, minimap.y)), ((500, 500), (minimap.right() - 1, minimap.bottom() - 1))] {
+        handle_mouse_event(&mut app, mouse(MouseEventKind::Drag(MouseButton::Left), outside_column, outside_row));
+        let clamped = (app.view_offset_x, app.view_offset_y);
+        handle_mouse_event(&mut app, mouse(MouseEventKind::Drag(MouseButton::Left), edge_column, edge_row));
+        assert_eq!(clamped, (app.view_offset_x, app.view_offset_y));
+    }
+    assert_eq!(app.view_offset_x, 200 - (pixel_area.width as i32 + 7) / 8, "the view stops at the canvas edge");
+    handle_mouse_event(&mut app, mouse(MouseEventKind::Up(MouseButton::Left), 500, 500));
+    assert!(!app.minimap_dragging);
+
+    handle_mouse_event(&mut app, mouse(MouseEventKind::ScrollUp, center_column, center_row));
+    assert_eq!(app.zoom_level, 10);
+    assert!(app.layers[0].canvas.pixels().iter().all(|p| p.alpha == 0.0), "the minimap never paints");
+}
+
+#[test]
+fn zooming_keeps_the_cursor_and_mouse_pixel_in_place() {
+    let mut app = test_app(200, 200);
+    app.minimap_mode = MinimapMode::Off;
+    render(&mut app, 120, 40);
+    app.zoom_level = 8;
+    app.view_offset_x = 30;
+    app.view_offset_y = 40;
+    render(&mut app, 120, 40);
+    let rect = app.last_centered_canvas_rect.unwrap();
+
+    // Keyboard zoom keeps the drawing cursor under the screen cell its middle was on.
+    app.cursor_pos = (36, 45);
+    let middle = ((36 - 30) * 8 + 4, (45 - 40) * 4 + 2);
+    app.zoom(2);
+    assert_eq!(app.zoom_level, 10);
+    assert_eq!((app.view_offset_x + middle.0 / 10, app.view_offset_y + middle.1 / 5), (36, 45));
+
+    // Alt+scroll anchors on the pixel under the mouse.
+    let (column, row) = (rect.x + 45, rect.y + 12);
+    let pixel_under = |app: &App| (app.view_offset_x + ((column - rect.x) / app.zoom_level) as i32, app.view_offset_y + ((row - rect.y) / (app.zoom_level / 2)) as i32);
+    let before = pixel_under(&app);
+    handle_mouse_event(&mut app, MouseEvent { kind: MouseEventKind::ScrollUp, column, row, modifiers: KeyModifiers::ALT });
+    assert_eq!(app.zoom_level, 12);
+    assert_eq!(pixel_under(&app), before);
+    assert_eq!(app.cursor_pos, (36, 45), "scroll zoom leaves the cursor alone");
+
+    for _ in 0..20 { app.zoom(2); }
+    assert_eq!(app.zoom_level, 32, "the default ceiling is 16x in status bar units");
+    app.max_zoom = 8;
+    app.zoom(0);
+    assert_eq!(app.zoom_level, 16);
+}
+
+#[test]
+fn double_clicking_a_layer_row_renames_it_in_place() {
+    use consolet::controller::handle_key_event;
+    use crossterm::event::{KeyCode, KeyEvent};
+    let key = |app: &mut App, code: KeyCode| handle_key_event(app, KeyEvent::new(code, KeyModifiers::NONE)).unwrap();
+    let type_text = |app: &mut App, text: &str| text.chars().for_each(|c| handle_key_event(app, KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)).unwrap());
+
+    let mut app = test_app(12, 8);
+    consolet::execute_command(&mut app, "add_layer");
+    render(&mut app, 80, 24);
+    let area = app.last_layer_area.unwrap();
+    let click = MouseEvent { kind: MouseEventKind::Down(MouseButton::Left), column: area.x + 3, row: area.y + 1, modifiers: KeyModifiers::NONE };
+    let release = MouseEvent { kind: MouseEventKind::Up(MouseButton::Left), ..click };
+    handle_mouse_event(&mut app, click);
+    handle_mouse_event(&mut app, release);
+    assert!(!app.is_renaming_layer);
+    handle_mouse_event(&mut app, click);
+    handle_mouse_event(&mut app, release);
+    assert!(app.is_renaming_layer);
+    assert_eq!((app.active_layer_index, app.layer_input_buffer.as_str()), (1, "Layer 1"));
+
+    // Backspace and Delete remove whole graphemes, so the combining accent goes with its letter.
+    for _ in 0..7 { key(&mut app, KeyCode::Backspace); }
+    key(&mut app, KeyCode::Enter);
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Layer name cannot be empty.");
+    assert!(app.is_renaming_layer);
+    type_text(&mut app, "Inx");
+    key(&mut app, KeyCode::Backspace);
+    type_text(&mut app, "k");
+    key(&mut app, KeyCode::Home);
+    type_text(&mut app, "e\u{301}");
+    key(&mut app, KeyCode::Left);
+    assert_eq!(app.layer_name_cursor_pos, 0);
+    key(&mut app, KeyCode::Delete);
+    assert_eq!(app.layer_input_buffer, "Ink");
+    let screen = render(&mut app, 80, 24);
+    assert!(screen.lines().any(|line| line.starts_with("│>") && line.contains(" Ink ")), "{}", screen);
+
+    key(&mut app, KeyCode::Enter);
+    assert!(!app.is_renaming_layer);
+    assert_eq!(app.layers[1].name, "Ink");
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Renamed layer to 'Ink'.");
+
+    // F2 reopens the input on the active layer, and Esc leaves the name alone.
+    key(&mut app, KeyCode::F(2));
+    type_text(&mut app, " wash");
+    key(&mut app, KeyCode::Esc);
+    assert!(!app.is_renaming_layer);
+    assert_eq!(app.layers[1].name, "Ink");
+}
+
+#[test]
+fn layer_rows_show_opacity_and_reorder_by_dragging() {
+    let mut app = test_app(12, 8);
+    app.layers[0].name = "A very long layer name".to_string();
+    consolet::execute_command(&mut app, "add_layer");
+    consolet::execute_command(&mut app, "add_layer");
+    app.layers[2].opacity = 0.5;
+    let screen = render(&mut app, 80, 24);
+    let rows: Vec<&str> = screen.lines().filter(|line| line.contains("% █")).collect();
+    assert!(rows[2].contains(" A very…  50% ██░│"), "{}", screen);
+    assert!(rows[0].contains(" Layer 3 100% ███│"), "{}", screen);
+
+    // Alt+scroll steps the opacity of the row under the mouse in 5% steps.
+    let area = app.last_layer_area.unwrap();
+    let at_row = |kind, row: u16, modifiers| MouseEvent { kind, column: area.x + 2, row: area.y + row, modifiers };
+    handle_mouse_event(&mut app, at_row(MouseEventKind::ScrollUp, 2, KeyModifiers::ALT));
+    assert_eq!(app.layers[2].opacity, 0.55);
+    handle_mouse_event(&mut app, at_row(MouseEventKind::ScrollDown, 1, KeyModifiers::ALT));
+    assert_eq!(app.layers[1].opacity, 0.95);
+    assert_eq!(app.layer_scroll_state, 0);
+
+    // Dragging the top row past the bottom drops it last and keeps it active.
+    handle_mouse_event(&mut app, at_row(MouseEventKind::Down(MouseButton::Left), 0, KeyModifiers::NONE));
+    handle_mouse_event(&mut app, at_row(MouseEventKind::Drag(MouseButton::Left), 1, KeyModifiers::NONE));
+    assert_eq!(app.layer_drag, Some((0, 1)));
+    handle_mouse_event(&mut app, at_row(MouseEventKind::Drag(MouseButton::Left), 9, KeyModifiers::NONE));
+    handle_mouse_event(&mut app, at_row(MouseEventKind::Up(MouseButton::Left), 9, KeyModifiers::NONE));
+    let names: Vec<&str> = app.layers.iter().map(|layer| layer.name.as_str()).collect();
+    assert_eq!(names, ["Layer 2", "A very long layer name", "Layer 3"]);
+    assert_eq!((app.active_layer_index, app.layer_drag), (2, None));
+    app.undo();
+    assert_eq!(app.layers[0].name, "Layer 3");
+}
+
+#[test]
+fn onion_skin_tints_earlier_and_later_layers_with_falloff() {
+    use ratatui::style::Color;
+    let mut app = test_app(6, 2);
+    for _ in 0..4 {
+        consolet::execute_command(&mut app, "add_layer");
+    }
+    select_color(&mut app, 255, 255, 255);
+    for index in 0..5 {
+        app.set_active_layer(index);
+        app.apply_brush(index as u16, 0);
+        // Zero layer opacity keeps them out of the composite; onion skin still shows them.
+        app.layers[index].opacity = 0.0;
+    }
+    app.set_active_layer(2);
+    app.cursor_pos = (5, 1);
+    consolet::execute_command(&mut app, "onion_skin=true");
+    consolet::execute_command(&mut app, "onion_skin_frames_before=2");
+    consolet::execute_command(&mut app, "onion_skin_frames_after=1");
+
+    let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+    terminal.draw(|frame| ui(frame, &mut app)).unwrap();
+    let rect = app.last_centered_canvas_rect.unwrap();
+    let buffer = terminal.backend().buffer();
+    let cell = |x: u16| buffer.get(rect.x + x * app.zoom_level, rect.y).bg;
+    assert_eq!(cell(0), Color::Rgb(38, 24, 24), "two steps back is fainter");
+    assert_eq!(cell(1), Color::Rgb(77, 47, 47));
+    assert_eq!(cell(3), Color::Rgb(47, 68, 77));
+    assert_eq!(cell(4), Color::Reset, "only one step ahead");
+}
+
+#[test]
+fn playback_shows_one_layer_at_a_time_without_touching_the_project() {
+    use crossterm::event::{KeyCode, KeyEvent};
+    let mut app = test_app(3, 1);
+    consolet::execute_command(&mut app, "add_layer");
+    consolet::execute_command(&mut app, "add_layer");
+    for index in 0..3 {
+        app.set_active_layer(index);
+        app.apply_brush(index as u16, 0);
+    }
+    app.layers[1].visible = false;
+    app.cursor_pos = (2, 0);
+    app.dirty = false;
+    let undo_steps = app.undo_stack.len();
+
+    consolet::execute_command(&mut app, "play 8");
+    assert_eq!((app.playing, app.playback_frame), (Some(8), 0));
+    let draw = |app: &mut App| {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        terminal.draw(|frame| ui(frame, app)).unwrap();
+        let rect = app.last_centered_canvas_rect.unwrap();
+        let (buffer, zoom) = (terminal.backend().buffer().clone(), app.zoom_level);
+        let painted: Vec<bool> = (0..2).map(|x| buffer.get(rect.x + x * zoom, rect.y).bg != ratatui::style::Color::Reset).collect();
+        let screen: String = (0..buffer.area.height).flat_map(|y| (0..buffer.area.width).map(move |x| (x, y))).map(|(x, y)| buffer.get(x, y).symbol().to_string()).collect();
+        (painted, screen)
+    };
+    let (painted, screen) = draw(&mut app);
+    assert_eq!(painted, [true, false]);
+    assert!(screen.contains("Playing 8fps — frame 1/3"), "{}", screen);
+
+    assert!(!app.advance_playback(), "not before the frame time");
+    app.last_playback_time = Some(chrono::Local::now() - chrono::Duration::milliseconds(200));
+    assert!(app.advance_playback());
+    let (painted, _) = draw(&mut app);
+    assert_eq!(painted, [false, true], "hidden layers still play");
+    assert!(!app.layers[1].visible);
+
+    let opacity = app.opacity;
+    consolet::controller::handle_key_event(&mut app, KeyEvent::new(KeyCode::Char('p'), KeyModifiers::NONE)).unwrap();
+    assert_eq!((app.playing, app.opacity), (None, opacity), "the key only stops playback");
+    assert!(!app.dirty);
+    assert_eq!(app.undo_stack.len(), undo_steps);
+}
+
+#[test]
+fn color_editor_mixes_a_color_with_sliders_and_adds_it_to_the_palette() {
+    use consolet::controller::handle_key_event;
+    use consolet::palette::PaletteEntry;
+    use crossterm::event::{KeyCode, KeyEvent};
+    use ratatui::style::Color;
+    let key = |app: &mut App, code: KeyCode, modifiers: KeyModifiers| handle_key_event(app, KeyEvent::new(code, modifiers)).unwrap();
+
+    let mut app = test_app(12, 8);
+    select_color(&mut app, 200, 0, 0);
+    key(&mut app, KeyCode::Char('c'), KeyModifiers::ALT);
+    assert!(app.mode == AppMode::ColorEditor);
+    assert_eq!(app.color_editor_rgb, (200, 0, 0));
+    let screen = render(&mut app, 80, 24);
+    assert!(screen.contains("New Color") && screen.contains("Hex #C80000"), "{}", screen);
+
+    // Hue survives dragging the value down to black and back up.
+    key(&mut app, KeyCode::Right, KeyModifiers::SHIFT);
+    assert_eq!(app.color_editor_hsv.0, 10.0);
+    let bars = app.last_color_editor_area.unwrap();
+    let value_row = MouseEvent { kind: MouseEventKind::Down(MouseButton::Left), column: bars.x, row: bars.y + 2, modifiers: KeyModifiers::NONE };
+    handle_mouse_event(&mut app, value_row);
+    assert_eq!((app.color_editor_row, app.color_editor_rgb), (2, (0, 0, 0)));
+    handle_mouse_event(&mut app, MouseEvent { kind: MouseEventKind::Drag(MouseButton::Left), column: bars.right() + 5, ..value_row });
+    assert_eq!(app.color_editor_hsv, (10.0, 1.0, 1.0));
+    assert_eq!(app.color_editor_rgb, consolet::palette::hsv_to_rgb(10.0, 1.0, 1.0));
+
+    key(&mut app, KeyCode::Down, KeyModifiers::NONE);
+    key(&mut app, KeyCode::Right, KeyModifiers::NONE);
+    assert_eq!(app.color_editor_rgb.0, 255, "channels stop at 255");
+    key(&mut app, KeyCode::Left, KeyModifiers::NONE);
+    assert_eq!(app.color_editor_rgb.0, 254);
+    key(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+    assert!(app.mode == AppMode::Drawing);
+    assert!(app.current_selection == PaletteEntry::Color(Color::Rgb(200, 0, 0)), "Esc keeps the old color");
+
+    // 'n' in the color picker starts from the highlighted palette color.
+    let palette_size = app.color_palette.len();
+    app.palette_index = 2;
+    key(&mut app, KeyCode::Char('c'), KeyModifiers::NONE);
+    key(&mut app, KeyCode::Char('n'), KeyModifiers::NONE);
+    assert!(app.mode == AppMode::ColorEditor);
+    assert_eq!(app.color_editor_rgb, (220, 220, 220));
+    key(&mut app, KeyCode::Down, KeyModifiers::NONE);
+    key(&mut app, KeyCode::Right, KeyModifiers::SHIFT);
+    let mixed = app.color_editor_rgb;
+    key(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+    let entry = PaletteEntry::Color(Color::Rgb(mixed.0, mixed.1, mixed.2));
+    assert!(app.current_selection == entry);
+    assert_eq!((app.color_palette.len(), app.palette_index), (palette_size + 1, palette_size));
+    assert_eq!(app.status_message.as_ref().unwrap().0, format!("Added #{:02X}{:02X}{:02X} to the palette.", mixed.0, mixed.1, mixed.2));
+
+    consolet::execute_command(&mut app, "color_editor");
+    key(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+    assert_eq!(app.color_palette.len(), palette_size + 1, "no duplicates");
+    consolet::execute_command(&mut app, "color_editor");
+    key(&mut app, KeyCode::Char('a'), KeyModifiers::NONE);
+    key(&mut app, KeyCode::Left, KeyModifiers::NONE);
+    key(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+    assert_eq!(app.color_palette.len(), palette_size + 1, "adding to the palette was turned off");
+    assert!(app.status_message.as_ref().unwrap().0.starts_with("Selected #"));
+}
+
+#[test]
+fn editing_a_palette_color_writes_it_back_and_can_remap_the_canvas() {
+    use consolet::controller::handle_key_event;
+    use consolet::palette::PaletteEntry;
+    use crossterm::event::{KeyCode, KeyEvent};
+    use ratatui::style::Color;
+    let key = |app: &mut App, code: KeyCode| handle_key_event(app, KeyEvent::new(code, KeyModifiers::NONE)).unwrap();
+
+    let mut app = test_app(3, 1);
+    select_color(&mut app, 220, 220, 220);
+    app.apply_brush(0, 0);
+    consolet::execute_command(&mut app, "add_layer");
+    app.apply_brush(1, 0);
+    select_color(&mut app, 10, 20, 30);
+    app.apply_brush(2, 0);
+    let palette_size = app.color_palette.len();
+
+    app.palette_index = 2;
+    key(&mut app, KeyCode::Char('c'));
+    key(&mut app, KeyCode::Char('e'));
+    assert!(app.mode == AppMode::ColorEditor);
+    assert_eq!((app.color_editor_target, app.color_editor_rgb), (Some(2), (220, 220, 220)));
+    assert!(render(&mut app, 80, 24).contains("Edit Color 3"));
+    key(&mut app, KeyCode::Char('a'));
+    assert!(app.color_editor_add_to_palette, "there is nothing to add when editing");
+    for _ in 0..3 { key(&mut app, KeyCode::Down); }
+    for _ in 0..20 { key(&mut app, KeyCode::Right); }
+    key(&mut app, KeyCode::Char('r'));
+    key(&mut app, KeyCode::Enter);
+
+    let new_color = Color::Rgb(240, 220, 220);
+    assert!(app.color_palette[2] == PaletteEntry::Color(new_color));
+    assert_eq!(app.color_palette.len(), palette_size);
+    assert!(app.current_selection == PaletteEntry::Color(new_color));
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Changed palette color 3 to #F0DCDC and recolored 2 pixels.");
+    assert_eq!(app.layers[1].canvas[0][0].color, new_color.into());
+    assert_eq!(app.layers[0].canvas[0][1].color, new_color.into());
+    assert_eq!(app.layers[0].canvas[0][2].color, Color::Rgb(10, 20, 30).into());
+
+    app.undo();
+    assert_eq!(app.layers[1].canvas[0][0].color, Color::Rgb(220, 220, 220).into());
+    assert_eq!(app.layers[0].canvas[0][1].color, Color::Rgb(220, 220, 220).into());
+
+    // Without --remap only the palette slot changes.
+    consolet::execute_command(&mut app, "edit_color");
+    key(&mut app, KeyCode::Down);
+    key(&mut app, KeyCode::Down);
+    key(&mut app, KeyCode::Left);
+    key(&mut app, KeyCode::Enter);
+    assert!(app.color_palette[2] != PaletteEntry::Color(new_color));
+    assert_eq!(app.layers[0].canvas[0][1].color, Color::Rgb(220, 220, 220).into());
+    consolet::execute_command(&mut app, "edit_color --remap");
+    assert!(app.color_editor_remap);
+    key(&mut app, KeyCode::Esc);
+    consolet::execute_command(&mut app, "edit_color --all");
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Usage: edit_color [--remap]");
+}
+
+#[test]
+fn recent_colors_strip_tracks_painting_and_swaps_between_two_colors() {
+    use consolet::controller::handle_key_event;
+    use consolet::palette::PaletteEntry;
+    use crossterm::event::{KeyCode, KeyEvent};
+    use ratatui::style::Color;
+    let quick_select = |app: &mut App| handle_key_event(app, KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE)).unwrap();
+
+    let mut app = test_app(8, 8);
+    assert!(!render(&mut app, 80, 30).contains(" Recent "), "the strip waits for the first stroke");
+    quick_select(&mut app);
+    assert_eq!(app.status_message.as_ref().unwrap().0, "No recent colors yet.");
+
+    for (r, g, b) in [(255, 0, 0), (0, 255, 0), (0, 0, 255), (255, 0, 0)] {
+        select_color(&mut app, r, g, b);
+        app.apply_brush(1, 1);
+        app.apply_brush(2, 1);
+    }
+    assert_eq!(app.recent_colors, vec![Color::Rgb(255, 0, 0), Color::Rgb(0, 0, 255), Color::Rgb(0, 255, 0)]);
+
+    quick_select(&mut app);
+    assert_eq!(app.current_selection, PaletteEntry::Color(Color::Rgb(0, 0, 255)));
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Recent color 2 of 3 (#0000FF).");
+    app.apply_brush(3, 3);
+    quick_select(&mut app);
+    assert_eq!(app.current_selection, PaletteEntry::Color(Color::Rgb(255, 0, 0)), "one press goes back to the other working color");
+
+    let screen = render(&mut app, 80, 30);
+    assert!(screen.contains(" Recent "));
+    let strip = app.last_recent_colors_area.unwrap();
+    handle_mouse_event(&mut app, MouseEvent { kind: MouseEventKind::Down(MouseButton::Left), column: strip.x + 7, row: strip.y, modifiers: KeyModifiers::NONE });
+    assert_eq!(app.current_selection, PaletteEntry::Color(Color::Rgb(0, 255, 0)));
+    assert!(!app.is_mouse_dragging);
+
+    for shade in 0..12 {
+        select_color(&mut app, shade, shade, shade);
+        app.apply_brush(0, 0);
+    }
+    assert_eq!(app.recent_colors.len(), consolet::RECENT_COLORS);
+    assert_eq!(app.recent_colors[0], Color::Rgb(11, 11, 11));
+
+    // They come back with the project's session.
+    std::fs::create_dir_all(scratch_dir()).unwrap();
+    let path = scratch_dir().join("recent_colors.consolet");
+    app.save_project(&path, false);
+    let mut loaded = test_app(2, 2);
+    loaded.load_project(&path);
+    assert_eq!(loaded.recent_colors, app.recent_colors);
+}
+
+#[test]
+fn color_info_reads_out_the_drawing_color_and_the_pixel_under_the_cursor() {
+    use consolet::controller::handle_key_event;
+    use consolet::ColorInfo;
+    use crossterm::event::{KeyCode, KeyEvent};
+
+    let mut app = test_app(12, 8);
+    app.apply_brush(2, 2);
+    consolet::execute_command(&mut app, "add_layer");
+    select_color(&mut app, 0, 128, 255);
+    app.opacity = 0.5;
+    app.apply_brush(3, 3);
+    app.cursor_pos = (2, 2);
+    assert!(!render(&mut app, 100, 24).contains("#0080FF"), "off by default");
+
+    handle_key_event(&mut app, KeyEvent::new(KeyCode::Char('v'), KeyModifiers::NONE)).unwrap();
+    assert_eq!(app.color_info, ColorInfo::StatusBar);
+    app.status_message = None;
+    assert_eq!(app.topmost_layer_at(2, 2), Some(1));
+    assert!(render(&mut app, 140, 24).contains("| Color #0080FF | Pixel #FF0000 100% on Layer 1"));
+
+    // Half-transparent paint on the new top layer; empty spots show only the drawing color.
+    app.cursor_pos = (3, 3);
+    assert_eq!(app.topmost_layer_at(3, 3), Some(0));
+    assert_eq!(app.color_info_text(), format!("Color #0080FF | Pixel #0080FF 50% on {}", app.layers[0].name));
+    app.cursor_pos = (5, 5);
+    assert_eq!(app.topmost_layer_at(5, 5), None);
+    assert_eq!(app.color_info_text(), "Color #0080FF");
+
+    consolet::execute_command(&mut app, "color_info=cursor");
+    assert_eq!(app.color_info, ColorInfo::Cursor);
+    app.status_message = None;
+    let screen = render(&mut app, 100, 24);
+    let line = screen.lines().find(|line| line.contains(" Color #0080FF ")).expect("the readout is drawn next to the cursor");
+    assert!(!line.contains("Controls"));
+}
+
+#[test]
+fn ctrl_clicking_a_pixel_selects_the_layer_it_is_painted_on() {
+    use consolet::controller::handle_key_event;
+    use crossterm::event::{KeyCode, KeyEvent};
+
+    let mut app = test_app(12, 8);
+    app.apply_brush(2, 2);
+    for _ in 0..6 {
+        consolet::execute_command(&mut app, "add_layer");
+    }
+    app.apply_brush(5, 5);
+    app.layers[1].visible = false;
+    app.layers[1].canvas[2][2] = app.layers[0].canvas[5][5];
+    render(&mut app, 80, 24);
+    assert_eq!(app.layer_scroll_state, 0);
+
+    // Clicks on the canvas pixel at (2, 2), skipping the hidden layer painted there too.
+    let canvas = app.last_centered_canvas_rect.unwrap();
+    let pixel_height = (app.zoom_level / consolet::PIXEL_WIDTH).max(1);
+    let click = MouseEvent { kind: MouseEventKind::Down(MouseButton::Left), column: canvas.x + 2 * app.zoom_level, row: canvas.y + 2 * pixel_height, modifiers: KeyModifiers::CONTROL };
+    handle_mouse_event(&mut app, click);
+    assert_eq!(app.active_layer_index, 6);
+    assert_eq!(app.status_message.as_ref().unwrap().0, "Active layer: 'Layer 1'.");
+    assert!(!app.is_mouse_dragging, "the click does not paint");
+    assert_eq!(app.layers[6].canvas.pixels().iter().filter(|p| p.alpha > 0.0).count(), 1);
+    let rows = app.last_layer_area.unwrap().height as usize - 2;
+    assert!(app.layer_scroll_state <= 6 && 6 < app.layer_scroll_state + rows, "the panel scrolls to the new active layer");
+
+    // The keyboard action works at the drawing cursor; empty spots leave the selection alone.
+    app.cursor_pos = (9, 1);
+    handle_key_event(&mut app, KeyEvent::new(KeyCode::Char('s'), KeyModifiers::ALT)).unwrap();
+    assert_eq!(app.active_layer_index, 6);
+    assert_eq!(app.status_message.as_ref().unwrap().0, "No layer has paint at (9, 1).");
+    app.cursor_pos = (5, 5);
+    handle_key_event(&mut app, KeyEvent::new(KeyCode::Char('s'), KeyModifiers::ALT)).unwrap();
+    assert_eq!((app.active_layer_index, app.layer_scroll_state), (0, 0));
+}